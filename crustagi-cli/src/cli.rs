@@ -0,0 +1,217 @@
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use crustagi::config_file::{self, DEFAULT_CONFIG_FILE};
+use crustagi::Command;
+use std::env;
+use std::io;
+
+// Command line of crustagi. The settings given as flags are put into the
+// environment before the configuration is read from it, so they override
+// the environment variables, `.env` entries and config file settings of the
+// same name.
+#[derive(Debug, Parser)]
+#[command(
+    name = "crustagi",
+    version,
+    about = "An AI agent creating, prioritizing and executing tasks towards an objective",
+    after_help = "Every setting can also be given as environment variable or in .env, see the README."
+)]
+pub struct Cli {
+    #[command(flatten)]
+    pub settings: Settings,
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+}
+
+// The commands of the library, and those only the command line has
+#[derive(Debug, Subcommand)]
+pub enum CliCommand {
+    #[command(flatten)]
+    Agent(Command),
+    /// Set up the LLM provider, memory backend and objective, and check them
+    Init,
+    /// Keep API keys in the OS keyring instead of .env
+    Secrets {
+        #[command(subcommand)]
+        action: SecretsAction,
+    },
+    /// Print the completion script for a shell
+    Completions { shell: Shell },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SecretsAction {
+    /// Store a secret, e.g. OPENAI_API_KEY, read from a prompt or stdin
+    Set { name: String },
+    /// Delete a stored secret
+    Delete { name: String },
+}
+
+// Flags of the most used settings, each named after its environment
+// variable. `--set` covers all others.
+#[derive(Debug, Args)]
+pub struct Settings {
+    /// Config file with settings and profiles [env: CONFIG_FILE] [default: crustagi.toml]
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<String>,
+    /// Profile of the config file to use, e.g. `local` for `[profile.local]` [env: PROFILE]
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
+    /// Objective of the run [env: OBJECTIVE]
+    #[arg(long, global = true, conflicts_with_all = ["objective_file", "objectives_file"])]
+    objective: Option<String>,
+    /// File with the objective, e.g. a brief of several lines [env: OBJECTIVE_FILE]
+    #[arg(
+        long,
+        global = true,
+        value_name = "PATH",
+        conflicts_with = "objectives_file"
+    )]
+    objective_file: Option<String>,
+    /// First task of the run [env: INITIAL_TASK]
+    #[arg(long, global = true)]
+    initial_task: Option<String>,
+    /// File listing several objectives, one per line [env: OBJECTIVES_FILE]
+    #[arg(long, global = true, value_name = "PATH")]
+    objectives_file: Option<String>,
+    /// Keep the run's state, logs and results apart under this name [env: RUN_NAME]
+    #[arg(long, global = true, value_name = "NAME")]
+    run_name: Option<String>,
+    /// openai, gemini or cohere [env: LLM_PROVIDER]
+    #[arg(long, global = true, value_name = "PROVIDER")]
+    llm_provider: Option<String>,
+    /// Vector store of the results [env: MEMORY_BACKEND]
+    #[arg(long, global = true, value_name = "BACKEND")]
+    memory_backend: Option<String>,
+    /// Stop after this many tasks [env: MAX_ITERATIONS]
+    #[arg(long, global = true, value_name = "N")]
+    max_iterations: Option<usize>,
+    /// Stop once the run has cost this much [env: MAX_COST_USD]
+    #[arg(long, global = true, value_name = "USD")]
+    max_cost_usd: Option<f64>,
+    /// Stop once the run has used this many tokens [env: MAX_TOKENS_TOTAL]
+    #[arg(long, global = true, value_name = "N")]
+    max_tokens_total: Option<usize>,
+    /// Tasks run at the same time [env: MAX_CONCURRENT_TASKS]
+    #[arg(long, global = true, value_name = "N")]
+    max_concurrent_tasks: Option<usize>,
+    /// Break the objective into milestones first [env: PLAN_MILESTONES]
+    #[arg(long, global = true)]
+    plan_milestones: bool,
+    /// Sample with temperature 0 and a fixed seed, and write a manifest [env: DETERMINISTIC]
+    #[arg(long, global = true)]
+    deterministic: bool,
+    /// Sampling seed [env: SEED]
+    #[arg(long, global = true, value_name = "N")]
+    seed: Option<u64>,
+    /// Also show the full prompts and raw API responses [env: LOG_LEVEL=verbose]
+    #[arg(long, short, global = true, conflicts_with = "quiet")]
+    verbose: bool,
+    /// Only show the task results and the report [env: LOG_LEVEL=quiet]
+    #[arg(long, short, global = true)]
+    quiet: bool,
+    /// Show a live dashboard instead of the scrolling output [env: TUI]
+    #[arg(long, global = true)]
+    tui: bool,
+    /// Don't color the output, which is only colored on a terminal anyway [env: NO_COLOR]
+    #[arg(long, global = true)]
+    no_color: bool,
+    /// Any other setting, e.g. `--set EXECUTION_MODEL=gpt-4o`
+    #[arg(long = "set", global = true, value_name = "NAME=VALUE", value_parser = parse_setting)]
+    set: Vec<(String, String)>,
+}
+
+fn parse_setting(setting: &str) -> Result<(String, String), String> {
+    match setting.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("expected NAME=VALUE, got \"{}\"", setting)),
+    }
+}
+
+impl Settings {
+    // Put the config file and the given settings into the environment. Must
+    // be called before any threads are started.
+    pub fn apply(&self) {
+        let config = self.config.clone().or_else(|| env::var("CONFIG_FILE").ok());
+        let profile = self.profile.clone().or_else(|| env::var("PROFILE").ok());
+        config_file::apply(
+            config.as_deref().unwrap_or(DEFAULT_CONFIG_FILE),
+            config.is_some(),
+            profile.as_deref(),
+        );
+        let options = [
+            ("OBJECTIVE", self.objective.clone()),
+            ("INITIAL_TASK", self.initial_task.clone()),
+            ("OBJECTIVE_FILE", self.objective_file.clone()),
+            ("OBJECTIVES_FILE", self.objectives_file.clone()),
+            ("RUN_NAME", self.run_name.clone()),
+            ("LLM_PROVIDER", self.llm_provider.clone()),
+            ("MEMORY_BACKEND", self.memory_backend.clone()),
+            ("MAX_ITERATIONS", self.max_iterations.map(|n| n.to_string())),
+            ("MAX_COST_USD", self.max_cost_usd.map(|usd| usd.to_string())),
+            (
+                "MAX_TOKENS_TOTAL",
+                self.max_tokens_total.map(|n| n.to_string()),
+            ),
+            (
+                "MAX_CONCURRENT_TASKS",
+                self.max_concurrent_tasks.map(|n| n.to_string()),
+            ),
+            ("SEED", self.seed.map(|seed| seed.to_string())),
+            (
+                "LOG_LEVEL",
+                match (self.verbose, self.quiet) {
+                    (true, _) => Some("verbose".to_string()),
+                    (_, true) => Some("quiet".to_string()),
+                    _ => None,
+                },
+            ),
+        ];
+        // An objective given as flag replaces one set in any other way
+        let objective_sources = [
+            ("OBJECTIVE", &self.objective),
+            ("OBJECTIVE_FILE", &self.objective_file),
+            ("OBJECTIVES_FILE", &self.objectives_file),
+        ];
+        if objective_sources.iter().any(|(_, flag)| flag.is_some()) {
+            for (name, _) in objective_sources {
+                env::remove_var(name);
+            }
+        }
+        let flags = [
+            ("PLAN_MILESTONES", self.plan_milestones),
+            ("DETERMINISTIC", self.deterministic),
+            ("TUI", self.tui),
+            ("NO_COLOR", self.no_color),
+        ];
+        let flags = flags
+            .into_iter()
+            .filter(|(_, set)| *set)
+            .map(|(name, _)| (name, Some("true".to_string())));
+        for (name, value) in options.into_iter().chain(flags) {
+            if let Some(value) = value {
+                env::set_var(name, value);
+            }
+        }
+        // `--set` comes last, so it wins over the flags above
+        for (name, value) in &self.set {
+            env::set_var(name, value);
+        }
+    }
+}
+
+impl Settings {
+    // Path of the config file, from `--config`, CONFIG_FILE or the default
+    pub fn config_file(&self) -> String {
+        self.config
+            .clone()
+            .or_else(|| env::var("CONFIG_FILE").ok())
+            .unwrap_or_else(|| DEFAULT_CONFIG_FILE.to_string())
+    }
+}
+
+// Write the completion script for `shell` to stdout
+pub fn print_completions(shell: Shell) {
+    let mut command = Cli::command();
+    clap_complete::generate(shell, &mut command, "crustagi", &mut io::stdout());
+}
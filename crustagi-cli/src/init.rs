@@ -0,0 +1,419 @@
+use crustagi::llm::{self, LlmParams, SUPPORTED_PROVIDERS};
+use crustagi::memory::{self, SUPPORTED_BACKENDS};
+use crustagi::middleware::{CostLayer, Layer, RateLimitLayer};
+use crustagi::openai::{DEFAULT_API_BASE, DEFAULT_EMBEDDING_MODEL, DEFAULT_MODEL};
+use crustagi::usage::{UsageMeter, UsageTracker};
+use crustagi::{Config, CrustagiError};
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+use std::sync::Arc;
+use toml::{Table, Value};
+
+use crate::secrets;
+
+const ENV_FILE: &str = ".env";
+
+// A setting answered in the wizard. Secrets go to `.env`, all others to the
+// config file.
+struct Answer {
+    name: &'static str,
+    value: String,
+    secret: bool,
+}
+
+// Ask for the settings of a first run, write them to `.env` and the config
+// file at `config_path`, and put them into the environment for the checks
+// that follow. Current values, e.g. from an earlier `init`, are the
+// defaults. Must be called before any threads are started.
+pub fn setup(config_path: &str) -> Result<(), CrustagiError> {
+    println!("Setting up crustagi. Press Enter to keep the value in brackets.\n");
+    let mut answers = Vec::new();
+    // The default models of the other providers
+    let defaults = Config::default();
+
+    let provider = choose(
+        "LLM provider",
+        SUPPORTED_PROVIDERS,
+        "LLM_PROVIDER",
+        "openai",
+    )?;
+    answers.push(plain("LLM_PROVIDER", provider.clone()));
+    match provider.as_str() {
+        "openai" => {
+            answers.push(secret("OPENAI_API_KEY", "OpenAI API key")?);
+            answers.push(plain(
+                "OPENAI_API_BASE",
+                ask(
+                    "API base URL, for OpenAI-compatible servers",
+                    "OPENAI_API_BASE",
+                    Some(DEFAULT_API_BASE),
+                )?,
+            ));
+            answers.push(plain(
+                "OPENAI_API_MODEL",
+                ask("Model", "OPENAI_API_MODEL", Some(DEFAULT_MODEL))?,
+            ));
+            answers.push(plain(
+                "EMBEDDING_MODEL",
+                ask(
+                    "Embedding model",
+                    "EMBEDDING_MODEL",
+                    Some(DEFAULT_EMBEDDING_MODEL),
+                )?,
+            ));
+        }
+        "gemini" => {
+            answers.push(secret("GEMINI_API_KEY", "Gemini API key")?);
+            answers.push(plain(
+                "GEMINI_MODEL",
+                ask(
+                    "Model",
+                    "GEMINI_MODEL",
+                    Some(defaults.gemini_model.as_str()),
+                )?,
+            ));
+            answers.push(plain(
+                "GEMINI_EMBEDDING_MODEL",
+                ask(
+                    "Embedding model",
+                    "GEMINI_EMBEDDING_MODEL",
+                    Some(defaults.gemini_embedding_model.as_str()),
+                )?,
+            ));
+        }
+        _ => {
+            answers.push(secret("COHERE_API_KEY", "Cohere API key")?);
+            answers.push(plain(
+                "COHERE_MODEL",
+                ask(
+                    "Model",
+                    "COHERE_MODEL",
+                    Some(defaults.cohere_model.as_str()),
+                )?,
+            ));
+            answers.push(plain(
+                "COHERE_EMBEDDING_MODEL",
+                ask(
+                    "Embedding model",
+                    "COHERE_EMBEDDING_MODEL",
+                    Some(defaults.cohere_embedding_model.as_str()),
+                )?,
+            ));
+        }
+    }
+
+    // SQLite needs no service, so it is the default of a first run
+    let backend = choose(
+        "Memory backend",
+        SUPPORTED_BACKENDS,
+        "MEMORY_BACKEND",
+        "sqlite",
+    )?;
+    answers.push(plain("MEMORY_BACKEND", backend.clone()));
+    match backend.as_str() {
+        "pinecone" => {
+            answers.push(secret("PINECONE_API_KEY", "Pinecone API key")?);
+            let serverless = choose(
+                "Serverless index",
+                &["true", "false"],
+                "PINECONE_SERVERLESS",
+                "true",
+            )?;
+            answers.push(plain(
+                "PINECONE_REGION",
+                ask("Region", "PINECONE_REGION", None)?,
+            ));
+            if serverless == "false" {
+                answers.push(plain(
+                    "PINECONE_PROJECT_ID",
+                    ask("Project id", "PINECONE_PROJECT_ID", None)?,
+                ));
+            }
+            answers.push(plain("PINECONE_SERVERLESS", serverless));
+            answers.push(plain(
+                "PINECONE_INDEX_NAME",
+                ask("Index name", "PINECONE_INDEX_NAME", Some("crustagi"))?,
+            ));
+        }
+        "qdrant" => {
+            answers.push(plain(
+                "QDRANT_URL",
+                ask("Qdrant URL", "QDRANT_URL", Some("http://localhost:6333"))?,
+            ));
+            answers.extend(optional_secret("QDRANT_API_KEY", "Qdrant API key")?);
+        }
+        "weaviate" => {
+            answers.push(plain(
+                "WEAVIATE_URL",
+                ask(
+                    "Weaviate URL",
+                    "WEAVIATE_URL",
+                    Some("http://localhost:8080"),
+                )?,
+            ));
+            answers.extend(optional_secret("WEAVIATE_API_KEY", "Weaviate API key")?);
+        }
+        "chroma" => {
+            answers.push(plain(
+                "CHROMA_URL",
+                ask("Chroma URL", "CHROMA_URL", Some("http://localhost:8000"))?,
+            ));
+        }
+        // The URL holds the password, so it is kept with the secrets
+        "pgvector" => answers.push(secret("DATABASE_URL", "Postgres URL")?),
+        "milvus" => {
+            answers.push(plain(
+                "MILVUS_URL",
+                ask("Milvus URL", "MILVUS_URL", Some("http://localhost:19530"))?,
+            ));
+            answers.extend(optional_secret("MILVUS_TOKEN", "Milvus token")?);
+        }
+        _ => {}
+    }
+
+    answers.push(plain("OBJECTIVE", ask("Objective", "OBJECTIVE", None)?));
+    answers.push(plain(
+        "INITIAL_TASK",
+        ask("Initial task", "INITIAL_TASK", Some("Develop a task list"))?,
+    ));
+
+    let (secrets, settings): (Vec<&Answer>, Vec<&Answer>) =
+        answers.iter().partition(|answer| answer.secret);
+    // Keys kept in the OS keyring are taken out of `.env`, as the
+    // environment takes precedence
+    let keyring = pick(
+        "Keep the API keys in",
+        &[ENV_FILE, "keyring"],
+        ENV_FILE.to_string(),
+    )? == "keyring";
+    if keyring {
+        for answer in &secrets {
+            secrets::store(answer.name, &answer.value)?;
+        }
+    }
+    let written: &[&Answer] = if keyring { &[] } else { &secrets };
+    write_env_file(ENV_FILE, &secrets, written)
+        .map_err(|err| CrustagiError::io(format!("Failed to write {}", ENV_FILE), err))?;
+    write_config_file(config_path, &settings).map_err(|err| {
+        CrustagiError::Config(format!("Failed to write {}: {}", config_path, err))
+    })?;
+    println!(
+        "\nWrote the API keys to {} and the other settings to {}.",
+        if keyring { "the OS keyring" } else { ENV_FILE },
+        config_path
+    );
+    for answer in &answers {
+        env::set_var(answer.name, &answer.value);
+    }
+    Ok(())
+}
+
+// Check the setup of a plain run with a test call of each kind: a
+// completion, an embedding and a round trip through the vector store
+pub async fn check() -> Result<(), CrustagiError> {
+    let config = crustagi::load_config()?;
+    let http = config.http_client()?;
+    println!("\nChecking the setup...");
+    let usage = UsageTracker::with_prices(config.model_prices.clone());
+    let meter = UsageMeter::new(Arc::new(usage), "init");
+    let rate_limit = RateLimitLayer::new(config.max_requests_per_minute.unwrap_or(0));
+    let llm = llm::from_config(&config, &http, None, &LlmParams::default(), &rate_limit)
+        .map_err(|err| CrustagiError::llm("Failed to set up the LLM provider", err))?;
+    let llm = CostLayer::new(meter).layer(llm);
+
+    checking(&format!("Completion with {}", llm.model()));
+    passed(llm.complete("Reply with OK.").await)?;
+    checking(&format!("Embedding with {}", llm.embedding_model()));
+    let dimension = passed(llm.embedding_dimension().await)?;
+    checking(&format!("Memory backend {}", config.memory_backend));
+    passed(
+        async {
+            let store = memory::from_config(&config, &http, &rate_limit, dimension).await?;
+            store.create().await?;
+            memory::health_check(store.as_ref(), &config.memory_backend, dimension).await
+        }
+        .await,
+    )?;
+
+    println!("\nThe setup works. Start a run with `crustagi run`.");
+    Ok(())
+}
+
+fn plain(name: &'static str, value: String) -> Answer {
+    Answer {
+        name,
+        value,
+        secret: false,
+    }
+}
+
+fn secret(name: &'static str, question: &str) -> Result<Answer, CrustagiError> {
+    loop {
+        if let Some(value) = optional_secret(name, question)? {
+            return Ok(value);
+        }
+        println!("{} is needed.", question);
+    }
+}
+
+// A secret read without echo. Enter keeps the current value, if any.
+fn optional_secret(name: &'static str, question: &str) -> Result<Option<Answer>, CrustagiError> {
+    let current = current(name);
+    let hint = if current.is_some() {
+        " [keep current]"
+    } else {
+        ""
+    };
+    print!("{}{}: ", question, hint);
+    io::stdout().flush().ok();
+    let value = if io::stdin().is_terminal() {
+        rpassword::read_password().map_err(failed_to_read)?
+    } else {
+        read_line()?
+    };
+    let value = Some(value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .or(current);
+    Ok(value.map(|value| Answer {
+        name,
+        value,
+        secret: true,
+    }))
+}
+
+// An answer defaulting to the current value of `name` or `default`. A
+// question without either default is asked until it gets an answer.
+fn ask(question: &str, name: &str, default: Option<&str>) -> Result<String, CrustagiError> {
+    prompt(question, current(name).or(default.map(str::to_string)))
+}
+
+fn current(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn prompt(question: &str, default: Option<String>) -> Result<String, CrustagiError> {
+    loop {
+        match &default {
+            Some(default) => print!("{} [{}]: ", question, default),
+            None => print!("{}: ", question),
+        }
+        io::stdout().flush().ok();
+        let answer = read_line()?;
+        let answer = answer.trim();
+        match (answer.is_empty(), &default) {
+            (false, _) => return Ok(answer.to_string()),
+            (true, Some(default)) => return Ok(default.clone()),
+            (true, None) => println!("{} is needed.", question),
+        }
+    }
+}
+
+// One of `options`, defaulting to the current value of `name` or `default`
+fn choose(
+    question: &str,
+    options: &[&str],
+    name: &str,
+    default: &str,
+) -> Result<String, CrustagiError> {
+    pick(
+        question,
+        options,
+        current(name).unwrap_or(default.to_string()),
+    )
+}
+
+fn pick(question: &str, options: &[&str], default: String) -> Result<String, CrustagiError> {
+    let question = format!("{} ({})", question, options.join(", "));
+    loop {
+        let answer = prompt(&question, Some(default.clone()))?;
+        if options.contains(&answer.as_str()) {
+            return Ok(answer);
+        }
+        println!("\"{}\" is not one of: {}", answer, options.join(", "));
+    }
+}
+
+fn read_line() -> Result<String, CrustagiError> {
+    let mut line = String::new();
+    match io::stdin().lock().read_line(&mut line) {
+        Ok(0) => Err(CrustagiError::Config(
+            "The setup was ended before all questions were answered".to_string(),
+        )),
+        Ok(_) => Ok(line),
+        Err(err) => Err(failed_to_read(err)),
+    }
+}
+
+fn failed_to_read(err: io::Error) -> CrustagiError {
+    CrustagiError::io("Failed to read the answer", err)
+}
+
+// Replace the lines of the answered settings in the `.env` file at `path`
+// with those of `written`, keeping all others
+fn write_env_file(path: &str, answers: &[&Answer], written: &[&Answer]) -> io::Result<()> {
+    let existing = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound && written.is_empty() => return Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err),
+    };
+    let answered = |line: &str| {
+        let line = line.trim_start().trim_start_matches("export ");
+        answers.iter().any(|answer| {
+            line.strip_prefix(answer.name)
+                .is_some_and(|rest| rest.trim_start().starts_with('='))
+        })
+    };
+    let mut lines: Vec<String> = existing
+        .lines()
+        .filter(|line| !answered(line))
+        .map(str::to_string)
+        .collect();
+    for answer in written {
+        let value = answer.value.replace('\\', "\\\\").replace('"', "\\\"");
+        lines.push(format!("{}=\"{}\"", answer.name, value));
+    }
+    fs::write(path, lines.join("\n") + "\n")
+}
+
+// Set the answered settings at the top level of the config file at `path`,
+// keeping its other settings and profiles
+fn write_config_file(path: &str, answers: &[&Answer]) -> Result<(), String> {
+    let mut table = match fs::read_to_string(path) {
+        Ok(text) => text.parse::<Table>().map_err(|err| err.to_string())?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Table::new(),
+        Err(err) => return Err(err.to_string()),
+    };
+    for answer in answers {
+        table.insert(answer.name.to_string(), Value::String(answer.value.clone()));
+    }
+    if let Some(dir) = Path::new(path).parent() {
+        fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+    }
+    let text = toml::to_string(&table).map_err(|err| err.to_string())?;
+    fs::write(path, text).map_err(|err| err.to_string())
+}
+
+fn checking(what: &str) {
+    print!("{}... ", what);
+    io::stdout().flush().ok();
+}
+
+fn passed<T, E: Display>(result: Result<T, E>) -> Result<T, CrustagiError> {
+    match result {
+        Ok(value) => {
+            println!("OK");
+            Ok(value)
+        }
+        Err(err) => {
+            println!("failed");
+            Err(CrustagiError::Config(format!(
+                "{}\n\nFix the setting in {} or the config file, or run `crustagi init` again.",
+                err, ENV_FILE
+            )))
+        }
+    }
+}
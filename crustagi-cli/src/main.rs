@@ -0,0 +1,56 @@
+// The crustagi command line. Besides parsing the command line, it sets up a
+// first run and keeps API keys in the OS keyring. Everything else lives in
+// the `crustagi` library, which programs embedding the loop use directly.
+
+mod cli;
+mod init;
+mod secrets;
+
+use clap::Parser;
+use crustagi::CrustagiError;
+use dotenv::dotenv;
+use std::process;
+
+use crate::cli::{Cli, CliCommand};
+
+fn main() {
+    dotenv().ok();
+    let cli = Cli::parse();
+    let init = matches!(cli.command, Some(CliCommand::Init));
+    let command = match cli.command {
+        Some(CliCommand::Completions { shell }) => {
+            cli::print_completions(shell);
+            return;
+        }
+        Some(CliCommand::Secrets { action }) => {
+            secrets::manage(&action).unwrap_or_else(fail);
+            return;
+        }
+        Some(CliCommand::Agent(command)) => Some(command),
+        Some(CliCommand::Init) | None => None,
+    };
+    // The config file, flags and secrets go into the environment before the
+    // runtime starts its threads
+    cli.settings.apply();
+    secrets::load();
+    if init {
+        init::setup(&cli.settings.config_file()).unwrap_or_else(fail);
+    }
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to start the tokio runtime");
+    // `init` checks the new setup instead of running
+    let result = if init {
+        runtime.block_on(init::check())
+    } else {
+        runtime.block_on(crustagi::run(command))
+    };
+    result.unwrap_or_else(fail);
+}
+
+// Print why the command failed and exit with status 1
+fn fail(err: CrustagiError) {
+    eprintln!("{}", err);
+    process::exit(1);
+}
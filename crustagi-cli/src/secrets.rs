@@ -0,0 +1,168 @@
+use crustagi::{diagnostics, CrustagiError};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal};
+
+use crate::cli::SecretsAction;
+
+// Settings holding credentials. Besides the environment, each is read from
+// the file named by `<NAME>_FILE`, e.g. a Docker secret, or from the OS
+// keyring.
+pub const SECRETS: &[&str] = &[
+    "OPENAI_API_KEY",
+    "GEMINI_API_KEY",
+    "COHERE_API_KEY",
+    "PINECONE_API_KEY",
+    "QDRANT_API_KEY",
+    "WEAVIATE_API_KEY",
+    "MILVUS_TOKEN",
+    "DATABASE_URL",
+    "WEBHOOK_URL",
+    "SLACK_WEBHOOK_URL",
+    "DISCORD_WEBHOOK_URL",
+];
+
+// Put the secrets the environment leaves unset into it, from `<NAME>_FILE`
+// or else the OS keyring. Must be called before any threads are started.
+pub fn load() {
+    for name in SECRETS {
+        if env::var_os(name).is_some() {
+            continue;
+        }
+        let file_var = format!("{}_FILE", name);
+        let value = match env::var(&file_var) {
+            // Files usually end with a newline, which isn't part of the key
+            Ok(path) => match fs::read_to_string(&path) {
+                Ok(value) => Some(value.trim_end().to_string()),
+                Err(err) => {
+                    diagnostics::report(format!("{} {} can't be read: {}", file_var, path, err));
+                    None
+                }
+            },
+            Err(_) => os_keyring::get(name),
+        };
+        if let Some(value) = value {
+            env::set_var(name, value);
+        }
+    }
+}
+
+// Run a `secrets` command
+pub fn manage(action: &SecretsAction) -> Result<(), CrustagiError> {
+    match action {
+        SecretsAction::Set { name } => {
+            check_name(name)?;
+            store(name, &read_value(name)?)?;
+            println!("Stored {} in the OS keyring", name);
+        }
+        SecretsAction::Delete { name } => {
+            delete(name)?;
+            println!("Deleted {} from the OS keyring", name);
+        }
+    }
+    Ok(())
+}
+
+// Value of a secret, typed without echo or piped into stdin
+fn read_value(name: &str) -> Result<String, CrustagiError> {
+    let failed = |err| CrustagiError::io(format!("Failed to read {}", name), err);
+    let value = if io::stdin().is_terminal() {
+        rpassword::prompt_password(format!("{}: ", name)).map_err(failed)?
+    } else {
+        let mut line = String::new();
+        io::stdin().lock().read_line(&mut line).map_err(failed)?;
+        line
+    };
+    match value.trim() {
+        "" => Err(CrustagiError::Config(format!(
+            "No value given for {}",
+            name
+        ))),
+        value => Ok(value.to_string()),
+    }
+}
+
+// Store secret `name` in the OS keyring
+pub fn store(name: &str, value: &str) -> Result<(), CrustagiError> {
+    check_name(name)?;
+    os_keyring::set(name, value)
+}
+
+// Delete secret `name` from the OS keyring
+pub fn delete(name: &str) -> Result<(), CrustagiError> {
+    check_name(name)?;
+    os_keyring::delete(name)
+}
+
+fn check_name(name: &str) -> Result<(), CrustagiError> {
+    if SECRETS.contains(&name) {
+        Ok(())
+    } else {
+        Err(CrustagiError::Config(format!(
+            "{} is not a secret, expected one of: {}",
+            name,
+            SECRETS.join(", ")
+        )))
+    }
+}
+
+// Secrets are kept under the `crustagi` service, with the setting's name as
+// user. Only compiled in with the `keyring` feature, which is on by default.
+#[cfg(feature = "keyring")]
+mod os_keyring {
+    use crustagi::CrustagiError;
+    use keyring::{Entry, Error};
+    use std::io;
+
+    const SERVICE: &str = "crustagi";
+
+    // A keyring that can't be reached, e.g. on a server without one, counts
+    // as an empty one
+    pub fn get(name: &str) -> Option<String> {
+        Entry::new(SERVICE, name).ok()?.get_password().ok()
+    }
+
+    pub fn set(name: &str, value: &str) -> Result<(), CrustagiError> {
+        Entry::new(SERVICE, name)
+            .and_then(|entry| entry.set_password(value))
+            .map_err(|err| {
+                CrustagiError::io(
+                    format!("Failed to store {} in the OS keyring", name),
+                    io::Error::other(err),
+                )
+            })
+    }
+
+    pub fn delete(name: &str) -> Result<(), CrustagiError> {
+        match Entry::new(SERVICE, name).and_then(|entry| entry.delete_credential()) {
+            Ok(()) => Ok(()),
+            Err(Error::NoEntry) => Err(CrustagiError::Config(format!(
+                "{} is not in the OS keyring",
+                name
+            ))),
+            Err(err) => Err(CrustagiError::io(
+                format!("Failed to delete {} from the OS keyring", name),
+                io::Error::other(err),
+            )),
+        }
+    }
+}
+
+#[cfg(not(feature = "keyring"))]
+mod os_keyring {
+    use crustagi::CrustagiError;
+
+    const DISABLED: &str = "crustagi was built without the keyring feature";
+
+    pub fn get(_name: &str) -> Option<String> {
+        None
+    }
+
+    pub fn set(_name: &str, _value: &str) -> Result<(), CrustagiError> {
+        Err(CrustagiError::Config(DISABLED.to_string()))
+    }
+
+    pub fn delete(_name: &str) -> Result<(), CrustagiError> {
+        Err(CrustagiError::Config(DISABLED.to_string()))
+    }
+}
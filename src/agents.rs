@@ -0,0 +1,1027 @@
+// The agents of the loop, each a prompt to a model and the parsing of its
+// answer: task creation, prioritization, execution with the context agent's
+// lookup of earlier results, the critic, and the planner, synthesis, report
+// and summarization agents. The stages of the loop call the first four
+// through traits, so that programs embedding the agent can replace them.
+
+use async_trait::async_trait;
+use colored::Color;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::iter;
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::engine::streams_result;
+use crate::error::CrustagiError;
+use crate::llm::{ChatMessage, LlmProvider, ToolSpec};
+use crate::logging;
+use crate::memory::{self, VectorStore};
+use crate::prompts::Prompts;
+use crate::rerank::Reranker;
+use crate::sanitize;
+use crate::task::Task;
+use crate::task_graph::TaskResult;
+use crate::tokens;
+use crate::tools::{self, Tool};
+use crate::tui::Dashboard;
+
+// Answer of the critic agent
+#[derive(Debug, Deserialize)]
+pub struct Review {
+    pub score: f64,
+    pub critique: String,
+}
+
+// Answer of the planner agent
+#[derive(Debug, Deserialize)]
+pub struct Plan {
+    pub milestones: Vec<String>,
+}
+
+// Answer of the prioritization agent
+#[derive(Debug, Deserialize)]
+pub struct Priorities {
+    pub tasks: Vec<TaskPriority>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TaskPriority {
+    pub task_id: i32,
+    pub priority: f64,
+}
+
+// Answer of the task creation agent
+#[derive(Debug, Deserialize)]
+pub struct NewTasks {
+    pub tasks: Vec<Task>,
+}
+
+// Attempts at getting a valid task list from the task creation agent
+pub const TASK_CREATION_ATTEMPTS: usize = 3;
+
+// Attempts at getting a priority for every task from the prioritization agent
+pub const PRIORITIZATION_ATTEMPTS: usize = 3;
+
+// Stored results per milestone the synthesis agent is given
+pub const SYNTHESIS_RESULTS: usize = 5;
+
+// JSON schema of `NewTasks`
+pub fn new_tasks_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "tasks": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "task_name": { "type": "string" },
+                        "depends_on": { "type": "array", "items": { "type": "integer" } }
+                    },
+                    "required": ["task_name"]
+                }
+            }
+        },
+        "required": ["tasks"]
+    })
+}
+
+// JSON answer without the Markdown code block models without a JSON mode
+// tend to wrap it in
+pub fn strip_code_block(response: &str) -> &str {
+    response
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+}
+
+// Parse and validate the task creation agent's answer. Tasks are numbered
+// from `next_task_id` in the order of the answer, which their dependencies
+// refer to. `parent` is the task whose result they were created from.
+pub fn parse_new_tasks(
+    response: &str,
+    next_task_id: i32,
+    parent: i32,
+) -> Result<Vec<Task>, CrustagiError> {
+    let new_tasks: NewTasks = serde_json::from_str(strip_code_block(response))
+        .map_err(|err| CrustagiError::Parse(err.to_string()))?;
+    let mut tasks: Vec<Task> = Vec::with_capacity(new_tasks.tasks.len());
+    for (task_id, task) in (next_task_id..).zip(new_tasks.tasks) {
+        let task_name = task.task_name.trim().to_string();
+        if !task_name.is_empty() && !tasks.iter().any(|t| t.task_name == task_name) {
+            tasks.push(Task {
+                depends_on: task.depends_on,
+                ..Task::new(task_id, task_name, Some(parent))
+            });
+        }
+    }
+    Ok(tasks)
+}
+
+// Task creation agent
+#[tracing::instrument(skip_all)]
+pub async fn task_creation_agent(
+    llm: &dyn LlmProvider,
+    prompts: &Prompts,
+    objective: &str,
+    result: &str,
+    task: &Task,
+    incompleted_task_list: &[Task],
+    next_task_id: i32,
+) -> Result<Vec<Task>, CrustagiError> {
+    // Tasks are listed with their ids, which new tasks can depend on
+    let render = |result: &str, incomplete_tasks: &[String]| {
+        prompts.render(
+            "task_creation",
+            &json!({
+                "objective": objective,
+                "result": result,
+                "task_id": task.task_id,
+                "task_description": task.task_name,
+                "incomplete_tasks": incomplete_tasks,
+                "next_task_id": next_task_id,
+            }),
+        )
+    };
+
+    // Leave out the lowest priority incomplete tasks, and then the end of
+    // the result, until the prompt fits into the model's context window
+    let model = llm.model();
+    let budget = llm.prompt_budget();
+    let mut incomplete_tasks: Vec<String> = incompleted_task_list
+        .iter()
+        .map(|t| format!("{}. {}", t.task_id, t.task_name))
+        .collect();
+    let mut prompt = render(result, &incomplete_tasks)?;
+    while tokens::count_tokens(&model, &prompt) > budget && !incomplete_tasks.is_empty() {
+        incomplete_tasks.pop();
+        prompt = render(result, &incomplete_tasks)?;
+    }
+    let excess = tokens::count_tokens(&model, &prompt).saturating_sub(budget);
+    if excess > 0 {
+        let result_tokens = tokens::count_tokens(&model, result);
+        let result = tokens::truncate(&model, result, result_tokens.saturating_sub(excess));
+        prompt = render(&result, &incomplete_tasks)?;
+    }
+
+    let schema = new_tasks_schema();
+    let mut attempt = 1;
+    loop {
+        let response = llm
+            .complete_json(&prompt, &schema)
+            .await
+            .map_err(|err| CrustagiError::llm("Task creation failed", err))?;
+        match parse_new_tasks(&response, next_task_id, task.task_id) {
+            Ok(tasks) => return Ok(tasks),
+            Err(err) if attempt < TASK_CREATION_ATTEMPTS => {
+                tracing::warn!(
+                    "Task creation returned an invalid task list ({}), retrying...",
+                    err
+                );
+                attempt += 1;
+            }
+            Err(err) => {
+                return Err(CrustagiError::Parse(format!(
+                    "Task creation returned an invalid task list {} times: {}",
+                    TASK_CREATION_ATTEMPTS, err
+                )))
+            }
+        }
+    }
+}
+
+// Task prioritization agent, scoring each task. Only the scores are taken
+// from the answer, so tasks can't get lost or renamed on the way.
+#[tracing::instrument(skip_all)]
+pub async fn prioritization_agent(
+    llm: &dyn LlmProvider,
+    prompts: &Prompts,
+    objective: &str,
+    task_list: &[&Task],
+) -> Result<HashMap<i32, f64>, CrustagiError> {
+    // Tasks are listed with their ids, which the answer refers to
+    let task_names: Vec<String> = task_list
+        .iter()
+        .map(|t| format!("{}. {}", t.task_id, t.task_name))
+        .collect();
+    let prompt = prompts.render(
+        "prioritization",
+        &json!({
+            "objective": objective,
+            "tasks": task_names,
+        }),
+    )?;
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "tasks": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "task_id": { "type": "integer" },
+                        "priority": { "type": "number" }
+                    },
+                    "required": ["task_id", "priority"]
+                }
+            }
+        },
+        "required": ["tasks"]
+    });
+
+    let mut attempt = 1;
+    loop {
+        let response = llm
+            .complete_json(&prompt, &schema)
+            .await
+            .map_err(|err| CrustagiError::llm("Task prioritization failed", err))?;
+        match parse_priorities(&response, task_list) {
+            Ok(priorities) => return Ok(priorities),
+            Err(err) if attempt < PRIORITIZATION_ATTEMPTS => {
+                tracing::warn!("{}, retrying...", err);
+                attempt += 1;
+            }
+            Err(err) => {
+                tracing::warn!(
+                    "{}, after {} attempts, keeping the current order",
+                    err,
+                    PRIORITIZATION_ATTEMPTS
+                );
+                return Ok(HashMap::new());
+            }
+        }
+    }
+}
+
+// Parse the prioritization agent's answer and check that it scores each
+// task exactly once, describing what is wrong otherwise
+pub fn parse_priorities(
+    response: &str,
+    task_list: &[&Task],
+) -> Result<HashMap<i32, f64>, CrustagiError> {
+    let invalid = |problem: String| CrustagiError::llm("Task prioritization", problem);
+    let answer: Priorities = serde_json::from_str(strip_code_block(response))
+        .map_err(|err| invalid(format!("returned invalid priorities ({})", err)))?;
+    let list = |ids: Vec<i32>| {
+        ids.iter()
+            .map(i32::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut priorities = HashMap::new();
+    let mut duplicated = Vec::new();
+    let mut unknown = Vec::new();
+    for task in answer.tasks {
+        if !task_list.iter().any(|t| t.task_id == task.task_id) {
+            unknown.push(task.task_id);
+        } else if priorities.insert(task.task_id, task.priority).is_some() {
+            duplicated.push(task.task_id);
+        }
+    }
+    let missing: Vec<i32> = task_list
+        .iter()
+        .map(|t| t.task_id)
+        .filter(|id| !priorities.contains_key(id))
+        .collect();
+    let mut problems = Vec::new();
+    if !missing.is_empty() {
+        problems.push(format!("dropped tasks {}", list(missing)));
+    }
+    if !duplicated.is_empty() {
+        problems.push(format!("repeated tasks {}", list(duplicated)));
+    }
+    if !unknown.is_empty() {
+        problems.push(format!("returned unknown tasks {}", list(unknown)));
+    }
+    if problems.is_empty() {
+        Ok(priorities)
+    } else {
+        Err(invalid(problems.join(", ")))
+    }
+}
+
+// Let the model call `tools` until it answers, for at most MAX_TOOL_STEPS
+// rounds of calls. The outputs are added to the conversation as
+// observations, each cut to a share of what is left of `budget`.
+async fn tool_loop(
+    config: &Config,
+    llm: &dyn LlmProvider,
+    tools: &[Arc<dyn Tool>],
+    mut messages: Vec<ChatMessage>,
+    budget: usize,
+) -> Result<String, CrustagiError> {
+    let specs: Vec<ToolSpec> = tools.iter().map(|tool| tool.spec()).collect();
+    let model = llm.model();
+    for _ in 0..config.max_tool_steps {
+        let answer = llm
+            .chat_with_tools(&messages, &specs)
+            .await
+            .map_err(|err| CrustagiError::llm("Execution failed", err))?;
+        if answer.tool_calls.is_empty() {
+            return Ok(answer.content);
+        }
+        let calls = answer.tool_calls.clone();
+        messages.push(answer);
+        // Half of the tokens left go to the observations of this round. The
+        // arguments of the calls take up tokens like the contents.
+        let used: usize = messages
+            .iter()
+            .flat_map(|message| {
+                iter::once(&message.content)
+                    .chain(message.tool_calls.iter().map(|call| &call.arguments))
+            })
+            .map(|text| tokens::count_tokens(&model, text))
+            .sum();
+        let observation_tokens = budget.saturating_sub(used) / 2 / calls.len();
+        for call in &calls {
+            let output = tools::call(tools, call).await;
+            let output = tokens::truncate(&model, &output, observation_tokens);
+            messages.push(ChatMessage::tool(&call.id, &output));
+        }
+    }
+    // The last call offers no tools, so that the model has to answer
+    messages.push(ChatMessage::user(
+        "Don't call any more tools. Give your final answer now.",
+    ));
+    let answer = llm
+        .chat(&messages)
+        .await
+        .map_err(|err| CrustagiError::llm("Execution failed", err))?;
+    if answer.is_empty() {
+        return Err(CrustagiError::llm(
+            "Execution failed",
+            format!(
+                "no answer after {} rounds of tool calls",
+                config.max_tool_steps
+            ),
+        ));
+    }
+    Ok(answer)
+}
+
+/// What executing a task sent to the model, and the result it got back
+pub struct Execution {
+    /// The prompt, written to the task file
+    pub prompt: String,
+    /// The earlier results in the prompt
+    pub context: Vec<String>,
+    pub result: String,
+}
+
+// Execution agent, looking up the context of the task with `context`. With
+// `tools`, the model may call them before it answers.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(skip_all)]
+pub async fn execution_agent(
+    config: &Config,
+    llm: &dyn LlmProvider,
+    prompts: &Prompts,
+    context: &dyn ContextAgent,
+    tools: &[Arc<dyn Tool>],
+    dashboard: Option<&Dashboard>,
+    objective: &str,
+    task: &Task,
+    dependencies: &[TaskResult],
+    critique: Option<&str>,
+    history: &[ChatMessage],
+) -> Result<Execution, CrustagiError> {
+    let context = context.context(objective, &task.task_name, 5).await?;
+    // The retrieved text is sanitized before anything reads it, so that the
+    // summary of it is made from the sanitized text too
+    let context: Vec<String> = if config.sanitize_context {
+        context
+            .iter()
+            .map(|item| sanitize::sanitize(item))
+            .collect()
+    } else {
+        context
+    };
+    let model = llm.model();
+    let mut context = match config.context_token_budget {
+        Some(budget) if tokens::count_tokens(&model, &context.join("\n")) > budget => {
+            vec![summarization_agent(llm, prompts, objective, &context, budget).await?]
+        }
+        _ => context,
+    };
+    // Results of the tasks this one depends on may take up a quarter of the
+    // prompt. They are earlier results like the context, so they are
+    // sanitized the same way.
+    let dependency_tokens = llm.prompt_budget() / 4 / dependencies.len().max(1);
+    let dependencies: Vec<Value> = dependencies
+        .iter()
+        .map(|dependency| {
+            let result = tokens::truncate(&model, &dependency.result, dependency_tokens);
+            let result = if config.sanitize_context {
+                sanitize::sanitize(&result)
+            } else {
+                result
+            };
+            json!({ "task": dependency.task_name, "result": result })
+        })
+        .collect();
+    let render = |context: &[String]| {
+        let context_str = if config.sanitize_context {
+            format!(
+                "{}\n{}",
+                sanitize::wrap_context(context),
+                sanitize::CONTEXT_INSTRUCTION
+            )
+        } else {
+            context.join("\n")
+        };
+        prompts.render(
+            "execution",
+            &json!({
+                "objective": objective,
+                "context": context_str,
+                "dependencies": dependencies,
+                "task": task.task_name,
+                "critique": critique,
+            }),
+        )
+    };
+
+    // Earlier exchanges may take up half of the model's context window, the
+    // oldest are left out first
+    let history_tokens = |history: &[ChatMessage]| -> usize {
+        history
+            .iter()
+            .map(|message| tokens::count_tokens(&model, &message.content))
+            .sum()
+    };
+    let budget = llm.prompt_budget();
+    let mut history = history;
+    while history_tokens(history) > budget / 2 {
+        history = &history[2.min(history.len())..];
+    }
+    let budget = budget - history_tokens(history);
+
+    // Leave out the least relevant context until the prompt fits into the
+    // model's context window
+    let mut prompt = render(&context)?;
+    while tokens::count_tokens(&model, &prompt) > budget && !context.is_empty() {
+        context.pop();
+        prompt = render(&context)?;
+    }
+
+    // The result is printed while it is being generated, unless other tasks
+    // run at the same time
+    let stream = streams_result(config);
+    if stream {
+        tracing::info!(
+            target: logging::RESULTS,
+            "\n{}",
+            logging::banner("TASK RESULT", Color::Yellow)
+        );
+    }
+    let mut messages = history.to_vec();
+    messages.push(ChatMessage::user(&prompt));
+    let result = if tools.is_empty() {
+        let result = llm
+            .chat_stream(&messages, &mut |chunk| {
+                if let Some(dashboard) = dashboard {
+                    dashboard.output(task.task_id, chunk);
+                }
+                if stream {
+                    print!("{}", chunk);
+                    let _ = io::stdout().flush();
+                }
+            })
+            .await
+            .map_err(|err| CrustagiError::llm("Execution failed", err))?;
+        if stream {
+            println!();
+        }
+        result
+    } else {
+        // Answers after tool calls aren't streamed, only the last one is
+        // the result
+        let result = tool_loop(config, llm, tools, messages, budget).await?;
+        if let Some(dashboard) = dashboard {
+            dashboard.output(task.task_id, &result);
+        }
+        if stream {
+            println!("{}", result);
+        }
+        result
+    };
+    Ok(Execution {
+        prompt,
+        context,
+        result,
+    })
+}
+
+// Critic agent, scoring a result against its task and the objective. A
+// review that can't be parsed is reported and counts as None.
+#[tracing::instrument(skip_all)]
+pub async fn critic_agent(
+    llm: &dyn LlmProvider,
+    prompts: &Prompts,
+    objective: &str,
+    task: &Task,
+    result: &str,
+) -> Result<Option<Review>, CrustagiError> {
+    let prompt = prompts.render(
+        "critic",
+        &json!({
+            "objective": objective,
+            "task": task.task_name,
+            "result": result,
+        }),
+    )?;
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "score": { "type": "number" },
+            "critique": { "type": "string" }
+        },
+        "required": ["score", "critique"]
+    });
+    let response = llm
+        .complete_json(&prompt, &schema)
+        .await
+        .map_err(|err| CrustagiError::llm("Review failed", err))?;
+    match serde_json::from_str(strip_code_block(&response)) {
+        Ok(review) => Ok(Some(review)),
+        Err(err) => {
+            tracing::warn!(
+                "The critic returned an invalid review ({}), accepting the result",
+                err
+            );
+            Ok(None)
+        }
+    }
+}
+
+// Planner agent, breaking the objective into at most `max_milestones`
+// milestones that are worked on one after the other
+#[tracing::instrument(skip_all)]
+pub async fn planner_agent(
+    llm: &dyn LlmProvider,
+    prompts: &Prompts,
+    objective: &str,
+    max_milestones: usize,
+) -> Result<Vec<String>, CrustagiError> {
+    tracing::info!("Planning milestones...");
+    let prompt = prompts.render(
+        "planner",
+        &json!({
+            "objective": objective,
+            "max_milestones": max_milestones,
+        }),
+    )?;
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "milestones": { "type": "array", "items": { "type": "string" } }
+        },
+        "required": ["milestones"]
+    });
+    let response = llm
+        .complete_json(&prompt, &schema)
+        .await
+        .map_err(|err| CrustagiError::llm("Planning failed", err))?;
+    let plan: Plan = serde_json::from_str(strip_code_block(&response)).map_err(|err| {
+        CrustagiError::Parse(format!("The planner returned an invalid plan: {}", err))
+    })?;
+    let milestones: Vec<String> = plan
+        .milestones
+        .iter()
+        .map(|milestone| milestone.trim().to_string())
+        .filter(|milestone| !milestone.is_empty())
+        .take(max_milestones)
+        .collect();
+    if milestones.is_empty() {
+        return Err(CrustagiError::Parse(
+            "The planner returned no milestones".to_string(),
+        ));
+    }
+    Ok(milestones)
+}
+
+// Synthesis agent, combining the results of all milestones into one answer
+// to the objective. Each milestone contributes the stored results closest to
+// it, shortened so that all of them fit into the prompt.
+#[tracing::instrument(skip_all)]
+pub async fn synthesis_agent(
+    config: &Config,
+    llm: &dyn LlmProvider,
+    synthesis_llm: &dyn LlmProvider,
+    prompts: &Prompts,
+    memory: &dyn VectorStore,
+    milestones: &[String],
+) -> Result<String, CrustagiError> {
+    tracing::info!("Synthesizing the results...");
+    let model = synthesis_llm.model();
+    let result_budget = synthesis_llm.prompt_budget() / (2 * milestones.len() * SYNTHESIS_RESULTS);
+    let filter = memory::objective_filter(&config.objective);
+    let mut sections = Vec::with_capacity(milestones.len());
+    for (index, milestone) in milestones.iter().enumerate() {
+        let vector = llm
+            .embed(milestone)
+            .await
+            .map_err(|err| CrustagiError::llm("Embedding a milestone failed", err))?;
+        let results: Vec<String> = memory
+            .query(&vector, SYNTHESIS_RESULTS, Some(&filter))
+            .await
+            .map_err(|err| CrustagiError::memory("Querying memory failed", err))?
+            .into_iter()
+            .filter_map(|item| item.metadata)
+            .map(|metadata| {
+                let result = format!("{}: {}", metadata.task, metadata.result);
+                tokens::truncate(&model, &result, result_budget)
+            })
+            .collect();
+        sections.push(json!({
+            "number": index + 1,
+            "milestone": milestone,
+            "results": results,
+        }));
+    }
+    let prompt = prompts.render(
+        "synthesis",
+        &json!({
+            "objective": config.objective,
+            "milestones": sections,
+        }),
+    )?;
+    let synthesis = synthesis_llm
+        .complete(&prompt)
+        .await
+        .map_err(|err| CrustagiError::llm("Synthesis failed", err))?;
+    Ok(synthesis.trim().to_string())
+}
+
+// Report agent, drawing the key findings of the run from the results of
+// its completed tasks. Results are shortened evenly to fit into the prompt.
+#[tracing::instrument(skip_all)]
+pub async fn report_agent(
+    llm: &dyn LlmProvider,
+    prompts: &Prompts,
+    objective: &str,
+    tasks: &[&Task],
+) -> Result<String, CrustagiError> {
+    tracing::info!("Writing the key findings...");
+    let model = llm.model();
+    let result_tokens = llm.prompt_budget() * 3 / 4 / tasks.len().max(1);
+    let results: Vec<Value> = tasks
+        .iter()
+        .map(|task| {
+            json!({
+                "task": task.task_name,
+                "result": tokens::truncate(&model, task.result.as_deref().unwrap_or_default(), result_tokens),
+            })
+        })
+        .collect();
+    let prompt = prompts.render(
+        "report",
+        &json!({
+            "objective": objective,
+            "results": results,
+        }),
+    )?;
+    let findings = llm
+        .complete(&prompt)
+        .await
+        .map_err(|err| CrustagiError::llm("Writing the key findings failed", err))?;
+    Ok(findings.trim().to_string())
+}
+
+// Summarization agent, compressing retrieved context that exceeds the token budget
+#[tracing::instrument(skip_all)]
+pub async fn summarization_agent(
+    llm: &dyn LlmProvider,
+    prompts: &Prompts,
+    objective: &str,
+    context: &[String],
+    budget: usize,
+) -> Result<String, CrustagiError> {
+    tracing::info!("Summarizing context...");
+    let prompt = prompts.render(
+        "summarization",
+        &json!({
+            "objective": objective,
+            "budget": budget,
+            "context": context,
+        }),
+    )?;
+
+    let summary = llm
+        .complete(&prompt)
+        .await
+        .map_err(|err| CrustagiError::llm("Summarizing the context failed", err))?;
+    Ok(summary.trim().to_string())
+}
+
+// Context agent
+// With a reranker, more candidates are retrieved and reordered by relevance
+// to `task` before keeping `n`
+#[tracing::instrument(skip_all, fields(n))]
+pub async fn context_agent(
+    config: &Config,
+    llm: &dyn LlmProvider,
+    reranker: Option<&Reranker>,
+    memory: &dyn VectorStore,
+    query: &str,
+    task: &str,
+    n: usize,
+) -> Result<Vec<String>, CrustagiError> {
+    tracing::info!("Getting context...");
+    let query_embedding = llm
+        .embed(query)
+        .await
+        .map_err(|err| CrustagiError::llm("Embedding the query failed", err))?;
+
+    // Only retrieve results stored for the current objective
+    let filter = memory::objective_filter(&config.objective);
+
+    // Collect the matches into a Vec and sort it
+    let mut sorted_results = memory
+        .hybrid_query(
+            query,
+            &query_embedding,
+            reranker.map_or(n, |_| config.rerank_candidates.max(n)),
+            Some(&filter),
+        )
+        .await
+        .map_err(|err| CrustagiError::memory("Querying memory failed", err))?;
+    sorted_results.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    // Map the sorted results to extract the "task" metadata and collect into a Vec.
+    // Chunks of one result would repeat its task, which is kept once.
+    let mut tasks: Vec<String> = sorted_results
+        .into_iter()
+        .filter_map(|item| item.metadata.map(|metadata| metadata.task))
+        .collect();
+    let mut seen = HashSet::new();
+    tasks.retain(|task| seen.insert(task.clone()));
+
+    let Some(reranker) = reranker else {
+        return Ok(tasks);
+    };
+    if tasks.len() <= 1 {
+        return Ok(tasks);
+    }
+    tracing::info!("Reranking {} context candidates...", tasks.len());
+    match reranker.rerank(task, &tasks, n).await {
+        Ok(reranked) => Ok(reranked),
+        // The vector order is still usable context
+        Err(err) => {
+            tracing::warn!("Reranking failed, using retrieval order: {}", err);
+            Ok(tasks.into_iter().take(n).collect())
+        }
+    }
+}
+
+/// Proposes the tasks that follow from the result of a task
+#[async_trait]
+pub trait TaskCreationAgent: Send + Sync {
+    /// New tasks after `task`, which completed with `result`, numbered from
+    /// `next_task_id` in the order returned. `pending` are the tasks still to
+    /// do, highest priority first. A new task's `depends_on` may refer to
+    /// these, to `task` and to new tasks before it.
+    async fn create_tasks(
+        &self,
+        objective: &str,
+        task: &Task,
+        result: &str,
+        pending: &[Task],
+        next_task_id: i32,
+    ) -> Result<Vec<Task>, CrustagiError>;
+}
+
+/// Orders the task list after each iteration
+#[async_trait]
+pub trait PrioritizationAgent: Send + Sync {
+    /// A priority for each of `tasks` by its id, higher first. Tasks left
+    /// out keep their current priority, so an empty map changes nothing.
+    async fn prioritize(
+        &self,
+        objective: &str,
+        tasks: &[&Task],
+    ) -> Result<HashMap<i32, f64>, CrustagiError>;
+}
+
+/// Executes a task
+#[async_trait]
+pub trait ExecutionAgent: Send + Sync {
+    /// Execute `task` given the results of the tasks it depends on and the
+    /// last tasks and results of the run as `history`. `critique` is set when
+    /// the critic asks for another attempt. An empty result counts as failed.
+    async fn execute(
+        &self,
+        objective: &str,
+        task: &Task,
+        dependencies: &[TaskResult],
+        critique: Option<&str>,
+        history: &[ChatMessage],
+    ) -> Result<Execution, CrustagiError>;
+}
+
+/// Looks up the earlier results a task is executed with
+#[async_trait]
+pub trait ContextAgent: Send + Sync {
+    /// At most `n` earlier results relevant to `query` and `task`, most
+    /// relevant first
+    async fn context(
+        &self,
+        query: &str,
+        task: &str,
+        n: usize,
+    ) -> Result<Vec<String>, CrustagiError>;
+}
+
+/// Asks `llm` for new tasks with the `task_creation` prompt
+pub struct LlmTaskCreationAgent {
+    pub llm: Arc<dyn LlmProvider>,
+    pub prompts: Arc<Prompts>,
+}
+
+#[async_trait]
+impl TaskCreationAgent for LlmTaskCreationAgent {
+    async fn create_tasks(
+        &self,
+        objective: &str,
+        task: &Task,
+        result: &str,
+        pending: &[Task],
+        next_task_id: i32,
+    ) -> Result<Vec<Task>, CrustagiError> {
+        task_creation_agent(
+            self.llm.as_ref(),
+            &self.prompts,
+            objective,
+            result,
+            task,
+            pending,
+            next_task_id,
+        )
+        .await
+    }
+}
+
+/// Asks `llm` to score the tasks with the `prioritization` prompt
+pub struct LlmPrioritizationAgent {
+    pub llm: Arc<dyn LlmProvider>,
+    pub prompts: Arc<Prompts>,
+}
+
+#[async_trait]
+impl PrioritizationAgent for LlmPrioritizationAgent {
+    async fn prioritize(
+        &self,
+        objective: &str,
+        tasks: &[&Task],
+    ) -> Result<HashMap<i32, f64>, CrustagiError> {
+        prioritization_agent(self.llm.as_ref(), &self.prompts, objective, tasks).await
+    }
+}
+
+/// Executes tasks with `llm` and the `execution` prompt, with the earlier
+/// results `context` finds
+pub struct LlmExecutionAgent {
+    pub config: Arc<Config>,
+    pub llm: Arc<dyn LlmProvider>,
+    pub prompts: Arc<Prompts>,
+    pub context: Arc<dyn ContextAgent>,
+    /// Offered to the model to call before it answers
+    pub tools: Vec<Arc<dyn Tool>>,
+    /// The dashboard of the run the result is streamed to, with `TUI=true`
+    pub dashboard: Option<Dashboard>,
+}
+
+#[async_trait]
+impl ExecutionAgent for LlmExecutionAgent {
+    async fn execute(
+        &self,
+        objective: &str,
+        task: &Task,
+        dependencies: &[TaskResult],
+        critique: Option<&str>,
+        history: &[ChatMessage],
+    ) -> Result<Execution, CrustagiError> {
+        execution_agent(
+            &self.config,
+            self.llm.as_ref(),
+            &self.prompts,
+            self.context.as_ref(),
+            &self.tools,
+            self.dashboard.as_ref(),
+            objective,
+            task,
+            dependencies,
+            critique,
+            history,
+        )
+        .await
+    }
+}
+
+/// Searches `memory` for the results of the objective closest to the query
+/// as embedded by `llm`, reordered by `reranker` if set
+pub struct LlmContextAgent {
+    pub config: Arc<Config>,
+    pub llm: Arc<dyn LlmProvider>,
+    pub reranker: Option<Reranker>,
+    pub memory: Arc<dyn VectorStore>,
+    /// The dashboard of the run counting the lookups that found stored
+    /// results, with `TUI=true`
+    pub dashboard: Option<Dashboard>,
+}
+
+#[async_trait]
+impl ContextAgent for LlmContextAgent {
+    async fn context(
+        &self,
+        query: &str,
+        task: &str,
+        n: usize,
+    ) -> Result<Vec<String>, CrustagiError> {
+        let context = context_agent(
+            &self.config,
+            self.llm.as_ref(),
+            self.reranker.as_ref(),
+            self.memory.as_ref(),
+            query,
+            task,
+            n,
+        )
+        .await?;
+        if let Some(dashboard) = &self.dashboard {
+            dashboard.memory_lookup(!context.is_empty());
+        }
+        Ok(context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(task_id: i32) -> Task {
+        Task::new(task_id, format!("Task {}", task_id), None)
+    }
+
+    #[test]
+    fn numbers_new_tasks_after_the_existing_ones() {
+        let response = r#"```json
+{"tasks": [
+  {"task_name": " Research "},
+  {"task_name": "Write", "depends_on": [4]},
+  {"task_name": ""},
+  {"task_name": "Research"},
+  {"task_name": "Review", "depends_on": [1, 5]}
+]}
+```"#;
+        let tasks = parse_new_tasks(response, 4, 2).unwrap();
+        let names: Vec<&str> = tasks.iter().map(|t| t.task_name.as_str()).collect();
+        assert_eq!(names, ["Research", "Write", "Review"]);
+        // Ids are those of the answer, dropped tasks leave gaps
+        let ids: Vec<i32> = tasks.iter().map(|t| t.task_id).collect();
+        assert_eq!(ids, [4, 5, 8]);
+        assert_eq!(tasks[1].depends_on, [4]);
+        assert_eq!(tasks[2].depends_on, [1, 5]);
+        assert!(tasks.iter().all(|t| t.parent == Some(2)));
+    }
+
+    #[test]
+    fn rejects_new_tasks_that_arent_json() {
+        assert!(parse_new_tasks("1. Research\n2. Write", 2, 1).is_err());
+        assert!(parse_new_tasks(r#"{"tasks": [{"name": "Research"}]}"#, 2, 1).is_err());
+    }
+
+    #[test]
+    fn parses_a_priority_for_every_task() {
+        let (one, two) = (task(1), task(2));
+        let response =
+            r#"{"tasks": [{"task_id": 2, "priority": 5}, {"task_id": 1, "priority": 0.5}]}"#;
+        let priorities = parse_priorities(response, &[&one, &two]).unwrap();
+        assert_eq!(priorities, HashMap::from([(1, 0.5), (2, 5.0)]));
+    }
+
+    #[test]
+    fn reports_dropped_repeated_and_unknown_tasks() {
+        let (one, two, three) = (task(1), task(2), task(3));
+        let response = r#"{"tasks": [
+            {"task_id": 1, "priority": 1},
+            {"task_id": 1, "priority": 2},
+            {"task_id": 7, "priority": 3}
+        ]}"#;
+        let err = parse_priorities(response, &[&one, &two, &three]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Task prioritization: dropped tasks 2, 3, repeated tasks 1, returned unknown tasks 7"
+        );
+        let err = parse_priorities("not json", &[&one]).unwrap_err();
+        assert!(err.to_string().contains("returned invalid priorities"));
+    }
+}
@@ -0,0 +1,1310 @@
+// Sets up a run from the environment and the command line, for the binary
+// and for embedders wanting the same behaviour.
+
+use colored::Color;
+use memory::{EvictionPolicy, Evictor, VectorStore, SUPPORTED_BACKENDS};
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::env;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+use crate::agents::{
+    planner_agent, LlmContextAgent, LlmExecutionAgent, LlmPrioritizationAgent, LlmTaskCreationAgent,
+};
+use crate::cohere::{DEFAULT_COHERE_EMBEDDING_MODEL, DEFAULT_COHERE_MODEL};
+use crate::command::{Command, MemoryAction, ReportFormat, RunArgs};
+use crate::config::{
+    load_env_flag, load_env_number, load_env_var, load_env_var_or, load_llm_params,
+    load_optional_env_number, Config, DEFAULT_SEED, DRY_RUN_INITIAL_TASK, DRY_RUN_OBJECTIVE,
+};
+use crate::diagnostics;
+use crate::engine::{
+    key_findings, run_loop, write_manifest, write_report, Agent, Engine, ObjectiveRun,
+};
+use crate::enrich::{Chain, Chunker, Enricher, NoEnrichment};
+use crate::error::CrustagiError;
+use crate::events::{Event, EventLog};
+use crate::fallback;
+use crate::files::{ListFiles, ReadFile, Sandbox, WriteFile};
+use crate::gemini::{DEFAULT_GEMINI_EMBEDDING_MODEL, DEFAULT_GEMINI_MODEL};
+use crate::html_report;
+use crate::http;
+use crate::llm::{self, LlmParams, LlmProvider, SUPPORTED_PROVIDERS};
+use crate::logging;
+use crate::memory;
+use crate::middleware::{CostLayer, LlmStack, RateLimitLayer, RedactionLayer};
+use crate::objectives::{self, Scheduling};
+use crate::observer::Observer;
+use crate::openai::{self, DEFAULT_API_BASE, DEFAULT_EMBEDDING_MODEL};
+use crate::pinecone;
+use crate::plugins;
+use crate::prompts::Prompts;
+use crate::replay::{Recording, Replay, Replayed, RunLog};
+use crate::rerank::Reranker;
+use crate::run::Run;
+use crate::shutdown;
+use crate::snapshot;
+use crate::state;
+use crate::task::unix_time;
+use crate::task_dedup;
+use crate::tools::Tool;
+use crate::tui::Dashboard;
+use crate::usage::{self, UsageMeter, UsageTracker};
+use crate::web_fetch::WebFetch;
+use crate::web_search::{SearchBackend, WebSearch, SEARCH_BACKENDS};
+use crate::webhooks::{self, Webhooks};
+
+/// Run the agent like the `crustagi` binary does for `command`, `None` being
+/// a plain run: the configuration is read from the environment, the task
+/// lists are set up, saved ones resumed, and `run_loop` works through them.
+/// `Report` writes the report of the saved run instead. Fails if the
+/// settings are invalid or what the run needs can't be set up.
+pub async fn run(command: Option<Command>) -> Result<(), CrustagiError> {
+    let _telemetry = logging::init();
+
+    // // Set config
+    let (config, objectives, run) = load_settings(command.as_ref())?;
+    let config = Arc::new(config);
+    if let Some(run) = &run {
+        run.create_dir().map_err(|err| {
+            CrustagiError::io(
+                format!("Failed to create the directory of run {}", run.id),
+                err,
+            )
+        })?;
+        tracing::info!("Run: {}", run.id);
+    }
+    let plugins = plugins::names();
+    if !plugins.is_empty() {
+        tracing::info!("Plugins: {}", plugins.join(", "));
+    }
+    if let Some(Command::Report {
+        format: ReportFormat::Html,
+    }) = command
+    {
+        return write_html_report(&config);
+    }
+    let prompts = Arc::new(Prompts::load(&config.prompts_dir)?);
+    // One HTTP client, and so one connection pool, for all API calls
+    let http = config.http_client()?;
+    // Token usage of all API calls, per agent. Embeddings of results and
+    // reranking are counted as "memory".
+    let usage = Arc::new(UsageTracker::with_prices(config.model_prices.clone()));
+    let llms = AgentLlms::new(&config, &http, &usage)?;
+    llms.check_prices(&config, &usage);
+    if config.deterministic {
+        llms.write_manifest(&config, &objectives, &prompts);
+    }
+    let dimension = embedding_dimension(&config, llms.memory.as_ref()).await?;
+
+    // // Set vector store
+    let memory = open_memory(&config, &http, &llms.rate_limit, dimension).await?;
+    // Resetting, exporting and importing memory run instead of the task loop
+    if let Some(Command::Memory { .. }) = command {
+        return manage_memory(&config, memory.as_ref()).await;
+    }
+    // Checked only for a run of the task loop, which writes to the store
+    let report = matches!(command, Some(Command::Report { .. }));
+    if !config.skip_health_check && !report {
+        memory::health_check(memory.as_ref(), &config.memory_backend, dimension)
+            .await
+            .map_err(|err| CrustagiError::memory("Vector store health check failed", err))?;
+    }
+    // Waited for before the process exits
+    let webhooks = webhooks(&config, &http, run.as_ref());
+    let observers = observers(&config, webhooks.as_ref(), command.as_ref())?;
+    let agents = Agents::new(&config, &http, llms, prompts, observers)?;
+
+    // // Create a task list per objective, or continue the ones saved by a
+    // stopped run. With PLAN_MILESTONES, the planner breaks the objective
+    // into milestones, which are worked on one after the other like separate
+    // objectives.
+    let milestones = plan_milestones(&config, &objectives, &agents).await?;
+    let run_started = Event::RunStarted {
+        objectives: objectives.iter().map(String::as_str).collect(),
+        resumed: config.resume,
+    };
+    for observer in &agents.observers {
+        observer.on_event(&run_started);
+    }
+    let scheduling = match milestones {
+        Some(_) => Scheduling::Sequential,
+        None => config.objective_scheduling,
+    };
+    let parts = parts(&config, objectives, milestones.as_deref());
+    let objective_runs = start_objective_runs(
+        &config,
+        &http,
+        &agents,
+        memory.clone(),
+        dimension,
+        parts,
+        run.as_ref(),
+    )
+    .await?;
+    let task_dedup = config
+        .task_dedup_threshold
+        .map(task_dedup::TaskDeduplicator::new);
+
+    // // Main loop
+    let mut shutdown = shutdown::listen();
+    // The report command writes the report of the saved run and stops
+    if let Some(Command::Report { .. }) = command {
+        report_saved_run(&config, &agents, &objective_runs, &usage, &mut shutdown).await;
+        return Ok(());
+    }
+    let Agents {
+        llms,
+        prompts,
+        observers,
+        dashboard,
+        ..
+    } = agents;
+    let engine = Engine {
+        synthesis: milestones.zip(llms.planner.map(|(_, synthesis_llm)| synthesis_llm)),
+        prioritization: Arc::new(LlmPrioritizationAgent {
+            llm: llms.prioritization,
+            prompts: prompts.clone(),
+        }),
+        config,
+        prompts,
+        usage,
+        llm: llms.memory,
+        report_llm: llms.report,
+        memory,
+        replay: llms.replay,
+        task_dedup,
+        scheduling,
+        read_stdin: true,
+        observers,
+        dashboard,
+    };
+    run_loop(&engine, objective_runs, shutdown).await;
+    if let Some(webhooks) = webhooks {
+        webhooks.flush().await;
+    }
+    Ok(())
+}
+
+/// The configuration of a plain run, read from the environment like
+/// [`run`] does. Fails with every invalid or missing setting.
+pub fn load_config() -> Result<Config, CrustagiError> {
+    load_settings(None).map(|(config, _, _)| config)
+}
+
+// The configuration of a run of `command`, its objectives and its name, if
+// it has one. All settings are read before failing with every problem found.
+fn load_settings(
+    command: Option<&Command>,
+) -> Result<(Config, Vec<String>, Option<Run>), CrustagiError> {
+    let run_args = match command {
+        Some(Command::Run(run_args)) => run_args.clone(),
+        _ => RunArgs::default(),
+    };
+    // A replay or a dry run needs neither API keys nor a vector store of
+    // its own
+    let replay = run_args.replay;
+    let dry_run = run_args.dry_run;
+    let offline = replay.is_some() || dry_run;
+    let memory_backend = if offline {
+        "memory".to_string()
+    } else {
+        load_env_var_or("MEMORY_BACKEND", "pinecone")
+    };
+    // Pinecone settings are only required when Pinecone is the memory backend
+    let pinecone_var = |name: &str| {
+        if memory_backend == "pinecone" {
+            load_env_var(name, "MEMORY_BACKEND=pinecone")
+        } else {
+            load_env_var_or(name, "")
+        }
+    };
+    // Several objectives are read from OBJECTIVES_FILE, one per line. A
+    // single one too long for a variable is read from OBJECTIVE_FILE.
+    let objectives = match (env::var("OBJECTIVES_FILE"), env::var("OBJECTIVE_FILE")) {
+        (Ok(path), _) => objectives::load(&path).unwrap_or_else(|err| {
+            diagnostics::report(format!("OBJECTIVES_FILE {}", err));
+            vec![String::new()]
+        }),
+        (_, Ok(path)) => vec![objectives::load_brief(&path).unwrap_or_else(|err| {
+            diagnostics::report(format!("OBJECTIVE_FILE {}", err));
+            String::new()
+        })],
+        _ if dry_run => vec![load_env_var_or("OBJECTIVE", DRY_RUN_OBJECTIVE)],
+        _ => vec![load_env_var(
+            "OBJECTIVE",
+            "a run without OBJECTIVE_FILE or OBJECTIVES_FILE",
+        )],
+    };
+    let objective = objectives[0].clone();
+    let run = env::var("RUN_NAME")
+        .ok()
+        // A dry run keeps its files apart from those of real runs
+        .or_else(|| dry_run.then(|| "dry_run".to_string()))
+        .map(|name| Run::new(&name, &load_env_var_or("RUNS_DIR", "runs")));
+    // A named run gets its own collections, files and namespace by default
+    let scoped = |name: &str| match &run {
+        Some(run) => run.scoped(name),
+        None => name.to_string(),
+    };
+    let run_path = |file: &str| match &run {
+        Some(run) => run.path(file),
+        None => file.to_string(),
+    };
+    let llm_provider = if dry_run {
+        "mock".to_string()
+    } else {
+        load_env_var_or("LLM_PROVIDER", "openai")
+    };
+    // Cohere's reranking isn't recorded or mocked, so a replay or a dry run
+    // goes without it
+    let rerank = env::var("RERANK")
+        .ok()
+        .filter(|rerank| !offline || rerank != "cohere");
+    let cohere_api_key = if rerank.as_deref() == Some("cohere") {
+        load_env_var("COHERE_API_KEY", "RERANK=cohere")
+    } else if llm_provider == "cohere" {
+        load_env_var("COHERE_API_KEY", "LLM_PROVIDER=cohere")
+    } else {
+        load_env_var_or("COHERE_API_KEY", "")
+    };
+    // Searches aren't recorded either
+    let web_search = env::var("WEB_SEARCH").ok().filter(|_| !offline);
+    let web_search_api_key = match &web_search {
+        Some(backend) => load_env_var("WEB_SEARCH_API_KEY", &format!("WEB_SEARCH={}", backend)),
+        None => String::new(),
+    };
+    // API keys are only required for the selected LLM provider
+    let provider_var = |provider: &str, name: &str| {
+        if llm_provider == provider && !offline {
+            load_env_var(name, &format!("LLM_PROVIDER={}", provider))
+        } else {
+            load_env_var_or(name, "")
+        }
+    };
+    // Deterministic runs sample with temperature 0 and a fixed seed
+    let deterministic = load_env_flag("DETERMINISTIC");
+    let llm_params = |agent: &str| {
+        if deterministic {
+            load_llm_params(agent).pinned(DEFAULT_SEED)
+        } else {
+            load_llm_params(agent)
+        }
+    };
+    let pinecone_serverless = load_env_flag("PINECONE_SERVERLESS");
+    let http_timeout_secs = load_env_number("HTTP_TIMEOUT_SECS", 120);
+    let config = Config {
+        openai_api_key: provider_var("openai", "OPENAI_API_KEY"),
+        openai_api_base: load_env_var_or("OPENAI_API_BASE", DEFAULT_API_BASE),
+        openai_api_mode: env::var("OPENAI_API_MODE").ok(),
+        openai_api_model: provider_var("openai", "OPENAI_API_MODEL"),
+        // The mock provider never fails, so it needs no fallback
+        fallback_models: if dry_run {
+            Vec::new()
+        } else {
+            fallback::parse_fallback_models(&load_env_var_or("FALLBACK_MODELS", "")).unwrap_or_else(
+                |err| {
+                    diagnostics::report(format!("FALLBACK_MODELS is invalid: {}", err));
+                    Vec::new()
+                },
+            )
+        },
+        openai_max_attempts: load_env_number("OPENAI_MAX_ATTEMPTS", openai::DEFAULT_MAX_ATTEMPTS),
+        openai_timeout_secs: load_env_number("OPENAI_TIMEOUT_SECS", http_timeout_secs),
+        gemini_api_key: provider_var("gemini", "GEMINI_API_KEY"),
+        gemini_model: load_env_var_or("GEMINI_MODEL", DEFAULT_GEMINI_MODEL),
+        gemini_embedding_model: load_env_var_or(
+            "GEMINI_EMBEDDING_MODEL",
+            DEFAULT_GEMINI_EMBEDDING_MODEL,
+        ),
+        pinecone_api_key: pinecone_var("PINECONE_API_KEY"),
+        pinecone_region: pinecone_var("PINECONE_REGION"),
+        // Serverless index hosts are looked up, so no project id is needed
+        pinecone_project_id: if pinecone_serverless {
+            load_env_var_or("PINECONE_PROJECT_ID", "")
+        } else {
+            pinecone_var("PINECONE_PROJECT_ID")
+        },
+        pinecone_index_name: pinecone_var("PINECONE_INDEX_NAME"),
+        pinecone_namespace: env::var("PINECONE_NAMESPACE")
+            .unwrap_or_else(|_| scoped(&pinecone::namespace_for_objective(&objective))),
+        pinecone_serverless_cloud: pinecone_serverless
+            .then(|| load_env_var_or("PINECONE_CLOUD", "aws")),
+        pinecone_pod_type: load_env_var_or("PINECONE_POD_TYPE", "p1.x1"),
+        pinecone_pods: load_env_number("PINECONE_PODS", 1),
+        pinecone_replicas: load_env_number("PINECONE_REPLICAS", 1),
+        pinecone_max_attempts: load_env_number(
+            "PINECONE_MAX_ATTEMPTS",
+            pinecone::DEFAULT_MAX_ATTEMPTS,
+        ),
+        pinecone_timeout_secs: load_env_number("PINECONE_TIMEOUT_SECS", http_timeout_secs),
+        embedding_model: load_env_var_or("EMBEDDING_MODEL", DEFAULT_EMBEDDING_MODEL),
+        embedding_cache_path: env::var("EMBEDDING_CACHE_PATH").ok(),
+        index_dimension: load_optional_env_number("INDEX_DIMENSION"),
+        index_metric: load_env_var_or("INDEX_METRIC", "cosine"),
+        hybrid_alpha: load_env_flag("HYBRID_SEARCH").then(|| load_env_number("HYBRID_ALPHA", 0.5)),
+        qdrant_url: load_env_var_or("QDRANT_URL", "http://localhost:6333"),
+        qdrant_api_key: env::var("QDRANT_API_KEY").ok(),
+        qdrant_collection: load_env_var_or("QDRANT_COLLECTION", &scoped("crustagi")),
+        weaviate_url: load_env_var_or("WEAVIATE_URL", "http://localhost:8080"),
+        weaviate_api_key: env::var("WEAVIATE_API_KEY").ok(),
+        weaviate_class: load_env_var_or("WEAVIATE_CLASS", &scoped("CrustagiResult")),
+        chroma_url: load_env_var_or("CHROMA_URL", "http://localhost:8000"),
+        chroma_collection: load_env_var_or("CHROMA_COLLECTION", &scoped("crustagi")),
+        sqlite_path: load_env_var_or("SQLITE_PATH", &run_path("crustagi.db")),
+        local_memory_dir: load_env_var_or("LOCAL_MEMORY_DIR", &run_path("crustagi-memory")),
+        database_url: if memory_backend == "pgvector" {
+            load_env_var("DATABASE_URL", "MEMORY_BACKEND=pgvector")
+        } else {
+            load_env_var_or("DATABASE_URL", "")
+        },
+        pgvector_table: load_env_var_or("PGVECTOR_TABLE", &scoped("crustagi_memory")),
+        milvus_url: load_env_var_or("MILVUS_URL", "http://localhost:19530"),
+        milvus_token: env::var("MILVUS_TOKEN").ok(),
+        milvus_collection: load_env_var_or("MILVUS_COLLECTION", &scoped("crustagi")),
+        execution_model: env::var("EXECUTION_MODEL").ok(),
+        creation_model: env::var("CREATION_MODEL").ok(),
+        prioritization_model: env::var("PRIORITIZATION_MODEL").ok(),
+        critic_model: env::var("CRITIC_MODEL").ok(),
+        execution_params: llm_params("EXECUTION"),
+        creation_params: llm_params("CREATION"),
+        prioritization_params: llm_params("PRIORITIZATION"),
+        critic_params: llm_params("CRITIC"),
+        planner_model: env::var("PLANNER_MODEL").ok(),
+        planner_params: llm_params("PLANNER"),
+        critic_min_score: load_optional_env_number("CRITIC_MIN_SCORE"),
+        memory_backend,
+        llm_provider,
+        initial_task: if dry_run {
+            load_env_var_or("INITIAL_TASK", DRY_RUN_INITIAL_TASK)
+        } else {
+            load_env_var("INITIAL_TASK", "every run")
+        },
+        objective_scheduling: Scheduling::parse(&load_env_var_or(
+            "OBJECTIVE_SCHEDULING",
+            "round_robin",
+        ))
+        .unwrap_or_else(|err| {
+            diagnostics::report(err.to_string());
+            Scheduling::RoundRobin
+        }),
+        objective,
+        milestone: None,
+        max_milestones: load_env_flag("PLAN_MILESTONES")
+            .then(|| load_env_number("MAX_MILESTONES", 5).max(1)),
+        plan_path: load_env_var_or("PLAN_PATH", &run_path("plan.json")),
+        llm_log_path: load_env_var_or("LLM_LOG_PATH", &run_path("llm_log.jsonl")),
+        events_path: load_env_var_or("EVENTS_PATH", &run_path("events.jsonl")),
+        webhooks: [
+            ("WEBHOOK_URL", webhooks::Format::Generic),
+            ("SLACK_WEBHOOK_URL", webhooks::Format::Slack),
+            ("DISCORD_WEBHOOK_URL", webhooks::Format::Discord),
+        ]
+        .into_iter()
+        .filter_map(|(name, format)| {
+            let url = env::var(name).ok()?;
+            Some(webhooks::Webhook { format, url })
+        })
+        .collect(),
+        webhook_events: env::var("WEBHOOK_EVENTS")
+            .map(|events| {
+                events
+                    .split(',')
+                    .map(|event| event.trim().to_string())
+                    .filter(|event| !event.is_empty())
+                    .collect()
+            })
+            .unwrap_or_else(|_| {
+                webhooks::WEBHOOK_EVENTS
+                    .iter()
+                    .map(|event| event.to_string())
+                    .collect()
+            }),
+        replay,
+        deterministic,
+        seed: load_optional_env_number("SEED"),
+        manifest_path: load_env_var_or("MANIFEST_PATH", &run_path("manifest.json")),
+        // Unnamed runs are told apart by their start time
+        report_path: format!(
+            "{}/{}.md",
+            load_env_var_or("REPORTS_DIR", "reports"),
+            run.as_ref()
+                .map_or_else(|| format!("run_{}", unix_time()), |run| run.id.clone())
+        ),
+        sanitize_context: load_env_flag("SANITIZE_CONTEXT"),
+        redact_patterns: env::var("REDACT_PATTERNS")
+            .map(|patterns| {
+                patterns
+                    .split(',')
+                    .map(|pattern| pattern.trim().to_string())
+                    .filter(|pattern| !pattern.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default(),
+        reset_memory: run_args.reset_memory
+            || matches!(
+                command,
+                Some(Command::Memory {
+                    action: MemoryAction::Reset
+                })
+            ),
+        export_memory: match command {
+            Some(Command::Memory {
+                action: MemoryAction::Export { path },
+            }) => Some(path.clone()),
+            _ => None,
+        },
+        import_memory: match command {
+            Some(Command::Memory {
+                action: MemoryAction::Import { path },
+            }) => Some(path.clone()),
+            _ => None,
+        },
+        skip_health_check: load_env_flag("SKIP_HEALTH_CHECK"),
+        tui: load_env_flag("TUI"),
+        state_path: load_env_var_or("STATE_PATH", &run_path("state.json")),
+        log_dir: env::var("LOG_DIR")
+            .ok()
+            .or_else(|| run.as_ref().map(Run::log_dir)),
+        tasks_dir: env::var("TASKS_DIR")
+            .ok()
+            .or_else(|| run.as_ref().map(Run::tasks_dir)),
+        prompts_dir: load_env_var_or("PROMPTS_DIR", "prompts"),
+        execution_history: load_env_number("EXECUTION_HISTORY", 0),
+        task_timeout_secs: load_optional_env_number("TASK_TIMEOUT_SECS"),
+        max_concurrent_tasks: load_env_number("MAX_CONCURRENT_TASKS", 1).max(1),
+        task_delay: Duration::try_from_secs_f64(load_env_number("TASK_DELAY_SECS", 1.0))
+            .unwrap_or_else(|err| {
+                diagnostics::report(format!("TASK_DELAY_SECS is invalid: {}", err));
+                Duration::from_secs(1)
+            }),
+        max_requests_per_minute: load_optional_env_number("MAX_REQUESTS_PER_MINUTE"),
+        max_task_failures: load_env_number("MAX_TASK_FAILURES", 3).max(1),
+        task_retry_delay_secs: load_env_number("TASK_RETRY_DELAY_SECS", 10),
+        // A report is written from the saved run
+        resume: matches!(command, Some(Command::Resume | Command::Report { .. })),
+        max_cost_usd: load_optional_env_number("MAX_COST_USD"),
+        model_prices: usage::parse_prices(&load_env_var_or("MODEL_PRICES", "")).unwrap_or_else(
+            |err| {
+                diagnostics::report(err.to_string());
+                Vec::new()
+            },
+        ),
+        max_tokens_total: load_optional_env_number("MAX_TOKENS_TOTAL"),
+        max_iterations: load_optional_env_number("MAX_ITERATIONS"),
+        max_tasks: load_optional_env_number("MAX_TASKS"),
+        dedup_threshold: load_optional_env_number("DEDUP_THRESHOLD"),
+        chunk_tokens: load_optional_env_number::<usize>("CHUNK_TOKENS").map(|tokens| tokens.max(1)),
+        chunk_overlap: load_env_number("CHUNK_OVERLAP", 50),
+        task_dedup_threshold: load_optional_env_number("TASK_DEDUP_THRESHOLD"),
+        context_token_budget: load_optional_env_number("CONTEXT_TOKEN_BUDGET"),
+        context_window: load_optional_env_number("CONTEXT_WINDOW"),
+        max_tool_steps: load_env_number("MAX_TOOL_STEPS", 5),
+        web_search,
+        web_search_api_key,
+        web_search_api_base: env::var("WEB_SEARCH_API_BASE").ok(),
+        web_search_results: load_env_number("WEB_SEARCH_RESULTS", 5),
+        // Pages aren't recorded either
+        web_fetch: load_env_flag("WEB_FETCH") && !offline,
+        web_fetch_chunk_tokens: load_env_number::<usize>("WEB_FETCH_CHUNK_TOKENS", 500).max(1),
+        web_fetch_chunks: load_env_number::<usize>("WEB_FETCH_CHUNKS", 4).max(1),
+        web_fetch_allow_private: load_env_flag("WEB_FETCH_ALLOW_PRIVATE"),
+        sandbox_dir: env::var("SANDBOX_DIR").ok(),
+        sandbox_read_only: load_env_flag("SANDBOX_READ_ONLY"),
+        rerank_candidates: load_env_number("RERANK_CANDIDATES", 20),
+        cohere_api_key,
+        cohere_rerank_model: load_env_var_or("COHERE_RERANK_MODEL", "rerank-v3.5"),
+        cohere_model: load_env_var_or("COHERE_MODEL", DEFAULT_COHERE_MODEL),
+        cohere_embedding_model: load_env_var_or(
+            "COHERE_EMBEDDING_MODEL",
+            DEFAULT_COHERE_EMBEDDING_MODEL,
+        ),
+        rerank,
+        max_memory_entries: load_optional_env_number("MAX_MEMORY_ENTRIES"),
+        eviction_policy: load_env_var_or("EVICTION_POLICY", "oldest"),
+        http_timeout_secs,
+        http_proxy: env::var("PROXY_URL").ok(),
+    };
+    validate(&config, dry_run);
+    diagnostics::check()?;
+    Ok((config, objectives, run))
+}
+
+// Report the settings chosen from a fixed set with a value outside of it,
+// and those that don't go together
+fn validate(config: &Config, dry_run: bool) {
+    // Settings chosen from a fixed set are checked here, so that a typo is
+    // listed with all other problems instead of failing later in the run
+    if !dry_run {
+        diagnostics::check_one_of("LLM_PROVIDER", &config.llm_provider, SUPPORTED_PROVIDERS);
+    }
+    // Plugins may add backends
+    let backends: Vec<String> = SUPPORTED_BACKENDS
+        .iter()
+        .map(|backend| backend.to_string())
+        .chain(plugins::memory_backends())
+        .collect();
+    let backends: Vec<&str> = backends.iter().map(String::as_str).collect();
+    diagnostics::check_one_of("MEMORY_BACKEND", &config.memory_backend, &backends);
+    if let Some(api_mode) = &config.openai_api_mode {
+        diagnostics::check_one_of("OPENAI_API_MODE", api_mode, openai::API_MODES);
+    }
+    if let Some(rerank) = &config.rerank {
+        diagnostics::check_one_of("RERANK", rerank, &["llm", "cohere"]);
+    }
+    if let Some(web_search) = &config.web_search {
+        diagnostics::check_one_of("WEB_SEARCH", web_search, SEARCH_BACKENDS);
+    }
+    diagnostics::check_one_of(
+        "EVICTION_POLICY",
+        &config.eviction_policy,
+        &["oldest", "relevance"],
+    );
+    // Eviction reads the stored results once, which pod-based Pinecone
+    // indexes can't list
+    if config.max_memory_entries.is_some()
+        && config.memory_backend == "pinecone"
+        && config.pinecone_serverless_cloud.is_none()
+    {
+        diagnostics::report(
+            "MAX_MEMORY_ENTRIES needs a serverless Pinecone index (PINECONE_SERVERLESS=true), \
+             pod-based indexes can't list their vectors"
+                .to_string(),
+        );
+    }
+    for event in &config.webhook_events {
+        diagnostics::check_one_of("WEBHOOK_EVENTS", event, webhooks::WEBHOOK_EVENTS);
+    }
+    for pattern in &config.redact_patterns {
+        if let Err(err) = Regex::new(pattern) {
+            diagnostics::report(format!("REDACT_PATTERNS has an invalid pattern: {}", err));
+        }
+    }
+}
+
+// The HTML report, which is made from the events file alone, without the
+// saved run, memory or any API
+fn write_html_report(config: &Config) -> Result<(), CrustagiError> {
+    let path = Path::new(&config.report_path)
+        .with_extension("html")
+        .to_string_lossy()
+        .into_owned();
+    html_report::write(&config.events_path, &path).map_err(|err| {
+        CrustagiError::io(
+            format!(
+                "Failed to write the report of {} to {}",
+                config.events_path, path
+            ),
+            err,
+        )
+    })?;
+    tracing::info!(target: logging::RESULTS, "Wrote the report to {}", path);
+    Ok(())
+}
+
+// The webhooks of the command line, which a replay doesn't send, like it
+// calls no APIs
+fn webhooks(config: &Config, http: &reqwest::Client, run: Option<&Run>) -> Option<Arc<Webhooks>> {
+    if config.webhooks.is_empty() || config.replay.is_some() {
+        return None;
+    }
+    let label = match run {
+        Some(run) => run.id.clone(),
+        // A brief of several lines is told apart by its first line
+        None => config
+            .objective
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+    };
+    Some(Arc::new(Webhooks::new(
+        http.clone(),
+        config.webhooks.clone(),
+        config.webhook_events.clone(),
+        &label,
+    )))
+}
+
+// The observers of the command line: the events file and `webhooks`. A
+// replay leaves the events of the recorded run alone, and the report
+// command runs no tasks.
+fn observers(
+    config: &Config,
+    webhooks: Option<&Arc<Webhooks>>,
+    command: Option<&Command>,
+) -> Result<Vec<Arc<dyn Observer>>, CrustagiError> {
+    let mut observers: Vec<Arc<dyn Observer>> = Vec::new();
+    if config.replay.is_some() || matches!(command, Some(Command::Report { .. })) {
+        return Ok(observers);
+    }
+    // A resumed run continues its events
+    let events = EventLog::open(&config.events_path, config.resume)?;
+    observers.push(Arc::new(events));
+    if let Some(webhooks) = webhooks {
+        observers.push(webhooks.clone());
+    }
+    Ok(observers)
+}
+
+// The providers of the agents, each with its own model and sampling
+// parameters, and with the middleware of the run around it
+struct AgentLlms {
+    // Embeds the results, and reranks them with RERANK=llm
+    memory: Arc<dyn LlmProvider>,
+    memory_params: LlmParams,
+    execution: Arc<dyn LlmProvider>,
+    creation: Arc<dyn LlmProvider>,
+    critic: Option<Arc<dyn LlmProvider>>,
+    prioritization: Arc<dyn LlmProvider>,
+    // The planner and the synthesis at the end of a planned run
+    planner: Option<(Arc<dyn LlmProvider>, Arc<dyn LlmProvider>)>,
+    // Writes the key findings of the final report
+    report: Arc<dyn LlmProvider>,
+    // The run log a replay answers the calls from
+    replay: Option<Arc<Replay>>,
+    // MAX_REQUESTS_PER_MINUTE, which the requests to Pinecone share
+    rate_limit: RateLimitLayer,
+}
+
+impl AgentLlms {
+    // Every LLM call is recorded to the run log, except in a replay, which
+    // answers them from the run log it replays instead
+    fn new(
+        config: &Config,
+        http: &reqwest::Client,
+        usage: &Arc<UsageTracker>,
+    ) -> Result<Self, CrustagiError> {
+        let replay = match &config.replay {
+            Some(path) => {
+                tracing::info!("Replaying the LLM calls recorded in {}", path);
+                let replay = Replay::load(path)?;
+                Some(Arc::new(replay))
+            }
+            None => None,
+        };
+        let run_log = match replay {
+            Some(_) => None,
+            // A resumed run continues its run log
+            None => Some(Arc::new(RunLog::open(&config.llm_log_path, config.resume)?)),
+        };
+        // Prompts are redacted before the run log records them, so that a
+        // replay sees the same prompts
+        let middleware = if config.redact_patterns.is_empty() {
+            LlmStack::new()
+        } else {
+            LlmStack::new().layer(RedactionLayer::new(&config.redact_patterns)?)
+        };
+        // The providers' requests wait for MAX_REQUESTS_PER_MINUTE and right
+        // around them their usage is counted per agent, so that replayed
+        // calls do neither
+        let rate_limit = RateLimitLayer::new(config.max_requests_per_minute.unwrap_or(0));
+        let logged = |agent: &str, provider: Arc<dyn LlmProvider>| -> Arc<dyn LlmProvider> {
+            let provider = LlmStack::new()
+                .layer(CostLayer::new(UsageMeter::new(usage.clone(), agent)))
+                .wrap(provider);
+            middleware.wrap(match (&replay, &run_log) {
+                (Some(replay), _) => Arc::new(Replayed::new(provider, agent, replay.clone())),
+                (None, Some(run_log)) => Arc::new(Recording::new(provider, agent, run_log.clone())),
+                (None, None) => provider,
+            })
+        };
+        // Reranking with the model keeps the provider's defaults, pinned in
+        // deterministic mode
+        let memory_params = LlmParams {
+            seed: config.seed,
+            ..LlmParams::default()
+        };
+        let memory_params = if config.deterministic {
+            memory_params.pinned(DEFAULT_SEED)
+        } else {
+            memory_params
+        };
+        let memory = logged(
+            "memory",
+            llm::from_config(config, http, None, &memory_params, &rate_limit)
+                .map_err(|err| CrustagiError::Config(err.to_string()))?,
+        );
+        let agent_llm = |agent: &str, model: &Option<String>, params: &LlmParams| {
+            let provider = llm::from_config(config, http, model.as_deref(), params, &rate_limit)
+                .map_err(|err| CrustagiError::Config(err.to_string()))?;
+            Ok::<_, CrustagiError>(logged(agent, provider))
+        };
+        Ok(AgentLlms {
+            memory,
+            memory_params,
+            execution: agent_llm(
+                "execution",
+                &config.execution_model,
+                &config.execution_params,
+            )?,
+            creation: agent_llm("creation", &config.creation_model, &config.creation_params)?,
+            critic: match config.critic_min_score {
+                Some(_) => Some(agent_llm(
+                    "critic",
+                    &config.critic_model,
+                    &config.critic_params,
+                )?),
+                None => None,
+            },
+            prioritization: agent_llm(
+                "prioritization",
+                &config.prioritization_model,
+                &config.prioritization_params,
+            )?,
+            // The synthesis uses the planner's model
+            planner: match config.max_milestones {
+                Some(_) => Some((
+                    agent_llm("planner", &config.planner_model, &config.planner_params)?,
+                    agent_llm("synthesis", &config.planner_model, &config.planner_params)?,
+                )),
+                None => None,
+            },
+            // The key findings use the execution model
+            report: agent_llm("report", &config.execution_model, &config.execution_params)?,
+            replay,
+            rate_limit,
+        })
+    }
+
+    // Warn of the models without a price, which count as free, so the
+    // budget wouldn't stop what they cost
+    fn check_prices(&self, config: &Config, usage: &UsageTracker) {
+        let Some(max_cost) = config.max_cost_usd else {
+            return;
+        };
+        let mut models = vec![
+            self.execution.model(),
+            self.creation.model(),
+            self.prioritization.model(),
+            self.memory.embedding_model().to_string(),
+        ];
+        models.extend(self.critic.iter().map(|critic| critic.model()));
+        models.extend(self.planner.iter().map(|(planner, _)| planner.model()));
+        models.retain(|model| !usage.has_price(model));
+        models.sort();
+        models.dedup();
+        if !models.is_empty() {
+            diagnostics::warn(format!(
+                "MAX_COST_USD is ${}, but {} {} no known price and won't count towards it. \
+                 Set the prices in MODEL_PRICES.",
+                max_cost,
+                models.join(", "),
+                if models.len() == 1 { "has" } else { "have" }
+            ));
+        }
+    }
+
+    // Write the manifest of a deterministic run, with the settings of each
+    // agent
+    fn write_manifest(&self, config: &Config, objectives: &[String], prompts: &Prompts) {
+        let settings = |llm: &Arc<dyn LlmProvider>, params: &LlmParams| state::AgentSettings {
+            model: llm.model(),
+            params: params.clone(),
+        };
+        let mut agents = BTreeMap::from([
+            (
+                "memory".to_string(),
+                settings(&self.memory, &self.memory_params),
+            ),
+            (
+                "execution".to_string(),
+                settings(&self.execution, &config.execution_params),
+            ),
+            (
+                "creation".to_string(),
+                settings(&self.creation, &config.creation_params),
+            ),
+            (
+                "prioritization".to_string(),
+                settings(&self.prioritization, &config.prioritization_params),
+            ),
+        ]);
+        if let Some(critic) = &self.critic {
+            agents.insert(
+                "critic".to_string(),
+                settings(critic, &config.critic_params),
+            );
+        }
+        if let Some((planner, _)) = &self.planner {
+            agents.insert(
+                "planner".to_string(),
+                settings(planner, &config.planner_params),
+            );
+        }
+        write_manifest(
+            config,
+            state::Manifest {
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                started_at: unix_time(),
+                objectives: objectives.to_vec(),
+                initial_task: config.initial_task.clone(),
+                seed: config.seed.unwrap_or(DEFAULT_SEED),
+                llm_provider: config.llm_provider.clone(),
+                embedding_model: self.memory.embedding_model().to_string(),
+                memory_backend: config.memory_backend.clone(),
+                agents,
+                prompts: prompts.sources().clone(),
+            },
+        );
+    }
+}
+
+// The dimension of the embedding model, which the index is created for and
+// checked against
+async fn embedding_dimension(
+    config: &Config,
+    llm: &dyn LlmProvider,
+) -> Result<usize, CrustagiError> {
+    let dimension = llm.embedding_dimension().await.map_err(|err| {
+        CrustagiError::llm(
+            format!(
+                "Failed to determine the dimension of {}",
+                llm.embedding_model()
+            ),
+            err,
+        )
+    })?;
+    match config.index_dimension {
+        Some(index_dimension) if index_dimension != dimension => {
+            Err(CrustagiError::Config(format!(
+                "INDEX_DIMENSION is {} but {} produces {}-dimensional embeddings. \
+                 Unset INDEX_DIMENSION or change the embedding model.",
+                index_dimension,
+                llm.embedding_model(),
+                dimension
+            )))
+        }
+        _ => Ok(dimension),
+    }
+}
+
+// Export or import the vector store, which the memory commands do instead of
+// the task loop. The reset is done when the store is opened.
+async fn manage_memory(config: &Config, memory: &dyn VectorStore) -> Result<(), CrustagiError> {
+    if let Some(path) = &config.export_memory {
+        let count = snapshot::export(memory, path)
+            .await
+            .map_err(|err| CrustagiError::memory("Failed to export memory", err))?;
+        tracing::info!("Exported {} vectors to {}", count, path);
+    }
+    if let Some(path) = &config.import_memory {
+        let count = snapshot::import(memory, path)
+            .await
+            .map_err(|err| CrustagiError::memory("Failed to import memory", err))?;
+        tracing::info!("Imported {} vectors from {}", count, path);
+    }
+    Ok(())
+}
+
+// What the agents of all objectives share: their providers and prompts, how
+// results are reranked and stored, and the tools of the execution agent
+struct Agents {
+    llms: AgentLlms,
+    prompts: Arc<Prompts>,
+    reranker: Option<Reranker>,
+    enricher: Arc<dyn Enricher>,
+    tools: Vec<Arc<dyn Tool>>,
+    // Passed the events of the tasks, like those of the engine
+    observers: Vec<Arc<dyn Observer>>,
+    // With TUI set, the dashboard of the run
+    dashboard: Option<Dashboard>,
+}
+
+impl Agents {
+    fn new(
+        config: &Config,
+        http: &reqwest::Client,
+        llms: AgentLlms,
+        prompts: Arc<Prompts>,
+        observers: Vec<Arc<dyn Observer>>,
+    ) -> Result<Self, CrustagiError> {
+        let reranker = reranker(config, http, &llms.memory)?;
+        let enricher = enricher(config, llms.memory.as_ref());
+        let tools = tools(config, http, &llms.execution)?;
+        Ok(Agents {
+            llms,
+            prompts,
+            reranker,
+            enricher,
+            tools,
+            observers,
+            dashboard: config.tui.then(Dashboard::default),
+        })
+    }
+
+    // The agent running the tasks of the objective of `config`, storing its
+    // results in `memory`
+    fn agent(
+        &self,
+        config: &Arc<Config>,
+        memory: Arc<dyn VectorStore>,
+        evictor: Option<Arc<Evictor>>,
+        result_prefix: String,
+    ) -> Agent {
+        Agent {
+            config: config.clone(),
+            prompts: self.prompts.clone(),
+            llm: self.llms.memory.clone(),
+            // The context is looked up with the memory provider, which
+            // embedded the stored results, and counted as "memory"
+            execution: Arc::new(LlmExecutionAgent {
+                config: config.clone(),
+                llm: self.llms.execution.clone(),
+                prompts: self.prompts.clone(),
+                context: Arc::new(LlmContextAgent {
+                    config: config.clone(),
+                    llm: self.llms.memory.clone(),
+                    reranker: self.reranker.clone(),
+                    memory: memory.clone(),
+                    dashboard: self.dashboard.clone(),
+                }),
+                tools: self.tools.clone(),
+                dashboard: self.dashboard.clone(),
+            }),
+            task_creation: Arc::new(LlmTaskCreationAgent {
+                llm: self.llms.creation.clone(),
+                prompts: self.prompts.clone(),
+            }),
+            critic_llm: self.llms.critic.clone(),
+            memory,
+            enricher: self.enricher.clone(),
+            evictor,
+            result_prefix,
+            observers: self.observers.clone(),
+        }
+    }
+}
+
+// How the context of the tasks is reranked with RERANK set, `llm` being the
+// provider of RERANK=llm
+pub fn reranker(
+    config: &Config,
+    http: &reqwest::Client,
+    llm: &Arc<dyn LlmProvider>,
+) -> Result<Option<Reranker>, CrustagiError> {
+    let reranker = match config.rerank.as_deref() {
+        None => None,
+        Some("llm") => Some(Reranker::Llm(llm.clone())),
+        Some("cohere") => Some(Reranker::Cohere {
+            http: http.clone(),
+            api_key: config.cohere_api_key.clone(),
+            model: config.cohere_rerank_model.clone(),
+            redaction: if config.redact_patterns.is_empty() {
+                None
+            } else {
+                Some(RedactionLayer::new(&config.redact_patterns)?)
+            },
+        }),
+        // Checked with the other settings
+        Some(_) => unreachable!(),
+    };
+    Ok(reranker)
+}
+
+// Results are stored as they are, or in chunks of the tokens of `llm`'s
+// embedding model with CHUNK_TOKENS set, after the enrichers of the plugins.
+// Register a plugin with an Enricher of your own to change what is embedded
+// and stored for each result.
+pub fn enricher(config: &Config, llm: &dyn LlmProvider) -> Arc<dyn Enricher> {
+    let mut enrichers = plugins::enrichers();
+    if let Some(chunk_tokens) = config.chunk_tokens {
+        enrichers.push(Arc::new(Chunker {
+            model: llm.embedding_model().to_string(),
+            chunk_tokens,
+            overlap: config.chunk_overlap,
+        }));
+    }
+    match enrichers.len() {
+        0 => Arc::new(NoEnrichment),
+        1 => enrichers.remove(0),
+        _ => Arc::new(Chain(enrichers)),
+    }
+}
+
+// The tools the execution agent may call: the built-in ones that are set up
+// and the tools of the plugins
+pub fn tools(
+    config: &Config,
+    http: &reqwest::Client,
+    execution_llm: &Arc<dyn LlmProvider>,
+) -> Result<Vec<Arc<dyn Tool>>, CrustagiError> {
+    let mut tools: Vec<Arc<dyn Tool>> = Vec::new();
+    if let Some(backend) = config
+        .web_search
+        .as_deref()
+        .and_then(SearchBackend::from_name)
+    {
+        tools.push(Arc::new(WebSearch {
+            http: http.clone(),
+            backend,
+            api_key: config.web_search_api_key.clone(),
+            api_base: config.web_search_api_base.clone(),
+            results: config.web_search_results,
+        }));
+    }
+    if config.web_fetch {
+        // Its own client, as it follows redirects itself to check where
+        // each one leads
+        let http = http::build_client(
+            Duration::from_secs(config.http_timeout_secs),
+            config.http_proxy.as_deref(),
+            false,
+        )?;
+        tools.push(Arc::new(WebFetch {
+            http,
+            llm: execution_llm.clone(),
+            chunk_tokens: config.web_fetch_chunk_tokens,
+            chunks: config.web_fetch_chunks,
+            allow_private: config.web_fetch_allow_private,
+        }));
+    }
+    if let Some(dir) = &config.sandbox_dir {
+        let sandbox = Sandbox::open(dir)?;
+        tracing::info!("Sandbox: {}", sandbox.root().display());
+        let sandbox = Arc::new(sandbox);
+        tools.push(Arc::new(ReadFile(sandbox.clone())));
+        tools.push(Arc::new(ListFiles(sandbox.clone())));
+        if !config.sandbox_read_only {
+            tools.push(Arc::new(WriteFile(sandbox)));
+        }
+    }
+    tools.extend(plugins::tools());
+    if !tools.is_empty() {
+        let names: Vec<&str> = tools.iter().map(|tool| tool.name()).collect();
+        tracing::info!("Tools: {}", names.join(", "));
+    }
+    Ok(tools)
+}
+
+// The milestones of a run with PLAN_MILESTONES: those of the saved plan when
+// resuming, or else the planner's, which are saved as the plan
+async fn plan_milestones(
+    config: &Config,
+    objectives: &[String],
+    agents: &Agents,
+) -> Result<Option<Vec<String>>, CrustagiError> {
+    let (Some(max_milestones), Some((planner_llm, _))) =
+        (config.max_milestones, &agents.llms.planner)
+    else {
+        return Ok(None);
+    };
+    if objectives.len() > 1 {
+        return Err(CrustagiError::Config(
+            "PLAN_MILESTONES needs a single OBJECTIVE, not OBJECTIVES_FILE".to_string(),
+        ));
+    }
+    if config.resume {
+        let plan: state::Plan = state::load(&config.plan_path)?;
+        if plan.objective != config.objective {
+            return Err(CrustagiError::Config(format!(
+                "The plan in {} is for a different objective: {}",
+                config.plan_path, plan.objective
+            )));
+        }
+        return Ok(Some(plan.milestones));
+    }
+    let milestones = planner_agent(
+        planner_llm.as_ref(),
+        &agents.prompts,
+        &config.objective,
+        max_milestones,
+    )
+    .await?;
+    let plan = state::Plan {
+        objective: config.objective.clone(),
+        milestones,
+    };
+    // A replay leaves the files of the recorded run alone
+    if config.replay.is_none() {
+        if let Err(err) = state::save(&config.plan_path, &plan) {
+            tracing::warn!("Failed to save the plan: {}", err);
+        }
+    }
+    Ok(Some(plan.milestones))
+}
+
+// The objective and the milestone, if any, of each task list: a list per
+// objective, or per milestone of the single objective of a planned run
+fn parts(
+    config: &Config,
+    objectives: Vec<String>,
+    milestones: Option<&[String]>,
+) -> Vec<(String, Option<String>)> {
+    let Some(milestones) = milestones else {
+        return objectives
+            .into_iter()
+            .map(|objective| (objective, None))
+            .collect();
+    };
+    let lines: Vec<String> = milestones
+        .iter()
+        .enumerate()
+        .map(|(index, milestone)| format!("{}: {}", index + 1, milestone))
+        .collect();
+    tracing::info!(
+        "\n{}\n{}",
+        logging::banner("MILESTONES", Color::Blue),
+        lines.join("\n")
+    );
+    milestones
+        .iter()
+        .map(|milestone| (config.objective.clone(), Some(milestone.clone())))
+        .collect()
+}
+
+// Start the task list of each of `parts`, or continue the ones saved by a
+// stopped run. With several objectives, each gets its own state file, task
+// log, store and, unless PINECONE_NAMESPACE is set, Pinecone namespace.
+// Milestones share the store of their objective.
+async fn start_objective_runs(
+    config: &Arc<Config>,
+    http: &reqwest::Client,
+    agents: &Agents,
+    memory: Arc<dyn VectorStore>,
+    dimension: usize,
+    parts: Vec<(String, Option<String>)>,
+    run: Option<&Run>,
+) -> Result<Vec<ObjectiveRun>, CrustagiError> {
+    let several = parts.len() > 1;
+    let mut objective_runs = Vec::with_capacity(parts.len());
+    // Milestones share the evictor of their store
+    let mut milestone_evictor: Option<Arc<Evictor>> = None;
+    for (index, (objective, milestone)) in parts.into_iter().enumerate() {
+        let number = index + 1;
+        let kind = match milestone {
+            Some(_) => "milestone",
+            None => "objective",
+        };
+        let config = if several || milestone.is_some() {
+            Arc::new(Config {
+                pinecone_namespace: env::var("PINECONE_NAMESPACE").unwrap_or_else(|_| {
+                    let namespace = pinecone::namespace_for_objective(&objective);
+                    run.map_or(namespace.clone(), |run| run.scoped(&namespace))
+                }),
+                state_path: objectives::numbered_path(&config.state_path, number),
+                log_dir: config
+                    .log_dir
+                    .as_ref()
+                    .map(|log_dir| format!("{}/{}_{}", log_dir, kind, number)),
+                tasks_dir: config
+                    .tasks_dir
+                    .as_ref()
+                    .map(|tasks_dir| format!("{}/{}_{}", tasks_dir, kind, number)),
+                objective,
+                milestone,
+                ..config.as_ref().clone()
+            })
+        } else {
+            config.clone()
+        };
+        let memory = if index == 0 || config.milestone.is_some() {
+            memory.clone()
+        } else {
+            open_memory(&config, http, &agents.llms.rate_limit, dimension).await?
+        };
+        let evictor = match &milestone_evictor {
+            Some(_) if config.milestone.is_some() => milestone_evictor.clone(),
+            _ => evictor(&config, agents.llms.memory.as_ref()).await?,
+        };
+        if config.milestone.is_some() {
+            milestone_evictor = evictor.clone();
+        }
+        let result_prefix = if several {
+            format!("{}_{}_result", kind, number)
+        } else {
+            "result".to_string()
+        };
+        let runner = Arc::new(agents.agent(&config, memory, evictor, result_prefix));
+        objective_runs.push(ObjectiveRun::start(number, config, runner)?);
+    }
+    Ok(objective_runs)
+}
+
+// The evictor keeping the results of the objective of `config` within
+// MAX_MEMORY_ENTRIES, which evicts results after each upsert
+pub async fn evictor(
+    config: &Config,
+    llm: &dyn LlmProvider,
+) -> Result<Option<Arc<Evictor>>, CrustagiError> {
+    let Some(max_entries) = config.max_memory_entries else {
+        return Ok(None);
+    };
+    let eviction_policy = match config.eviction_policy.as_str() {
+        "oldest" => EvictionPolicy::Oldest,
+        "relevance" => {
+            let objective = llm.embed(&config.objective).await.map_err(|err| {
+                CrustagiError::llm("Embedding the objective for EVICTION_POLICY failed", err)
+            })?;
+            EvictionPolicy::LeastRelevant(objective)
+        }
+        // Checked with the other settings
+        _ => unreachable!(),
+    };
+    Ok(Some(Arc::new(Evictor::new(
+        &config.objective,
+        max_entries,
+        eviction_policy,
+    ))))
+}
+
+// Write the report of the saved run, which the report command does instead
+// of continuing it
+async fn report_saved_run(
+    config: &Config,
+    agents: &Agents,
+    objective_runs: &[ObjectiveRun],
+    usage: &Arc<UsageTracker>,
+    shutdown: &mut watch::Receiver<u32>,
+) {
+    let findings = key_findings(
+        config,
+        agents.llms.report.as_ref(),
+        &agents.prompts,
+        objective_runs,
+        usage,
+        shutdown,
+    )
+    .await;
+    let completed = objective_runs
+        .iter()
+        .map(|objective_run| objective_run.completed_tasks.len())
+        .sum();
+    write_report(
+        config,
+        objective_runs,
+        "Saved run, not continued",
+        completed,
+        &findings,
+        usage,
+    );
+}
+
+// The vector store of `config`, created if it doesn't exist yet, and emptied
+// with RESET_MEMORY
+async fn open_memory(
+    config: &Config,
+    http: &reqwest::Client,
+    rate_limit: &RateLimitLayer,
+    dimension: usize,
+) -> Result<Arc<dyn VectorStore>, CrustagiError> {
+    let memory: Arc<dyn VectorStore> = memory::from_config(config, http, rate_limit, dimension)
+        .await
+        .map_err(|err| CrustagiError::memory("Failed to set up the vector store", err))?
+        .into();
+    memory
+        .create()
+        .await
+        .map_err(|err| CrustagiError::memory("Failed to create the vector store", err))?;
+    if config.reset_memory {
+        tracing::info!("Resetting memory...");
+        memory
+            .reset()
+            .await
+            .map_err(|err| CrustagiError::memory("Failed to reset memory", err))?;
+    }
+    Ok(memory)
+}
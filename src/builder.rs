@@ -0,0 +1,342 @@
+// Runs set up in code instead of from the environment, for programs
+// embedding the agent
+
+use std::sync::Arc;
+use tokio::sync::watch;
+
+use crate::agents::{
+    ContextAgent, ExecutionAgent, LlmContextAgent, LlmExecutionAgent, LlmPrioritizationAgent,
+    LlmTaskCreationAgent, PrioritizationAgent, TaskCreationAgent,
+};
+use crate::app;
+use crate::config::Config;
+use crate::engine::{run_loop, Agent, Engine, ObjectiveRun, RunOutcome};
+use crate::error::CrustagiError;
+use crate::events::Event;
+use crate::in_memory::InMemoryStore;
+use crate::llm::LlmProvider;
+use crate::memory::VectorStore;
+use crate::middleware::{CostLayer, Layer, LlmStack, RateLimitLayer, RedactionLayer};
+use crate::observer::Observer;
+use crate::prompts::Prompts;
+use crate::task_dedup::TaskDeduplicator;
+use crate::tools::Tool;
+use crate::tui::Dashboard;
+use crate::usage::{UsageMeter, UsageTracker};
+
+/// Sets up a run without reading any environment variable. The settings not
+/// given keep the defaults of the command line, e.g. the built-in prompts
+/// unless `prompts/` has templates, and results kept in memory.
+///
+/// ```no_run
+/// # async fn example(
+/// #     provider: impl crustagi::llm::LlmProvider + 'static,
+/// # ) -> Result<(), crustagi::CrustagiError> {
+/// let outcome = crustagi::CrustagiBuilder::new()
+///     .objective("Write a packing list for a week of hiking")
+///     .llm(provider)
+///     .max_iterations(5)
+///     .on_event(|event| println!("{:?}", event))
+///     .run()
+///     .await?;
+/// println!("{}: {}", outcome.stop_reason, outcome.findings);
+/// # Ok::<(), crustagi::CrustagiError>(())
+/// # }
+/// ```
+pub struct CrustagiBuilder {
+    config: Config,
+    llm: Option<Arc<dyn LlmProvider>>,
+    memory: Option<Arc<dyn VectorStore>>,
+    layers: LlmStack,
+    usage: Option<Arc<UsageTracker>>,
+    observers: Vec<Arc<dyn Observer>>,
+    task_creation: Option<Arc<dyn TaskCreationAgent>>,
+    prioritization: Option<Arc<dyn PrioritizationAgent>>,
+    execution: Option<Arc<dyn ExecutionAgent>>,
+    context: Option<Arc<dyn ContextAgent>>,
+    tools: Vec<Arc<dyn Tool>>,
+}
+
+impl Default for CrustagiBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CrustagiBuilder {
+    pub fn new() -> Self {
+        CrustagiBuilder {
+            config: Config {
+                initial_task: "Develop a task list".to_string(),
+                ..Config::default()
+            },
+            llm: None,
+            memory: None,
+            layers: LlmStack::new(),
+            usage: None,
+            observers: Vec::new(),
+            task_creation: None,
+            prioritization: None,
+            execution: None,
+            context: None,
+            tools: Vec::new(),
+        }
+    }
+
+    pub fn objective(mut self, objective: impl Into<String>) -> Self {
+        self.config.objective = objective.into();
+        self
+    }
+
+    /// The first task of the run, "Develop a task list" if not given
+    pub fn initial_task(mut self, initial_task: impl Into<String>) -> Self {
+        self.config.initial_task = initial_task.into();
+        self
+    }
+
+    /// The provider all agents complete and embed with. Required.
+    pub fn llm(mut self, provider: impl LlmProvider + 'static) -> Self {
+        self.llm = Some(Arc::new(provider));
+        self
+    }
+
+    /// Wrap the LLM provider in `layer`, inside the layers added before,
+    /// e.g. a `CacheLayer`. The usage of the run is counted inside them.
+    pub fn layer(mut self, layer: impl Layer + 'static) -> Self {
+        self.layers = self.layers.layer(layer);
+        self
+    }
+
+    /// Count the tokens and cost of the run in `usage` instead of a tracker
+    /// of its own, e.g. to total several runs. The cost is then reckoned at
+    /// the prices `usage` was made with, not those of `model_prices`.
+    pub fn usage(mut self, usage: Arc<UsageTracker>) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    /// The vector store of the results, an `InMemoryStore` if not given
+    pub fn memory(mut self, store: impl VectorStore + 'static) -> Self {
+        self.memory = Some(Arc::new(store));
+        self
+    }
+
+    /// Stop after this many tasks, which a run without it only does once
+    /// all tasks are done
+    pub fn max_iterations(mut self, max_iterations: usize) -> Self {
+        self.config.max_iterations = Some(max_iterations);
+        self
+    }
+
+    /// Call `callback` with every event of the run, the same events the
+    /// events file gets, as an observer added at this point
+    pub fn on_event(self, callback: impl Fn(&Event) + Send + Sync + 'static) -> Self {
+        self.observer(EventCallback(callback))
+    }
+
+    /// Call the hooks of `observer` as the run goes on, after those of the
+    /// observers added before
+    pub fn observer(mut self, observer: impl Observer + 'static) -> Self {
+        self.observers.push(Arc::new(observer));
+        self
+    }
+
+    /// Create the new tasks with `agent` instead of asking the LLM provider
+    pub fn task_creation_agent(mut self, agent: impl TaskCreationAgent + 'static) -> Self {
+        self.task_creation = Some(Arc::new(agent));
+        self
+    }
+
+    /// Order the task list with `agent` instead of asking the LLM provider,
+    /// e.g. by rules
+    pub fn prioritization_agent(mut self, agent: impl PrioritizationAgent + 'static) -> Self {
+        self.prioritization = Some(Arc::new(agent));
+        self
+    }
+
+    /// Execute the tasks with `agent` instead of asking the LLM provider.
+    /// The context agent is then only used if `agent` holds it.
+    pub fn execution_agent(mut self, agent: impl ExecutionAgent + 'static) -> Self {
+        self.execution = Some(Arc::new(agent));
+        self
+    }
+
+    /// Look up the context of the tasks with `agent` instead of searching
+    /// the vector store
+    pub fn context_agent(mut self, agent: impl ContextAgent + 'static) -> Self {
+        self.context = Some(Arc::new(agent));
+        self
+    }
+
+    /// Offer `tool` to the execution agent, after the tools of the plugins
+    /// and those added before. Unused with a custom execution agent.
+    pub fn tool(mut self, tool: impl Tool + 'static) -> Self {
+        self.tools.push(Arc::new(tool));
+        self
+    }
+
+    /// Any other setting of the run, e.g. `|config| config.max_cost_usd =
+    /// Some(0.5)`. The settings of the LLM providers and the memory backends
+    /// are unused, as `llm` and `memory` set them, and so are those of the
+    /// command line only, like the run log, events file, webhooks and
+    /// dashboard. Planned milestones and replays fail the run.
+    pub fn configure(mut self, change: impl FnOnce(&mut Config)) -> Self {
+        change(&mut self.config);
+        self
+    }
+
+    /// Work through the tasks until all are done, the budget or
+    /// `max_iterations` is used up, and return how the run ended. The
+    /// report and the task list are written like those of the command line.
+    /// Fails without an LLM provider, with settings the builder can't run
+    /// with or if the vector store can't be set up, while failed tasks are
+    /// retried and don't fail the run.
+    pub async fn run(self) -> Result<RunOutcome, CrustagiError> {
+        let config = Arc::new(self.config);
+        if config.max_milestones.is_some() {
+            return Err(CrustagiError::Config(
+                "CrustagiBuilder can't plan milestones, unset max_milestones".to_string(),
+            ));
+        }
+        if config.replay.is_some() {
+            return Err(CrustagiError::Config(
+                "CrustagiBuilder can't replay a run log, unset replay".to_string(),
+            ));
+        }
+        let llm = self.llm.ok_or_else(|| {
+            CrustagiError::Config(
+                "CrustagiBuilder needs an LLM provider, set one with `llm`".to_string(),
+            )
+        })?;
+        // Like on the command line, prompts are redacted before anything
+        // else sees them, and right around the provider the calls wait for
+        // max_requests_per_minute and their usage is counted per agent
+        let redaction = if config.redact_patterns.is_empty() {
+            None
+        } else {
+            Some(RedactionLayer::new(&config.redact_patterns)?)
+        };
+        let rate_limit = config.max_requests_per_minute.map(RateLimitLayer::new);
+        let usage = self
+            .usage
+            .unwrap_or_else(|| Arc::new(UsageTracker::with_prices(config.model_prices.clone())));
+        let layers = self.layers;
+        let agent_llm = |agent: &str| -> Arc<dyn LlmProvider> {
+            let mut stack = LlmStack::new();
+            if let Some(rate_limit) = &rate_limit {
+                stack = stack.layer(rate_limit.clone());
+            }
+            let provider = layers.wrap(
+                stack
+                    .layer(CostLayer::new(UsageMeter::new(usage.clone(), agent)))
+                    .wrap(llm.clone()),
+            );
+            match &redaction {
+                Some(redaction) => redaction.layer(provider),
+                None => provider,
+            }
+        };
+        let memory_llm = agent_llm("memory");
+        let execution_llm = agent_llm("execution");
+        let http = config.http_client()?;
+        let memory: Arc<dyn VectorStore> = match self.memory {
+            Some(memory) => memory,
+            None => Arc::new(InMemoryStore::new()),
+        };
+        memory
+            .create()
+            .await
+            .map_err(|err| CrustagiError::memory("Failed to create the vector store", err))?;
+        if config.reset_memory {
+            memory
+                .reset()
+                .await
+                .map_err(|err| CrustagiError::memory("Failed to reset memory", err))?;
+        }
+        let prompts = Arc::new(Prompts::load(&config.prompts_dir)?);
+        let dashboard = config.tui.then(Dashboard::default);
+        let context = match self.context {
+            Some(context) => context,
+            None => Arc::new(LlmContextAgent {
+                config: config.clone(),
+                llm: memory_llm.clone(),
+                reranker: app::reranker(&config, &http, &memory_llm)?,
+                memory: memory.clone(),
+                dashboard: dashboard.clone(),
+            }),
+        };
+        let execution = match self.execution {
+            Some(execution) => execution,
+            // The built-in tools that are set up come first, then those of
+            // the plugins and the builder
+            None => Arc::new(LlmExecutionAgent {
+                config: config.clone(),
+                llm: execution_llm.clone(),
+                prompts: prompts.clone(),
+                context,
+                tools: app::tools(&config, &http, &execution_llm)?
+                    .into_iter()
+                    .chain(self.tools)
+                    .collect(),
+                dashboard: dashboard.clone(),
+            }),
+        };
+        let task_creation = self.task_creation.unwrap_or_else(|| {
+            Arc::new(LlmTaskCreationAgent {
+                llm: agent_llm("creation"),
+                prompts: prompts.clone(),
+            })
+        });
+        let prioritization = self.prioritization.unwrap_or_else(|| {
+            Arc::new(LlmPrioritizationAgent {
+                llm: agent_llm("prioritization"),
+                prompts: prompts.clone(),
+            })
+        });
+        let runner = Arc::new(Agent {
+            config: config.clone(),
+            prompts: prompts.clone(),
+            llm: memory_llm.clone(),
+            execution,
+            task_creation,
+            critic_llm: config.critic_min_score.map(|_| agent_llm("critic")),
+            memory: memory.clone(),
+            enricher: app::enricher(&config, memory_llm.as_ref()),
+            evictor: app::evictor(&config, memory_llm.as_ref()).await?,
+            result_prefix: "result".to_string(),
+            observers: self.observers.clone(),
+        });
+        let objective_runs = vec![ObjectiveRun::start(1, config.clone(), runner)?];
+        let report_llm = agent_llm("report");
+        let engine = Engine {
+            scheduling: config.objective_scheduling,
+            task_dedup: config.task_dedup_threshold.map(TaskDeduplicator::new),
+            config,
+            prompts,
+            usage,
+            prioritization,
+            report_llm,
+            llm: memory_llm,
+            synthesis: None,
+            memory,
+            replay: None,
+            read_stdin: false,
+            observers: self.observers,
+            dashboard,
+        };
+        // Nothing stops the run from outside, unlike Ctrl+C on the command
+        // line
+        let (_stop, shutdown) = watch::channel(0);
+        Ok(run_loop(&engine, objective_runs, shutdown).await)
+    }
+}
+
+// The callback of `CrustagiBuilder::on_event`, called with the events of
+// its run only
+struct EventCallback<F>(F);
+
+impl<F: Fn(&Event) + Send + Sync> Observer for EventCallback<F> {
+    fn on_event(&self, event: &Event) {
+        (self.0)(event)
+    }
+}
@@ -0,0 +1,236 @@
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::OnceLock;
+
+use crate::error::CrustagiError;
+use crate::memory::{Match, Metadata, StoredVector, VectorMetadata, VectorStore};
+
+const TENANT: &str = "default_tenant";
+const DATABASE: &str = "default_database";
+
+#[derive(Deserialize, Debug)]
+struct Collection {
+    id: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct GetResponse {
+    ids: Vec<String>,
+    embeddings: Option<Vec<Vec<f64>>>,
+    metadatas: Option<Vec<Option<Metadata>>>,
+}
+
+// Records fetched per `get` request when exporting
+const GET_PAGE_SIZE: usize = 256;
+
+// Results are nested one level per query embedding
+#[derive(Deserialize, Debug)]
+struct QueryResponse {
+    ids: Vec<Vec<String>>,
+    distances: Option<Vec<Vec<f64>>>,
+    metadatas: Option<Vec<Vec<Option<Metadata>>>>,
+}
+
+// ChromaDB collection used as a `VectorStore` via the v2 HTTP API
+pub struct ChromaStore {
+    url: String,
+    collection: String,
+    collection_id: OnceLock<String>,
+}
+
+impl ChromaStore {
+    pub fn new(url: &str, collection: &str) -> Self {
+        ChromaStore {
+            url: url.trim_end_matches('/').to_string(),
+            collection: collection.to_string(),
+            collection_id: OnceLock::new(),
+        }
+    }
+
+    fn collections_url(&self) -> String {
+        format!(
+            "{}/api/v2/tenants/{}/databases/{}/collections",
+            self.url, TENANT, DATABASE
+        )
+    }
+
+    // Request against the collection resolved by `create`
+    fn collection_request(&self, action: &str) -> Result<RequestBuilder, CrustagiError> {
+        let id = self
+            .collection_id
+            .get()
+            .ok_or("Chroma collection has not been created yet")?;
+        let url = format!("{}/{}/{}", self.collections_url(), id, action);
+        Ok(Client::new().post(url))
+    }
+}
+
+#[async_trait]
+impl VectorStore for ChromaStore {
+    #[tracing::instrument(skip_all, fields(collection = %self.collection))]
+    async fn create(&self) -> Result<(), CrustagiError> {
+        let body = json!({
+            "name": self.collection,
+            "get_or_create": true,
+            "metadata": { "hnsw:space": "cosine" }
+        });
+        let collection = Client::new()
+            .post(self.collections_url())
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Collection>()
+            .await?;
+        // A second `create` resolves the same collection, so keeping the first id is fine
+        let _ = self.collection_id.set(collection.id);
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all, fields(collection = %self.collection, id))]
+    async fn upsert(
+        &self,
+        id: &str,
+        vector: &[f64],
+        metadata: &VectorMetadata,
+    ) -> Result<usize, CrustagiError> {
+        let body = json!({
+            "ids": [id],
+            "embeddings": [vector],
+            "metadatas": [metadata]
+        });
+
+        tracing::debug!("Storing to Chroma...");
+
+        self.collection_request("upsert")?
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(1)
+    }
+
+    #[tracing::instrument(skip_all, fields(collection = %self.collection, top_k))]
+    async fn query(
+        &self,
+        vector: &[f64],
+        top_k: usize,
+        filter: Option<&Metadata>,
+    ) -> Result<Vec<Match>, CrustagiError> {
+        let mut body = json!({
+            "query_embeddings": [vector],
+            "n_results": top_k,
+            "include": ["metadatas", "distances"]
+        });
+        // Chroma only accepts a single field per `where` unless wrapped in `$and`
+        match filter {
+            Some(filter) if filter.len() == 1 => body["where"] = json!(filter),
+            Some(filter) if filter.len() > 1 => {
+                let clauses: Vec<_> = filter.iter().map(|(k, v)| json!({ k: v })).collect();
+                body["where"] = json!({ "$and": clauses });
+            }
+            _ => {}
+        }
+
+        tracing::debug!("Querying Chroma...");
+
+        let res = self
+            .collection_request("query")?
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<QueryResponse>()
+            .await?;
+
+        let ids = res.ids.into_iter().next().unwrap_or_default();
+        let distances = res
+            .distances
+            .and_then(|d| d.into_iter().next())
+            .unwrap_or_default();
+        let mut metadatas = res
+            .metadatas
+            .and_then(|m| m.into_iter().next())
+            .unwrap_or_default()
+            .into_iter();
+        Ok(ids
+            .into_iter()
+            .zip(distances)
+            .map(|(id, distance)| Match {
+                id,
+                // Cosine distance is 1 - similarity
+                score: 1.0 - distance,
+                metadata: metadatas
+                    .next()
+                    .flatten()
+                    .and_then(VectorMetadata::from_map),
+            })
+            .collect())
+    }
+
+    #[tracing::instrument(skip_all, fields(collection = %self.collection))]
+    async fn delete(&self, ids: &[String]) -> Result<(), CrustagiError> {
+        self.collection_request("delete")?
+            .json(&json!({ "ids": ids }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all, fields(collection = %self.collection))]
+    async fn dump(&self) -> Result<Vec<StoredVector>, CrustagiError> {
+        let mut vectors = Vec::new();
+        loop {
+            let body = json!({
+                "include": ["embeddings", "metadatas"],
+                "limit": GET_PAGE_SIZE,
+                "offset": vectors.len()
+            });
+            let res = self
+                .collection_request("get")?
+                .json(&body)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<GetResponse>()
+                .await?;
+            let count = res.ids.len();
+            let mut embeddings = res.embeddings.unwrap_or_default().into_iter();
+            let mut metadatas = res.metadatas.unwrap_or_default().into_iter();
+            for id in res.ids {
+                vectors.push(StoredVector {
+                    id,
+                    vector: embeddings.next().unwrap_or_default(),
+                    metadata: metadatas
+                        .next()
+                        .flatten()
+                        .and_then(VectorMetadata::from_map),
+                });
+            }
+            if count < GET_PAGE_SIZE {
+                return Ok(vectors);
+            }
+        }
+    }
+
+    // Deletes the collection's contents rather than the collection itself, so
+    // the id resolved by `create` stays valid
+    #[tracing::instrument(skip_all, fields(collection = %self.collection))]
+    async fn reset(&self) -> Result<(), CrustagiError> {
+        let res = self
+            .collection_request("get")?
+            .json(&json!({ "include": [] }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<GetResponse>()
+            .await?;
+        if res.ids.is_empty() {
+            return Ok(());
+        }
+        self.delete(&res.ids).await
+    }
+}
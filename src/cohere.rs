@@ -0,0 +1,242 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::error::CrustagiError;
+use crate::llm::{json_prompt, ChatMessage, LlmParams, LlmProvider};
+use crate::logging;
+use crate::usage;
+
+pub const COHERE_API_BASE: &str = "https://api.cohere.com/v2";
+pub const DEFAULT_COHERE_MODEL: &str = "command-r-plus";
+pub const DEFAULT_COHERE_EMBEDDING_MODEL: &str = "embed-english-v3.0";
+
+// Size of the vectors returned by Cohere's embedding models, None for
+// models not listed here
+fn known_embedding_dimension(model: &str) -> Option<usize> {
+    match model {
+        "embed-english-v3.0" | "embed-multilingual-v3.0" => Some(1024),
+        "embed-english-light-v3.0" | "embed-multilingual-light-v3.0" => Some(384),
+        "embed-v4.0" => Some(1536),
+        _ => None,
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatResponse {
+    message: AssistantMessage,
+    usage: Option<Meta>,
+}
+
+// Usage of chat responses and `meta` of embed responses
+#[derive(Deserialize, Debug)]
+struct Meta {
+    billed_units: Option<BilledUnits>,
+}
+
+#[derive(Deserialize, Debug)]
+struct BilledUnits {
+    #[serde(default)]
+    input_tokens: f64,
+    #[serde(default)]
+    output_tokens: f64,
+}
+
+#[derive(Deserialize, Debug)]
+struct AssistantMessage {
+    #[serde(default)]
+    content: Vec<ContentBlock>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ContentBlock {
+    text: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct EmbedResponse {
+    embeddings: Embeddings,
+    meta: Option<Meta>,
+}
+
+// Most texts Cohere accepts in one embed request
+const EMBED_BATCH: usize = 96;
+
+#[derive(Deserialize, Debug)]
+struct Embeddings {
+    float: Vec<Vec<f64>>,
+}
+
+// Cohere API client, using the v2 chat and embed endpoints
+#[derive(Clone)]
+pub struct CohereClient {
+    http: Client,
+    api_key: String,
+    model: String,
+    embedding_model: String,
+    params: LlmParams,
+}
+
+impl CohereClient {
+    pub fn new(http: Client, api_key: &str) -> Self {
+        CohereClient {
+            http,
+            api_key: api_key.to_string(),
+            model: DEFAULT_COHERE_MODEL.to_string(),
+            embedding_model: DEFAULT_COHERE_EMBEDDING_MODEL.to_string(),
+            params: LlmParams::default(),
+        }
+    }
+
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = model.to_string();
+        self
+    }
+
+    pub fn with_embedding_model(mut self, model: &str) -> Self {
+        self.embedding_model = model.to_string();
+        self
+    }
+
+    pub fn with_params(mut self, params: LlmParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    #[tracing::instrument(skip_all, fields(endpoint))]
+    async fn call(&self, endpoint: &str, body: Value) -> Result<Value, CrustagiError> {
+        tracing::debug!("Calling Cohere API...");
+        logging::request("Cohere", endpoint, &body);
+        let res = self
+            .http
+            .post(format!("{}/{}", COHERE_API_BASE, endpoint))
+            .bearer_auth(&self.api_key)
+            .json(&body)
+            .send()
+            .await?;
+        let status = res.status();
+        let body: Value = res.json().await?;
+        logging::response("Cohere", endpoint, &body);
+        if !status.is_success() {
+            let message = body["message"].as_str().unwrap_or_default();
+            return Err(format!("Cohere returned {}: {}", status, message).into());
+        }
+        Ok(body)
+    }
+
+    // Report the billed tokens to the `CostLayer` of the call
+    fn report_usage(&self, model: &str, meta: Option<&Meta>) {
+        if let Some(billed) = meta.and_then(|meta| meta.billed_units.as_ref()) {
+            usage::report(
+                model,
+                billed.input_tokens as usize,
+                billed.output_tokens as usize,
+            );
+        }
+    }
+
+    async fn generate(
+        &self,
+        messages: &[ChatMessage],
+        response_format: Option<Value>,
+    ) -> Result<String, CrustagiError> {
+        let mut body = json!({
+            "model": self.model,
+            "messages": self.params.with_system_prompt(messages)
+        });
+        let params = &self.params;
+        if let Some(temperature) = params.temperature {
+            body["temperature"] = json!(temperature);
+        }
+        if let Some(max_tokens) = params.max_tokens {
+            body["max_tokens"] = json!(max_tokens);
+        }
+        if let Some(top_p) = params.top_p {
+            body["p"] = json!(top_p);
+        }
+        if let Some(presence_penalty) = params.presence_penalty {
+            body["presence_penalty"] = json!(presence_penalty);
+        }
+        if let Some(frequency_penalty) = params.frequency_penalty {
+            body["frequency_penalty"] = json!(frequency_penalty);
+        }
+        if !params.stop.is_empty() {
+            body["stop_sequences"] = json!(params.stop);
+        }
+        if let Some(seed) = params.seed {
+            body["seed"] = json!(seed);
+        }
+        if let Some(response_format) = response_format {
+            body["response_format"] = response_format;
+        }
+        let res: ChatResponse = serde_json::from_value(self.call("chat", body).await?)?;
+        self.report_usage(&self.model, res.usage.as_ref());
+        Ok(res
+            .message
+            .content
+            .into_iter()
+            .filter_map(|block| block.text)
+            .collect())
+    }
+}
+
+#[async_trait]
+impl LlmProvider for CohereClient {
+    async fn complete_json(&self, prompt: &str, schema: &Value) -> Result<String, CrustagiError> {
+        let messages = [ChatMessage::user(&json_prompt(prompt, schema))];
+        let response_format = json!({ "type": "json_object", "json_schema": schema });
+        self.generate(&messages, Some(response_format)).await
+    }
+
+    async fn chat(&self, messages: &[ChatMessage]) -> Result<String, CrustagiError> {
+        self.generate(messages, None).await
+    }
+
+    // Both stored results and queries are embedded as documents, since the
+    // trait doesn't tell them apart
+    async fn embed(&self, text: &str) -> Result<Vec<f64>, CrustagiError> {
+        self.embed_batch(&[text.to_string()])
+            .await?
+            .pop()
+            .ok_or_else(|| "Cohere returned no embedding".into())
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f64>>, CrustagiError> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(EMBED_BATCH) {
+            let body = json!({
+                "model": self.embedding_model,
+                "texts": batch,
+                "input_type": "search_document",
+                "embedding_types": ["float"]
+            });
+            let res: EmbedResponse = serde_json::from_value(self.call("embed", body).await?)?;
+            self.report_usage(&self.embedding_model, res.meta.as_ref());
+            if res.embeddings.float.len() != batch.len() {
+                return Err("Cohere returned fewer embeddings than texts".into());
+            }
+            embeddings.extend(res.embeddings.float);
+        }
+        Ok(embeddings)
+    }
+
+    fn model(&self) -> String {
+        self.model.clone()
+    }
+
+    fn prompt_budget(&self) -> usize {
+        self.params.prompt_budget(&self.model)
+    }
+
+    fn embedding_model(&self) -> &str {
+        &self.embedding_model
+    }
+
+    async fn embedding_dimension(&self) -> Result<usize, CrustagiError> {
+        match known_embedding_dimension(&self.embedding_model) {
+            Some(dimension) => Ok(dimension),
+            None => Ok(self.embed("dimension probe").await?.len()),
+        }
+    }
+}
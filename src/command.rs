@@ -0,0 +1,52 @@
+use clap::{Args, Subcommand, ValueEnum};
+
+// Commands of crustagi, besides the run started without one. The command
+// line parses them with `clap`, a program embedding the loop passes them to
+// `run` directly.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Start a new run, the default without a command
+    Run(RunArgs),
+    /// Continue the run saved in STATE_PATH
+    Resume,
+    /// Export, import or reset the vector store
+    Memory {
+        #[command(subcommand)]
+        action: MemoryAction,
+    },
+    /// Write the report of the saved run without running any tasks
+    Report {
+        /// markdown of the saved run, or a page made from its events file
+        #[arg(long, value_enum, default_value_t = ReportFormat::Markdown)]
+        format: ReportFormat,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+#[derive(Debug, Clone, Default, Args)]
+pub struct RunArgs {
+    /// Delete the stored results before the run
+    #[arg(long)]
+    pub reset_memory: bool,
+    /// Answer the LLM calls from a recorded run log instead of the API
+    #[arg(long, value_name = "RUN_LOG", conflicts_with = "dry_run")]
+    pub replay: Option<String>,
+    /// Use a mock provider and an in-memory store, without credentials
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MemoryAction {
+    /// Write all stored vectors to a JSON Lines file
+    Export { path: String },
+    /// Add the vectors of a JSON Lines file written by export
+    Import { path: String },
+    /// Delete all stored vectors
+    Reset,
+}
@@ -0,0 +1,404 @@
+use std::env;
+use std::time::Duration;
+
+use crate::cohere::{DEFAULT_COHERE_EMBEDDING_MODEL, DEFAULT_COHERE_MODEL};
+use crate::diagnostics;
+use crate::error::CrustagiError;
+use crate::fallback::FallbackModel;
+use crate::gemini::{DEFAULT_GEMINI_EMBEDDING_MODEL, DEFAULT_GEMINI_MODEL};
+use crate::http;
+use crate::llm::LlmParams;
+use crate::objectives::Scheduling;
+use crate::openai::{self, DEFAULT_API_BASE, DEFAULT_EMBEDDING_MODEL};
+use crate::pinecone;
+use crate::task::unix_time;
+use crate::usage::ModelPrice;
+use crate::webhooks;
+
+// Settings of a run, read from the environment by `run`
+#[derive(Clone)]
+pub struct Config {
+    pub llm_provider: String,
+    pub openai_api_key: String,
+    pub openai_api_base: String,
+    pub openai_api_mode: Option<String>,
+    pub openai_api_model: String,
+    pub fallback_models: Vec<FallbackModel>,
+    pub openai_max_attempts: u32,
+    pub openai_timeout_secs: u64,
+    pub gemini_api_key: String,
+    pub gemini_model: String,
+    pub gemini_embedding_model: String,
+    pub execution_model: Option<String>,
+    pub creation_model: Option<String>,
+    pub prioritization_model: Option<String>,
+    pub critic_model: Option<String>,
+    pub planner_model: Option<String>,
+    pub execution_params: LlmParams,
+    pub creation_params: LlmParams,
+    pub prioritization_params: LlmParams,
+    pub critic_params: LlmParams,
+    pub planner_params: LlmParams,
+    // Results scoring below this get one retry, None without a critic
+    pub critic_min_score: Option<f64>,
+    pub memory_backend: String,
+    pub pinecone_api_key: String,
+    pub pinecone_region: String,
+    pub pinecone_project_id: String,
+    pub pinecone_index_name: String,
+    pub pinecone_namespace: String,
+    pub pinecone_serverless_cloud: Option<String>,
+    pub pinecone_pod_type: String,
+    pub pinecone_pods: usize,
+    pub pinecone_replicas: usize,
+    pub pinecone_max_attempts: u32,
+    pub pinecone_timeout_secs: u64,
+    pub embedding_model: String,
+    pub embedding_cache_path: Option<String>,
+    pub index_dimension: Option<usize>,
+    pub index_metric: String,
+    pub hybrid_alpha: Option<f64>,
+    pub qdrant_url: String,
+    pub qdrant_api_key: Option<String>,
+    pub qdrant_collection: String,
+    pub weaviate_url: String,
+    pub weaviate_api_key: Option<String>,
+    pub weaviate_class: String,
+    pub chroma_url: String,
+    pub chroma_collection: String,
+    pub sqlite_path: String,
+    pub local_memory_dir: String,
+    pub database_url: String,
+    pub pgvector_table: String,
+    pub milvus_url: String,
+    pub milvus_token: Option<String>,
+    pub milvus_collection: String,
+    pub initial_task: String,
+    pub objective_scheduling: Scheduling,
+    pub objective: String,
+    // Milestone of the objective being worked on, with PLAN_MILESTONES
+    pub milestone: Option<String>,
+    // Break the objective into at most this many milestones, None to work
+    // on it as a whole
+    pub max_milestones: Option<usize>,
+    pub plan_path: String,
+    // Markdown report written when the run stops
+    pub report_path: String,
+    // Run log every LLM call is recorded to
+    pub llm_log_path: String,
+    // Events of the run, one JSON line each
+    pub events_path: String,
+    pub webhooks: Vec<webhooks::Webhook>,
+    pub webhook_events: Vec<String>,
+    // Run log whose answers replace the LLM calls
+    pub replay: Option<String>,
+    // Pin the sampling of all agents and write a manifest of the run
+    pub deterministic: bool,
+    pub seed: Option<u64>,
+    pub manifest_path: String,
+    pub sanitize_context: bool,
+    // Regular expressions whose matches are replaced before anything is sent
+    // to the LLM provider
+    pub redact_patterns: Vec<String>,
+    pub reset_memory: bool,
+    pub export_memory: Option<String>,
+    pub import_memory: Option<String>,
+    pub skip_health_check: bool,
+    // Show the live dashboard instead of the scrolling output
+    pub tui: bool,
+    pub state_path: String,
+    pub log_dir: Option<String>,
+    // Directory of a markdown file per completed task
+    pub tasks_dir: Option<String>,
+    pub prompts_dir: String,
+    pub execution_history: usize,
+    pub task_timeout_secs: Option<u64>,
+    pub max_concurrent_tasks: usize,
+    // Pause between iterations
+    pub task_delay: Duration,
+    pub max_requests_per_minute: Option<u32>,
+    pub max_task_failures: u32,
+    pub task_retry_delay_secs: u64,
+    pub resume: bool,
+    pub max_cost_usd: Option<f64>,
+    pub model_prices: Vec<ModelPrice>,
+    pub max_tokens_total: Option<usize>,
+    pub max_iterations: Option<usize>,
+    pub max_tasks: Option<usize>,
+    pub dedup_threshold: Option<f64>,
+    // Results longer than this many tokens are stored in chunks
+    pub chunk_tokens: Option<usize>,
+    pub chunk_overlap: usize,
+    pub task_dedup_threshold: Option<f64>,
+    pub context_token_budget: Option<usize>,
+    pub context_window: Option<usize>,
+    // Rounds of tool calls the execution agent may make for a task before it
+    // has to answer
+    pub max_tool_steps: usize,
+    // The search API of the web_search tool, none without the tool
+    pub web_search: Option<String>,
+    pub web_search_api_key: String,
+    pub web_search_api_base: Option<String>,
+    pub web_search_results: usize,
+    // Whether the execution agent may read web pages with web_fetch, and the
+    // parts of a page it is shown
+    pub web_fetch: bool,
+    pub web_fetch_chunk_tokens: usize,
+    pub web_fetch_chunks: usize,
+    // Whether web_fetch may read pages on loopback, private and link-local
+    // addresses
+    pub web_fetch_allow_private: bool,
+    // Directory the file tools work in, none without them, and whether they
+    // may only read it
+    pub sandbox_dir: Option<String>,
+    pub sandbox_read_only: bool,
+    pub rerank: Option<String>,
+    pub rerank_candidates: usize,
+    pub cohere_api_key: String,
+    pub cohere_rerank_model: String,
+    pub cohere_model: String,
+    pub cohere_embedding_model: String,
+    pub max_memory_entries: Option<usize>,
+    pub eviction_policy: String,
+    pub http_timeout_secs: u64,
+    pub http_proxy: Option<String>,
+}
+
+impl Config {
+    // The objective as the agents' prompts show it, with the milestone
+    // being worked on
+    pub fn prompt_objective(&self) -> String {
+        match &self.milestone {
+            Some(milestone) => format!("{} (current milestone: {})", self.objective, milestone),
+            None => self.objective.clone(),
+        }
+    }
+
+    // The HTTP client of the API calls, with HTTP_TIMEOUT_SECS and
+    // PROXY_URL
+    pub fn http_client(&self) -> Result<reqwest::Client, CrustagiError> {
+        http::build_client(
+            Duration::from_secs(self.http_timeout_secs),
+            self.http_proxy.as_deref(),
+            true,
+        )
+    }
+}
+
+// The settings of a run with no environment variables set, apart from the
+// required ones, which are empty, and the memory backend, which is kept in
+// memory. `CrustagiBuilder` starts from these.
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            llm_provider: "openai".to_string(),
+            openai_api_key: String::new(),
+            openai_api_base: DEFAULT_API_BASE.to_string(),
+            openai_api_mode: None,
+            openai_api_model: String::new(),
+            fallback_models: Vec::new(),
+            openai_max_attempts: openai::DEFAULT_MAX_ATTEMPTS,
+            openai_timeout_secs: 120,
+            gemini_api_key: String::new(),
+            gemini_model: DEFAULT_GEMINI_MODEL.to_string(),
+            gemini_embedding_model: DEFAULT_GEMINI_EMBEDDING_MODEL.to_string(),
+            execution_model: None,
+            creation_model: None,
+            prioritization_model: None,
+            critic_model: None,
+            planner_model: None,
+            execution_params: LlmParams::default(),
+            creation_params: LlmParams::default(),
+            prioritization_params: LlmParams::default(),
+            critic_params: LlmParams::default(),
+            planner_params: LlmParams::default(),
+            critic_min_score: None,
+            memory_backend: "memory".to_string(),
+            pinecone_api_key: String::new(),
+            pinecone_region: String::new(),
+            pinecone_project_id: String::new(),
+            pinecone_index_name: String::new(),
+            pinecone_namespace: String::new(),
+            pinecone_serverless_cloud: None,
+            pinecone_pod_type: "p1.x1".to_string(),
+            pinecone_pods: 1,
+            pinecone_replicas: 1,
+            pinecone_max_attempts: pinecone::DEFAULT_MAX_ATTEMPTS,
+            pinecone_timeout_secs: 120,
+            embedding_model: DEFAULT_EMBEDDING_MODEL.to_string(),
+            embedding_cache_path: None,
+            index_dimension: None,
+            index_metric: "cosine".to_string(),
+            hybrid_alpha: None,
+            qdrant_url: "http://localhost:6333".to_string(),
+            qdrant_api_key: None,
+            qdrant_collection: "crustagi".to_string(),
+            weaviate_url: "http://localhost:8080".to_string(),
+            weaviate_api_key: None,
+            weaviate_class: "CrustagiResult".to_string(),
+            chroma_url: "http://localhost:8000".to_string(),
+            chroma_collection: "crustagi".to_string(),
+            sqlite_path: "crustagi.db".to_string(),
+            local_memory_dir: "crustagi-memory".to_string(),
+            database_url: String::new(),
+            pgvector_table: "crustagi_memory".to_string(),
+            milvus_url: "http://localhost:19530".to_string(),
+            milvus_token: None,
+            milvus_collection: "crustagi".to_string(),
+            initial_task: String::new(),
+            objective_scheduling: Scheduling::RoundRobin,
+            objective: String::new(),
+            milestone: None,
+            max_milestones: None,
+            plan_path: "plan.json".to_string(),
+            report_path: format!("reports/run_{}.md", unix_time()),
+            llm_log_path: "llm_log.jsonl".to_string(),
+            events_path: "events.jsonl".to_string(),
+            webhooks: Vec::new(),
+            webhook_events: webhooks::WEBHOOK_EVENTS
+                .iter()
+                .map(|event| event.to_string())
+                .collect(),
+            replay: None,
+            deterministic: false,
+            seed: None,
+            manifest_path: "manifest.json".to_string(),
+            sanitize_context: false,
+            redact_patterns: Vec::new(),
+            reset_memory: false,
+            export_memory: None,
+            import_memory: None,
+            skip_health_check: false,
+            tui: false,
+            state_path: "state.json".to_string(),
+            log_dir: None,
+            tasks_dir: None,
+            prompts_dir: "prompts".to_string(),
+            execution_history: 0,
+            task_timeout_secs: None,
+            max_concurrent_tasks: 1,
+            task_delay: Duration::from_secs(1),
+            max_requests_per_minute: None,
+            max_task_failures: 3,
+            task_retry_delay_secs: 10,
+            resume: false,
+            max_cost_usd: None,
+            model_prices: Vec::new(),
+            max_tokens_total: None,
+            max_iterations: None,
+            max_tasks: None,
+            dedup_threshold: None,
+            chunk_tokens: None,
+            chunk_overlap: 50,
+            task_dedup_threshold: None,
+            context_token_budget: None,
+            context_window: None,
+            max_tool_steps: 5,
+            web_search: None,
+            web_search_api_key: String::new(),
+            web_search_api_base: None,
+            web_search_results: 5,
+            web_fetch: false,
+            web_fetch_chunk_tokens: 500,
+            web_fetch_chunks: 4,
+            web_fetch_allow_private: false,
+            sandbox_dir: None,
+            sandbox_read_only: false,
+            rerank: None,
+            rerank_candidates: 20,
+            cohere_api_key: String::new(),
+            cohere_rerank_model: "rerank-v3.5".to_string(),
+            cohere_model: DEFAULT_COHERE_MODEL.to_string(),
+            cohere_embedding_model: DEFAULT_COHERE_EMBEDDING_MODEL.to_string(),
+            max_memory_entries: None,
+            eviction_policy: "oldest".to_string(),
+            http_timeout_secs: 120,
+            http_proxy: None,
+        }
+    }
+}
+
+// Seed of a deterministic run without SEED
+pub const DEFAULT_SEED: u64 = 0;
+
+// Objective and first task of a dry run without OBJECTIVE and INITIAL_TASK
+pub const DRY_RUN_OBJECTIVE: &str = "Try out the task loop";
+pub const DRY_RUN_INITIAL_TASK: &str = "Develop a task list";
+
+// Load a required environment variable. A missing one is reported along
+// with the setting that needs it, and an empty value stands in for it until
+// the configuration is checked.
+pub fn load_env_var(name: &str, needed_by: &str) -> String {
+    env::var(name).unwrap_or_else(|_| {
+        diagnostics::missing(name, needed_by);
+        String::new()
+    })
+}
+
+// Load an optional environment variable with a default value
+pub fn load_env_var_or(name: &str, default: &str) -> String {
+    env::var(name).unwrap_or_else(|_| default.to_string())
+}
+
+// Load an optional numeric environment variable with a default value
+pub fn load_env_number<T: std::str::FromStr>(name: &str, default: T) -> T {
+    match env::var(name) {
+        Ok(value) => value.parse().unwrap_or_else(|_| {
+            not_a_number(name, &value);
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+// Load an optional numeric environment variable without a default
+pub fn load_optional_env_number<T: std::str::FromStr>(name: &str) -> Option<T> {
+    env::var(name).ok().and_then(|value| {
+        value
+            .parse()
+            .inspect_err(|_| not_a_number(name, &value))
+            .ok()
+    })
+}
+
+pub fn not_a_number(name: &str, value: &str) {
+    diagnostics::report(format!("{} must be a number, got \"{}\"", name, value));
+}
+
+// Load an optional boolean environment variable, defaulting to false
+pub fn load_env_flag(name: &str) -> bool {
+    env::var(name).is_ok_and(|v| v.eq_ignore_ascii_case("true"))
+}
+
+// Sampling parameters of an agent, e.g. `EXECUTION_TEMPERATURE`, falling back
+// to the unprefixed variable, e.g. `TEMPERATURE`, for all agents
+pub fn load_llm_params(agent: &str) -> LlmParams {
+    fn load<T: std::str::FromStr>(agent: &str, name: &str) -> Option<T> {
+        load_optional_env_number(&format!("{}_{}", agent, name))
+            .or_else(|| load_optional_env_number(name))
+    }
+    // Comma-separated, with `\n` for a newline
+    let stop = env::var(format!("{}_STOP", agent))
+        .or_else(|_| env::var("STOP"))
+        .map(|stop| {
+            stop.split(',')
+                .filter(|s| !s.is_empty())
+                .map(|s| s.replace("\\n", "\n"))
+                .collect()
+        })
+        .unwrap_or_default();
+    LlmParams {
+        system_prompt: env::var(format!("{}_SYSTEM_PROMPT", agent))
+            .or_else(|_| env::var("SYSTEM_PROMPT"))
+            .ok(),
+        temperature: load(agent, "TEMPERATURE"),
+        max_tokens: load(agent, "MAX_TOKENS"),
+        top_p: load(agent, "TOP_P"),
+        presence_penalty: load(agent, "PRESENCE_PENALTY"),
+        frequency_penalty: load(agent, "FREQUENCY_PENALTY"),
+        stop,
+        seed: load(agent, "SEED"),
+        // CONTEXT_WINDOW is applied to every agent's provider
+        context_window: None,
+    }
+}
@@ -0,0 +1,93 @@
+use std::env;
+use std::fs;
+use std::io;
+use toml::{Table, Value};
+
+use crate::diagnostics;
+
+pub const DEFAULT_CONFIG_FILE: &str = "crustagi.toml";
+
+// Put the settings of the config file at `path` into the environment. Its
+// top-level keys are settings named after their environment variable, and
+// only fill in those the environment and `.env` leave unset. The tables
+// under `profile` are named setups, e.g. `[profile.local]`; the settings of
+// the selected one override the environment. A missing file is fine unless
+// it was asked for. Must be called before any threads are started.
+pub fn apply(path: &str, required: bool, profile: Option<&str>) {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound && !required && profile.is_none() => {
+            return
+        }
+        Err(err) => {
+            diagnostics::report(format!("The config file {} can't be read: {}", path, err));
+            return;
+        }
+    };
+    let mut table: Table = match text.parse() {
+        Ok(table) => table,
+        Err(err) => {
+            diagnostics::report(format!("The config file {} is invalid: {}", path, err));
+            return;
+        }
+    };
+    let profiles = match table.remove("profile") {
+        Some(Value::Table(profiles)) => profiles,
+        Some(_) => {
+            diagnostics::report(format!(
+                "profile in {} must be a table of profiles, e.g. [profile.local]",
+                path
+            ));
+            Table::new()
+        }
+        None => Table::new(),
+    };
+    for (name, value) in settings(path, &table) {
+        if env::var_os(&name).is_none() {
+            env::set_var(name, value);
+        }
+    }
+    let Some(profile) = profile else {
+        return;
+    };
+    match profiles.get(profile) {
+        Some(Value::Table(profile)) => {
+            for (name, value) in settings(path, profile) {
+                env::set_var(name, value);
+            }
+        }
+        Some(_) => diagnostics::report(format!(
+            "profile.{} in {} must be a table of settings",
+            profile, path
+        )),
+        None if profiles.is_empty() => diagnostics::report(format!(
+            "Profile \"{}\" is not in {}, which has no profiles",
+            profile, path
+        )),
+        None => {
+            let names: Vec<&str> = profiles.keys().map(String::as_str).collect();
+            diagnostics::check_one_of("The profile", profile, &names);
+        }
+    }
+}
+
+// Settings of a table as environment variables. Strings, numbers and
+// booleans are taken as they are written, other values are reported.
+fn settings(path: &str, table: &Table) -> Vec<(String, String)> {
+    let mut settings = Vec::new();
+    for (name, value) in table {
+        let value = match value {
+            Value::String(value) => value.clone(),
+            Value::Integer(_) | Value::Float(_) | Value::Boolean(_) => value.to_string(),
+            _ => {
+                diagnostics::report(format!(
+                    "{} in {} must be a string, number or boolean",
+                    name, path
+                ));
+                continue;
+            }
+        };
+        settings.push((name.clone(), value));
+    }
+    settings
+}
@@ -0,0 +1,54 @@
+use std::sync::Mutex;
+
+use crate::error::CrustagiError;
+
+// Problems found while loading the configuration. They are collected instead
+// of failing on the first one, so that a single error lists everything that
+// has to be fixed.
+static PROBLEMS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Report a problem with the configuration, which fails the next `check`
+pub fn report(problem: String) {
+    PROBLEMS.lock().unwrap().push(problem);
+}
+
+/// A setting that works, but likely not as intended. Logged right away, as
+/// it doesn't stop the run.
+pub fn warn(warning: String) {
+    tracing::warn!("{}", warning);
+}
+
+/// A setting that is not set although it is needed, and what needs it
+pub fn missing(name: &str, needed_by: &str) {
+    report(format!("{} is missing, {} needs it", name, needed_by));
+}
+
+/// Report a setting whose value isn't one of `expected`
+pub fn check_one_of(name: &str, value: &str, expected: &[&str]) {
+    if !expected.contains(&value) {
+        report(format!(
+            "{} is \"{}\", expected one of: {}",
+            name,
+            value,
+            expected.join(", ")
+        ));
+    }
+}
+
+/// Fail with every problem found, if there are any
+pub fn check() -> Result<(), CrustagiError> {
+    let problems = std::mem::take(&mut *PROBLEMS.lock().unwrap());
+    if problems.is_empty() {
+        return Ok(());
+    }
+    let mut message = format!(
+        "The configuration has {} problem{}:",
+        problems.len(),
+        if problems.len() == 1 { "" } else { "s" }
+    );
+    for problem in &problems {
+        message.push_str(&format!("\n  - {}", problem));
+    }
+    message.push_str("\n\nSet them in the environment, in .env, in crustagi.toml or with --set NAME=VALUE. The README describes every setting.");
+    Err(CrustagiError::Config(message))
+}
@@ -0,0 +1,155 @@
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+
+use crate::error::CrustagiError;
+use crate::llm::{ChatMessage, LlmProvider, ToolSpec};
+use crate::sqlite::{decode_vector, encode_vector};
+
+// Embeddings of previously seen texts, kept in a SQLite file so repeated
+// texts (the objective, identical results) are only embedded once, also
+// across runs
+pub struct EmbeddingCache {
+    conn: Mutex<Connection>,
+}
+
+impl EmbeddingCache {
+    pub fn open(path: &str) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS embeddings (
+                model TEXT NOT NULL,
+                text TEXT NOT NULL,
+                vector BLOB NOT NULL,
+                PRIMARY KEY (model, text)
+            )",
+            [],
+        )?;
+        Ok(EmbeddingCache {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    pub fn get(&self, model: &str, text: &str) -> rusqlite::Result<Option<Vec<f64>>> {
+        let blob: Option<Vec<u8>> = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT vector FROM embeddings WHERE model = ?1 AND text = ?2",
+                params![model, text],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(blob.map(|blob| decode_vector(&blob)))
+    }
+
+    pub fn put(&self, model: &str, text: &str, vector: &[f64]) -> rusqlite::Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO embeddings (model, text, vector) VALUES (?1, ?2, ?3)",
+            params![model, text, encode_vector(vector)],
+        )?;
+        Ok(())
+    }
+}
+
+// Provider wrapper answering `embed` from the cache when possible
+pub struct CachedEmbeddings {
+    inner: Arc<dyn LlmProvider>,
+    cache: EmbeddingCache,
+}
+
+impl CachedEmbeddings {
+    pub fn new(inner: Arc<dyn LlmProvider>, cache: EmbeddingCache) -> Self {
+        CachedEmbeddings { inner, cache }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for CachedEmbeddings {
+    async fn complete(&self, prompt: &str) -> Result<String, CrustagiError> {
+        self.inner.complete(prompt).await
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut (dyn for<'c> FnMut(&'c str) + Send),
+    ) -> Result<String, CrustagiError> {
+        self.inner.chat_stream(messages, on_chunk).await
+    }
+
+    async fn complete_json(&self, prompt: &str, schema: &Value) -> Result<String, CrustagiError> {
+        self.inner.complete_json(prompt, schema).await
+    }
+
+    async fn chat(&self, messages: &[ChatMessage]) -> Result<String, CrustagiError> {
+        self.inner.chat(messages).await
+    }
+
+    async fn chat_with_tools(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+    ) -> Result<ChatMessage, CrustagiError> {
+        self.inner.chat_with_tools(messages, tools).await
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f64>, CrustagiError> {
+        let model = self.inner.embedding_model();
+        if let Some(embedding) = self.cache.get(model, text)? {
+            return Ok(embedding);
+        }
+        let embedding = self.inner.embed(text).await?;
+        self.cache.put(model, text, &embedding)?;
+        Ok(embedding)
+    }
+
+    // Only the texts missing from the cache are sent, in one batch
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f64>>, CrustagiError> {
+        let model = self.inner.embedding_model();
+        let mut embeddings = Vec::with_capacity(texts.len());
+        let mut missing = Vec::new();
+        for (i, text) in texts.iter().enumerate() {
+            let embedding = self.cache.get(model, text)?;
+            if embedding.is_none() {
+                missing.push(i);
+            }
+            embeddings.push(embedding);
+        }
+        if !missing.is_empty() {
+            let missing_texts: Vec<String> = missing.iter().map(|&i| texts[i].clone()).collect();
+            let fetched = self.inner.embed_batch(&missing_texts).await?;
+            if fetched.len() != missing.len() {
+                return Err(format!(
+                    "Got {} embeddings for {} texts",
+                    fetched.len(),
+                    missing.len()
+                )
+                .into());
+            }
+            for (i, embedding) in missing.into_iter().zip(fetched) {
+                self.cache.put(model, &texts[i], &embedding)?;
+                embeddings[i] = Some(embedding);
+            }
+        }
+        Ok(embeddings.into_iter().flatten().collect())
+    }
+
+    fn model(&self) -> String {
+        self.inner.model()
+    }
+
+    fn prompt_budget(&self) -> usize {
+        self.inner.prompt_budget()
+    }
+
+    fn embedding_model(&self) -> &str {
+        self.inner.embedding_model()
+    }
+
+    async fn embedding_dimension(&self) -> Result<usize, CrustagiError> {
+        self.inner.embedding_dimension().await
+    }
+}
@@ -0,0 +1,1428 @@
+// The task loop: the objectives' task lists, the agent working through them
+// and what runs around it, from the retries of failed tasks to the report
+// written when the run stops.
+
+use colored::Color;
+use std::collections::HashMap;
+use std::fmt;
+use std::panic;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinSet;
+use tokio::time::{self, sleep};
+use tracing::Instrument;
+
+use crate::agents::{
+    critic_agent, report_agent, synthesis_agent, ExecutionAgent, PrioritizationAgent,
+    TaskCreationAgent,
+};
+use crate::config::Config;
+use crate::enrich::{Enricher, Record};
+use crate::error::CrustagiError;
+use crate::events::Event;
+use crate::inbox;
+use crate::llm::{ChatMessage, LlmProvider};
+use crate::logging;
+use crate::memory::{self, Evictor, StoredVector, VectorMetadata, VectorStore};
+use crate::objectives::Scheduling;
+use crate::observer::Observer;
+use crate::progress::Progress;
+use crate::prompts::Prompts;
+use crate::replay::Replay;
+use crate::report;
+use crate::run;
+use crate::sanitize;
+use crate::state;
+use crate::task::{unix_time, Task, TaskStatus};
+use crate::task_dedup::TaskDeduplicator;
+use crate::task_graph::{TaskGraph, TaskResult};
+use crate::task_ids::TaskIdAllocator;
+use crate::tui::{self, Dashboard};
+use crate::usage::UsageTracker;
+
+// Add a task to the list
+pub fn add_task(task: Task, task_list: &mut TaskGraph, observers: &[Arc<dyn Observer>]) {
+    tracing::info!("Adding task: {}...", task.task_name);
+    record_event(
+        observers,
+        Event::TaskCreated {
+            task_id: task.task_id,
+            task_name: &task.task_name,
+            depends_on: &task.depends_on,
+        },
+    );
+    task_list.add(task);
+}
+
+// Pass `event` to the observers of the run, e.g. the events file and the
+// webhooks of the command line
+fn record_event(observers: &[Arc<dyn Observer>], event: Event) {
+    for observer in observers {
+        observer.on_event(&event);
+    }
+}
+
+/// Runs the tasks of an objective: what running a task takes. The tasks of
+/// a batch share it while each runs on its own tokio task.
+pub struct Agent {
+    pub config: Arc<Config>,
+    pub prompts: Arc<Prompts>,
+    // Embeddings of results
+    pub llm: Arc<dyn LlmProvider>,
+    pub execution: Arc<dyn ExecutionAgent>,
+    pub task_creation: Arc<dyn TaskCreationAgent>,
+    // Only with CRITIC_MIN_SCORE set
+    pub critic_llm: Option<Arc<dyn LlmProvider>>,
+    pub memory: Arc<dyn VectorStore>,
+    // Turns each result into the records stored for it
+    pub enricher: Arc<dyn Enricher>,
+    // Only with MAX_MEMORY_ENTRIES set, shared by the milestones of an
+    // objective
+    pub evictor: Option<Arc<Evictor>>,
+    // Start of the ids results are stored under, telling apart the results
+    // of objectives and milestones sharing a collection
+    pub result_prefix: String,
+    // Passed the events of the tasks, the observers of the engine
+    pub observers: Vec<Arc<dyn Observer>>,
+}
+
+/// What running a task produced
+pub struct TaskOutcome {
+    pub result: String,
+    // Id of the stored result, None if it was a near duplicate
+    pub result_id: Option<String>,
+    pub new_tasks: Vec<Task>,
+}
+
+impl Agent {
+    /// Execute `task`, store its result and propose new tasks based on it,
+    /// numbered from `next_task_id`. An empty result counts as an error, like
+    /// a failed model or vector store call.
+    pub async fn run(
+        &self,
+        task: &Task,
+        dependencies: &[TaskResult],
+        history: &[ChatMessage],
+        pending: &[Task],
+        next_task_id: i32,
+    ) -> Result<TaskOutcome, CrustagiError> {
+        let config = self.config.as_ref();
+        let memory = self.memory.as_ref();
+        let objective = config.prompt_objective();
+        record_event(
+            &self.observers,
+            Event::TaskStarted {
+                task_id: task.task_id,
+                task_name: &task.task_name,
+            },
+        );
+        tracing::info!(
+            task_id = task.task_id,
+            "Executing task: {}...",
+            task.task_name
+        );
+        let mut execution = self
+            .execution
+            .execute(&objective, task, dependencies, None, history)
+            .await?;
+        ensure_result(&execution.result)?;
+
+        // The critic reviews the result before it is stored. A result
+        // scoring below CRITIC_MIN_SCORE gets one more attempt, with the
+        // critique added to the prompt.
+        if let (Some(critic_llm), Some(min_score)) = (&self.critic_llm, config.critic_min_score) {
+            let review = critic_agent(
+                critic_llm.as_ref(),
+                &self.prompts,
+                &objective,
+                task,
+                &execution.result,
+            )
+            .await?;
+            if let Some(review) = review {
+                tracing::info!(
+                    task_id = task.task_id,
+                    score = review.score,
+                    "Critic score for task {}: {}/10",
+                    task.task_id,
+                    review.score
+                );
+                if review.score < min_score {
+                    tracing::info!("Retrying task {}: {}", task.task_id, review.critique);
+                    execution = self
+                        .execution
+                        .execute(
+                            &objective,
+                            task,
+                            dependencies,
+                            Some(&review.critique),
+                            history,
+                        )
+                        .await?;
+                    ensure_result(&execution.result)?;
+                }
+            }
+        }
+
+        if let Some(tasks_dir) = &config.tasks_dir {
+            if let Err(err) = run::write_task_file(
+                tasks_dir,
+                task.task_id,
+                &task.task_name,
+                &execution.prompt,
+                &execution.context,
+                &execution.result,
+            ) {
+                tracing::warn!("Failed to write the task file in {}: {}", tasks_dir, err);
+            }
+        }
+        let result = execution.result;
+        if let Some(log_dir) = &config.log_dir {
+            if let Err(err) = run::log_task(log_dir, task.task_id, &task.task_name, &result) {
+                tracing::warn!("Failed to write the task log in {}: {}", log_dir, err);
+            }
+        }
+
+        // Step 2: Enrich result and store in memory. A result enriched into
+        // several records is stored under numbered ids.
+        let result_id = format!("{}_{}", self.result_prefix, task.task_id);
+        let record = Record {
+            text: result.clone(),
+            metadata: VectorMetadata {
+                task: task.task_name.clone(),
+                result: result.clone(),
+                task_id: task.task_id,
+                objective: config.objective.clone(),
+                chunk: None,
+            },
+        };
+        let records = self
+            .enricher
+            .enrich(record)
+            .await
+            .map_err(|err| CrustagiError::memory("Enriching the result failed", err))?;
+        let texts: Vec<String> = records.iter().map(|record| record.text.clone()).collect();
+        let vectors = if texts.is_empty() {
+            Vec::new()
+        } else {
+            self.llm
+                .embed_batch(&texts)
+                .await
+                .map_err(|err| CrustagiError::llm("Embedding the result failed", err))?
+        };
+        let several_records = records.len() > 1;
+        let mut stored = Vec::new();
+        for (index, (record, vector)) in records.iter().zip(&vectors).enumerate() {
+            let duplicate = match config.dedup_threshold {
+                Some(threshold) => memory::find_near_duplicate(
+                    memory,
+                    vector,
+                    Some(&memory::objective_filter(&config.objective)),
+                    threshold,
+                )
+                .await
+                .map_err(|err| CrustagiError::memory("Looking up duplicates failed", err))?,
+                None => None,
+            };
+            if let Some(duplicate) = duplicate {
+                tracing::info!(
+                    "Not storing result, it is a near duplicate of {} (score {:.3})",
+                    duplicate.id,
+                    duplicate.score
+                );
+                continue;
+            }
+            let id = if several_records {
+                format!("{}_{}", result_id, index)
+            } else {
+                result_id.clone()
+            };
+            memory
+                .upsert(&id, vector, &record.metadata)
+                .await
+                .map_err(|err| CrustagiError::memory("Storing the result failed", err))?;
+            record_event(
+                &self.observers,
+                Event::Upsert {
+                    task_id: task.task_id,
+                    id: &id,
+                },
+            );
+            stored.push(StoredVector {
+                id,
+                vector: vector.clone(),
+                metadata: Some(record.metadata.clone()),
+            });
+        }
+        let result_id = (!stored.is_empty()).then_some(result_id);
+        if let Some(evictor) = &self.evictor {
+            let evicted = evictor
+                .evict(memory, &stored)
+                .await
+                .map_err(|err| CrustagiError::memory("Evicting results failed", err))?;
+            if evicted > 0 {
+                tracing::info!("Evicted {} results from memory", evicted);
+                record_event(&self.observers, Event::Evicted { count: evicted });
+            }
+        }
+
+        // Step 3: Create new tasks
+        let new_tasks = self
+            .task_creation
+            .create_tasks(&objective, task, &result, pending, next_task_id)
+            .await?;
+        Ok(TaskOutcome {
+            result,
+            result_id,
+            new_tasks,
+        })
+    }
+}
+
+// Results that are empty or only whitespace count as failed executions
+pub fn ensure_result(result: &str) -> Result<(), CrustagiError> {
+    if result.trim().is_empty() {
+        Err(CrustagiError::llm(
+            "Execution failed",
+            "the execution agent returned an empty result",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// An objective the run works on, with its own task list
+pub struct ObjectiveRun {
+    // Position among the objectives or milestones, starting at 1
+    pub number: usize,
+    pub config: Arc<Config>,
+    pub runner: Arc<Agent>,
+    pub task_list: TaskGraph,
+    pub task_ids: TaskIdAllocator,
+    // Earlier tasks and results, sent along with each execution prompt
+    pub history: Vec<ChatMessage>,
+    // Completed tasks, which new tasks must not repeat
+    pub completed_tasks: Vec<Task>,
+    // Tasks that failed MAX_TASK_FAILURES times
+    pub failed_tasks: Vec<Task>,
+}
+
+impl ObjectiveRun {
+    /// Start with the initial task, or continue the task list saved by a
+    /// stopped run
+    pub fn start(
+        number: usize,
+        config: Arc<Config>,
+        runner: Arc<Agent>,
+    ) -> Result<Self, CrustagiError> {
+        let mut objective_run = ObjectiveRun {
+            number,
+            config,
+            runner,
+            task_list: TaskGraph::default(),
+            task_ids: TaskIdAllocator::default(),
+            history: Vec::new(),
+            completed_tasks: Vec::new(),
+            failed_tasks: Vec::new(),
+        };
+        let config = objective_run.config.clone();
+        if !config.resume {
+            let first_task = Task::new(
+                objective_run.task_ids.next(),
+                config.initial_task.clone(),
+                None,
+            );
+            add_task(
+                first_task,
+                &mut objective_run.task_list,
+                &objective_run.runner.observers,
+            );
+            return Ok(objective_run);
+        }
+
+        let state: state::RunState = state::load(&config.state_path)?;
+        if state.objective != config.objective {
+            return Err(CrustagiError::Config(format!(
+                "The run state in {} is for a different objective: {}",
+                config.state_path, state.objective
+            )));
+        }
+        tracing::info!(
+            "Resuming with {} tasks from {}",
+            state.tasks.len(),
+            config.state_path
+        );
+        let in_use = state.tasks.iter().map(|task| task.task_id);
+        objective_run.task_ids = TaskIdAllocator::new(
+            state.task_id_counter,
+            in_use.chain(state.results.keys().copied()),
+        );
+        objective_run.task_list = TaskGraph::new(state.tasks, state.results);
+        objective_run.completed_tasks = state.completed_tasks;
+        objective_run.failed_tasks = state.failed_tasks;
+        Ok(objective_run)
+    }
+
+    // Save the task list, so the run can be continued
+    fn save(self) {
+        // A replay leaves the files of the recorded run alone
+        if self.config.replay.is_some() {
+            return;
+        }
+        let (tasks, results) = self.task_list.into_parts();
+        let state = state::RunState {
+            objective: self.config.objective.clone(),
+            task_id_counter: self.task_ids.last(),
+            tasks,
+            completed_tasks: self.completed_tasks,
+            results,
+            failed_tasks: self.failed_tasks,
+        };
+        match state::save(&self.config.state_path, &state) {
+            Ok(()) => tracing::info!(
+                "Saved the task list to {}. Continue the run with the resume command.",
+                self.config.state_path
+            ),
+            Err(err) => tracing::warn!("Failed to save the task list: {}", err),
+        }
+    }
+
+    // List the tasks that were given up on, with the error of their last
+    // attempt
+    fn print_failed_tasks(&self, several: bool) {
+        if self.failed_tasks.is_empty() {
+            return;
+        }
+        let heading = match (&self.config.milestone, several) {
+            (Some(_), _) => format!("FAILED TASKS OF MILESTONE {}", self.number),
+            (None, true) => format!("FAILED TASKS OF OBJECTIVE {}", self.number),
+            (None, false) => "FAILED TASKS".to_string(),
+        };
+        let lines: Vec<String> = self
+            .failed_tasks
+            .iter()
+            .map(|task| {
+                format!(
+                    "{}: {} ({})",
+                    task.task_id,
+                    task.task_name,
+                    task.error.as_deref().unwrap_or("unknown error")
+                )
+            })
+            .collect();
+        let failed_tasks: Vec<i32> = self.failed_tasks.iter().map(|task| task.task_id).collect();
+        tracing::info!(
+            failed_tasks = ?failed_tasks,
+            "\n{}\n{}",
+            logging::banner(heading, Color::Red),
+            lines.join("\n")
+        );
+    }
+
+    // Show the objective or milestone worked on and its task list
+    fn print_task_list(&self, several: bool, dashboard: Option<&Dashboard>) {
+        match &self.config.milestone {
+            Some(milestone) => tracing::info!(
+                "\n{}",
+                logging::banner(
+                    format!("MILESTONE {}: {}", self.number, milestone),
+                    Color::Blue
+                )
+            ),
+            None if several => {
+                tracing::info!(
+                    "\n{}",
+                    logging::banner(
+                        format!("OBJECTIVE {}: {}", self.number, self.config.objective),
+                        Color::Blue
+                    )
+                )
+            }
+            None => {}
+        }
+        let lines: Vec<String> = self
+            .task_list
+            .tasks()
+            .into_iter()
+            .map(|t| match t.depends_on.as_slice() {
+                [] => format!("{}: {}", t.task_id, t.task_name),
+                depends_on => format!(
+                    "{}: {} (after {})",
+                    t.task_id,
+                    t.task_name,
+                    depends_on
+                        .iter()
+                        .map(i32::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            })
+            .collect();
+        tracing::info!(
+            tasks = lines.len(),
+            "\n{}\n{}",
+            logging::banner("TASK LIST", Color::Cyan),
+            lines.join("\n")
+        );
+        if let Some(dashboard) = dashboard {
+            dashboard.set_tasks(&self.config.objective, lines);
+        }
+    }
+
+    // Take over what a completed iteration left
+    fn apply(&mut self, iteration: Iteration) {
+        self.task_list = iteration.task_list;
+        self.task_ids = iteration.task_ids;
+        self.history = iteration.history;
+        self.completed_tasks.extend(iteration.done);
+        self.failed_tasks.extend(iteration.failed);
+    }
+}
+
+// Index of the objective to work on after the one at `last`, None once all
+// task lists are empty. Unless they run one after the other, objectives with
+// a task that can run right away come before those only waiting for retries.
+pub fn next_objective(
+    objective_runs: &[ObjectiveRun],
+    scheduling: Scheduling,
+    last: Option<usize>,
+) -> Option<usize> {
+    let start = last.map_or(0, |last| last + 1);
+    // In round robin order, starting after the last objective
+    let candidates: Vec<usize> = (0..objective_runs.len())
+        .map(|offset| (start + offset) % objective_runs.len())
+        .filter(|&index| !objective_runs[index].task_list.is_empty())
+        .collect();
+    let mut ready = candidates
+        .iter()
+        .copied()
+        .filter(|&index| objective_runs[index].task_list.has_ready());
+    let priority = |index: usize| {
+        objective_runs[index]
+            .task_list
+            .top_priority()
+            .unwrap_or_default()
+    };
+    let next = match scheduling {
+        Scheduling::RoundRobin => ready.next(),
+        // Of several objectives with the same top priority, the next in
+        // round robin order, so that they still take turns
+        Scheduling::Priority => ready.fold(None, |best, index| match best {
+            Some(best) if priority(best) >= priority(index) => Some(best),
+            _ => Some(index),
+        }),
+        // The first objective not done yet, even while it waits
+        Scheduling::Sequential => candidates.iter().copied().min(),
+    };
+    next.or(candidates.first().copied())
+}
+
+// Write the manifest of a deterministic run. A resumed run that was started
+// differently is pointed out, as its results can't be compared anymore.
+pub fn write_manifest(config: &Config, manifest: state::Manifest) {
+    if config.resume {
+        if let Ok(previous) = state::load::<state::Manifest>(&config.manifest_path) {
+            let differences = previous.differences(&manifest);
+            if !differences.is_empty() {
+                tracing::warn!(
+                    "The run was started with different settings for: {}",
+                    differences.join(", ")
+                );
+            }
+        }
+    }
+    match state::save(&config.manifest_path, &manifest) {
+        Ok(()) => tracing::info!("Wrote the manifest to {}", config.manifest_path),
+        Err(err) => tracing::warn!("Failed to write the manifest: {}", err),
+    }
+}
+
+// Key findings for the final report. They are left out once the budget is
+// used up, and when a signal arrives while they are written.
+pub async fn key_findings(
+    config: &Config,
+    llm: &dyn LlmProvider,
+    prompts: &Prompts,
+    objective_runs: &[ObjectiveRun],
+    usage: &UsageTracker,
+    shutdown: &mut watch::Receiver<u32>,
+) -> String {
+    let tasks: Vec<&Task> = objective_runs
+        .iter()
+        .flat_map(|objective_run| &objective_run.completed_tasks)
+        .collect();
+    if tasks.is_empty() {
+        return "No tasks were completed.".to_string();
+    }
+    if budget_exceeded(config, usage).is_some() {
+        return "Not written, the budget is used up.".to_string();
+    }
+    // Milestones share their objective
+    let mut objectives: Vec<&str> = objective_runs
+        .iter()
+        .map(|objective_run| objective_run.config.objective.as_str())
+        .collect();
+    objectives.dedup();
+    let objective = objectives.join("; ");
+    let signals = *shutdown.borrow();
+    tokio::select! {
+        findings = report_agent(llm, prompts, &objective, &tasks) => {
+            findings.unwrap_or_else(|err| format!("Not written. {}", err))
+        }
+        _ = shutdown.wait_for(|&received| received > signals) => {
+            "Not written, the run was cancelled.".to_string()
+        }
+    }
+}
+
+// Write the final report to REPORTS_DIR
+pub fn write_report(
+    config: &Config,
+    objective_runs: &[ObjectiveRun],
+    outcome: &str,
+    iterations: usize,
+    findings: &str,
+    usage: &UsageTracker,
+) {
+    let several = objective_runs.len() > 1;
+    let sections = objective_runs
+        .iter()
+        .map(|objective_run| report::Section {
+            title: match (&objective_run.config.milestone, several) {
+                (Some(milestone), _) => {
+                    Some(format!("Milestone {}: {}", objective_run.number, milestone))
+                }
+                (None, true) => Some(format!(
+                    "Objective {}: {}",
+                    objective_run.number, objective_run.config.objective
+                )),
+                (None, false) => None,
+            },
+            completed_tasks: &objective_run.completed_tasks,
+            failed_tasks: &objective_run.failed_tasks,
+        })
+        .collect();
+    let objective = if several && objective_runs[0].config.milestone.is_none() {
+        "Several objectives"
+    } else {
+        config.objective.as_str()
+    };
+    let report = report::Report {
+        objective,
+        outcome,
+        iterations,
+        findings,
+        sections,
+        usage: usage.summary(),
+    };
+    match report::write(&config.report_path, &report) {
+        Ok(()) => {
+            tracing::info!(target: logging::RESULTS, "Wrote the report to {}", config.report_path)
+        }
+        Err(err) => tracing::warn!(
+            "Failed to write the report to {}: {}",
+            config.report_path,
+            err
+        ),
+    }
+}
+
+// Whether task results are printed while they are generated. Not when tasks
+// run at the same time, as their output would interleave, nor with JSON logs,
+// which own stdout, nor in the TUI, which streams them itself.
+pub fn streams_result(config: &Config) -> bool {
+    config.max_concurrent_tasks == 1 && !logging::is_json() && !tui::is_active()
+}
+
+// Why the run has to stop, if its usage crossed `MAX_COST_USD` or `MAX_TOKENS_TOTAL`
+pub fn budget_exceeded(config: &Config, usage: &UsageTracker) -> Option<String> {
+    if let Some(max_cost) = config.max_cost_usd {
+        let cost = usage.total_cost();
+        if cost >= max_cost {
+            return Some(format!(
+                "The cost of ${:.4} reached MAX_COST_USD (${})",
+                cost, max_cost
+            ));
+        }
+    }
+    if let Some(max_tokens) = config.max_tokens_total {
+        let tokens = usage.total_tokens();
+        if tokens >= max_tokens {
+            return Some(format!(
+                "The {} tokens used reached MAX_TOKENS_TOTAL ({})",
+                tokens, max_tokens
+            ));
+        }
+    }
+    None
+}
+
+/// What the task loop works with besides the task lists of the objectives.
+/// `run` sets it up from the environment.
+pub struct Engine {
+    pub config: Arc<Config>,
+    pub prompts: Arc<Prompts>,
+    pub usage: Arc<UsageTracker>,
+    /// Embeddings, e.g. for the deduplication of new tasks
+    pub llm: Arc<dyn LlmProvider>,
+    pub prioritization: Arc<dyn PrioritizationAgent>,
+    /// Writes the key findings of the report
+    pub report_llm: Arc<dyn LlmProvider>,
+    /// The milestones of a planned run, and the model combining their
+    /// results once all are done
+    pub synthesis: Option<(Vec<String>, Arc<dyn LlmProvider>)>,
+    /// The store of the first objective, which the synthesis searches
+    pub memory: Arc<dyn VectorStore>,
+    /// The run log a replay answers the LLM calls from
+    pub replay: Option<Arc<Replay>>,
+    pub task_dedup: Option<TaskDeduplicator>,
+    pub scheduling: Scheduling,
+    /// Add the lines typed into stdin as tasks, unless the dashboard takes
+    /// them
+    pub read_stdin: bool,
+    /// Called as tasks start, complete, fail or create new tasks
+    pub observers: Vec<Arc<dyn Observer>>,
+    /// The dashboard of the run, shown with `TUI=true`. The execution and
+    /// context agents of the run update it too.
+    pub dashboard: Option<Dashboard>,
+}
+
+/// Why a run stopped
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    /// The task lists are empty
+    Done,
+    /// Ctrl+C or a termination signal
+    Interrupted,
+    /// `MAX_COST_USD` or `MAX_TOKENS_TOTAL` was reached, as described
+    BudgetExceeded(String),
+    /// The run log a replay answers from has no more calls
+    ReplayExhausted,
+    /// `MAX_ITERATIONS` iterations were run
+    MaxIterations(usize),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Done => write!(f, "All tasks are done"),
+            StopReason::Interrupted => write!(f, "Interrupted"),
+            StopReason::BudgetExceeded(reason) => write!(f, "{}", reason),
+            StopReason::ReplayExhausted => write!(f, "The run log has no more recorded calls"),
+            StopReason::MaxIterations(max_iterations) => {
+                write!(f, "{} iterations reached MAX_ITERATIONS", max_iterations)
+            }
+        }
+    }
+}
+
+/// How a run ended, as returned by `run_loop`
+#[derive(Debug, Clone)]
+pub struct RunOutcome {
+    pub stop_reason: StopReason,
+    pub iterations: usize,
+    /// The key findings of the report, or the synthesis of a planned run
+    pub findings: String,
+    /// The task lists of the objectives or milestones, in order
+    pub objectives: Vec<ObjectiveOutcome>,
+    /// Usage of the LLM calls counted in the run's `UsageTracker`
+    pub total_tokens: usize,
+    pub cost_usd: f64,
+}
+
+/// Where the task list of an objective or milestone stood when the run
+/// stopped
+#[derive(Debug, Clone)]
+pub struct ObjectiveOutcome {
+    pub objective: String,
+    pub milestone: Option<String>,
+    /// With their results
+    pub completed_tasks: Vec<Task>,
+    /// With the error of their last attempt
+    pub failed_tasks: Vec<Task>,
+    /// In priority order
+    pub pending_tasks: Vec<Task>,
+}
+
+impl RunOutcome {
+    fn new(
+        stop_reason: StopReason,
+        iterations: usize,
+        findings: String,
+        objective_runs: &[ObjectiveRun],
+        usage: &UsageTracker,
+    ) -> Self {
+        let objectives = objective_runs
+            .iter()
+            .map(|objective_run| ObjectiveOutcome {
+                objective: objective_run.config.objective.clone(),
+                milestone: objective_run.config.milestone.clone(),
+                completed_tasks: objective_run.completed_tasks.clone(),
+                failed_tasks: objective_run.failed_tasks.clone(),
+                pending_tasks: objective_run
+                    .task_list
+                    .tasks()
+                    .into_iter()
+                    .cloned()
+                    .collect(),
+            })
+            .collect();
+        RunOutcome {
+            stop_reason,
+            iterations,
+            findings,
+            objectives,
+            total_tokens: usage.total_tokens(),
+            cost_usd: usage.total_cost(),
+        }
+    }
+}
+
+/// Work through the task lists of `objective_runs` until all tasks are done,
+/// or until Ctrl+C, which `shutdown` counts, the budget or `MAX_ITERATIONS`
+/// stops the run. Each iteration executes the next ready tasks, adds the
+/// tasks created from their results and reprioritizes the task list. The
+/// report is written and the task lists are saved once the run stops.
+pub async fn run_loop(
+    engine: &Engine,
+    mut objective_runs: Vec<ObjectiveRun>,
+    mut shutdown: watch::Receiver<u32>,
+) -> RunOutcome {
+    let config = &engine.config;
+    let observers = &engine.observers;
+    let several = objective_runs.len() > 1;
+    let shown = engine
+        .dashboard
+        .as_ref()
+        .and_then(|dashboard| tui::start(dashboard, engine.usage.clone()));
+    let mut inbox = match engine.dashboard.as_ref().filter(|_| shown.is_some()) {
+        Some(dashboard) => dashboard.inbox(),
+        None if engine.read_stdin => inbox::listen(),
+        None => mpsc::unbounded_channel().1,
+    };
+    let mut iterations = 0;
+    let mut progress = Progress::start();
+    // Parent of the iteration spans, so that a run is one trace
+    let run_span = tracing::info_span!("run", report = %config.report_path);
+    // Index of the objective worked on last
+    let mut last_objective = None;
+    loop {
+        // Tasks typed in meanwhile join the end of the first objective's
+        // list, and are prioritized along with its next new tasks
+        let first = &mut objective_runs[0];
+        while let Ok(task_name) = inbox.try_recv() {
+            add_task(
+                Task::new(first.task_ids.next(), task_name, None),
+                &mut first.task_list,
+                observers,
+            );
+        }
+
+        let stop_reason = stop_reason(engine, &shutdown, iterations);
+        let next = match stop_reason {
+            Some(_) => None,
+            None => next_objective(&objective_runs, engine.scheduling, last_objective),
+        };
+        let Some(index) = next else {
+            // The summary and report are printed after the dashboard
+            drop(shown);
+            let stop_reason = stop_reason.unwrap_or(StopReason::Done);
+            return finish(
+                engine,
+                objective_runs,
+                stop_reason,
+                iterations,
+                &mut shutdown,
+            )
+            .await;
+        };
+        last_objective = Some(index);
+        let objective_run = &mut objective_runs[index];
+        objective_run.print_task_list(several, engine.dashboard.as_ref());
+
+        // Step 1: Pull the first tasks whose dependencies are complete,
+        // as many as run at the same time and MAX_ITERATIONS leaves
+        let config = Arc::clone(&objective_run.config);
+        let limit = config
+            .max_iterations
+            .map_or(config.max_concurrent_tasks, |max_iterations| {
+                config.max_concurrent_tasks.min(max_iterations - iterations)
+            });
+        let batch = objective_run.task_list.take_ready(limit);
+        // All tasks that could run are failed ones waiting for their
+        // retry. Ctrl+C ends the wait.
+        if batch.is_empty() {
+            let wait = objective_run
+                .task_list
+                .next_retry()
+                .map_or(1, |retry_at| retry_at.saturating_sub(unix_time()).max(1));
+            tracing::info!("\nWaiting {} seconds to retry failed tasks...", wait);
+            tokio::select! {
+                _ = sleep(Duration::from_secs(wait)) => {}
+                _ = shutdown.changed() => {}
+            }
+            continue;
+        }
+        let batch_ids: Vec<i32> = batch.iter().map(|task| task.task_id).collect();
+        let lines: Vec<String> = batch
+            .iter()
+            .map(|task| format!("{}: {}", task.task_id, task.task_name))
+            .collect();
+        tracing::info!(
+            task_ids = ?batch_ids,
+            "\n{}\n{}",
+            logging::banner("NEXT TASK", Color::Green),
+            lines.join("\n")
+        );
+        let span = tracing::info_span!(
+            parent: &run_span,
+            "iteration",
+            objective = objective_run.number,
+            task_ids = ?batch_ids
+        );
+        let iteration = run_iteration(engine, objective_run, &batch).instrument(span);
+
+        // A second Ctrl+C cancels the iteration, which leaves the task
+        // list as it was and doesn't count towards MAX_ITERATIONS
+        let outcome = tokio::select! {
+            iteration = iteration => Some(iteration),
+            _ = shutdown.wait_for(|&signals| signals >= 2) => None,
+        };
+        match outcome {
+            Some(iteration) => {
+                iterations += batch.len();
+                progress.record(batch.len());
+                objective_run.apply(iteration);
+            }
+            None => {
+                for task in batch.into_iter().rev() {
+                    objective_run.task_list.push_front(task);
+                }
+            }
+        }
+        report_progress(engine, &config, &objective_runs, iterations, &progress);
+        sleep(config.task_delay).await; // Sleep before checking the task list again
+    }
+}
+
+// Why the run stops before the next iteration, if it does: Ctrl+C, the
+// budget or MAX_ITERATIONS used up, or a replay past the end of its run log.
+// The task lists are kept, so that the run can be continued.
+fn stop_reason(
+    engine: &Engine,
+    shutdown: &watch::Receiver<u32>,
+    iterations: usize,
+) -> Option<StopReason> {
+    let config = &engine.config;
+    let interrupted = *shutdown.borrow() > 0;
+    interrupted
+        .then_some(StopReason::Interrupted)
+        .or_else(|| budget_exceeded(config, &engine.usage).map(StopReason::BudgetExceeded))
+        .or_else(|| {
+            engine
+                .replay
+                .as_ref()
+                .filter(|replay| replay.is_exhausted())
+                .map(|_| StopReason::ReplayExhausted)
+        })
+        .or_else(|| {
+            config
+                .max_iterations
+                .filter(|&max_iterations| iterations >= max_iterations)
+                .map(StopReason::MaxIterations)
+        })
+}
+
+// End the run for `stop_reason`: list the failed tasks and the usage, write
+// the report with the key findings, or with the synthesis of a planned run
+// whose milestones are all done, and save the task lists of a run that was
+// stopped before, so that it can be continued
+async fn finish(
+    engine: &Engine,
+    objective_runs: Vec<ObjectiveRun>,
+    stop_reason: StopReason,
+    iterations: usize,
+    shutdown: &mut watch::Receiver<u32>,
+) -> RunOutcome {
+    let Engine {
+        config,
+        prompts,
+        usage,
+        llm,
+        report_llm,
+        synthesis: planned,
+        memory,
+        observers,
+        ..
+    } = engine;
+    let reason = stop_reason.to_string();
+    let done = stop_reason == StopReason::Done;
+    if let StopReason::BudgetExceeded(reason) = &stop_reason {
+        record_event(observers, Event::BudgetExceeded { reason });
+    }
+    record_event(
+        observers,
+        Event::RunFinished {
+            reason: &reason,
+            iterations,
+        },
+    );
+    if done {
+        tracing::info!("\nAll tasks are done after {} iterations.", iterations);
+    } else {
+        tracing::info!("\n{}, stopping.", reason);
+    }
+    let several = objective_runs.len() > 1;
+    for objective_run in &objective_runs {
+        objective_run.print_failed_tasks(several);
+    }
+    // Completing all milestones completes the objective, and its synthesis
+    // is the key findings of the report
+    let synthesis = match planned {
+        Some((milestones, synthesis_llm)) if done => {
+            match synthesis_agent(
+                config,
+                llm.as_ref(),
+                synthesis_llm.as_ref(),
+                prompts,
+                memory.as_ref(),
+                milestones,
+            )
+            .await
+            {
+                Ok(text) => {
+                    tracing::info!(
+                        target: logging::RESULTS,
+                        "\n{}\n{}",
+                        logging::banner("SYNTHESIS", Color::Yellow),
+                        text
+                    );
+                    Some(text)
+                }
+                Err(err) => {
+                    tracing::warn!("{}", err);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+    usage.print_summary();
+    let findings = match synthesis {
+        Some(synthesis) => synthesis,
+        None => {
+            key_findings(
+                config,
+                report_llm.as_ref(),
+                prompts,
+                &objective_runs,
+                usage,
+                shutdown,
+            )
+            .await
+        }
+    };
+    write_report(
+        config,
+        &objective_runs,
+        &reason,
+        iterations,
+        &findings,
+        usage,
+    );
+    let outcome = RunOutcome::new(stop_reason, iterations, findings, &objective_runs, usage);
+    if !done {
+        for objective_run in objective_runs {
+            objective_run.save();
+        }
+    }
+    outcome
+}
+
+// What an iteration leaves of its objective's task list. The iteration works
+// on a copy, which only replaces the task list once the iteration is
+// complete, so that a cancelled iteration leaves the list intact.
+struct Iteration {
+    task_list: TaskGraph,
+    task_ids: TaskIdAllocator,
+    history: Vec<ChatMessage>,
+    done: Vec<Task>,
+    failed: Vec<Task>,
+}
+
+// Execute the tasks of `batch`, add the tasks created from their results and
+// reprioritize the task list
+async fn run_iteration(engine: &Engine, objective_run: &ObjectiveRun, batch: &[Task]) -> Iteration {
+    let outcomes = execute(engine, objective_run, batch).await;
+    let mut iteration = Iteration {
+        task_list: objective_run.task_list.clone(),
+        task_ids: objective_run.task_ids.clone(),
+        history: objective_run.history.clone(),
+        done: Vec::with_capacity(batch.len()),
+        failed: Vec::new(),
+    };
+    // Failed tasks go back into the list first, so that the results they
+    // depend on are kept when the others complete
+    let mut succeeded = Vec::with_capacity(batch.len());
+    for (task, outcome) in batch.iter().zip(outcomes) {
+        match outcome {
+            Ok(outcome) => succeeded.push((task, outcome)),
+            Err(err) => iteration.fail(engine, &objective_run.config, task, err),
+        }
+    }
+    for (task, outcome) in succeeded {
+        iteration
+            .complete(engine, objective_run, batch, task, outcome)
+            .await;
+    }
+    iteration.reprioritize(engine, &objective_run.config).await;
+    iteration
+}
+
+// Run each task of `batch` on its own tokio task, within TASK_TIMEOUT_SECS if
+// set. The outcomes are in the order the tasks were pulled, whichever
+// finished first, so that runs stay reproducible. Dropping the future aborts
+// the tasks.
+async fn execute(
+    engine: &Engine,
+    objective_run: &ObjectiveRun,
+    batch: &[Task],
+) -> Vec<Result<TaskOutcome, CrustagiError>> {
+    let ObjectiveRun {
+        config,
+        runner,
+        task_list,
+        task_ids,
+        history,
+        ..
+    } = objective_run;
+    let mut running = JoinSet::new();
+    for (index, task) in batch.iter().cloned().enumerate() {
+        for observer in &engine.observers {
+            observer.on_task_start(&task);
+        }
+        if let Some(dashboard) = &engine.dashboard {
+            dashboard.start_task(task.task_id, &task.task_name);
+        }
+        let runner = runner.clone();
+        let dependencies = task_list.dependency_results(&task);
+        let history = history.clone();
+        let pending: Vec<Task> = task_list.tasks().into_iter().cloned().collect();
+        let next_task_id = task_ids.peek();
+        let span = tracing::info_span!("task", task_id = task.task_id);
+        let timeout = config.task_timeout_secs.map(Duration::from_secs);
+        running.spawn(
+            async move {
+                let run = runner.run(&task, &dependencies, &history, &pending, next_task_id);
+                let outcome = match timeout {
+                    Some(timeout) => time::timeout(timeout, run)
+                        .await
+                        .unwrap_or(Err(CrustagiError::Timeout)),
+                    None => run.await,
+                };
+                (index, outcome)
+            }
+            .instrument(span),
+        );
+    }
+    let mut outcomes = Vec::with_capacity(batch.len());
+    while let Some(joined) = running.join_next().await {
+        outcomes.push(joined.unwrap_or_else(|err| panic::resume_unwind(err.into_panic())));
+    }
+    outcomes.sort_by_key(|(index, _)| *index);
+    outcomes.into_iter().map(|(_, outcome)| outcome).collect()
+}
+
+impl Iteration {
+    // Put `task` back into the list to be retried after a delay, or give up
+    // on it after MAX_TASK_FAILURES failures
+    fn fail(&mut self, engine: &Engine, config: &Config, task: &Task, err: CrustagiError) {
+        if let Some(dashboard) = &engine.dashboard {
+            dashboard.finish_task(task.task_id, None);
+        }
+        let mut task = Task {
+            failures: task.failures + 1,
+            error: Some(err.to_string()),
+            ..task.clone()
+        };
+        let retried = task.failures < config.max_task_failures;
+        record_event(
+            &engine.observers,
+            Event::TaskFailed {
+                task_id: task.task_id,
+                error: &err.to_string(),
+                retried,
+            },
+        );
+        for observer in &engine.observers {
+            observer.on_error(&task, &err, retried);
+        }
+        if retried {
+            // The delay doubles with each failure
+            let delay = config
+                .task_retry_delay_secs
+                .saturating_mul(2u64.saturating_pow(task.failures - 1));
+            tracing::warn!(
+                task_id = task.task_id,
+                "\nTask {} failed ({}), retrying it in {} seconds",
+                task.task_id,
+                err,
+                delay
+            );
+            task.retry_at = Some(unix_time() + delay);
+            self.task_list.push_front(task);
+        } else {
+            tracing::warn!(
+                task_id = task.task_id,
+                "\nTask {} failed {} times ({}), giving up on it",
+                task.task_id,
+                task.failures,
+                err
+            );
+            task.status = TaskStatus::Failed;
+            task.retry_at = None;
+            self.failed.push(task);
+        }
+    }
+
+    // Mark `task` of `batch` as done with the result of `outcome`, and add
+    // the new tasks created from it that don't repeat an existing one
+    async fn complete(
+        &mut self,
+        engine: &Engine,
+        objective_run: &ObjectiveRun,
+        batch: &[Task],
+        task: &Task,
+        outcome: TaskOutcome,
+    ) {
+        let config = &objective_run.config;
+        let observers = &engine.observers;
+        let TaskOutcome {
+            result,
+            result_id,
+            new_tasks,
+        } = outcome;
+        if let Some(dashboard) = &engine.dashboard {
+            dashboard.finish_task(task.task_id, Some(&result));
+        }
+        record_event(
+            observers,
+            Event::TaskResult {
+                task_id: task.task_id,
+                task_name: &task.task_name,
+                result: &result,
+            },
+        );
+        for observer in observers {
+            observer.on_task_result(task, &result);
+        }
+        // Results that weren't streamed are printed once done
+        if !streams_result(config) {
+            tracing::info!(
+                target: logging::RESULTS,
+                task_id = task.task_id,
+                "\n{}\n{}: {}\n{}",
+                logging::banner("TASK RESULT", Color::Yellow),
+                task.task_id,
+                task.task_name,
+                result
+            );
+        }
+
+        // Keep the last EXECUTION_HISTORY tasks and results for the
+        // execution agent's next calls. They are earlier results like the
+        // context, so they are sanitized the same way.
+        if config.execution_history > 0 {
+            let result = if config.sanitize_context {
+                sanitize::sanitize(&result)
+            } else {
+                result.clone()
+            };
+            self.history.push(ChatMessage::user(&task.task_name));
+            self.history.push(ChatMessage::assistant(&result));
+            let excess = self
+                .history
+                .len()
+                .saturating_sub(2 * config.execution_history);
+            self.history.drain(..excess);
+        }
+
+        // Drop new tasks that repeat a pending or completed one
+        let new_tasks = match &engine.task_dedup {
+            Some(task_dedup) => {
+                let existing: Vec<&str> = self
+                    .task_list
+                    .tasks()
+                    .into_iter()
+                    .chain(batch)
+                    .chain(objective_run.completed_tasks.iter())
+                    .map(|t| t.task_name.as_str())
+                    .collect();
+                // Without embeddings, all new tasks are kept
+                let candidates = new_tasks.clone();
+                task_dedup
+                    .filter(engine.llm.as_ref(), new_tasks, &existing)
+                    .await
+                    .unwrap_or_else(|err| {
+                        tracing::warn!("{}, keeping all new tasks", err);
+                        candidates
+                    })
+            }
+            None => new_tasks,
+        };
+        self.task_list.complete(task, &result);
+        self.done.push(Task {
+            status: TaskStatus::Done,
+            completed_at: Some(unix_time()),
+            result_id,
+            result: Some(result),
+            error: None,
+            retry_at: None,
+            ..task.clone()
+        });
+        // Ids of the new tasks as numbered in the answer, by the ids they
+        // end up with after dropped tasks
+        let mut renumbered = HashMap::new();
+        let mut added = Vec::new();
+        for new_task in new_tasks {
+            // Once MAX_TASKS tasks were created, the run only works through
+            // the remaining ones
+            if config
+                .max_tasks
+                .is_some_and(|max_tasks| self.task_ids.last() as usize >= max_tasks)
+            {
+                tracing::info!("MAX_TASKS reached, not adding new tasks");
+                break;
+            }
+            let task_id = self.task_ids.next();
+            renumbered.insert(new_task.task_id, task_id);
+            // Dependencies on other new tasks follow them to their ids,
+            // those on dropped or later tasks are removed
+            let depends_on = new_task
+                .depends_on
+                .iter()
+                .filter_map(|&id| {
+                    if id > objective_run.task_ids.last() {
+                        renumbered.get(&id).copied()
+                    } else {
+                        Some(id)
+                    }
+                })
+                .collect();
+            let task = Task {
+                task_id,
+                depends_on,
+                ..new_task
+            };
+            add_task(task.clone(), &mut self.task_list, observers);
+            added.push(task);
+        }
+        record_event(
+            observers,
+            Event::NewTasks {
+                task_id: task.task_id,
+                new_task_ids: added.iter().map(|task| task.task_id).collect(),
+            },
+        );
+        if !added.is_empty() {
+            for observer in observers {
+                observer.on_tasks_created(task, &added);
+            }
+        }
+    }
+
+    // Step 4: Reprioritize the task list. An empty list stays empty, as the
+    // model would only make tasks up, and a replay past the end of its run
+    // log stops before asking.
+    async fn reprioritize(&mut self, engine: &Engine, config: &Config) {
+        let replayed_all = engine
+            .replay
+            .as_ref()
+            .is_some_and(|replay| replay.is_exhausted());
+        if self.task_list.is_empty() || replayed_all {
+            return;
+        }
+        let priorities = engine
+            .prioritization
+            .prioritize(&config.prompt_objective(), &self.task_list.tasks())
+            .await;
+        match priorities {
+            Ok(priorities) => {
+                self.task_list.set_priorities(&priorities);
+                record_event(
+                    &engine.observers,
+                    Event::Reprioritized {
+                        task_ids: self
+                            .task_list
+                            .tasks()
+                            .iter()
+                            .map(|task| task.task_id)
+                            .collect(),
+                    },
+                );
+            }
+            // The tasks are tried again in their current order
+            Err(err) => tracing::warn!("{}, keeping the current order", err),
+        }
+    }
+}
+
+// Log the usage of the run after an iteration, and estimate what the tasks
+// queued will take, as far as the MAX_ITERATIONS of `config` lets them run.
+// The task lists of all objectives count.
+fn report_progress(
+    engine: &Engine,
+    config: &Config,
+    objective_runs: &[ObjectiveRun],
+    iterations: usize,
+    progress: &Progress,
+) {
+    let Engine {
+        usage, observers, ..
+    } = engine;
+    tracing::info!(
+        cost_usd = usage.total_cost(),
+        tokens = usage.total_tokens(),
+        "\nCost so far: ${:.4} ({} tokens)",
+        usage.total_cost(),
+        usage.total_tokens()
+    );
+    record_event(
+        observers,
+        Event::Usage {
+            tokens: usage.total_tokens(),
+            cost_usd: usage.total_cost(),
+            agents: usage.agents(),
+        },
+    );
+    for observer in observers {
+        observer.on_iteration_end(iterations, usage);
+    }
+    let queued: usize = objective_runs.iter().map(|run| run.task_list.len()).sum();
+    let tasks_left = config.max_iterations.map_or(queued, |max_iterations| {
+        queued.min(max_iterations.saturating_sub(iterations))
+    });
+    let Some(estimate) = progress
+        .estimate(tasks_left, usage.total_cost())
+        .filter(|estimate| estimate.tasks_left > 0)
+    else {
+        return;
+    };
+    let over_budget = config
+        .max_cost_usd
+        .filter(|&max_cost| estimate.projected_cost > max_cost)
+        .map(|max_cost| format!(", beyond MAX_COST_USD (${})", max_cost))
+        .unwrap_or_default();
+    tracing::info!(
+        tasks_left = estimate.tasks_left,
+        eta_secs = estimate.time_left.as_secs(),
+        projected_cost_usd = estimate.projected_cost,
+        "Estimate: {}{}",
+        estimate,
+        over_budget
+    );
+    if let Some(dashboard) = &engine.dashboard {
+        dashboard.set_estimate(format!("{}{}", estimate, over_budget));
+    }
+}
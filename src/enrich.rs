@@ -0,0 +1,80 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+
+use crate::error::CrustagiError;
+use crate::memory::VectorMetadata;
+use crate::tokens;
+
+// What is stored for a result: the text that is embedded and the metadata
+// stored with its vector
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub text: String,
+    pub metadata: VectorMetadata,
+}
+
+// Hook between executing a task and storing its result, e.g. to extract
+// entities into the embedded text, split the result into chunks or adjust
+// the metadata. Each returned record is embedded and stored on its own.
+#[async_trait]
+pub trait Enricher: Send + Sync {
+    // The records to store for `record`, which holds the result as it came
+    // from the execution agent. By default it is stored as it is.
+    async fn enrich(&self, record: Record) -> Result<Vec<Record>, CrustagiError> {
+        Ok(vec![record])
+    }
+}
+
+// Stores results unchanged
+pub struct NoEnrichment;
+
+impl Enricher for NoEnrichment {}
+
+// Splits results longer than `chunk_tokens` into overlapping chunks, so that
+// each vector stands for one passage instead of a long result as a whole.
+// Tokens are counted for the embedding model.
+pub struct Chunker {
+    pub model: String,
+    pub chunk_tokens: usize,
+    pub overlap: usize,
+}
+
+#[async_trait]
+impl Enricher for Chunker {
+    async fn enrich(&self, record: Record) -> Result<Vec<Record>, CrustagiError> {
+        if tokens::count_tokens(&self.model, &record.text) <= self.chunk_tokens {
+            return Ok(vec![record]);
+        }
+        let chunks = tokens::split(&self.model, &record.text, self.chunk_tokens, self.overlap);
+        Ok(chunks
+            .into_iter()
+            .enumerate()
+            .map(|(index, text)| Record {
+                metadata: VectorMetadata {
+                    result: text.clone(),
+                    chunk: Some(index),
+                    ..record.metadata.clone()
+                },
+                text,
+            })
+            .collect())
+    }
+}
+
+// Runs each enricher on every record the ones before it returned
+pub struct Chain(pub Vec<Arc<dyn Enricher>>);
+
+#[async_trait]
+impl Enricher for Chain {
+    async fn enrich(&self, record: Record) -> Result<Vec<Record>, CrustagiError> {
+        let mut records = vec![record];
+        for enricher in &self.0 {
+            let mut enriched = Vec::with_capacity(records.len());
+            for record in records {
+                enriched.extend(enricher.enrich(record).await?);
+            }
+            records = enriched;
+        }
+        Ok(records)
+    }
+}
@@ -0,0 +1,84 @@
+use std::error::Error;
+use std::io;
+
+// An error of an API, database or library, as it reported it
+type Source = Box<dyn Error + Send + Sync>;
+
+// Why a run, or setting one up, failed. A failed task doesn't fail the run:
+// its error is kept with the task, which is retried.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum CrustagiError {
+    // A model call failed after its retries, or its answer was unusable
+    #[error("{context}: {source}")]
+    Llm { context: String, source: Source },
+    // A vector store call failed
+    #[error("{context}: {source}")]
+    Memory { context: String, source: Source },
+    // A file written by an earlier run, e.g. the run state, the plan or the
+    // run log, can't be used
+    #[error("{0}")]
+    Parse(String),
+    // Settings are missing, invalid or don't fit together
+    #[error("{0}")]
+    Config(String),
+    // A file or directory the run needs can't be read or written
+    #[error("{context}: {source}")]
+    Io { context: String, source: io::Error },
+    // A task didn't finish within TASK_TIMEOUT_SECS
+    #[error("did not finish within TASK_TIMEOUT_SECS")]
+    Timeout,
+    // An API, database or library an LLM provider or vector store uses
+    // failed. Their callers wrap it in `Llm` or `Memory`, saying what they
+    // were doing.
+    #[error(transparent)]
+    Backend(#[from] Source),
+}
+
+impl CrustagiError {
+    pub fn llm(context: impl Into<String>, source: impl Into<Source>) -> Self {
+        CrustagiError::Llm {
+            context: context.into(),
+            source: source.into(),
+        }
+    }
+
+    pub fn memory(context: impl Into<String>, source: impl Into<Source>) -> Self {
+        CrustagiError::Memory {
+            context: context.into(),
+            source: source.into(),
+        }
+    }
+
+    pub fn io(context: impl Into<String>, source: io::Error) -> Self {
+        CrustagiError::Io {
+            context: context.into(),
+            source,
+        }
+    }
+}
+
+// The errors of the APIs, databases and libraries the providers and vector
+// stores use, so that they can be returned with `?`
+macro_rules! from_backend {
+    ($($error:ty),*) => {
+        $(impl From<$error> for CrustagiError {
+            fn from(err: $error) -> Self {
+                CrustagiError::Backend(err.into())
+            }
+        })*
+    };
+}
+
+from_backend!(
+    String,
+    &str,
+    io::Error,
+    reqwest::Error,
+    serde_json::Error,
+    tokio_postgres::Error,
+    rusqlite::Error,
+    arrow_schema::ArrowError,
+    crate::openai::OpenAiError,
+    crate::pinecone::PineconeError
+);
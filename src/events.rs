@@ -0,0 +1,128 @@
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::CrustagiError;
+use crate::observer::Observer;
+use crate::usage::AgentUsage;
+
+// What happened in a run, written to EVENTS_PATH as one JSON line each for
+// tools analysing runs afterwards. Each line has the time in milliseconds
+// since the Unix epoch, the `event` name and its fields.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    RunStarted {
+        objectives: Vec<&'a str>,
+        resumed: bool,
+    },
+    TaskCreated {
+        task_id: i32,
+        task_name: &'a str,
+        depends_on: &'a [i32],
+    },
+    TaskStarted {
+        task_id: i32,
+        task_name: &'a str,
+    },
+    TaskResult {
+        task_id: i32,
+        task_name: &'a str,
+        result: &'a str,
+    },
+    TaskFailed {
+        task_id: i32,
+        error: &'a str,
+        // Whether the task is tried again
+        retried: bool,
+    },
+    // Tasks the task creation agent proposed after a task, as added
+    NewTasks {
+        task_id: i32,
+        new_task_ids: Vec<i32>,
+    },
+    // The task list in its new order
+    Reprioritized {
+        task_ids: Vec<i32>,
+    },
+    // A result, or a chunk of it, stored in memory
+    Upsert {
+        task_id: i32,
+        id: &'a str,
+    },
+    Evicted {
+        count: usize,
+    },
+    // Usage of the run so far, after each iteration. A resumed run counts
+    // from 0.
+    Usage {
+        tokens: usize,
+        cost_usd: f64,
+        agents: Vec<AgentUsage>,
+    },
+    // MAX_COST_USD or MAX_TOKENS_TOTAL was reached, the run stops
+    BudgetExceeded {
+        reason: &'a str,
+    },
+    RunFinished {
+        reason: &'a str,
+        iterations: usize,
+    },
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    time_ms: u128,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+/// Writes the events of the run it observes to an events file
+pub struct EventLog {
+    path: String,
+    file: Mutex<File>,
+}
+
+impl EventLog {
+    /// Write the events to `path`, continuing the file with `append`
+    pub fn open(path: &str, append: bool) -> Result<Self, CrustagiError> {
+        let open = || -> io::Result<File> {
+            if let Some(dir) = Path::new(path).parent() {
+                fs::create_dir_all(dir)?;
+            }
+            OpenOptions::new()
+                .create(true)
+                .write(true)
+                .append(append)
+                .truncate(!append)
+                .open(path)
+        };
+        let file = open().map_err(|err| {
+            CrustagiError::io(format!("Failed to open the events file {}", path), err)
+        })?;
+        Ok(EventLog {
+            path: path.to_string(),
+            file: Mutex::new(file),
+        })
+    }
+}
+
+// An event that can't be written is only reported, the run goes on
+impl Observer for EventLog {
+    fn on_event(&self, event: &Event) {
+        let line = Line {
+            time_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis(),
+            event,
+        };
+        let line = serde_json::to_string(&line).expect("Events serialize to JSON");
+        if let Err(err) = writeln!(self.file.lock().unwrap(), "{}", line) {
+            tracing::warn!("Failed to write to the events file {}: {}", self.path, err);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use async_trait::async_trait;
+use serde_json::Value;
+use std::sync::Arc;
+
+use crate::error::CrustagiError;
+use crate::llm::{ChatMessage, LlmProvider, ToolSpec, SUPPORTED_PROVIDERS};
+
+// Entry of `FALLBACK_MODELS`: `model`, `provider:model`, or for
+// OpenAI-compatible servers `openai:model@api_base`
+#[derive(Debug, Clone)]
+pub struct FallbackModel {
+    // None for the provider selected by `LLM_PROVIDER`
+    pub provider: Option<String>,
+    pub model: String,
+    pub api_base: Option<String>,
+}
+
+impl FallbackModel {
+    // A prefix only counts as provider if it is one, so that model names with
+    // a colon like `llama3:8b` keep working
+    pub fn parse(entry: &str) -> Result<Self, CrustagiError> {
+        let (provider, rest) = match entry.split_once(':') {
+            Some((provider, rest)) if SUPPORTED_PROVIDERS.contains(&provider) => {
+                (Some(provider.to_string()), rest)
+            }
+            _ => (None, entry),
+        };
+        let (model, api_base) = match rest.split_once('@') {
+            Some((model, api_base)) => (model, Some(api_base.to_string())),
+            None => (rest, None),
+        };
+        if model.is_empty() {
+            return Err(CrustagiError::Config(format!(
+                "Fallback model \"{}\" has no model name",
+                entry
+            )));
+        }
+        if api_base.is_some() && provider.as_deref().unwrap_or("openai") != "openai" {
+            return Err(CrustagiError::Config(format!(
+                "Fallback model \"{}\": an API base is only supported for openai",
+                entry
+            )));
+        }
+        Ok(FallbackModel {
+            provider,
+            model: model.to_string(),
+            api_base,
+        })
+    }
+}
+
+// Comma-separated list of fallback models, in the order they are tried
+pub fn parse_fallback_models(value: &str) -> Result<Vec<FallbackModel>, CrustagiError> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(FallbackModel::parse)
+        .collect()
+}
+
+// Provider wrapper trying each text model in turn until one answers. Every
+// call starts with the primary model again, so it takes over once it has
+// recovered. Embeddings always come from the primary provider, as vectors of
+// different models can't be mixed in one index.
+pub struct FallbackChain {
+    providers: Vec<Arc<dyn LlmProvider>>,
+}
+
+impl FallbackChain {
+    pub fn new(primary: Arc<dyn LlmProvider>, fallbacks: Vec<Arc<dyn LlmProvider>>) -> Self {
+        let mut providers = vec![primary];
+        providers.extend(fallbacks);
+        FallbackChain { providers }
+    }
+
+    fn primary(&self) -> &dyn LlmProvider {
+        self.providers[0].as_ref()
+    }
+
+    // Log a failed model and the one tried next
+    fn switch(&self, index: usize, err: &CrustagiError) {
+        let failed = self.providers[index].model();
+        let next = self.providers[index + 1].model();
+        tracing::warn!("{} failed ({}), falling back to {}", failed, err, next);
+    }
+}
+
+// Runs `$call` on each provider of the chain until one succeeds, returning
+// the last error if all of them fail
+macro_rules! with_fallback {
+    ($chain:expr, |$provider:ident| $call:expr) => {{
+        let mut index = 0;
+        loop {
+            let $provider = $chain.providers[index].as_ref();
+            match $call {
+                Ok(answer) => break Ok(answer),
+                Err(err) if index + 1 < $chain.providers.len() => {
+                    $chain.switch(index, &err);
+                    index += 1;
+                }
+                Err(err) => break Err(err),
+            }
+        }
+    }};
+}
+
+#[async_trait]
+impl LlmProvider for FallbackChain {
+    async fn complete(&self, prompt: &str) -> Result<String, CrustagiError> {
+        with_fallback!(self, |provider| provider.complete(prompt).await)
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut (dyn for<'c> FnMut(&'c str) + Send),
+    ) -> Result<String, CrustagiError> {
+        with_fallback!(self, |provider| provider
+            .chat_stream(messages, on_chunk)
+            .await)
+    }
+
+    async fn complete_json(&self, prompt: &str, schema: &Value) -> Result<String, CrustagiError> {
+        with_fallback!(self, |provider| provider
+            .complete_json(prompt, schema)
+            .await)
+    }
+
+    async fn chat(&self, messages: &[ChatMessage]) -> Result<String, CrustagiError> {
+        with_fallback!(self, |provider| provider.chat(messages).await)
+    }
+
+    async fn chat_with_tools(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+    ) -> Result<ChatMessage, CrustagiError> {
+        with_fallback!(self, |provider| provider
+            .chat_with_tools(messages, tools)
+            .await)
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f64>, CrustagiError> {
+        self.primary().embed(text).await
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f64>>, CrustagiError> {
+        self.primary().embed_batch(texts).await
+    }
+
+    fn model(&self) -> String {
+        self.primary().model()
+    }
+
+    // Prompts have to fit every model they may end up at
+    fn prompt_budget(&self) -> usize {
+        self.providers
+            .iter()
+            .map(|provider| provider.prompt_budget())
+            .min()
+            .unwrap_or_default()
+    }
+
+    fn embedding_model(&self) -> &str {
+        self.primary().embedding_model()
+    }
+
+    async fn embedding_dimension(&self) -> Result<usize, CrustagiError> {
+        self.primary().embedding_dimension().await
+    }
+}
@@ -0,0 +1,320 @@
+// File tools for the execution agent, so that tasks can leave notes, code or
+// data behind as files. All paths are relative to a sandbox directory and
+// can't lead out of it.
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+use crate::error::CrustagiError;
+use crate::tools::Tool;
+
+// Files larger than this aren't read
+const MAX_READ_BYTES: u64 = 1_000_000;
+
+/// The directory the file tools work in
+pub struct Sandbox {
+    root: PathBuf,
+}
+
+impl Sandbox {
+    /// The sandbox at `dir`, created if it doesn't exist
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, CrustagiError> {
+        let dir = dir.as_ref();
+        let root = std::fs::create_dir_all(dir).and_then(|_| dir.canonicalize());
+        let root = root.map_err(|err| {
+            CrustagiError::io(
+                format!("Failed to create the sandbox directory {}", dir.display()),
+                err,
+            )
+        })?;
+        Ok(Sandbox { root })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    // The path in the sandbox that `path`, as the model wrote it, stands for.
+    // Absolute paths and `..` are refused, and so are paths through symbolic
+    // links, which could lead anywhere, dangling ones included.
+    fn resolve(&self, path: &str) -> Result<PathBuf, CrustagiError> {
+        let mut full = self.root.clone();
+        for component in Path::new(path).components() {
+            match component {
+                Component::CurDir => continue,
+                Component::Normal(name) => full.push(name),
+                _ => {
+                    return Err(format!(
+                        "{} is outside the sandbox, use a path relative to it without ..",
+                        path
+                    )
+                    .into())
+                }
+            }
+            if let Ok(metadata) = std::fs::symlink_metadata(&full) {
+                if metadata.file_type().is_symlink() {
+                    return Err(format!(
+                        "{} goes through a symbolic link, which the sandbox doesn't follow",
+                        path
+                    )
+                    .into());
+                }
+            }
+        }
+        Ok(full)
+    }
+}
+
+// The `path` argument of a call, `default` if there is none
+fn path_argument<'a>(
+    arguments: &'a Value,
+    default: Option<&'a str>,
+) -> Result<&'a str, CrustagiError> {
+    match arguments["path"].as_str().or(default) {
+        Some(path) => Ok(path),
+        None => Err("the path is missing".into()),
+    }
+}
+
+// An error of the file system, with the path as the model wrote it
+fn failed(path: &str, err: io::Error) -> CrustagiError {
+    format!("{}: {}", path, err).into()
+}
+
+/// The `read_file` tool: reads a text file of the sandbox
+pub struct ReadFile(pub Arc<Sandbox>);
+
+#[async_trait]
+impl Tool for ReadFile {
+    fn name(&self) -> &str {
+        "read_file"
+    }
+
+    fn description(&self) -> &str {
+        "Read a text file from the workspace directory."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": {
+                    "type": "string",
+                    "description": "Path of the file, relative to the workspace",
+                },
+            },
+            "required": ["path"],
+        })
+    }
+
+    async fn execute(&self, arguments: Value) -> Result<String, CrustagiError> {
+        let path = path_argument(&arguments, None)?;
+        let full = self.0.resolve(path)?;
+        let metadata = fs::metadata(&full).await.map_err(|err| failed(path, err))?;
+        if !metadata.is_file() {
+            return Err(format!("{} is not a file", path).into());
+        }
+        if metadata.len() > MAX_READ_BYTES {
+            return Err(format!("{} is larger than {} bytes", path, MAX_READ_BYTES).into());
+        }
+        let content = fs::read(&full).await?;
+        String::from_utf8(content).map_err(|_| format!("{} is not a text file", path).into())
+    }
+}
+
+/// The `write_file` tool: writes or appends to a file of the sandbox,
+/// creating the directories it is in
+pub struct WriteFile(pub Arc<Sandbox>);
+
+#[async_trait]
+impl Tool for WriteFile {
+    fn name(&self) -> &str {
+        "write_file"
+    }
+
+    fn description(&self) -> &str {
+        "Write a text file to the workspace directory, e.g. notes, code or CSV data. Replaces \
+         the file unless append is true."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": {
+                    "type": "string",
+                    "description": "Path of the file, relative to the workspace",
+                },
+                "content": { "type": "string" },
+                "append": {
+                    "type": "boolean",
+                    "description": "Add the content to the end of the file",
+                },
+            },
+            "required": ["path", "content"],
+        })
+    }
+
+    async fn execute(&self, arguments: Value) -> Result<String, CrustagiError> {
+        let path = path_argument(&arguments, None)?;
+        let content = arguments["content"]
+            .as_str()
+            .ok_or("the content is missing")?;
+        let append = arguments["append"].as_bool().unwrap_or(false);
+        let full = self.0.resolve(path)?;
+        if let Some(dir) = full.parent() {
+            fs::create_dir_all(dir)
+                .await
+                .map_err(|err| failed(path, err))?;
+        }
+        // A new file is created without following a link that took its place
+        // since the path was checked. An existing file is opened first and
+        // only emptied once it is known to be inside the sandbox.
+        let mut options = fs::OpenOptions::new();
+        let existing = fs::symlink_metadata(&full).await.is_ok();
+        if existing {
+            options.write(true).append(append);
+        } else {
+            options.write(true).create_new(true);
+        }
+        let mut file = options.open(&full).await.map_err(|err| failed(path, err))?;
+        if existing {
+            let opened = fs::canonicalize(&full)
+                .await
+                .map_err(|err| failed(path, err))?;
+            if !opened.starts_with(self.0.root()) {
+                return Err(format!(
+                    "{} goes through a symbolic link, which the sandbox doesn't follow",
+                    path
+                )
+                .into());
+            }
+            if !append {
+                file.set_len(0).await?;
+            }
+        }
+        file.write_all(content.as_bytes()).await?;
+        file.flush().await?;
+        tracing::info!("Wrote {} bytes to {}", content.len(), full.display());
+        Ok(format!("Wrote {} bytes to {}", content.len(), path))
+    }
+}
+
+/// The `list_files` tool: lists a directory of the sandbox
+pub struct ListFiles(pub Arc<Sandbox>);
+
+#[async_trait]
+impl Tool for ListFiles {
+    fn name(&self) -> &str {
+        "list_files"
+    }
+
+    fn description(&self) -> &str {
+        "List the files and directories in a directory of the workspace, with the size of \
+         each file. Directories end with /."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": {
+                    "type": "string",
+                    "description": "Directory relative to the workspace, the workspace itself if not given",
+                },
+            },
+        })
+    }
+
+    async fn execute(&self, arguments: Value) -> Result<String, CrustagiError> {
+        let path = path_argument(&arguments, Some("."))?;
+        let full = self.0.resolve(path)?;
+        let mut entries = Vec::new();
+        let mut dir = fs::read_dir(&full).await.map_err(|err| failed(path, err))?;
+        while let Some(entry) = dir.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let metadata = entry.metadata().await?;
+            entries.push(if metadata.is_dir() {
+                format!("{}/", name)
+            } else {
+                format!("{} ({} bytes)", name, metadata.len())
+            });
+        }
+        if entries.is_empty() {
+            return Ok(format!("{} is empty", path));
+        }
+        entries.sort();
+        Ok(entries.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A new sandbox in the temp directory, removed by the test
+    fn sandbox(name: &str) -> Sandbox {
+        let dir = std::env::temp_dir().join(format!("crustagi_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        Sandbox::open(dir).unwrap()
+    }
+
+    #[test]
+    fn resolves_paths_within_the_sandbox() {
+        let sandbox = sandbox("resolve");
+        let root = sandbox.root().to_path_buf();
+        assert_eq!(sandbox.resolve("notes.md").unwrap(), root.join("notes.md"));
+        assert_eq!(
+            sandbox.resolve("./data/./out.csv").unwrap(),
+            root.join("data/out.csv")
+        );
+        assert_eq!(sandbox.resolve(".").unwrap(), root);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn refuses_paths_leading_out() {
+        let sandbox = sandbox("escape");
+        for path in ["../secret", "data/../../secret", "data/..", "/etc/passwd"] {
+            let err = sandbox.resolve(path).unwrap_err();
+            assert!(err.to_string().contains("outside the sandbox"), "{}", path);
+        }
+        std::fs::remove_dir_all(sandbox.root()).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_paths_through_symbolic_links() {
+        use std::os::unix::fs::symlink;
+
+        let sandbox = sandbox("symlink");
+        let root = sandbox.root();
+        symlink("/etc", root.join("etc")).unwrap();
+        symlink(root.join("missing"), root.join("dangling")).unwrap();
+        for path in ["etc", "etc/passwd", "dangling", "./dangling/new.txt"] {
+            let err = sandbox.resolve(path).unwrap_err();
+            assert!(err.to_string().contains("symbolic link"), "{}", path);
+        }
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn replaces_and_appends_to_files() {
+        let sandbox = Arc::new(sandbox("write"));
+        let write = WriteFile(sandbox.clone());
+        let read = ReadFile(sandbox.clone());
+        for (content, append) in [("first", false), ("second", false), (" third", true)] {
+            let arguments =
+                json!({ "path": "data/notes.md", "content": content, "append": append });
+            write.execute(arguments).await.unwrap();
+        }
+        let content = read.execute(json!({ "path": "data/notes.md" })).await;
+        std::fs::remove_dir_all(sandbox.root()).unwrap();
+        assert_eq!(content.unwrap(), "second third");
+    }
+}
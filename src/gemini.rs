@@ -0,0 +1,278 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::error::CrustagiError;
+use crate::llm::{json_prompt, ChatMessage, LlmParams, LlmProvider};
+use crate::logging;
+use crate::usage;
+
+const GEMINI_API_BASE: &str = "https://generativelanguage.googleapis.com/v1beta";
+pub const DEFAULT_GEMINI_MODEL: &str = "gemini-2.0-flash";
+pub const DEFAULT_GEMINI_EMBEDDING_MODEL: &str = "text-embedding-004";
+
+// Size of the vectors returned by Gemini's embedding models, None for
+// models not listed here
+fn known_embedding_dimension(model: &str) -> Option<usize> {
+    match model {
+        "text-embedding-004" | "embedding-001" => Some(768),
+        "gemini-embedding-001" => Some(3072),
+        _ => None,
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct GenerateContentResponse {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+    #[serde(rename = "usageMetadata")]
+    usage_metadata: Option<UsageMetadata>,
+}
+
+#[derive(Deserialize, Debug)]
+struct UsageMetadata {
+    #[serde(default, rename = "promptTokenCount")]
+    prompt_token_count: usize,
+    #[serde(default, rename = "candidatesTokenCount")]
+    candidates_token_count: usize,
+}
+
+#[derive(Deserialize, Debug)]
+struct Candidate {
+    content: Option<Content>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Content {
+    #[serde(default)]
+    parts: Vec<Part>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Part {
+    text: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct EmbedContentResponse {
+    embedding: ContentEmbedding,
+}
+
+#[derive(Deserialize, Debug)]
+struct BatchEmbedContentsResponse {
+    embeddings: Vec<ContentEmbedding>,
+}
+
+// Most requests Gemini accepts in one batchEmbedContents call
+const EMBED_BATCH: usize = 100;
+
+#[derive(Deserialize, Debug)]
+struct ContentEmbedding {
+    values: Vec<f64>,
+}
+
+// Google Gemini API client, using the generateContent and embedContent endpoints
+#[derive(Clone)]
+pub struct GeminiClient {
+    http: Client,
+    api_key: String,
+    model: String,
+    embedding_model: String,
+    params: LlmParams,
+}
+
+impl GeminiClient {
+    pub fn new(http: Client, api_key: &str) -> Self {
+        GeminiClient {
+            http,
+            api_key: api_key.to_string(),
+            model: DEFAULT_GEMINI_MODEL.to_string(),
+            embedding_model: DEFAULT_GEMINI_EMBEDDING_MODEL.to_string(),
+            params: LlmParams::default(),
+        }
+    }
+
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = model.to_string();
+        self
+    }
+
+    pub fn with_embedding_model(mut self, model: &str) -> Self {
+        self.embedding_model = model.to_string();
+        self
+    }
+
+    pub fn with_params(mut self, params: LlmParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    #[tracing::instrument(skip_all, fields(method, model))]
+    async fn call(&self, model: &str, method: &str, body: Value) -> Result<Value, CrustagiError> {
+        let url = format!("{}/models/{}:{}", GEMINI_API_BASE, model, method);
+        tracing::debug!("Calling Gemini API...");
+        logging::request("Gemini", method, &body);
+        let res = self
+            .http
+            .post(url)
+            .header("x-goog-api-key", &self.api_key)
+            .json(&body)
+            .send()
+            .await?;
+        let status = res.status();
+        let body: Value = res.json().await?;
+        logging::response("Gemini", method, &body);
+        if !status.is_success() {
+            let message = body["error"]["message"].as_str().unwrap_or_default();
+            return Err(format!("Gemini returned {}: {}", status, message).into());
+        }
+        Ok(body)
+    }
+
+    fn generation_config(&self, response_mime_type: Option<&str>) -> Value {
+        let params = &self.params;
+        let mut config = json!({});
+        if let Some(temperature) = params.temperature {
+            config["temperature"] = json!(temperature);
+        }
+        if let Some(max_tokens) = params.max_tokens {
+            config["maxOutputTokens"] = json!(max_tokens);
+        }
+        if let Some(top_p) = params.top_p {
+            config["topP"] = json!(top_p);
+        }
+        if let Some(presence_penalty) = params.presence_penalty {
+            config["presencePenalty"] = json!(presence_penalty);
+        }
+        if let Some(frequency_penalty) = params.frequency_penalty {
+            config["frequencyPenalty"] = json!(frequency_penalty);
+        }
+        if !params.stop.is_empty() {
+            config["stopSequences"] = json!(params.stop);
+        }
+        if let Some(seed) = params.seed {
+            config["seed"] = json!(seed);
+        }
+        if let Some(response_mime_type) = response_mime_type {
+            config["responseMimeType"] = json!(response_mime_type);
+        }
+        config
+    }
+
+    // System messages become the system instruction, assistant turns are
+    // called "model" by Gemini
+    async fn generate(
+        &self,
+        messages: &[ChatMessage],
+        response_mime_type: Option<&str>,
+    ) -> Result<String, CrustagiError> {
+        let mut system = Vec::new();
+        let mut contents = Vec::new();
+        for message in &self.params.with_system_prompt(messages) {
+            match message.role.as_str() {
+                "system" => system.push(json!({ "text": message.content })),
+                "assistant" => contents.push(json!({
+                    "role": "model",
+                    "parts": [{ "text": message.content }]
+                })),
+                _ => contents.push(json!({
+                    "role": "user",
+                    "parts": [{ "text": message.content }]
+                })),
+            }
+        }
+        let mut body = json!({ "contents": contents });
+        if !system.is_empty() {
+            body["systemInstruction"] = json!({ "parts": system });
+        }
+        body["generationConfig"] = self.generation_config(response_mime_type);
+
+        let res: GenerateContentResponse =
+            serde_json::from_value(self.call(&self.model, "generateContent", body).await?)?;
+        if let Some(metadata) = &res.usage_metadata {
+            usage::report(
+                &self.model,
+                metadata.prompt_token_count,
+                metadata.candidates_token_count,
+            );
+        }
+        let parts = res
+            .candidates
+            .into_iter()
+            .next()
+            .and_then(|candidate| candidate.content)
+            .ok_or("Gemini returned no candidates")?
+            .parts;
+        Ok(parts.into_iter().filter_map(|part| part.text).collect())
+    }
+}
+
+#[async_trait]
+impl LlmProvider for GeminiClient {
+    // Gemini's response schemas are a subset of JSON schema, so the schema
+    // only goes into the prompt
+    async fn complete_json(&self, prompt: &str, schema: &Value) -> Result<String, CrustagiError> {
+        let messages = [ChatMessage::user(&json_prompt(prompt, schema))];
+        self.generate(&messages, Some("application/json")).await
+    }
+
+    async fn chat(&self, messages: &[ChatMessage]) -> Result<String, CrustagiError> {
+        self.generate(messages, None).await
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f64>, CrustagiError> {
+        let body = json!({
+            "model": format!("models/{}", self.embedding_model),
+            "content": { "parts": [{ "text": text }] }
+        });
+        let res: EmbedContentResponse = serde_json::from_value(
+            self.call(&self.embedding_model, "embedContent", body)
+                .await?,
+        )?;
+        Ok(res.embedding.values)
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f64>>, CrustagiError> {
+        let model = format!("models/{}", self.embedding_model);
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(EMBED_BATCH) {
+            let requests: Vec<Value> = batch
+                .iter()
+                .map(|text| json!({ "model": model, "content": { "parts": [{ "text": text }] } }))
+                .collect();
+            let res: BatchEmbedContentsResponse = serde_json::from_value(
+                self.call(
+                    &self.embedding_model,
+                    "batchEmbedContents",
+                    json!({ "requests": requests }),
+                )
+                .await?,
+            )?;
+            if res.embeddings.len() != batch.len() {
+                return Err("Gemini returned fewer embeddings than texts".into());
+            }
+            embeddings.extend(res.embeddings.into_iter().map(|embedding| embedding.values));
+        }
+        Ok(embeddings)
+    }
+
+    fn model(&self) -> String {
+        self.model.clone()
+    }
+
+    fn prompt_budget(&self) -> usize {
+        self.params.prompt_budget(&self.model)
+    }
+
+    fn embedding_model(&self) -> &str {
+        &self.embedding_model
+    }
+
+    async fn embedding_dimension(&self) -> Result<usize, CrustagiError> {
+        match known_embedding_dimension(&self.embedding_model) {
+            Some(dimension) => Ok(dimension),
+            None => Ok(self.embed("dimension probe").await?.len()),
+        }
+    }
+}
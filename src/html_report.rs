@@ -0,0 +1,400 @@
+// Report of a run as a self-contained HTML page, made from its events file
+// alone, for sharing the outcome with people who don't use the terminal. It
+// shows the tasks as a tree of the tasks each one led to, with their
+// results, and charts of the cost over the run and the tokens per agent.
+
+use handlebars::Handlebars;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::progress::format_duration;
+
+const TEMPLATE: &str = include_str!("report.html.hbs");
+
+// Size of the cost chart, in SVG units
+const CHART_WIDTH: f64 = 600.0;
+const CHART_HEIGHT: f64 = 160.0;
+
+// A task as its events tell it
+#[derive(Default)]
+struct TaskEvents {
+    name: String,
+    depends_on: Vec<i64>,
+    started_ms: Option<u64>,
+    finished_ms: Option<u64>,
+    result: Option<String>,
+    error: Option<String>,
+    // Failed for good, after its retries
+    failed: bool,
+}
+
+// What the events of a run add up to
+#[derive(Default)]
+struct RunEvents {
+    objective: String,
+    outcome: Option<String>,
+    iterations: usize,
+    first_ms: Option<u64>,
+    last_ms: u64,
+    tasks: BTreeMap<i64, TaskEvents>,
+    // Tasks created after each task
+    children: BTreeMap<i64, Vec<i64>>,
+    // Cost of the run after each iteration, counted across resumed runs
+    costs: Vec<f64>,
+    // Usage per agent and model of the runs before the last resume
+    resumed_agents: BTreeMap<(String, String), AgentTotals>,
+    agents: BTreeMap<(String, String), AgentTotals>,
+}
+
+#[derive(Default, Clone, Copy)]
+struct AgentTotals {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    cost_usd: Option<f64>,
+}
+
+impl AgentTotals {
+    fn add(&mut self, other: AgentTotals) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.cost_usd = match (self.cost_usd, other.cost_usd) {
+            (None, None) => None,
+            (a, b) => Some(a.unwrap_or_default() + b.unwrap_or_default()),
+        };
+    }
+}
+
+impl RunEvents {
+    fn read(path: &str) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut run = RunEvents::default();
+        // Cost of the runs before the last resume
+        let mut resumed_cost = 0.0;
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            // A line cut off by a crash is skipped, like unknown events
+            let Ok(event) = serde_json::from_str::<Value>(line) else {
+                continue;
+            };
+            run.add(&event, &mut resumed_cost);
+        }
+        Ok(run)
+    }
+
+    fn add(&mut self, event: &Value, resumed_cost: &mut f64) {
+        let time_ms = event["time_ms"].as_u64().unwrap_or_default();
+        self.first_ms.get_or_insert(time_ms);
+        self.last_ms = self.last_ms.max(time_ms);
+        let text = |field: &str| event[field].as_str().unwrap_or_default().to_string();
+        let task_id = event["task_id"].as_i64().unwrap_or_default();
+        match event["event"].as_str().unwrap_or_default() {
+            "run_started" => {
+                if let Some(objectives) = event["objectives"].as_array() {
+                    let objectives: Vec<&str> =
+                        objectives.iter().filter_map(Value::as_str).collect();
+                    self.objective = match objectives.as_slice() {
+                        [objective] => objective.to_string(),
+                        _ => "Several objectives".to_string(),
+                    };
+                }
+                // Usage starts over with each resumed run
+                *resumed_cost = self.costs.last().copied().unwrap_or_default();
+                for (key, totals) in std::mem::take(&mut self.agents) {
+                    self.resumed_agents.entry(key).or_default().add(totals);
+                }
+            }
+            "task_created" => {
+                let task = self.tasks.entry(task_id).or_default();
+                task.name = text("task_name");
+                task.depends_on = event["depends_on"]
+                    .as_array()
+                    .map(|ids| ids.iter().filter_map(Value::as_i64).collect())
+                    .unwrap_or_default();
+            }
+            "task_started" => {
+                let task = self.tasks.entry(task_id).or_default();
+                task.name = text("task_name");
+                task.started_ms = Some(time_ms);
+            }
+            "task_result" => {
+                let task = self.tasks.entry(task_id).or_default();
+                task.result = Some(text("result"));
+                task.finished_ms = Some(time_ms);
+                task.failed = false;
+            }
+            "task_failed" => {
+                let task = self.tasks.entry(task_id).or_default();
+                task.error = Some(text("error"));
+                task.failed = event["retried"] == Value::Bool(false);
+            }
+            "new_tasks" => {
+                let new_task_ids = event["new_task_ids"].as_array();
+                self.children
+                    .entry(task_id)
+                    .or_default()
+                    .extend(new_task_ids.into_iter().flatten().filter_map(Value::as_i64));
+            }
+            "usage" => {
+                let cost = event["cost_usd"].as_f64().unwrap_or_default();
+                self.costs.push(*resumed_cost + cost);
+                self.agents = event["agents"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|agent| {
+                        let key = (
+                            agent["agent"].as_str().unwrap_or_default().to_string(),
+                            agent["model"].as_str().unwrap_or_default().to_string(),
+                        );
+                        let totals = AgentTotals {
+                            prompt_tokens: agent["prompt_tokens"].as_u64().unwrap_or_default(),
+                            completion_tokens: agent["completion_tokens"]
+                                .as_u64()
+                                .unwrap_or_default(),
+                            cost_usd: agent["cost_usd"].as_f64(),
+                        };
+                        (key, totals)
+                    })
+                    .collect();
+            }
+            "run_finished" => {
+                self.outcome = Some(text("reason"));
+                self.iterations += event["iterations"].as_u64().unwrap_or_default() as usize;
+            }
+            _ => {}
+        }
+    }
+
+    // Usage per agent and model over all runs, resumed ones included
+    fn agent_totals(&self) -> BTreeMap<(String, String), AgentTotals> {
+        let mut totals = self.resumed_agents.clone();
+        for (key, usage) in &self.agents {
+            totals.entry(key.clone()).or_default().add(*usage);
+        }
+        totals
+    }
+}
+
+// What the template shows
+#[derive(Serialize)]
+struct Page {
+    title: String,
+    // The rest of an objective of several lines
+    brief: String,
+    outcome: String,
+    iterations: usize,
+    duration: String,
+    completed: usize,
+    failed: usize,
+    pending: usize,
+    tokens: u64,
+    cost: String,
+    tree: Vec<TaskNode>,
+    cost_chart: Option<CostChart>,
+    agents: Vec<AgentRow>,
+}
+
+#[derive(Serialize)]
+struct TaskNode {
+    id: i64,
+    name: String,
+    // completed, failed or pending
+    status: &'static str,
+    after: String,
+    duration: String,
+    result: Option<String>,
+    error: Option<String>,
+    children: Vec<TaskNode>,
+}
+
+#[derive(Serialize)]
+struct CostChart {
+    width: f64,
+    height: f64,
+    points: String,
+    max_cost: String,
+    iterations: usize,
+}
+
+#[derive(Serialize)]
+struct AgentRow {
+    agent: String,
+    model: String,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    cost: String,
+    // Bar widths, in percent of the agent using the most tokens
+    prompt_width: f64,
+    completion_width: f64,
+}
+
+impl RunEvents {
+    fn page(&self) -> Page {
+        let (title, brief) = match self.objective.split_once('\n') {
+            Some((title, rest)) => (title.trim().to_string(), rest.trim().to_string()),
+            None => (self.objective.clone(), String::new()),
+        };
+        let count = |status: &str| {
+            self.tasks
+                .values()
+                .filter(|task| status_of(task) == status)
+                .count()
+        };
+        let agents = self.agent_totals();
+        let most_tokens = agents
+            .values()
+            .map(|usage| usage.prompt_tokens + usage.completion_tokens)
+            .max()
+            .unwrap_or_default()
+            .max(1) as f64;
+        let agents: Vec<AgentRow> = agents
+            .into_iter()
+            .map(|((agent, model), usage)| AgentRow {
+                agent,
+                model,
+                prompt_tokens: usage.prompt_tokens,
+                completion_tokens: usage.completion_tokens,
+                cost: usage.cost_usd.map_or_else(
+                    || "unknown price".to_string(),
+                    |cost| format!("${:.4}", cost),
+                ),
+                prompt_width: percent(usage.prompt_tokens, most_tokens),
+                completion_width: percent(usage.completion_tokens, most_tokens),
+            })
+            .collect();
+        Page {
+            title,
+            brief,
+            outcome: self
+                .outcome
+                .clone()
+                .unwrap_or_else(|| "Still running, or stopped without finishing".to_string()),
+            iterations: self.iterations,
+            duration: format_duration(Duration::from_millis(
+                self.last_ms - self.first_ms.unwrap_or(self.last_ms),
+            )),
+            completed: count("completed"),
+            failed: count("failed"),
+            pending: count("pending"),
+            tokens: agents
+                .iter()
+                .map(|agent| agent.prompt_tokens + agent.completion_tokens)
+                .sum(),
+            cost: format!("${:.4}", self.costs.last().copied().unwrap_or_default()),
+            tree: self.tree(),
+            cost_chart: self.cost_chart(),
+            agents,
+        }
+    }
+
+    // The tasks no other task created at the top, each followed by those it
+    // led to
+    fn tree(&self) -> Vec<TaskNode> {
+        let created: BTreeSet<i64> = self.children.values().flatten().copied().collect();
+        let mut shown = BTreeSet::new();
+        self.tasks
+            .keys()
+            .filter(|task_id| !created.contains(task_id))
+            .filter_map(|&task_id| self.node(task_id, &mut shown))
+            .collect()
+    }
+
+    fn node(&self, task_id: i64, shown: &mut BTreeSet<i64>) -> Option<TaskNode> {
+        let task = self.tasks.get(&task_id)?;
+        // Each task appears once, even if several tasks proposed it
+        if !shown.insert(task_id) {
+            return None;
+        }
+        let children = self
+            .children
+            .get(&task_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|&child| self.node(child, shown))
+            .collect();
+        Some(TaskNode {
+            id: task_id,
+            name: task.name.clone(),
+            status: status_of(task),
+            after: task
+                .depends_on
+                .iter()
+                .map(i64::to_string)
+                .collect::<Vec<_>>()
+                .join(", "),
+            duration: match (task.started_ms, task.finished_ms) {
+                (Some(started), Some(finished)) => {
+                    format_duration(Duration::from_millis(finished.saturating_sub(started)))
+                }
+                _ => String::new(),
+            },
+            result: task.result.as_ref().map(|result| result.trim().to_string()),
+            error: task.error.clone(),
+            children,
+        })
+    }
+
+    // Cost after each iteration as a line, None before the first one is done
+    fn cost_chart(&self) -> Option<CostChart> {
+        if self.costs.is_empty() {
+            return None;
+        }
+        let max_cost = self.costs.iter().copied().fold(0.0, f64::max);
+        // The line starts at no cost before the first iteration
+        let points = std::iter::once(0.0)
+            .chain(self.costs.iter().copied())
+            .enumerate()
+            .map(|(index, cost)| {
+                let x = index as f64 * CHART_WIDTH / self.costs.len() as f64;
+                let y = if max_cost > 0.0 {
+                    CHART_HEIGHT - cost / max_cost * CHART_HEIGHT
+                } else {
+                    CHART_HEIGHT
+                };
+                format!("{:.1},{:.1}", x, y)
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        Some(CostChart {
+            width: CHART_WIDTH,
+            height: CHART_HEIGHT,
+            points,
+            max_cost: format!("${:.4}", max_cost),
+            iterations: self.costs.len(),
+        })
+    }
+}
+
+// `part` in percent of `whole`, rounded to a tenth
+fn percent(part: u64, whole: f64) -> f64 {
+    (part as f64 * 1000.0 / whole).round() / 10.0
+}
+
+fn status_of(task: &TaskEvents) -> &'static str {
+    match (&task.result, task.failed) {
+        (Some(_), _) => "completed",
+        (None, true) => "failed",
+        (None, false) => "pending",
+    }
+}
+
+// Write the report of the run recorded in the events file `events_path` to
+// `path`, creating its directory
+pub fn write(events_path: &str, path: &str) -> io::Result<()> {
+    let run = RunEvents::read(events_path)?;
+    let mut registry = Handlebars::new();
+    registry
+        .register_template_string("report", TEMPLATE)
+        .expect("The HTML report template is valid");
+    let html = registry
+        .render("report", &run.page())
+        .map_err(io::Error::other)?;
+    if let Some(dir) = Path::new(path).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, html)
+}
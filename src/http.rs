@@ -0,0 +1,89 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use reqwest::redirect::Policy;
+use reqwest::{Client, Proxy};
+use tokio::time::sleep;
+
+use crate::error::CrustagiError;
+
+// How long to wait for a TCP/TLS connection to be established
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// Pooled connections unused for this long are closed
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+// Delay before retry number `attempt` (starting at 1), doubling from `base`
+// up to `max`. The delay is between half and all of that, so that concurrent
+// clients don't retry in lockstep.
+pub fn backoff(attempt: u32, base: Duration, max: Duration) -> Duration {
+    let delay = base.saturating_mul(1 << (attempt - 1).min(16)).min(max);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    delay.mul_f64(0.5 + f64::from(nanos % 1000) / 2000.0)
+}
+
+// Hands out slots for requests, spread evenly over the minute in the order
+// requests arrive, so that a burst of concurrent requests is sent one after
+// the other
+pub struct RateLimiter {
+    // When the next request may be sent
+    next_request: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    pub const fn new() -> Self {
+        RateLimiter {
+            next_request: Mutex::new(None),
+        }
+    }
+
+    // Wait for the next free slot at `requests_per_minute`, which must not
+    // be 0
+    pub async fn wait(&self, requests_per_minute: u32) {
+        let interval = Duration::from_secs(60) / requests_per_minute;
+        let wait = {
+            let mut next_request = self.next_request.lock().unwrap();
+            let now = Instant::now();
+            let slot = next_request.map_or(now, |next| next.max(now));
+            *next_request = Some(slot + interval);
+            slot - now
+        };
+        if !wait.is_zero() {
+            sleep(wait).await;
+        }
+    }
+}
+
+// Build the HTTP client shared by the OpenAI and Pinecone clients, so that
+// connections are pooled across all their requests. `timeout` bounds each
+// request as a whole. Without an explicit `proxy`, the standard
+// `HTTP_PROXY`/`HTTPS_PROXY` variables are honored. Without
+// `follow_redirects`, redirects are returned to the caller.
+pub fn build_client(
+    timeout: Duration,
+    proxy: Option<&str>,
+    follow_redirects: bool,
+) -> Result<Client, CrustagiError> {
+    let redirects = if follow_redirects {
+        Policy::default()
+    } else {
+        Policy::none()
+    };
+    let builder = Client::builder()
+        .timeout(timeout)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .redirect(redirects);
+    let builder = match proxy {
+        Some(url) => builder.proxy(
+            Proxy::all(url)
+                .map_err(|err| CrustagiError::Config(format!("Invalid PROXY_URL: {}", err)))?,
+        ),
+        None => builder,
+    };
+    builder
+        .build()
+        .map_err(|err| CrustagiError::Config(format!("Failed to build HTTP client: {}", err)))
+}
@@ -0,0 +1,99 @@
+use async_trait::async_trait;
+use std::sync::Mutex;
+
+use crate::error::CrustagiError;
+use crate::memory::{
+    cosine_similarity, Match, Metadata, StoredVector, VectorMetadata, VectorStore,
+};
+
+struct Entry {
+    id: String,
+    vector: Vec<f64>,
+    metadata: VectorMetadata,
+}
+
+// Process-local vector store using brute-force cosine similarity.
+// Nothing is persisted, so memory is lost when the program exits.
+#[derive(Default)]
+pub struct InMemoryStore {
+    entries: Mutex<Vec<Entry>>,
+}
+
+impl InMemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl VectorStore for InMemoryStore {
+    async fn create(&self) -> Result<(), CrustagiError> {
+        Ok(())
+    }
+
+    async fn upsert(
+        &self,
+        id: &str,
+        vector: &[f64],
+        metadata: &VectorMetadata,
+    ) -> Result<usize, CrustagiError> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|e| e.id != id);
+        entries.push(Entry {
+            id: id.to_string(),
+            vector: vector.to_vec(),
+            metadata: metadata.clone(),
+        });
+        Ok(1)
+    }
+
+    async fn query(
+        &self,
+        vector: &[f64],
+        top_k: usize,
+        filter: Option<&Metadata>,
+    ) -> Result<Vec<Match>, CrustagiError> {
+        let entries = self.entries.lock().unwrap();
+        let mut matches: Vec<Match> = entries
+            .iter()
+            .filter(|e| filter.is_none_or(|filter| e.metadata.matches(filter)))
+            .map(|e| Match {
+                id: e.id.clone(),
+                score: cosine_similarity(vector, &e.vector),
+                metadata: Some(e.metadata.clone()),
+            })
+            .collect();
+        matches.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        matches.truncate(top_k);
+        Ok(matches)
+    }
+
+    async fn delete(&self, ids: &[String]) -> Result<(), CrustagiError> {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|e| !ids.contains(&e.id));
+        Ok(())
+    }
+
+    async fn dump(&self) -> Result<Vec<StoredVector>, CrustagiError> {
+        let entries = self.entries.lock().unwrap();
+        Ok(entries
+            .iter()
+            .map(|e| StoredVector {
+                id: e.id.clone(),
+                vector: e.vector.clone(),
+                metadata: Some(e.metadata.clone()),
+            })
+            .collect())
+    }
+
+    async fn reset(&self) -> Result<(), CrustagiError> {
+        self.entries.lock().unwrap().clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,23 @@
+use std::io::{self, BufRead, IsTerminal};
+use std::thread;
+use tokio::sync::mpsc;
+
+// Tasks typed into stdin while the run goes on, one per line. Lines are read
+// on a plain thread, as a pending read on tokio's stdin would keep the
+// runtime from shutting down at the end of the run.
+pub fn listen() -> mpsc::UnboundedReceiver<String> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    if io::stdin().is_terminal() {
+        tracing::info!("Type a task and press Enter to add it to the task list.");
+    }
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            let task_name = line.trim();
+            if !task_name.is_empty() && sender.send(task_name.to_string()).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
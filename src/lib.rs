@@ -0,0 +1,102 @@
+//! CrustAGI: an agent that creates, prioritizes and executes tasks towards an
+//! objective, in the manner of BabyAGI.
+//!
+//! The `crustagi` binary of the `crustagi-cli` crate is a thin command line
+//! around this library. Programs embedding the agent either call [`run`],
+//! which reads its configuration from the environment like the binary, or
+//! set up an [`Engine`] and the task lists of the objectives themselves and
+//! hand them to [`run_loop`]:
+//!
+//! - [`Agent`] runs a single task: it executes the task with the context
+//!   found in memory, stores the result and proposes new tasks. Execution,
+//!   the context lookup and task creation are each an [`ExecutionAgent`],
+//!   [`ContextAgent`] and [`TaskCreationAgent`], and the loop reorders the
+//!   tasks with a [`PrioritizationAgent`]. The `Llm` implementations in
+//!   [`agents`] ask the models.
+//! - [`TaskQueue`] is the task list of an objective, ordered by priority and
+//!   by the dependencies between tasks.
+//! - [`run_loop`] works through the task lists until all tasks are done or
+//!   the run is stopped, and writes the report.
+//!
+//! The LLM providers implement [`llm::LlmProvider`] and the vector stores
+//! [`memory::VectorStore`]. An [`Observer`] added to the engine is told as
+//! tasks start, complete, fail or create new tasks. A [`Tool`] lets the
+//! execution agent act, e.g. search or read files, before it answers. Plugins
+//! registered with [`plugins::register`] add enrichers, memory backends and
+//! tools to all runs.
+
+pub mod agents;
+mod app;
+mod builder;
+mod chroma;
+mod cohere;
+pub mod command;
+pub mod config;
+pub mod config_file;
+pub mod diagnostics;
+mod embedding_cache;
+pub mod engine;
+pub mod enrich;
+pub mod error;
+pub mod events;
+mod fallback;
+pub mod files;
+mod gemini;
+mod html_report;
+mod http;
+pub mod in_memory;
+mod inbox;
+pub mod llm;
+mod local;
+mod logging;
+pub mod memory;
+pub mod middleware;
+mod milvus;
+pub mod mock;
+pub mod objectives;
+pub mod observer;
+pub mod openai;
+mod pgvector;
+mod pinecone;
+pub mod plugins;
+mod progress;
+pub mod prompts;
+mod qdrant;
+pub mod replay;
+mod report;
+pub mod rerank;
+mod run;
+mod sanitize;
+mod shutdown;
+mod snapshot;
+mod sparse;
+mod sqlite;
+mod state;
+pub mod task;
+pub mod task_dedup;
+pub mod task_graph;
+mod task_ids;
+mod telemetry;
+mod tokens;
+pub mod tools;
+mod tui;
+pub mod usage;
+mod weaviate;
+pub mod web_fetch;
+pub mod web_search;
+mod webhooks;
+
+pub use agents::{ContextAgent, ExecutionAgent, PrioritizationAgent, TaskCreationAgent};
+pub use app::{load_config, run};
+pub use builder::CrustagiBuilder;
+pub use command::{Command, MemoryAction, ReportFormat, RunArgs};
+pub use config::Config;
+pub use engine::{
+    run_loop, Agent, Engine, ObjectiveOutcome, ObjectiveRun, RunOutcome, StopReason, TaskOutcome,
+};
+pub use error::CrustagiError;
+pub use observer::Observer;
+pub use task::{Task, TaskStatus};
+pub use task_graph::TaskGraph as TaskQueue;
+pub use tools::Tool;
+pub use tui::Dashboard;
@@ -0,0 +1,348 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::cohere::CohereClient;
+use crate::embedding_cache::{CachedEmbeddings, EmbeddingCache};
+use crate::error::CrustagiError;
+use crate::fallback::FallbackChain;
+use crate::gemini::GeminiClient;
+use crate::middleware::{Layer, RateLimitLayer};
+use crate::mock::MockLlm;
+use crate::openai::{ApiMode, OpenAiClient};
+use crate::tokens;
+use crate::Config;
+
+// A message of a chat conversation, in the OpenAI role/content format
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+    // Tools an assistant message calls, instead of or besides its content
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    // The call a `tool` message holds the output of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl ChatMessage {
+    fn new(role: &str, content: &str) -> Self {
+        ChatMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+
+    pub fn system(content: &str) -> Self {
+        ChatMessage::new("system", content)
+    }
+
+    pub fn user(content: &str) -> Self {
+        ChatMessage::new("user", content)
+    }
+
+    pub fn assistant(content: &str) -> Self {
+        ChatMessage::new("assistant", content)
+    }
+
+    // The output of the tool call `tool_call_id`
+    pub fn tool(tool_call_id: &str, content: &str) -> Self {
+        ChatMessage {
+            tool_call_id: Some(tool_call_id.to_string()),
+            ..ChatMessage::new("tool", content)
+        }
+    }
+}
+
+// A function the model may call, with a JSON schema of its arguments
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolSpec {
+    pub name: String,
+    pub description: String,
+    pub parameters: Value,
+}
+
+// A call of a tool the model asked for. The arguments are the JSON text the
+// model wrote, which may not match the schema or be JSON at all.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: String,
+}
+
+// Sampling parameters and system prompt of an agent's calls. Unset values
+// keep the provider's defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LlmParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    // Sampling seed, for providers that support one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    // Context window overriding the model's, from CONTEXT_WINDOW
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<usize>,
+}
+
+impl LlmParams {
+    // The parameters with sampling made as repeatable as the provider
+    // allows: temperature 0, no nucleus sampling and a fixed seed
+    pub fn pinned(self, seed: u64) -> Self {
+        LlmParams {
+            temperature: Some(0.0),
+            top_p: None,
+            seed: Some(self.seed.unwrap_or(seed)),
+            ..self
+        }
+    }
+
+    // `messages` preceded by the system prompt, if there is one
+    pub fn with_system_prompt(&self, messages: &[ChatMessage]) -> Vec<ChatMessage> {
+        self.system_prompt
+            .iter()
+            .map(|system_prompt| ChatMessage::system(system_prompt))
+            .chain(messages.iter().cloned())
+            .collect()
+    }
+
+    // Tokens left for the prompt of `model` after the answer and the system prompt
+    pub fn prompt_budget(&self, model: &str) -> usize {
+        let system_tokens = self.system_prompt.as_deref().map_or(0, |system_prompt| {
+            tokens::count_tokens(model, system_prompt)
+        });
+        tokens::prompt_budget(model, self.context_window, self.max_tokens)
+            .saturating_sub(system_tokens)
+    }
+}
+
+// Text generation and embeddings used by the agents, so that providers other
+// than OpenAI can be plugged in
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    // Answer a single prompt
+    async fn complete(&self, prompt: &str) -> Result<String, CrustagiError> {
+        self.chat(&[ChatMessage::user(prompt)]).await
+    }
+
+    // Answer the last message of a conversation like `chat`, passing the
+    // answer to `on_chunk` piece by piece as it is generated. Providers
+    // without streaming pass it in one piece.
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut (dyn for<'c> FnMut(&'c str) + Send),
+    ) -> Result<String, CrustagiError> {
+        let text = self.chat(messages).await?;
+        on_chunk(&text);
+        Ok(text)
+    }
+
+    // Answer a single prompt with JSON matching `schema`. Providers without a
+    // JSON mode only get the schema as part of the prompt.
+    async fn complete_json(&self, prompt: &str, schema: &Value) -> Result<String, CrustagiError> {
+        self.complete(&json_prompt(prompt, schema)).await
+    }
+
+    // Answer the last message of a conversation
+    async fn chat(&self, messages: &[ChatMessage]) -> Result<String, CrustagiError>;
+
+    // Answer the last message of a conversation with an assistant message,
+    // which may call some of `tools` instead of answering. The outputs of the
+    // calls follow as `tool` messages in the next call. Providers without
+    // function calling always answer in text.
+    async fn chat_with_tools(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+    ) -> Result<ChatMessage, CrustagiError> {
+        let _ = tools;
+        Ok(ChatMessage::assistant(&self.chat(messages).await?))
+    }
+
+    // Embedding vector of a text
+    async fn embed(&self, text: &str) -> Result<Vec<f64>, CrustagiError>;
+
+    // Embedding vectors of several texts, in the same order. Providers that
+    // accept several inputs per request embed them in as few calls as possible.
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f64>>, CrustagiError> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for text in texts {
+            embeddings.push(self.embed(text).await?);
+        }
+        Ok(embeddings)
+    }
+
+    // Name of the model behind `complete` and `chat`
+    fn model(&self) -> String;
+
+    // Tokens a prompt may use, leaving room for the answer in the model's
+    // context window
+    fn prompt_budget(&self) -> usize {
+        tokens::prompt_budget(&self.model(), None, None)
+    }
+
+    // Name of the model behind `embed`
+    fn embedding_model(&self) -> &str;
+
+    // Size of the vectors returned by `embed`
+    async fn embedding_dimension(&self) -> Result<usize, CrustagiError> {
+        Ok(self.embed("dimension probe").await?.len())
+    }
+}
+
+// Prompt asking for JSON matching `schema`. OpenAI's JSON mode also requires
+// the word JSON to appear in the messages.
+pub fn json_prompt(prompt: &str, schema: &Value) -> String {
+    format!(
+        "{}\n\nRespond only with a JSON object matching this JSON schema: {}",
+        prompt, schema
+    )
+}
+
+// Values accepted by `LLM_PROVIDER`
+pub const SUPPORTED_PROVIDERS: &[&str] = &["openai", "gemini", "cohere"];
+
+// Build the provider selected by `LLM_PROVIDER`, followed by the models of
+// `FALLBACK_MODELS` and behind the embedding cache if one is configured.
+// `model` overrides the provider's default text model. Each of their
+// requests waits for `rate_limit`.
+pub fn from_config(
+    config: &Config,
+    http: &reqwest::Client,
+    model: Option<&str>,
+    params: &LlmParams,
+    rate_limit: &RateLimitLayer,
+) -> Result<Arc<dyn LlmProvider>, CrustagiError> {
+    // CONTEXT_WINDOW applies to the models of every agent
+    let params = &LlmParams {
+        context_window: params.context_window.or(config.context_window),
+        ..params.clone()
+    };
+    let mut provider = build_provider(
+        config,
+        http,
+        &config.llm_provider,
+        model,
+        None,
+        params,
+        rate_limit,
+    )?;
+    if !config.fallback_models.is_empty() {
+        let fallbacks = config
+            .fallback_models
+            .iter()
+            .map(|fallback| {
+                let name = fallback.provider.as_deref().unwrap_or(&config.llm_provider);
+                let api_key = match name {
+                    "openai" => &config.openai_api_key,
+                    "gemini" => &config.gemini_api_key,
+                    _ => &config.cohere_api_key,
+                };
+                // Local OpenAI-compatible servers usually need no key
+                if api_key.is_empty() && fallback.api_base.is_none() {
+                    return Err(format!(
+                        "Fallback model {} needs the {} API key",
+                        fallback.model, name
+                    )
+                    .into());
+                }
+                build_provider(
+                    config,
+                    http,
+                    name,
+                    Some(&fallback.model),
+                    fallback.api_base.as_deref(),
+                    params,
+                    rate_limit,
+                )
+            })
+            .collect::<Result<Vec<_>, CrustagiError>>()?;
+        provider = Arc::new(FallbackChain::new(provider, fallbacks));
+    }
+    Ok(match &config.embedding_cache_path {
+        Some(path) => {
+            let cache = EmbeddingCache::open(path)
+                .map_err(|err| format!("Failed to open embedding cache {}: {}", path, err))?;
+            Arc::new(CachedEmbeddings::new(provider, cache))
+        }
+        None => provider,
+    })
+}
+
+// Client of one provider, with `model` or the provider's configured text model.
+// The OpenAI client waits for `rate_limit` before each HTTP request, so its
+// retries and continuations count too. The other clients wait once per call.
+fn build_provider(
+    config: &Config,
+    http: &reqwest::Client,
+    provider: &str,
+    model: Option<&str>,
+    api_base: Option<&str>,
+    params: &LlmParams,
+    rate_limit: &RateLimitLayer,
+) -> Result<Arc<dyn LlmProvider>, CrustagiError> {
+    Ok(match provider {
+        "openai" => {
+            let client = OpenAiClient::new(http.clone(), &config.openai_api_key)
+                .with_retries(
+                    config.openai_max_attempts,
+                    Duration::from_secs(config.openai_timeout_secs),
+                )
+                .with_api_base(api_base.unwrap_or(&config.openai_api_base))
+                .with_model(model.unwrap_or(&config.openai_api_model))
+                .with_params(params.clone())
+                .with_embedding_model(&config.embedding_model)
+                .with_rate_limit(rate_limit.clone());
+            // Checked with the other settings
+            match config
+                .openai_api_mode
+                .as_deref()
+                .and_then(ApiMode::from_name)
+            {
+                Some(api_mode) => Arc::new(client.with_api_mode(api_mode)),
+                None => Arc::new(client),
+            }
+        }
+        "gemini" => rate_limit.layer(Arc::new(
+            GeminiClient::new(http.clone(), &config.gemini_api_key)
+                .with_model(model.unwrap_or(&config.gemini_model))
+                .with_params(params.clone())
+                .with_embedding_model(&config.gemini_embedding_model),
+        )),
+        "cohere" => rate_limit.layer(Arc::new(
+            CohereClient::new(http.clone(), &config.cohere_api_key)
+                .with_model(model.unwrap_or(&config.cohere_model))
+                .with_params(params.clone())
+                .with_embedding_model(&config.cohere_embedding_model),
+        )),
+        // Selected by `--dry-run`, not by LLM_PROVIDER
+        "mock" => Arc::new(MockLlm::new()),
+        other => {
+            return Err(format!(
+                "Unknown LLM_PROVIDER \"{}\", expected one of: {}",
+                other,
+                SUPPORTED_PROVIDERS.join(", ")
+            )
+            .into())
+        }
+    })
+}
@@ -0,0 +1,213 @@
+use arrow_array::cast::AsArray;
+use arrow_array::types::Float64Type;
+use arrow_array::{Array, ArrayRef, ListArray, RecordBatch, StringArray};
+use arrow_ipc::reader::FileReader;
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{DataType, Field, Schema};
+use async_trait::async_trait;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::error::CrustagiError;
+use crate::memory::{
+    cosine_similarity, Match, Metadata, StoredVector, VectorMetadata, VectorStore,
+};
+
+// Name of the table file inside the data directory
+const TABLE: &str = "memory.arrow";
+
+// Embedded vector store persisted to a local directory, with no service to run.
+// The table is an Arrow IPC file (the columnar format LanceDB builds on), so it
+// can be read with pyarrow or polars. It is loaded on open, searched by brute
+// force and rewritten after each change.
+pub struct LocalStore {
+    dir: PathBuf,
+    entries: Mutex<Vec<StoredVector>>,
+}
+
+impl LocalStore {
+    pub fn open(dir: &str) -> Result<Self, CrustagiError> {
+        let dir = PathBuf::from(dir);
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(TABLE);
+        let entries = if path.exists() {
+            read_table(File::open(&path)?)?
+        } else {
+            Vec::new()
+        };
+        Ok(LocalStore {
+            dir,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    // Replace the table file, writing a temporary file first so a crash
+    // cannot leave a truncated table behind
+    fn save(&self, entries: &[StoredVector]) -> Result<(), CrustagiError> {
+        let temp = self.dir.join(format!("{}.tmp", TABLE));
+        write_table(File::create(&temp)?, entries)?;
+        fs::rename(&temp, self.dir.join(TABLE))?;
+        Ok(())
+    }
+}
+
+fn schema() -> Schema {
+    Schema::new(vec![
+        Field::new("id", DataType::Utf8, false),
+        Field::new(
+            "vector",
+            DataType::List(Arc::new(Field::new("item", DataType::Float64, true))),
+            false,
+        ),
+        Field::new("metadata", DataType::Utf8, true),
+    ])
+}
+
+fn write_table(file: File, entries: &[StoredVector]) -> Result<(), CrustagiError> {
+    let schema = Arc::new(schema());
+    let ids = StringArray::from_iter_values(entries.iter().map(|e| e.id.as_str()));
+    let vectors = ListArray::from_iter_primitive::<Float64Type, _, _>(
+        entries
+            .iter()
+            .map(|e| Some(e.vector.iter().copied().map(Some))),
+    );
+    let metadata = entries
+        .iter()
+        .map(|e| e.metadata.as_ref().map(serde_json::to_string).transpose())
+        .collect::<Result<StringArray, _>>()?;
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(ids) as ArrayRef,
+            Arc::new(vectors),
+            Arc::new(metadata),
+        ],
+    )?;
+    let mut writer = FileWriter::try_new(file, &schema)?;
+    writer.write(&batch)?;
+    writer.finish()?;
+    Ok(())
+}
+
+fn read_table(file: File) -> Result<Vec<StoredVector>, CrustagiError> {
+    let mut entries = Vec::new();
+    for batch in FileReader::try_new(file, None)? {
+        let batch = batch?;
+        let ids = batch.column(0).as_string::<i32>();
+        let vectors = batch.column(1).as_list::<i32>();
+        let metadata = batch.column(2).as_string::<i32>();
+        for row in 0..batch.num_rows() {
+            entries.push(StoredVector {
+                id: ids.value(row).to_string(),
+                vector: vectors
+                    .value(row)
+                    .as_primitive::<Float64Type>()
+                    .values()
+                    .to_vec(),
+                metadata: if metadata.is_null(row) {
+                    None
+                } else {
+                    Some(serde_json::from_str(metadata.value(row))?)
+                },
+            });
+        }
+    }
+    Ok(entries)
+}
+
+#[async_trait]
+impl VectorStore for LocalStore {
+    async fn create(&self) -> Result<(), CrustagiError> {
+        Ok(())
+    }
+
+    async fn upsert(
+        &self,
+        id: &str,
+        vector: &[f64],
+        metadata: &VectorMetadata,
+    ) -> Result<usize, CrustagiError> {
+        tracing::debug!("Storing to the local directory...");
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|e| e.id != id);
+        entries.push(StoredVector {
+            id: id.to_string(),
+            vector: vector.to_vec(),
+            metadata: Some(metadata.clone()),
+        });
+        self.save(&entries)?;
+        Ok(1)
+    }
+
+    async fn query(
+        &self,
+        vector: &[f64],
+        top_k: usize,
+        filter: Option<&Metadata>,
+    ) -> Result<Vec<Match>, CrustagiError> {
+        let entries = self.entries.lock().unwrap();
+        let mut matches: Vec<Match> = entries
+            .iter()
+            .filter(|e| {
+                filter.is_none_or(|filter| e.metadata.as_ref().is_some_and(|m| m.matches(filter)))
+            })
+            .map(|e| Match {
+                id: e.id.clone(),
+                score: cosine_similarity(vector, &e.vector),
+                metadata: e.metadata.clone(),
+            })
+            .collect();
+        matches.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        matches.truncate(top_k);
+        Ok(matches)
+    }
+
+    async fn delete(&self, ids: &[String]) -> Result<(), CrustagiError> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|e| !ids.contains(&e.id));
+        self.save(&entries)
+    }
+
+    async fn dump(&self) -> Result<Vec<StoredVector>, CrustagiError> {
+        Ok(self.entries.lock().unwrap().clone())
+    }
+
+    async fn reset(&self) -> Result<(), CrustagiError> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clear();
+        self.save(&entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reopens_stored_vectors() {
+        let dir = std::env::temp_dir().join(format!("crustagi-local-{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        let metadata = VectorMetadata {
+            task: "Research".to_string(),
+            ..Default::default()
+        };
+
+        let store = LocalStore::open(dir).unwrap();
+        store.upsert("a", &[1.0, 0.0], &metadata).await.unwrap();
+        store.upsert("b", &[0.0, 1.0], &metadata).await.unwrap();
+        store.delete(&["b".to_string()]).await.unwrap();
+
+        let store = LocalStore::open(dir).unwrap();
+        let stored = store.dump().await.unwrap();
+        fs::remove_dir_all(dir).unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].id, "a");
+        assert_eq!(stored[0].vector, vec![1.0, 0.0]);
+        assert_eq!(stored[0].metadata.as_ref().unwrap().task, "Research");
+    }
+}
@@ -0,0 +1,197 @@
+// Console output of the run, written as `tracing` events. `LOG_LEVEL` sets
+// which events are shown (default `info`), either as a level for crustagi's
+// own events or as `tracing` filter directives like `crustagi=debug,hyper=info`.
+// `quiet` only shows the task results and the report, `verbose` everything
+// down to the prompts and raw responses of the API calls. Banners and levels
+// are colored on a terminal, unless `NO_COLOR` is set.
+// `LOG_FORMAT=json` prints each event as a JSON object with its fields and
+// spans, and a line for each closed span, for log pipelines.
+
+use colored::{Color, Colorize};
+use serde::Serialize;
+use std::env;
+use std::fmt;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::fmt::format::{FmtSpan, Writer};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+use crate::telemetry::{self, TelemetryGuard};
+use crate::tui;
+
+const DEFAULT_LOG_LEVEL: &str = "info";
+
+// Target of the events still shown with `LOG_LEVEL=quiet`
+pub const RESULTS: &str = "crustagi::results";
+
+static JSON: AtomicBool = AtomicBool::new(false);
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+// Whether events are printed as JSON, in which case nothing else may be
+// written to stdout
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+// Install the console output, and the OpenTelemetry export if built in. The
+// returned guard flushes the exported spans when dropped.
+pub fn init() -> Option<TelemetryGuard> {
+    let (otel, guard) = telemetry::layer();
+    let json = env::var("LOG_FORMAT").is_ok_and(|format| format.eq_ignore_ascii_case("json"));
+    JSON.store(json, Ordering::Relaxed);
+    // Any value of NO_COLOR turns colors off, see https://no-color.org
+    let color = !json && env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal();
+    COLOR.store(color, Ordering::Relaxed);
+    colored::control::set_override(color);
+    let console = if json {
+        tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .with_span_events(FmtSpan::CLOSE)
+            .with_writer(io::stdout)
+            .with_filter(filter())
+            .boxed()
+    } else {
+        tracing_subscriber::fmt::layer()
+            .event_format(ConsoleFormat)
+            .with_writer(Console)
+            .with_filter(filter())
+            .boxed()
+    };
+    tracing_subscriber::registry()
+        .with(otel)
+        .with(console)
+        .init();
+    guard
+}
+
+// Turn colors off while the output goes elsewhere than the terminal, like
+// the TUI's log
+pub fn pause_colors(paused: bool) {
+    colored::control::set_override(!paused && COLOR.load(Ordering::Relaxed));
+}
+
+// Warnings and errors go to stderr, as they did before, the other events to
+// stdout. While the TUI is shown, all of them go to its log.
+struct Console;
+
+impl<'a> MakeWriter<'a> for Console {
+    type Writer = Box<dyn io::Write>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        Box::new(io::stdout())
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        match (tui::is_active(), *meta.level() <= Level::WARN) {
+            (true, _) => Box::new(tui::LogWriter::default()),
+            (false, true) => Box::new(io::stderr()),
+            (false, false) => Box::new(io::stdout()),
+        }
+    }
+}
+
+fn filter() -> EnvFilter {
+    let level = env::var("LOG_LEVEL").unwrap_or_else(|_| DEFAULT_LOG_LEVEL.to_string());
+    // A plain level applies to crustagi, other crates only show warnings
+    let directives = match level.as_str() {
+        "quiet" => format!("warn,{}=info", RESULTS),
+        "verbose" => "warn,crustagi=trace".to_string(),
+        level if level.contains(['=', ',']) => level.to_string(),
+        level => format!("warn,crustagi={}", level),
+    };
+    EnvFilter::try_new(&directives).unwrap_or_else(|err| {
+        eprintln!(
+            "Invalid LOG_LEVEL \"{}\" ({}), using {}",
+            level, err, DEFAULT_LOG_LEVEL
+        );
+        EnvFilter::new(format!("warn,crustagi={}", DEFAULT_LOG_LEVEL))
+    })
+}
+
+// Heading of a section of the output, like BabyAGI's `*****TASK LIST*****`
+pub fn banner(title: impl fmt::Display, color: Color) -> String {
+    format!("*****{}*****", title)
+        .color(color)
+        .bold()
+        .to_string()
+}
+
+// Log the body of a request to `api`, shown with `LOG_LEVEL=verbose`
+pub fn request(api: &str, endpoint: &str, body: &impl Serialize) {
+    tracing::trace!(
+        "{} {} request:\n{}",
+        api,
+        endpoint,
+        serde_json::to_string_pretty(body).unwrap_or_default()
+    );
+}
+
+// Log the raw body of a response from `api`, shown with `LOG_LEVEL=verbose`.
+// Embedding vectors would bury everything else, so only their size is.
+pub fn response(api: &str, endpoint: &str, body: impl fmt::Display) {
+    if !tracing::enabled!(Level::TRACE) {
+        return;
+    }
+    let body = body.to_string();
+    if endpoint.to_lowercase().contains("embed") {
+        tracing::trace!("{} {} response: {} bytes", api, endpoint, body.len());
+    } else {
+        tracing::trace!("{} {} response:\n{}", api, endpoint, body);
+    }
+}
+
+// Only the message of each event, as the console output was before it went
+// through `tracing`. Levels other than info are prefixed.
+struct ConsoleFormat;
+
+impl<S, N> FormatEvent<S, N> for ConsoleFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        _ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut message = Message::default();
+        event.record(&mut message);
+        // Blank lines separating the output come before the level
+        let text = message.0.trim_start_matches('\n');
+        let blank_lines = &message.0[..message.0.len() - text.len()];
+        let level = match *event.metadata().level() {
+            Level::INFO => return writeln!(writer, "{}{}", blank_lines, text),
+            Level::ERROR => "ERROR".red().bold(),
+            Level::WARN => "WARN".yellow().bold(),
+            level => level.as_str().dimmed(),
+        };
+        writeln!(writer, "{}{}: {}", blank_lines, level, text)
+    }
+}
+
+#[derive(Default)]
+struct Message(String);
+
+impl Visit for Message {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0 = value.to_string();
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
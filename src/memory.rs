@@ -0,0 +1,560 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::chroma::ChromaStore;
+use crate::error::CrustagiError;
+use crate::in_memory::InMemoryStore;
+use crate::local::LocalStore;
+use crate::middleware::RateLimitLayer;
+use crate::milvus::MilvusStore;
+use crate::pgvector::PgVectorStore;
+use crate::pinecone::{IndexParams, PineconeClient, PineconeStore};
+use crate::plugins;
+use crate::qdrant::QdrantStore;
+use crate::sqlite::SqliteStore;
+use crate::weaviate::WeaviateStore;
+use crate::Config;
+
+pub type Metadata = HashMap<String, serde_json::Value>;
+
+// Task information stored alongside each result vector
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VectorMetadata {
+    pub task: String,
+    pub result: String,
+    pub task_id: i32,
+    #[serde(default)]
+    pub objective: String,
+    // Position of the chunk in its result, None for results stored whole
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk: Option<usize>,
+}
+
+impl VectorMetadata {
+    // Parse metadata returned by a backend, None if it wasn't written by us
+    pub fn from_map(map: Metadata) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::Object(map.into_iter().collect())).ok()
+    }
+
+    pub fn to_map(&self) -> Metadata {
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::Object(map)) => map.into_iter().collect(),
+            _ => Metadata::new(),
+        }
+    }
+
+    // Whether every field in the filter has the same value here, for
+    // backends that filter in process
+    pub fn matches(&self, filter: &Metadata) -> bool {
+        let map = self.to_map();
+        filter
+            .iter()
+            .all(|(key, value)| map.get(key) == Some(value))
+    }
+}
+
+// A stored vector with its metadata, one line of a memory export
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredVector {
+    pub id: String,
+    pub vector: Vec<f64>,
+    pub metadata: Option<VectorMetadata>,
+}
+
+// A stored vector returned by a similarity query
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct Match {
+    pub id: String,
+    pub score: f64,
+    pub metadata: Option<VectorMetadata>,
+}
+
+// Storage for task results, queried by embedding similarity
+#[async_trait]
+pub trait VectorStore: Send + Sync {
+    // Create the underlying index/collection if it does not exist yet
+    async fn create(&self) -> Result<(), CrustagiError>;
+
+    // Vector size the index/collection was created with, None if the backend
+    // doesn't report it
+    async fn dimension(&self) -> Result<Option<usize>, CrustagiError> {
+        Ok(None)
+    }
+
+    // Insert or overwrite a vector with its metadata, returning the number of vectors written
+    async fn upsert(
+        &self,
+        id: &str,
+        vector: &[f64],
+        metadata: &VectorMetadata,
+    ) -> Result<usize, CrustagiError>;
+
+    // Insert or overwrite several vectors, one call per vector unless the
+    // backend supports batching
+    async fn upsert_batch(
+        &self,
+        vectors: &[(String, Vec<f64>, VectorMetadata)],
+    ) -> Result<usize, CrustagiError> {
+        let mut count = 0;
+        for (id, vector, metadata) in vectors {
+            count += self.upsert(id, vector, metadata).await?;
+        }
+        Ok(count)
+    }
+
+    // Return the `top_k` vectors most similar to `vector`. A filter keeps only
+    // vectors whose metadata equals every given field.
+    async fn query(
+        &self,
+        vector: &[f64],
+        top_k: usize,
+        filter: Option<&Metadata>,
+    ) -> Result<Vec<Match>, CrustagiError>;
+
+    // Query using both the text and its embedding. Backends without keyword
+    // search fall back to the dense `query`.
+    async fn hybrid_query(
+        &self,
+        text: &str,
+        vector: &[f64],
+        top_k: usize,
+        filter: Option<&Metadata>,
+    ) -> Result<Vec<Match>, CrustagiError> {
+        let _ = text;
+        self.query(vector, top_k, filter).await
+    }
+
+    // Remove vectors by id
+    async fn delete(&self, ids: &[String]) -> Result<(), CrustagiError>;
+
+    // Every stored vector, for exporting the memory
+    async fn dump(&self) -> Result<Vec<StoredVector>, CrustagiError> {
+        Err("This memory backend does not support exporting".into())
+    }
+
+    // Remove every stored vector, leaving an empty but usable store
+    async fn reset(&self) -> Result<(), CrustagiError>;
+}
+
+// Values accepted by `MEMORY_BACKEND`
+pub const SUPPORTED_BACKENDS: &[&str] = &[
+    "pinecone", "qdrant", "weaviate", "chroma", "sqlite", "local", "pgvector", "milvus", "memory",
+];
+
+// Build the vector store selected by `MEMORY_BACKEND`. Pinecone's requests
+// wait for `rate_limit` along with the LLM calls.
+pub async fn from_config(
+    config: &Config,
+    http: &reqwest::Client,
+    rate_limit: &RateLimitLayer,
+    dimension: usize,
+) -> Result<Box<dyn VectorStore>, CrustagiError> {
+    let store: Box<dyn VectorStore> = match config.memory_backend.as_str() {
+        "pinecone" => {
+            let client = PineconeClient::new(http.clone(), &config.pinecone_api_key)
+                .with_retries(
+                    config.pinecone_max_attempts,
+                    Duration::from_secs(config.pinecone_timeout_secs),
+                )
+                .with_rate_limit(rate_limit.clone());
+            let store = PineconeStore::new(
+                client,
+                &config.pinecone_region,
+                &config.pinecone_project_id,
+                &config.pinecone_index_name,
+                &config.pinecone_namespace,
+                config.pinecone_serverless_cloud.as_deref(),
+                IndexParams {
+                    dimension,
+                    metric: config.index_metric.clone(),
+                    pod_type: config.pinecone_pod_type.clone(),
+                    pods: config.pinecone_pods,
+                    replicas: config.pinecone_replicas,
+                },
+            );
+            match config.hybrid_alpha {
+                Some(alpha) => Box::new(store.with_hybrid(alpha)),
+                None => Box::new(store),
+            }
+        }
+        "qdrant" => Box::new(QdrantStore {
+            url: config.qdrant_url.clone(),
+            api_key: config.qdrant_api_key.clone(),
+            collection: config.qdrant_collection.clone(),
+            dimension,
+        }),
+        "weaviate" => Box::new(WeaviateStore {
+            url: config.weaviate_url.clone(),
+            api_key: config.weaviate_api_key.clone(),
+            class_name: config.weaviate_class.clone(),
+        }),
+        "chroma" => Box::new(ChromaStore::new(
+            &config.chroma_url,
+            &config.chroma_collection,
+        )),
+        "sqlite" => Box::new(SqliteStore::open(&config.sqlite_path)?),
+        "local" => Box::new(LocalStore::open(&config.local_memory_dir)?),
+        "pgvector" => Box::new(
+            PgVectorStore::connect(&config.database_url, &config.pgvector_table, dimension).await?,
+        ),
+        "milvus" => Box::new(MilvusStore {
+            url: config.milvus_url.clone(),
+            token: config.milvus_token.clone(),
+            collection: config.milvus_collection.clone(),
+            dimension,
+        }),
+        "memory" => Box::new(InMemoryStore::new()),
+        other => match plugins::memory_backend(other) {
+            Some(backend) => backend.open(config, dimension).await?,
+            None => {
+                return Err(format!(
+                    "Unsupported MEMORY_BACKEND \"{}\", expected one of: {}",
+                    other,
+                    SUPPORTED_BACKENDS.join(", ")
+                )
+                .into())
+            }
+        },
+    };
+    Ok(store)
+}
+
+// Stable 64-bit FNV-1a hash, for backends that need numeric or UUID ids
+pub fn hash_id(id: &str) -> u64 {
+    id.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+// Cosine similarity of two vectors, 0.0 if either is all zeros
+pub fn cosine_similarity(a: &[f64], b: &[f64]) -> f64 {
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f64>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+// Filter restricting queries to results stored for one objective
+pub fn objective_filter(objective: &str) -> Metadata {
+    Metadata::from([("objective".to_string(), serde_json::json!(objective))])
+}
+
+// The most similar stored vector if its score reaches `threshold`, used to
+// avoid storing results that are nearly identical to an existing one
+pub async fn find_near_duplicate(
+    store: &dyn VectorStore,
+    vector: &[f64],
+    filter: Option<&Metadata>,
+    threshold: f64,
+) -> Result<Option<Match>, CrustagiError> {
+    let matches = store.query(vector, 1, filter).await?;
+    Ok(matches.into_iter().find(|m| m.score >= threshold))
+}
+
+// Which results an `Evictor` deletes first
+pub enum EvictionPolicy {
+    // Lowest task id first
+    Oldest,
+    // Least similar to the given (objective) embedding first
+    LeastRelevant(Vec<f64>),
+}
+
+impl EvictionPolicy {
+    // Where a stored vector stands, the lowest going first
+    fn rank(&self, vector: &[f64], metadata: Option<&VectorMetadata>) -> f64 {
+        match self {
+            EvictionPolicy::Oldest => metadata.map_or(0.0, |m| m.task_id as f64),
+            EvictionPolicy::LeastRelevant(reference) => cosine_similarity(reference, vector),
+        }
+    }
+}
+
+// Keeps the results of an objective within `max_entries`. The store is read
+// once, on the first eviction, and the results are tracked from then on as
+// they are stored and deleted, so that eviction doesn't read back the whole
+// store after each upsert.
+pub struct Evictor {
+    filter: Metadata,
+    max_entries: usize,
+    policy: EvictionPolicy,
+    // Ids of the results with their rank, None until the store was read
+    tracked: tokio::sync::Mutex<Option<Vec<(String, f64)>>>,
+}
+
+impl Evictor {
+    pub fn new(objective: &str, max_entries: usize, policy: EvictionPolicy) -> Self {
+        Evictor {
+            filter: objective_filter(objective),
+            max_entries,
+            policy,
+            tracked: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    // Track the results just stored and delete those beyond `max_entries`.
+    // Returns the number of deleted vectors.
+    pub async fn evict(
+        &self,
+        store: &dyn VectorStore,
+        stored: &[StoredVector],
+    ) -> Result<usize, CrustagiError> {
+        let mut tracked = self.tracked.lock().await;
+        let entries = match tracked.as_mut() {
+            Some(entries) => entries,
+            // The store already holds the results just stored
+            None => tracked.insert(
+                store
+                    .dump()
+                    .await?
+                    .into_iter()
+                    .filter(|v| v.metadata.as_ref().is_some_and(|m| m.matches(&self.filter)))
+                    .map(|v| {
+                        let rank = self.policy.rank(&v.vector, v.metadata.as_ref());
+                        (v.id, rank)
+                    })
+                    .collect(),
+            ),
+        };
+        for vector in stored {
+            entries.retain(|(id, _)| *id != vector.id);
+            let rank = self.policy.rank(&vector.vector, vector.metadata.as_ref());
+            entries.push((vector.id.clone(), rank));
+        }
+        if entries.len() <= self.max_entries {
+            return Ok(0);
+        }
+        entries.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let surplus = entries.len() - self.max_entries;
+        let ids: Vec<String> = entries[..surplus]
+            .iter()
+            .map(|(id, _)| id.clone())
+            .collect();
+        store.delete(&ids).await?;
+        entries.drain(..surplus);
+        Ok(ids.len())
+    }
+}
+
+// Id and objective of the probe vector written by `health_check`
+const HEALTH_CHECK_ID: &str = "crustagi_health_check";
+// Some backends (Pinecone in particular) make upserts visible to queries
+// only after a short delay
+const HEALTH_CHECK_POLLS: usize = 10;
+
+// Verify on startup that the store is reachable, has the expected dimension
+// and round-trips a vector, so misconfiguration fails fast with a clear error
+// instead of in the middle of the task loop
+pub async fn health_check(
+    store: &dyn VectorStore,
+    backend: &str,
+    dimension: usize,
+) -> Result<(), CrustagiError> {
+    match store.dimension().await {
+        Ok(Some(actual)) if actual != dimension => {
+            return Err(format!(
+                "The {} index has dimension {}, but embeddings have dimension {}. \
+                 Use an embedding model with {} dimensions or point the backend at a new index.",
+                backend, actual, dimension, actual
+            )
+            .into())
+        }
+        Ok(_) => {}
+        Err(err) => return Err(format!("Could not describe the {} index: {}", backend, err).into()),
+    }
+
+    // Unit vector, as a zero vector has no cosine similarity
+    let mut probe = vec![0.0; dimension];
+    if let Some(first) = probe.first_mut() {
+        *first = 1.0;
+    }
+    let metadata = VectorMetadata {
+        task: "health check".to_string(),
+        result: "health check".to_string(),
+        objective: HEALTH_CHECK_ID.to_string(),
+        ..Default::default()
+    };
+    store
+        .upsert(HEALTH_CHECK_ID, &probe, &metadata)
+        .await
+        .map_err(|err| {
+            format!(
+                "Could not write to the {} store, check its URL and credentials: {}",
+                backend, err
+            )
+        })?;
+
+    let filter = objective_filter(HEALTH_CHECK_ID);
+    let found = async {
+        for poll in 0..HEALTH_CHECK_POLLS {
+            let matches = store
+                .query(&probe, 1, Some(&filter))
+                .await
+                .map_err(|err| format!("Could not query the {} store: {}", backend, err))?;
+            if matches.iter().any(|m| m.id == HEALTH_CHECK_ID) {
+                return Ok::<_, CrustagiError>(true);
+            }
+            if poll + 1 < HEALTH_CHECK_POLLS {
+                sleep(Duration::from_secs(1)).await;
+            }
+        }
+        Ok(false)
+    }
+    .await;
+    // The probe is removed even when querying failed
+    let deleted = store.delete(&[HEALTH_CHECK_ID.to_string()]).await;
+    if !found? {
+        return Err(format!(
+            "The {} store accepted a test vector but did not return it from a query",
+            backend
+        )
+        .into());
+    }
+    deleted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(objective: &str, task_id: i32, vector: Vec<f64>) -> StoredVector {
+        StoredVector {
+            id: format!("{}_{}", objective, task_id),
+            vector,
+            metadata: Some(VectorMetadata {
+                task: format!("Task {}", task_id),
+                result: format!("Result {}", task_id),
+                task_id,
+                objective: objective.to_string(),
+                chunk: None,
+            }),
+        }
+    }
+
+    async fn store(store: &InMemoryStore, vector: &StoredVector) {
+        let metadata = vector.metadata.clone().unwrap();
+        store
+            .upsert(&vector.id, &vector.vector, &metadata)
+            .await
+            .unwrap();
+    }
+
+    async fn stored_ids(store: &InMemoryStore) -> Vec<String> {
+        let mut ids: Vec<String> = store
+            .dump()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|v| v.id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[tokio::test]
+    async fn evicts_the_oldest_results_of_the_objective() {
+        let memory = InMemoryStore::new();
+        for task_id in 1..=3 {
+            store(&memory, &result("a", task_id, vec![1.0, 0.0])).await;
+        }
+        // Results of other objectives don't count and stay
+        store(&memory, &result("b", 1, vec![1.0, 0.0])).await;
+        let evictor = Evictor::new("a", 2, EvictionPolicy::Oldest);
+        let new = result("a", 4, vec![1.0, 0.0]);
+        store(&memory, &new).await;
+        assert_eq!(evictor.evict(&memory, &[new]).await.unwrap(), 2);
+        assert_eq!(stored_ids(&memory).await, ["a_3", "a_4", "b_1"]);
+
+        // Later evictions go by the tracked results, without reading the
+        // store again
+        let new = result("a", 5, vec![1.0, 0.0]);
+        store(&memory, &new).await;
+        assert_eq!(evictor.evict(&memory, &[new]).await.unwrap(), 1);
+        assert_eq!(stored_ids(&memory).await, ["a_4", "a_5", "b_1"]);
+    }
+
+    #[tokio::test]
+    async fn evicts_the_least_relevant_results() {
+        let memory = InMemoryStore::new();
+        let results = [
+            result("a", 1, vec![1.0, 0.0]),
+            result("a", 2, vec![0.0, 1.0]),
+            result("a", 3, vec![0.7, 0.7]),
+        ];
+        for result in &results {
+            store(&memory, result).await;
+        }
+        let evictor = Evictor::new("a", 2, EvictionPolicy::LeastRelevant(vec![1.0, 0.1]));
+        assert_eq!(evictor.evict(&memory, &results[2..]).await.unwrap(), 1);
+        assert_eq!(stored_ids(&memory).await, ["a_1", "a_3"]);
+    }
+
+    #[tokio::test]
+    async fn keeps_results_within_the_limit() {
+        let memory = InMemoryStore::new();
+        let new = result("a", 1, vec![1.0]);
+        store(&memory, &new).await;
+        let evictor = Evictor::new("a", 1, EvictionPolicy::Oldest);
+        assert_eq!(
+            evictor
+                .evict(&memory, std::slice::from_ref(&new))
+                .await
+                .unwrap(),
+            0
+        );
+        // Storing a result again replaces it
+        assert_eq!(evictor.evict(&memory, &[new]).await.unwrap(), 0);
+        assert_eq!(stored_ids(&memory).await, ["a_1"]);
+    }
+
+    // Stores vectors, but fails every query
+    struct Unqueryable(InMemoryStore);
+
+    #[async_trait]
+    impl VectorStore for Unqueryable {
+        async fn create(&self) -> Result<(), CrustagiError> {
+            Ok(())
+        }
+
+        async fn upsert(
+            &self,
+            id: &str,
+            vector: &[f64],
+            metadata: &VectorMetadata,
+        ) -> Result<usize, CrustagiError> {
+            self.0.upsert(id, vector, metadata).await
+        }
+
+        async fn query(
+            &self,
+            _vector: &[f64],
+            _top_k: usize,
+            _filter: Option<&Metadata>,
+        ) -> Result<Vec<Match>, CrustagiError> {
+            Err("unavailable".into())
+        }
+
+        async fn delete(&self, ids: &[String]) -> Result<(), CrustagiError> {
+            self.0.delete(ids).await
+        }
+
+        async fn reset(&self) -> Result<(), CrustagiError> {
+            self.0.reset().await
+        }
+    }
+
+    #[tokio::test]
+    async fn removes_the_probe_when_the_health_check_fails() {
+        let memory = Unqueryable(InMemoryStore::new());
+        let err = health_check(&memory, "test", 2).await.unwrap_err();
+        assert!(err.to_string().contains("Could not query the test store"));
+        assert!(stored_ids(&memory.0).await.is_empty());
+    }
+}
@@ -0,0 +1,805 @@
+// Middleware around LLM providers: each layer wraps a provider in another
+// one that handles a concern such as logging, caching, rate limiting, cost
+// accounting or redaction, and passes the calls on. Layers stack like tower's,
+// so the providers themselves only talk to their API.
+
+use async_trait::async_trait;
+use regex::Regex;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::error::CrustagiError;
+use crate::http::RateLimiter;
+use crate::llm::{ChatMessage, LlmProvider, ToolCall, ToolSpec};
+use crate::tokens;
+use crate::usage::{self, TokenUsage, UsageMeter};
+
+/// Wraps a provider in one that adds a concern to its calls. State kept by
+/// the layer, e.g. a cache, is shared by all providers it wraps.
+pub trait Layer: Send + Sync {
+    fn layer(&self, inner: Arc<dyn LlmProvider>) -> Arc<dyn LlmProvider>;
+}
+
+/// Layers to wrap providers in. The layer added first is the outermost, so
+/// it sees the calls first and the answers last.
+///
+/// ```
+/// use crustagi::middleware::{CacheLayer, LlmStack, LoggingLayer, RedactionLayer};
+///
+/// let stack = LlmStack::new()
+///     .layer(LoggingLayer)
+///     .layer(RedactionLayer::new(&["sk-[A-Za-z0-9]+"]).unwrap())
+///     .layer(CacheLayer::new());
+/// let provider = stack.service(crustagi::mock::MockLlm::new());
+/// ```
+#[derive(Clone, Default)]
+pub struct LlmStack {
+    layers: Vec<Arc<dyn Layer>>,
+}
+
+impl LlmStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `layer` inside the layers added before
+    pub fn layer(mut self, layer: impl Layer + 'static) -> Self {
+        self.layers.push(Arc::new(layer));
+        self
+    }
+
+    /// `provider` wrapped in the layers
+    pub fn service(&self, provider: impl LlmProvider + 'static) -> Arc<dyn LlmProvider> {
+        self.wrap(Arc::new(provider))
+    }
+
+    /// Like `service`, for a provider that is shared already
+    pub fn wrap(&self, provider: Arc<dyn LlmProvider>) -> Arc<dyn LlmProvider> {
+        self.layers
+            .iter()
+            .rev()
+            .fold(provider, |inner, layer| layer.layer(inner))
+    }
+}
+
+/// Logs every call with its model, size and duration at debug level, and
+/// failed calls as warnings
+pub struct LoggingLayer;
+
+impl Layer for LoggingLayer {
+    fn layer(&self, inner: Arc<dyn LlmProvider>) -> Arc<dyn LlmProvider> {
+        Arc::new(Logged { inner })
+    }
+}
+
+struct Logged {
+    inner: Arc<dyn LlmProvider>,
+}
+
+impl Logged {
+    fn log<T>(
+        &self,
+        call: &str,
+        model: &str,
+        prompt_tokens: usize,
+        started: Instant,
+        result: &Result<T, CrustagiError>,
+    ) {
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        match result {
+            Ok(_) => tracing::debug!(
+                call,
+                model,
+                prompt_tokens,
+                elapsed_ms,
+                "LLM {} with {}: {} prompt tokens, {} ms",
+                call,
+                model,
+                prompt_tokens,
+                elapsed_ms
+            ),
+            Err(err) => tracing::warn!(
+                call,
+                model,
+                elapsed_ms,
+                "LLM {} with {} failed after {} ms: {}",
+                call,
+                model,
+                elapsed_ms,
+                err
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for Logged {
+    async fn complete(&self, prompt: &str) -> Result<String, CrustagiError> {
+        let (model, started) = (self.inner.model(), Instant::now());
+        let result = self.inner.complete(prompt).await;
+        let prompt_tokens = tokens::count_tokens(&model, prompt);
+        self.log("complete", &model, prompt_tokens, started, &result);
+        result
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut (dyn for<'c> FnMut(&'c str) + Send),
+    ) -> Result<String, CrustagiError> {
+        let (model, started) = (self.inner.model(), Instant::now());
+        let result = self.inner.chat_stream(messages, on_chunk).await;
+        let prompt_tokens = message_tokens(&model, messages);
+        self.log("chat_stream", &model, prompt_tokens, started, &result);
+        result
+    }
+
+    async fn complete_json(&self, prompt: &str, schema: &Value) -> Result<String, CrustagiError> {
+        let (model, started) = (self.inner.model(), Instant::now());
+        let result = self.inner.complete_json(prompt, schema).await;
+        let prompt_tokens = tokens::count_tokens(&model, prompt);
+        self.log("complete_json", &model, prompt_tokens, started, &result);
+        result
+    }
+
+    async fn chat(&self, messages: &[ChatMessage]) -> Result<String, CrustagiError> {
+        let (model, started) = (self.inner.model(), Instant::now());
+        let result = self.inner.chat(messages).await;
+        let prompt_tokens = message_tokens(&model, messages);
+        self.log("chat", &model, prompt_tokens, started, &result);
+        result
+    }
+
+    async fn chat_with_tools(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+    ) -> Result<ChatMessage, CrustagiError> {
+        let (model, started) = (self.inner.model(), Instant::now());
+        let result = self.inner.chat_with_tools(messages, tools).await;
+        let prompt_tokens = message_tokens(&model, messages);
+        self.log("chat_with_tools", &model, prompt_tokens, started, &result);
+        result
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f64>, CrustagiError> {
+        let model = self.inner.embedding_model().to_string();
+        let started = Instant::now();
+        let result = self.inner.embed(text).await;
+        let prompt_tokens = tokens::count_tokens(&model, text);
+        self.log("embed", &model, prompt_tokens, started, &result);
+        result
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f64>>, CrustagiError> {
+        let model = self.inner.embedding_model().to_string();
+        let started = Instant::now();
+        let result = self.inner.embed_batch(texts).await;
+        let prompt_tokens = texts
+            .iter()
+            .map(|text| tokens::count_tokens(&model, text))
+            .sum();
+        self.log("embed_batch", &model, prompt_tokens, started, &result);
+        result
+    }
+
+    fn model(&self) -> String {
+        self.inner.model()
+    }
+
+    fn prompt_budget(&self) -> usize {
+        self.inner.prompt_budget()
+    }
+
+    fn embedding_model(&self) -> &str {
+        self.inner.embedding_model()
+    }
+
+    async fn embedding_dimension(&self) -> Result<usize, CrustagiError> {
+        self.inner.embedding_dimension().await
+    }
+}
+
+// Embedding vectors by embedding model and text
+type Embeddings = HashMap<(String, String), Vec<f64>>;
+
+/// Answers a call from memory if the same call was answered before, with
+/// the same model, for as long as the layer lives. Embeddings are cached
+/// the same way, failed calls are not.
+#[derive(Default)]
+pub struct CacheLayer {
+    answers: Arc<Mutex<HashMap<String, String>>>,
+    embeddings: Arc<Mutex<Embeddings>>,
+}
+
+impl CacheLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Layer for CacheLayer {
+    fn layer(&self, inner: Arc<dyn LlmProvider>) -> Arc<dyn LlmProvider> {
+        Arc::new(Cached {
+            inner,
+            answers: self.answers.clone(),
+            embeddings: self.embeddings.clone(),
+        })
+    }
+}
+
+struct Cached {
+    inner: Arc<dyn LlmProvider>,
+    answers: Arc<Mutex<HashMap<String, String>>>,
+    embeddings: Arc<Mutex<Embeddings>>,
+}
+
+impl Cached {
+    // The call and its model, as the key of its answer. A streamed chat is
+    // the same call as a chat.
+    fn key(&self, call: &str, request: Value) -> String {
+        json!({ "model": self.inner.model(), "call": call, "request": request }).to_string()
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        self.answers.lock().unwrap().get(key).cloned()
+    }
+
+    fn put(&self, key: String, result: &Result<String, CrustagiError>) {
+        if let Ok(answer) = result {
+            self.answers.lock().unwrap().insert(key, answer.clone());
+        }
+    }
+
+    fn embedding(&self, text: &str) -> Option<Vec<f64>> {
+        let key = (self.inner.embedding_model().to_string(), text.to_string());
+        self.embeddings.lock().unwrap().get(&key).cloned()
+    }
+
+    fn put_embedding(&self, text: &str, embedding: &[f64]) {
+        let key = (self.inner.embedding_model().to_string(), text.to_string());
+        self.embeddings
+            .lock()
+            .unwrap()
+            .insert(key, embedding.to_vec());
+    }
+}
+
+#[async_trait]
+impl LlmProvider for Cached {
+    async fn complete(&self, prompt: &str) -> Result<String, CrustagiError> {
+        let key = self.key("complete", json!(prompt));
+        if let Some(answer) = self.get(&key) {
+            return Ok(answer);
+        }
+        let result = self.inner.complete(prompt).await;
+        self.put(key, &result);
+        result
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut (dyn for<'c> FnMut(&'c str) + Send),
+    ) -> Result<String, CrustagiError> {
+        let key = self.key("chat", json!(messages));
+        if let Some(answer) = self.get(&key) {
+            on_chunk(&answer);
+            return Ok(answer);
+        }
+        let result = self.inner.chat_stream(messages, on_chunk).await;
+        self.put(key, &result);
+        result
+    }
+
+    async fn complete_json(&self, prompt: &str, schema: &Value) -> Result<String, CrustagiError> {
+        let key = self.key(
+            "complete_json",
+            json!({ "prompt": prompt, "schema": schema }),
+        );
+        if let Some(answer) = self.get(&key) {
+            return Ok(answer);
+        }
+        let result = self.inner.complete_json(prompt, schema).await;
+        self.put(key, &result);
+        result
+    }
+
+    async fn chat(&self, messages: &[ChatMessage]) -> Result<String, CrustagiError> {
+        let key = self.key("chat", json!(messages));
+        if let Some(answer) = self.get(&key) {
+            return Ok(answer);
+        }
+        let result = self.inner.chat(messages).await;
+        self.put(key, &result);
+        result
+    }
+
+    // The answer is kept as JSON, with the tools it calls
+    async fn chat_with_tools(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+    ) -> Result<ChatMessage, CrustagiError> {
+        let key = self.key(
+            "chat_with_tools",
+            json!({ "messages": messages, "tools": tools }),
+        );
+        if let Some(answer) = self
+            .get(&key)
+            .and_then(|answer| serde_json::from_str(&answer).ok())
+        {
+            return Ok(answer);
+        }
+        let result = self.inner.chat_with_tools(messages, tools).await;
+        if let Ok(answer) = &result {
+            let answer = serde_json::to_string(answer).expect("Messages serialize to JSON");
+            self.put(key, &Ok(answer));
+        }
+        result
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f64>, CrustagiError> {
+        if let Some(embedding) = self.embedding(text) {
+            return Ok(embedding);
+        }
+        let embedding = self.inner.embed(text).await?;
+        self.put_embedding(text, &embedding);
+        Ok(embedding)
+    }
+
+    // Only the texts not seen before are sent, in one batch
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f64>>, CrustagiError> {
+        let mut embeddings: Vec<Option<Vec<f64>>> =
+            texts.iter().map(|text| self.embedding(text)).collect();
+        let missing: Vec<usize> = (0..texts.len())
+            .filter(|&i| embeddings[i].is_none())
+            .collect();
+        if !missing.is_empty() {
+            let missing_texts: Vec<String> = missing.iter().map(|&i| texts[i].clone()).collect();
+            let fetched = self.inner.embed_batch(&missing_texts).await?;
+            if fetched.len() != missing.len() {
+                return Err(format!(
+                    "Got {} embeddings for {} texts",
+                    fetched.len(),
+                    missing.len()
+                )
+                .into());
+            }
+            for (i, embedding) in missing.into_iter().zip(fetched) {
+                self.put_embedding(&texts[i], &embedding);
+                embeddings[i] = Some(embedding);
+            }
+        }
+        Ok(embeddings.into_iter().flatten().collect())
+    }
+
+    fn model(&self) -> String {
+        self.inner.model()
+    }
+
+    fn prompt_budget(&self) -> usize {
+        self.inner.prompt_budget()
+    }
+
+    fn embedding_model(&self) -> &str {
+        self.inner.embedding_model()
+    }
+
+    async fn embedding_dimension(&self) -> Result<usize, CrustagiError> {
+        self.inner.embedding_dimension().await
+    }
+}
+
+/// Limits the calls of the providers it wraps to `requests_per_minute`
+/// together, spread evenly over the minute, 0 for no limit. As a layer it
+/// counts calls rather than HTTP requests. The OpenAI and Pinecone clients
+/// take it with `with_rate_limit` instead, to wait before every request,
+/// retries and continuations included.
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    requests_per_minute: u32,
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimitLayer {
+    pub fn new(requests_per_minute: u32) -> Self {
+        RateLimitLayer {
+            requests_per_minute,
+            limiter: Arc::new(RateLimiter::new()),
+        }
+    }
+
+    /// Wait for the next free slot, e.g. before a request to another API
+    /// that shares the limit. Its clones share the slots.
+    pub async fn wait(&self) {
+        if self.requests_per_minute != 0 {
+            self.limiter.wait(self.requests_per_minute).await;
+        }
+    }
+}
+
+impl Layer for RateLimitLayer {
+    fn layer(&self, inner: Arc<dyn LlmProvider>) -> Arc<dyn LlmProvider> {
+        Arc::new(RateLimited {
+            inner,
+            limit: self.clone(),
+        })
+    }
+}
+
+struct RateLimited {
+    inner: Arc<dyn LlmProvider>,
+    limit: RateLimitLayer,
+}
+
+impl RateLimited {
+    async fn wait(&self) {
+        self.limit.wait().await;
+    }
+}
+
+#[async_trait]
+impl LlmProvider for RateLimited {
+    async fn complete(&self, prompt: &str) -> Result<String, CrustagiError> {
+        self.wait().await;
+        self.inner.complete(prompt).await
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut (dyn for<'c> FnMut(&'c str) + Send),
+    ) -> Result<String, CrustagiError> {
+        self.wait().await;
+        self.inner.chat_stream(messages, on_chunk).await
+    }
+
+    async fn complete_json(&self, prompt: &str, schema: &Value) -> Result<String, CrustagiError> {
+        self.wait().await;
+        self.inner.complete_json(prompt, schema).await
+    }
+
+    async fn chat(&self, messages: &[ChatMessage]) -> Result<String, CrustagiError> {
+        self.wait().await;
+        self.inner.chat(messages).await
+    }
+
+    async fn chat_with_tools(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+    ) -> Result<ChatMessage, CrustagiError> {
+        self.wait().await;
+        self.inner.chat_with_tools(messages, tools).await
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f64>, CrustagiError> {
+        self.wait().await;
+        self.inner.embed(text).await
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f64>>, CrustagiError> {
+        self.wait().await;
+        self.inner.embed_batch(texts).await
+    }
+
+    fn model(&self) -> String {
+        self.inner.model()
+    }
+
+    fn prompt_budget(&self) -> usize {
+        self.inner.prompt_budget()
+    }
+
+    fn embedding_model(&self) -> &str {
+        self.inner.embedding_model()
+    }
+
+    async fn embedding_dimension(&self) -> Result<usize, CrustagiError> {
+        self.inner.embedding_dimension().await
+    }
+}
+
+/// Records the token usage of every call to `meter`, which prices it: the
+/// usage the providers report with `usage::report`, as the built-in ones do
+/// from the API's answer. Calls answered without the API, e.g. from a cache,
+/// cost nothing.
+#[derive(Clone)]
+pub struct CostLayer {
+    meter: UsageMeter,
+    estimate: bool,
+}
+
+impl CostLayer {
+    pub fn new(meter: UsageMeter) -> Self {
+        CostLayer {
+            meter,
+            estimate: false,
+        }
+    }
+
+    /// Also count the tokens of successful calls that report no usage with
+    /// the model's tokenizer, for providers that don't report theirs, e.g.
+    /// custom ones. Put caches outside the layer, as their answers would be
+    /// counted too.
+    pub fn estimating(mut self) -> Self {
+        self.estimate = true;
+        self
+    }
+}
+
+impl Layer for CostLayer {
+    fn layer(&self, inner: Arc<dyn LlmProvider>) -> Arc<dyn LlmProvider> {
+        Arc::new(Metered {
+            inner,
+            meter: self.meter.clone(),
+            estimate: self.estimate,
+        })
+    }
+}
+
+struct Metered {
+    inner: Arc<dyn LlmProvider>,
+    meter: UsageMeter,
+    estimate: bool,
+}
+
+impl Metered {
+    // Record the usage reported during a call. Returns whether the call's
+    // tokens still have to be counted.
+    fn record_reported(&self, reported: Vec<(String, TokenUsage)>) -> bool {
+        for (model, usage) in &reported {
+            self.meter
+                .record(model, usage.prompt_tokens, usage.completion_tokens);
+        }
+        reported.is_empty() && self.estimate
+    }
+
+    fn record(&self, prompt_tokens: usize, result: &Result<String, CrustagiError>) {
+        if let Ok(answer) = result {
+            let model = self.inner.model();
+            let completion_tokens = tokens::count_tokens(&model, answer);
+            self.meter.record(&model, prompt_tokens, completion_tokens);
+        }
+    }
+
+    fn record_embeddings<'t>(&self, texts: impl Iterator<Item = &'t str>) {
+        let model = self.inner.embedding_model();
+        let prompt_tokens = texts.map(|text| tokens::count_tokens(model, text)).sum();
+        self.meter.record(model, prompt_tokens, 0);
+    }
+}
+
+#[async_trait]
+impl LlmProvider for Metered {
+    async fn complete(&self, prompt: &str) -> Result<String, CrustagiError> {
+        let (result, reported) = usage::collect(self.inner.complete(prompt)).await;
+        if self.record_reported(reported) {
+            self.record(tokens::count_tokens(&self.inner.model(), prompt), &result);
+        }
+        result
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut (dyn for<'c> FnMut(&'c str) + Send),
+    ) -> Result<String, CrustagiError> {
+        let (result, reported) = usage::collect(self.inner.chat_stream(messages, on_chunk)).await;
+        if self.record_reported(reported) {
+            self.record(message_tokens(&self.inner.model(), messages), &result);
+        }
+        result
+    }
+
+    async fn complete_json(&self, prompt: &str, schema: &Value) -> Result<String, CrustagiError> {
+        let (result, reported) = usage::collect(self.inner.complete_json(prompt, schema)).await;
+        if self.record_reported(reported) {
+            self.record(tokens::count_tokens(&self.inner.model(), prompt), &result);
+        }
+        result
+    }
+
+    async fn chat(&self, messages: &[ChatMessage]) -> Result<String, CrustagiError> {
+        let (result, reported) = usage::collect(self.inner.chat(messages)).await;
+        if self.record_reported(reported) {
+            self.record(message_tokens(&self.inner.model(), messages), &result);
+        }
+        result
+    }
+
+    // The tools offered count as the prompt, the calls as the answer
+    async fn chat_with_tools(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+    ) -> Result<ChatMessage, CrustagiError> {
+        let (result, reported) = usage::collect(self.inner.chat_with_tools(messages, tools)).await;
+        if !self.record_reported(reported) {
+            return result;
+        }
+        if let Ok(answer) = &result {
+            let model = self.inner.model();
+            let tools = serde_json::to_string(tools).expect("Tools serialize to JSON");
+            let prompt_tokens =
+                message_tokens(&model, messages) + tokens::count_tokens(&model, &tools);
+            let answer = answer
+                .tool_calls
+                .iter()
+                .fold(answer.content.clone(), |text, call| text + &call.arguments);
+            self.record(prompt_tokens, &Ok(answer));
+        }
+        result
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f64>, CrustagiError> {
+        let (result, reported) = usage::collect(self.inner.embed(text)).await;
+        if self.record_reported(reported) && result.is_ok() {
+            self.record_embeddings([text].into_iter());
+        }
+        result
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f64>>, CrustagiError> {
+        let (result, reported) = usage::collect(self.inner.embed_batch(texts)).await;
+        if self.record_reported(reported) && result.is_ok() {
+            self.record_embeddings(texts.iter().map(String::as_str));
+        }
+        result
+    }
+
+    fn model(&self) -> String {
+        self.inner.model()
+    }
+
+    fn prompt_budget(&self) -> usize {
+        self.inner.prompt_budget()
+    }
+
+    fn embedding_model(&self) -> &str {
+        self.inner.embedding_model()
+    }
+
+    async fn embedding_dimension(&self) -> Result<usize, CrustagiError> {
+        self.inner.embedding_dimension().await
+    }
+}
+
+/// Replaces every match of the patterns in the prompts, messages and texts
+/// to embed with `[REDACTED]` before they are passed on, e.g. to keep API
+/// keys or email addresses found in results from leaving the machine
+#[derive(Clone)]
+pub struct RedactionLayer {
+    patterns: Arc<Vec<Regex>>,
+}
+
+impl RedactionLayer {
+    /// Fails if one of the patterns isn't a valid regular expression
+    pub fn new(patterns: &[impl AsRef<str>]) -> Result<Self, CrustagiError> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| Regex::new(pattern.as_ref()))
+            .collect::<Result<_, _>>()
+            .map_err(|err| CrustagiError::Config(err.to_string()))?;
+        Ok(RedactionLayer {
+            patterns: Arc::new(patterns),
+        })
+    }
+
+    /// `text` with every match replaced, for texts sent elsewhere than to
+    /// the LLM provider
+    pub fn redact(&self, text: &str) -> String {
+        self.patterns
+            .iter()
+            .fold(text.to_string(), |text, pattern| {
+                pattern.replace_all(&text, "[REDACTED]").into_owned()
+            })
+    }
+}
+
+impl Layer for RedactionLayer {
+    fn layer(&self, inner: Arc<dyn LlmProvider>) -> Arc<dyn LlmProvider> {
+        Arc::new(Redacted {
+            inner,
+            redaction: self.clone(),
+        })
+    }
+}
+
+struct Redacted {
+    inner: Arc<dyn LlmProvider>,
+    redaction: RedactionLayer,
+}
+
+impl Redacted {
+    fn redact(&self, text: &str) -> String {
+        self.redaction.redact(text)
+    }
+
+    fn redact_messages(&self, messages: &[ChatMessage]) -> Vec<ChatMessage> {
+        messages
+            .iter()
+            .map(|message| ChatMessage {
+                content: self.redact(&message.content),
+                tool_calls: message
+                    .tool_calls
+                    .iter()
+                    .map(|call| ToolCall {
+                        arguments: self.redact(&call.arguments),
+                        ..call.clone()
+                    })
+                    .collect(),
+                ..message.clone()
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl LlmProvider for Redacted {
+    async fn complete(&self, prompt: &str) -> Result<String, CrustagiError> {
+        self.inner.complete(&self.redact(prompt)).await
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut (dyn for<'c> FnMut(&'c str) + Send),
+    ) -> Result<String, CrustagiError> {
+        let messages = self.redact_messages(messages);
+        self.inner.chat_stream(&messages, on_chunk).await
+    }
+
+    async fn complete_json(&self, prompt: &str, schema: &Value) -> Result<String, CrustagiError> {
+        self.inner.complete_json(&self.redact(prompt), schema).await
+    }
+
+    async fn chat(&self, messages: &[ChatMessage]) -> Result<String, CrustagiError> {
+        self.inner.chat(&self.redact_messages(messages)).await
+    }
+
+    async fn chat_with_tools(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+    ) -> Result<ChatMessage, CrustagiError> {
+        let messages = self.redact_messages(messages);
+        self.inner.chat_with_tools(&messages, tools).await
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f64>, CrustagiError> {
+        self.inner.embed(&self.redact(text)).await
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f64>>, CrustagiError> {
+        let texts: Vec<String> = texts.iter().map(|text| self.redact(text)).collect();
+        self.inner.embed_batch(&texts).await
+    }
+
+    fn model(&self) -> String {
+        self.inner.model()
+    }
+
+    fn prompt_budget(&self) -> usize {
+        self.inner.prompt_budget()
+    }
+
+    fn embedding_model(&self) -> &str {
+        self.inner.embedding_model()
+    }
+
+    async fn embedding_dimension(&self) -> Result<usize, CrustagiError> {
+        self.inner.embedding_dimension().await
+    }
+}
+
+// Tokens of the contents of `messages`
+fn message_tokens(model: &str, messages: &[ChatMessage]) -> usize {
+    messages
+        .iter()
+        .map(|message| tokens::count_tokens(model, &message.content))
+        .sum()
+}
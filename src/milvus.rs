@@ -0,0 +1,205 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::{json, Value};
+
+use crate::error::CrustagiError;
+use crate::memory::{Match, Metadata, StoredVector, VectorMetadata, VectorStore};
+
+// Entities fetched per query when exporting; Milvus caps offset + limit at 16384
+const QUERY_PAGE_SIZE: usize = 1000;
+
+// Milvus collection used as a `VectorStore` via the v2 RESTful API
+pub struct MilvusStore {
+    pub url: String,
+    pub token: Option<String>,
+    pub collection: String,
+    pub dimension: usize,
+}
+
+impl MilvusStore {
+    // Milvus reports failures in the body's `code` field, usually with HTTP 200
+    async fn call(&self, path: &str, body: Value) -> Result<Value, CrustagiError> {
+        let url = format!("{}/v2/vectordb{}", self.url.trim_end_matches('/'), path);
+        let request = Client::new().post(url).json(&body);
+        let request = match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        };
+        let res: Value = request.send().await?.error_for_status()?.json().await?;
+        match res["code"].as_i64() {
+            Some(0) => Ok(res),
+            _ => Err(format!("Milvus request to {} failed: {}", path, res).into()),
+        }
+    }
+}
+
+#[async_trait]
+impl VectorStore for MilvusStore {
+    #[tracing::instrument(skip_all, fields(collection = %self.collection))]
+    async fn create(&self) -> Result<(), CrustagiError> {
+        let res = self
+            .call(
+                "/collections/has",
+                json!({ "collectionName": self.collection }),
+            )
+            .await?;
+        if res["data"]["has"].as_bool() == Some(true) {
+            return Ok(());
+        }
+
+        tracing::info!("Creating Milvus collection {}...", self.collection);
+        // Quick-setup collection: string primary key, one vector field and
+        // dynamic fields for metadata
+        self.call(
+            "/collections/create",
+            json!({
+                "collectionName": self.collection,
+                "dimension": self.dimension,
+                "metricType": "COSINE",
+                "idType": "VarChar",
+                "params": { "max_length": 512 }
+            }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all, fields(collection = %self.collection, id))]
+    async fn upsert(
+        &self,
+        id: &str,
+        vector: &[f64],
+        metadata: &VectorMetadata,
+    ) -> Result<usize, CrustagiError> {
+        tracing::debug!("Storing to Milvus...");
+        // Metadata goes into dynamic fields next to the primary key and vector
+        let mut row = metadata.to_map();
+        row.insert("id".to_string(), json!(id));
+        row.insert("vector".to_string(), json!(vector));
+        let res = self
+            .call(
+                "/entities/upsert",
+                json!({
+                    "collectionName": self.collection,
+                    "data": [row]
+                }),
+            )
+            .await?;
+        Ok(res["data"]["upsertCount"].as_u64().unwrap_or(1) as usize)
+    }
+
+    #[tracing::instrument(skip_all, fields(collection = %self.collection, top_k))]
+    async fn query(
+        &self,
+        vector: &[f64],
+        top_k: usize,
+        filter: Option<&Metadata>,
+    ) -> Result<Vec<Match>, CrustagiError> {
+        tracing::debug!("Querying Milvus...");
+        let mut body = json!({
+            "collectionName": self.collection,
+            "data": [vector],
+            "annsField": "vector",
+            "limit": top_k,
+            "outputFields": ["*"]
+        });
+        if let Some(filter) = filter {
+            body["filter"] = json!(filter_expression(filter)?);
+        }
+        let res = self.call("/entities/search", body).await?;
+
+        let hits = res["data"].as_array().cloned().unwrap_or_default();
+        Ok(hits
+            .into_iter()
+            .filter_map(|hit| {
+                let Value::Object(mut fields) = hit else {
+                    return None;
+                };
+                let id = fields.remove("id")?.as_str()?.to_string();
+                // With the COSINE metric Milvus returns the similarity itself
+                let score = fields.remove("distance")?.as_f64()?;
+                fields.remove("vector");
+                Some(Match {
+                    id,
+                    score,
+                    metadata: VectorMetadata::from_map(fields.into_iter().collect()),
+                })
+            })
+            .collect())
+    }
+
+    #[tracing::instrument(skip_all, fields(collection = %self.collection))]
+    async fn delete(&self, ids: &[String]) -> Result<(), CrustagiError> {
+        self.call(
+            "/entities/delete",
+            json!({
+                "collectionName": self.collection,
+                "filter": format!("id in {}", serde_json::to_string(ids)?)
+            }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all, fields(collection = %self.collection))]
+    async fn dump(&self) -> Result<Vec<StoredVector>, CrustagiError> {
+        let mut vectors = Vec::new();
+        loop {
+            let res = self
+                .call(
+                    "/entities/query",
+                    json!({
+                        "collectionName": self.collection,
+                        "filter": "id != \"\"",
+                        "outputFields": ["*"],
+                        "limit": QUERY_PAGE_SIZE,
+                        "offset": vectors.len()
+                    }),
+                )
+                .await?;
+            let entities = res["data"].as_array().cloned().unwrap_or_default();
+            let count = entities.len();
+            for entity in entities {
+                let Value::Object(mut fields) = entity else {
+                    continue;
+                };
+                let Some(id) = fields
+                    .remove("id")
+                    .and_then(|id| id.as_str().map(|id| id.to_string()))
+                else {
+                    continue;
+                };
+                let vector = serde_json::from_value(fields.remove("vector").unwrap_or_default())?;
+                vectors.push(StoredVector {
+                    id,
+                    vector,
+                    metadata: VectorMetadata::from_map(fields.into_iter().collect()),
+                });
+            }
+            if count < QUERY_PAGE_SIZE {
+                return Ok(vectors);
+            }
+        }
+    }
+
+    #[tracing::instrument(skip_all, fields(collection = %self.collection))]
+    async fn reset(&self) -> Result<(), CrustagiError> {
+        tracing::info!("Dropping Milvus collection {}...", self.collection);
+        self.call(
+            "/collections/drop",
+            json!({ "collectionName": self.collection }),
+        )
+        .await?;
+        self.create().await
+    }
+}
+
+// Boolean expression over dynamic fields, e.g. `objective == "..." and task_id == 3`;
+// JSON literals double as Milvus string and number literals
+fn filter_expression(filter: &Metadata) -> Result<String, CrustagiError> {
+    let mut clauses = Vec::with_capacity(filter.len());
+    for (key, value) in filter {
+        clauses.push(format!("{} == {}", key, serde_json::to_string(value)?));
+    }
+    Ok(clauses.join(" and "))
+}
@@ -0,0 +1,181 @@
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::error::CrustagiError;
+use crate::llm::{ChatMessage, LlmProvider};
+use crate::tokens;
+use crate::usage;
+
+pub const MOCK_MODEL: &str = "mock";
+pub const MOCK_EMBEDDING_MODEL: &str = "mock-embedding";
+
+// Size of the mock embeddings
+const DIMENSION: usize = 64;
+
+// Task creation calls answered with new tasks. Later calls get an empty
+// list, so a dry run comes to an end.
+const TASK_ROUNDS: usize = 3;
+
+// New tasks per task creation call
+const TASKS_PER_ROUND: usize = 2;
+
+// Provider of `--dry-run`, answering every call with canned output instead
+// of calling an API. Answers only depend on the prompt and the calls made
+// before, so the same run gives the same answers. JSON answers are picked by
+// the fields the schema requires.
+pub struct MockLlm {
+    calls: AtomicUsize,
+    task_rounds: AtomicUsize,
+}
+
+impl Default for MockLlm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockLlm {
+    pub fn new() -> Self {
+        MockLlm {
+            calls: AtomicUsize::new(0),
+            task_rounds: AtomicUsize::new(0),
+        }
+    }
+
+    // Report the tokens of a call, so that budgets and the cost summary can
+    // be tried out too
+    fn record(&self, model: &str, prompt: &str, answer: &str) {
+        usage::report(
+            model,
+            tokens::count_tokens(MOCK_MODEL, prompt),
+            tokens::count_tokens(MOCK_MODEL, answer),
+        );
+    }
+
+    fn answer(&self, prompt: &str) -> String {
+        let call = self.calls.fetch_add(1, Ordering::Relaxed) + 1;
+        let answer = format!(
+            "Mock answer {} to a prompt of {} tokens.",
+            call,
+            tokens::count_tokens(MOCK_MODEL, prompt)
+        );
+        self.record(MOCK_MODEL, prompt, &answer);
+        answer
+    }
+
+    fn json_answer(&self, prompt: &str, schema: &Value) -> Value {
+        let requires = |field: &str| {
+            schema["required"]
+                .as_array()
+                .is_some_and(|required| required.iter().any(|name| name == field))
+        };
+        if requires("milestones") {
+            json!({ "milestones": ["First mock milestone", "Second mock milestone"] })
+        } else if requires("score") {
+            json!({ "score": 10, "critique": "Mock review, nothing to improve." })
+        } else if requires("tasks") && schema.to_string().contains("\"priority\"") {
+            // Tasks keep their order, the first listed gets the highest priority
+            let ids = listed_ids(prompt);
+            let count = ids.len();
+            let tasks: Vec<Value> = ids
+                .into_iter()
+                .enumerate()
+                .map(|(index, task_id)| json!({ "task_id": task_id, "priority": count - index }))
+                .collect();
+            json!({ "tasks": tasks })
+        } else if requires("tasks") {
+            let round = self.task_rounds.fetch_add(1, Ordering::Relaxed);
+            if round >= TASK_ROUNDS {
+                return json!({ "tasks": [] });
+            }
+            let tasks: Vec<Value> = (1..=TASKS_PER_ROUND)
+                .map(|n| json!({ "task_name": format!("Mock task {}", round * TASKS_PER_ROUND + n) }))
+                .collect();
+            json!({ "tasks": tasks })
+        } else {
+            json!({})
+        }
+    }
+}
+
+// Numbers the prompt lists items with, e.g. 3 and 4 in `3. Research, 4. Write`
+fn listed_ids(prompt: &str) -> Vec<i64> {
+    let mut ids = Vec::new();
+    let mut digits = String::new();
+    let mut chars = prompt.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        if c == '.' && chars.peek() == Some(&' ') {
+            if let Ok(id) = digits.parse() {
+                if !ids.contains(&id) {
+                    ids.push(id);
+                }
+            }
+        }
+        digits.clear();
+    }
+    ids
+}
+
+// Bag of words hashed into a unit vector, so that texts sharing words are
+// close to each other
+fn embedding(text: &str) -> Vec<f64> {
+    let mut vector = vec![0.0; DIMENSION];
+    for word in text.split_whitespace() {
+        let mut hasher = DefaultHasher::new();
+        word.to_lowercase().hash(&mut hasher);
+        vector[hasher.finish() as usize % DIMENSION] += 1.0;
+    }
+    let norm = vector.iter().map(|x| x * x).sum::<f64>().sqrt();
+    if norm > 0.0 {
+        vector.iter().map(|x| x / norm).collect()
+    } else {
+        // Empty texts still get a vector the stores accept
+        vector[0] = 1.0;
+        vector
+    }
+}
+
+#[async_trait]
+impl LlmProvider for MockLlm {
+    async fn complete(&self, prompt: &str) -> Result<String, CrustagiError> {
+        Ok(self.answer(prompt))
+    }
+
+    async fn complete_json(&self, prompt: &str, schema: &Value) -> Result<String, CrustagiError> {
+        let answer = self.json_answer(prompt, schema).to_string();
+        self.record(MOCK_MODEL, prompt, &answer);
+        Ok(answer)
+    }
+
+    async fn chat(&self, messages: &[ChatMessage]) -> Result<String, CrustagiError> {
+        let prompt: Vec<&str> = messages
+            .iter()
+            .map(|message| message.content.as_str())
+            .collect();
+        Ok(self.answer(&prompt.join("\n")))
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f64>, CrustagiError> {
+        self.record(MOCK_EMBEDDING_MODEL, text, "");
+        Ok(embedding(text))
+    }
+
+    fn model(&self) -> String {
+        MOCK_MODEL.to_string()
+    }
+
+    fn embedding_model(&self) -> &str {
+        MOCK_EMBEDDING_MODEL
+    }
+
+    async fn embedding_dimension(&self) -> Result<usize, CrustagiError> {
+        Ok(DIMENSION)
+    }
+}
@@ -0,0 +1,73 @@
+use std::fs;
+
+use crate::error::CrustagiError;
+
+// How the run picks the objective to work on next, set by
+// `OBJECTIVE_SCHEDULING`
+#[derive(Debug, Clone, Copy)]
+pub enum Scheduling {
+    // One iteration for each objective in turn
+    RoundRobin,
+    // The objective whose next task has the highest priority
+    Priority,
+    // Each objective until its task list is empty, then the next
+    Sequential,
+}
+
+impl Scheduling {
+    pub fn parse(value: &str) -> Result<Self, CrustagiError> {
+        match value {
+            "round_robin" => Ok(Scheduling::RoundRobin),
+            "priority" => Ok(Scheduling::Priority),
+            "sequential" => Ok(Scheduling::Sequential),
+            other => Err(CrustagiError::Config(format!(
+                "Unknown OBJECTIVE_SCHEDULING \"{}\", expected \"round_robin\", \"priority\" or \"sequential\"",
+                other
+            ))),
+        }
+    }
+}
+
+// Objectives listed in `path`, one per line. Empty lines and lines starting
+// with `#` are skipped.
+pub fn load(path: &str) -> Result<Vec<String>, CrustagiError> {
+    let objectives: Vec<String> = read(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect();
+    if objectives.is_empty() {
+        return Err(CrustagiError::Config(format!(
+            "{} lists no objectives",
+            path
+        )));
+    }
+    Ok(objectives)
+}
+
+// Single objective written out in the file at `path`, e.g. a longer brief
+// spanning several lines
+pub fn load_brief(path: &str) -> Result<String, CrustagiError> {
+    let brief = read(path)?.trim().to_string();
+    if brief.is_empty() {
+        return Err(CrustagiError::Config(format!("{} is empty", path)));
+    }
+    Ok(brief)
+}
+
+fn read(path: &str) -> Result<String, CrustagiError> {
+    fs::read_to_string(path)
+        .map_err(|err| CrustagiError::io(format!("{} can't be read", path), err))
+}
+
+// `path` with the objective's number added to the file name, e.g.
+// `state_2.json` for `state.json`
+pub fn numbered_path(path: &str, number: usize) -> String {
+    match path.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && !extension.contains('/') => {
+            format!("{}_{}.{}", stem, number, extension)
+        }
+        _ => format!("{}_{}", path, number),
+    }
+}
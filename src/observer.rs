@@ -0,0 +1,34 @@
+// Hooks into the progress of a run, for programs embedding the agent
+
+use crate::error::CrustagiError;
+use crate::events::Event;
+use crate::task::Task;
+use crate::usage::UsageTracker;
+
+/// Reacts to the progress of a run. [`run_loop`](crate::run_loop) calls the
+/// observers of the [`Engine`](crate::Engine) in the order they were added,
+/// between the steps of an iteration, so a slow hook holds up the run. All
+/// hooks do nothing unless implemented.
+pub trait Observer: Send + Sync {
+    /// `task` is about to be executed. The tasks of an iteration that run at
+    /// the same time are all started before any result.
+    fn on_task_start(&self, _task: &Task) {}
+
+    /// `task` completed with `result`
+    fn on_task_result(&self, _task: &Task, _result: &str) {}
+
+    /// The task creation agent proposed `new_tasks` after `task`, as they
+    /// were added to the task list. Not called if none were added.
+    fn on_tasks_created(&self, _task: &Task, _new_tasks: &[Task]) {}
+
+    /// `task` failed with `error`, and is tried again if `retried`
+    fn on_error(&self, _task: &Task, _error: &CrustagiError, _retried: bool) {}
+
+    /// An iteration ended, after `iterations` tasks in total. `usage` holds
+    /// the tokens and cost of the run so far.
+    fn on_iteration_end(&self, _iterations: usize, _usage: &UsageTracker) {}
+
+    /// `event` happened in the run, as written to the events file. Called
+    /// from the tasks of an iteration too, as they run at the same time.
+    fn on_event(&self, _event: &Event) {}
+}
@@ -1,115 +1,1091 @@
-use std::time::Duration;
+use std::fmt;
+use std::time::{Duration, Instant};
 
-use serde::Deserialize;
-use serde_json::{json, Value};
-use tokio::time::sleep;
+use async_trait::async_trait;
+use reqwest::header::HeaderMap;
+use reqwest::{Client, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::time::{self, sleep};
+use tracing::field::Empty;
+use tracing::Instrument;
 
-use crate::load_env_var;
+use crate::error::CrustagiError;
+use crate::http;
+use crate::llm::{json_prompt, ChatMessage, LlmParams, LlmProvider, ToolCall, ToolSpec};
+use crate::logging;
+use crate::middleware::RateLimitLayer;
+use crate::usage;
 
-#[derive(Debug, Deserialize)]
-struct OpenAiResponse {
-    data: Vec<EmbeddingData>,
-    model: String,
-    object: String,
-    usage: Usage,
+pub const DEFAULT_API_BASE: &str = "https://api.openai.com/v1";
+pub const DEFAULT_MODEL: &str = "gpt-3.5-turbo";
+pub const DEFAULT_EMBEDDING_MODEL: &str = "text-embedding-ada-002";
+pub const API_MODES: &[&str] = &["chat", "completions"];
+
+/// The endpoint an `OpenAiClient` sends prompts to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiMode {
+    /// `/chat/completions`
+    Chat,
+    /// `/completions`, for completion models like `gpt-3.5-turbo-instruct`
+    Completions,
+}
+
+impl ApiMode {
+    /// The mode called `name` in `OPENAI_API_MODE`, one of `API_MODES`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "chat" => Some(ApiMode::Chat),
+            "completions" => Some(ApiMode::Completions),
+            _ => None,
+        }
+    }
+}
+
+// Rate limits and server errors are retried, waiting as long as the
+// `Retry-After` header asks or with exponential backoff from
+// `RETRY_BASE_DELAY` up to `RETRY_MAX_DELAY`
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 6;
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
+// Time limit of a single OpenAI request by default. Streamed answers may
+// take longer as a whole, up to `STREAM_TIMEOUT`, but fail when no data
+// arrives for this long.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(120);
+const STREAM_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+// Failure of an OpenAI request
+#[derive(Debug)]
+pub enum OpenAiError {
+    // The request could not be sent or the response body not read
+    Http(reqwest::Error),
+    // 400: the request is invalid, e.g. the prompt is too long for the model
+    BadRequest(String),
+    // 401: the API key is missing or invalid
+    Unauthorized(String),
+    // 403: the API key has no access to the model or endpoint
+    Forbidden(String),
+    // 429: too many requests, or the quota is used up
+    RateLimited {
+        code: Option<String>,
+        message: String,
+        retry_after: Option<Duration>,
+    },
+    // Any other non-success status, e.g. 5xx server errors
+    Api {
+        status: StatusCode,
+        message: String,
+        retry_after: Option<Duration>,
+    },
+    // The response had no choice or a choice without content
+    NoAnswer(Option<String>),
+    // The response body was not the JSON we expected
+    Decode {
+        body: String,
+        source: serde_json::Error,
+    },
+}
+
+impl fmt::Display for OpenAiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpenAiError::Http(err) => write!(f, "OpenAI request failed: {}", err),
+            OpenAiError::BadRequest(message) => {
+                write!(f, "OpenAI rejected the request: {}", message)
+            }
+            OpenAiError::Unauthorized(message) => {
+                write!(f, "OpenAI rejected the API key: {}", message)
+            }
+            OpenAiError::Forbidden(message) => write!(f, "OpenAI denied access: {}", message),
+            OpenAiError::RateLimited { message, .. } => {
+                write!(f, "OpenAI rate limit exceeded: {}", message)
+            }
+            OpenAiError::Api {
+                status, message, ..
+            } => write!(f, "OpenAI returned {}: {}", status, message),
+            OpenAiError::NoAnswer(None) => write!(f, "OpenAI returned no answer"),
+            OpenAiError::NoAnswer(Some(finish_reason)) => {
+                write!(
+                    f,
+                    "OpenAI returned no answer (finish_reason {})",
+                    finish_reason
+                )
+            }
+            OpenAiError::Decode { body, source } => {
+                write!(f, "Unexpected OpenAI response ({}): {}", source, body)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OpenAiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            OpenAiError::Http(err) => Some(err),
+            OpenAiError::Decode { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for OpenAiError {
+    fn from(err: reqwest::Error) -> Self {
+        OpenAiError::Http(err)
+    }
+}
+
+impl OpenAiError {
+    // OpenAI errors look like `{"error": {"message": ..., "code": ...}}`;
+    // anything else is kept as the raw body
+    fn from_response(status: StatusCode, headers: &HeaderMap, body: String) -> Self {
+        let (message, code) = match serde_json::from_str::<ErrorResponse>(&body) {
+            Ok(ErrorResponse { error }) => {
+                let code = error.code();
+                (error.message, code)
+            }
+            Err(_) => (body, None),
+        };
+        let retry_after = retry_after(headers);
+        match status {
+            StatusCode::BAD_REQUEST => OpenAiError::BadRequest(message),
+            StatusCode::UNAUTHORIZED => OpenAiError::Unauthorized(message),
+            StatusCode::FORBIDDEN => OpenAiError::Forbidden(message),
+            StatusCode::TOO_MANY_REQUESTS => OpenAiError::RateLimited {
+                code,
+                message,
+                retry_after,
+            },
+            _ => OpenAiError::Api {
+                status,
+                message,
+                retry_after,
+            },
+        }
+    }
+
+    // Exhausted quota is reported as 429 too, but waiting doesn't help
+    fn is_transient(&self) -> bool {
+        match self {
+            OpenAiError::Http(err) => err.is_timeout() || err.is_connect(),
+            OpenAiError::RateLimited { code, .. } => code.as_deref() != Some("insufficient_quota"),
+            OpenAiError::Api { status, .. } => status.is_server_error(),
+            _ => false,
+        }
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            OpenAiError::RateLimited { retry_after, .. } | OpenAiError::Api { retry_after, .. } => {
+                *retry_after
+            }
+            _ => None,
+        }
+    }
 }
 
+// Delay requested by the server, from OpenAI's `retry-after-ms` or the
+// standard `Retry-After` header in seconds
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<f64>().ok())
+            .filter(|value| value.is_finite() && *value >= 0.0)
+    };
+    header("retry-after-ms")
+        .map(|ms| Duration::from_secs_f64(ms / 1000.0))
+        .or_else(|| header("retry-after").map(Duration::from_secs_f64))
+}
+
+// Size of the vectors returned by OpenAI's embedding models, None for
+// models not listed here
+fn known_embedding_dimension(model: &str) -> Option<usize> {
+    match model {
+        "text-embedding-ada-002" | "text-embedding-3-small" => Some(1536),
+        "text-embedding-3-large" => Some(3072),
+        _ => None,
+    }
+}
+
+// Most inputs OpenAI accepts in one embeddings request
+const EMBEDDING_BATCH: usize = 2048;
+
 #[derive(Debug, Deserialize)]
 pub struct EmbeddingData {
-    pub embedding: Vec<f64>,
+    // Position of the input this embedding belongs to
+    #[serde(default)]
     pub index: usize,
-    pub object: String,
+    pub embedding: Vec<f64>,
 }
 
 #[derive(Debug, Deserialize)]
 struct Usage {
     prompt_tokens: usize,
+    #[serde(default)]
+    completion_tokens: usize,
     total_tokens: usize,
 }
 
-// Get embedding using OpenAI API
-pub async fn get_ada_embedding(
-    api_key: &str,
-    text: &str,
-) -> Result<EmbeddingData, Box<dyn std::error::Error>> {
-    let text = text.replace('\n', " ");
-    let input = serde_json::json!({
-        "input": text,
-        "model": "text-embedding-ada-002"
-    });
-
-    let res: Value = openai_call_api(api_key, "embeddings", input).await.into();
-    let embedding_data: EmbeddingData = serde_json::from_value(res["data"][0].clone())?;
-    Ok(embedding_data)
-}
-
-// Call OpenAI API with completion or chat completion
-pub async fn openai_call(openai_api_key: &str, prompt: &str) -> String {
-    let openai_api_model = load_env_var("OPENAI_API_MODEL");
-    let (endpoint, input, is_chat_completion) = if openai_api_model.starts_with("gpt-") {
-        // Use chat completion API
-        let messages = vec![json!({ "role": "user", "content": prompt })];
-        let input = json!({
-            "model": openai_api_model,
-            "messages": messages,
-            "temperature": 0.5,
-            "max_tokens": 100,
-            "n": 1,
-            "stop": Value::Null,
-        });
-        ("chat/completions", input, true)
-    } else {
-        // Use completion API
-        let input = json!({
-            "model": openai_api_model,
-            "prompt": prompt,
-            "temperature": 0.7,
-            "max_tokens": 2000,
+// Sampling parameters shared by chat and completion requests
+#[derive(Debug, Serialize)]
+struct Sampling {
+    temperature: f64,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct ResponseFormat {
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+// Asks for the token usage in the last chunk of a streamed answer
+#[derive(Debug, Serialize)]
+struct StreamOptions {
+    include_usage: bool,
+}
+
+// A message of a request. Tool calls nest their name and arguments in a
+// `function` object, and the content of an assistant message only calling
+// tools is null.
+#[derive(Debug, Serialize)]
+struct RequestMessage {
+    role: String,
+    content: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<FunctionCall>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+}
+
+impl From<ChatMessage> for RequestMessage {
+    fn from(message: ChatMessage) -> Self {
+        let content = if message.content.is_empty() && !message.tool_calls.is_empty() {
+            None
+        } else {
+            Some(message.content)
+        };
+        RequestMessage {
+            role: message.role,
+            content,
+            tool_calls: message
+                .tool_calls
+                .into_iter()
+                .map(|call| FunctionCall {
+                    id: call.id,
+                    kind: "function".to_string(),
+                    function: Function {
+                        name: call.name,
+                        arguments: call.arguments,
+                    },
+                })
+                .collect(),
+            tool_call_id: message.tool_call_id,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FunctionCall {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    function: Function,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Function {
+    name: String,
+    arguments: String,
+}
+
+// A tool offered to the model, `{"type": "function", "function": {...}}`
+#[derive(Debug, Serialize)]
+struct RequestTool<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    function: &'a ToolSpec,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatRequest<'a> {
+    model: String,
+    messages: Vec<RequestMessage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<RequestTool<'a>>,
+    n: u32,
+    #[serde(flatten)]
+    sampling: Sampling,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<ResponseFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+}
+
+#[derive(Debug, Serialize)]
+struct CompletionRequest {
+    model: String,
+    prompt: String,
+    #[serde(flatten)]
+    sampling: Sampling,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+}
+
+// Body of a streamed request to either endpoint
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum StreamRequest<'a> {
+    Chat(ChatRequest<'a>),
+    Completion(CompletionRequest),
+}
+
+#[derive(Debug, Serialize)]
+struct EmbeddingRequest {
+    model: String,
+    input: Vec<String>,
+}
+
+// Body of an error response, `{"error": {"message": ..., "code": ...}}`
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ErrorObject,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorObject {
+    #[serde(default)]
+    message: String,
+    // A string like `insufficient_quota` from OpenAI, but some compatible
+    // servers send numbers
+    #[serde(default)]
+    code: Option<Value>,
+}
+
+impl ErrorObject {
+    fn code(&self) -> Option<String> {
+        self.code.as_ref()?.as_str().map(|code| code.to_string())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+    usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatChoice {
+    message: ResponseMessage,
+    finish_reason: Option<String>,
+}
+
+// Content is null when the model refused or called a tool
+#[derive(Debug, Deserialize)]
+struct ResponseMessage {
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<FunctionCall>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionResponse {
+    choices: Vec<CompletionChoice>,
+    usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionChoice {
+    text: String,
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+    usage: Option<Usage>,
+}
+
+// One server-sent event of a streamed chat or completion answer. Chat
+// answers arrive in `delta`, completions in `text`.
+#[derive(Debug, Deserialize)]
+struct StreamChunk {
+    #[serde(default)]
+    choices: Vec<StreamChoice>,
+    usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamChoice {
+    #[serde(default)]
+    delta: StreamDelta,
+    text: Option<String>,
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct StreamDelta {
+    content: Option<String>,
+}
+
+// Response bodies that report the tokens used
+trait ApiResponse: DeserializeOwned {
+    fn usage(&self) -> Option<&Usage>;
+}
+
+impl ApiResponse for ChatResponse {
+    fn usage(&self) -> Option<&Usage> {
+        self.usage.as_ref()
+    }
+}
+
+impl ApiResponse for CompletionResponse {
+    fn usage(&self) -> Option<&Usage> {
+        self.usage.as_ref()
+    }
+}
+
+impl ApiResponse for EmbeddingResponse {
+    fn usage(&self) -> Option<&Usage> {
+        self.usage.as_ref()
+    }
+}
+
+impl ChatResponse {
+    // Text of the first choice and whether it was cut off at max_tokens
+    fn answer(self) -> Result<(String, bool), OpenAiError> {
+        let choice = self
+            .choices
+            .into_iter()
+            .next()
+            .ok_or(OpenAiError::NoAnswer(None))?;
+        let truncated = is_truncated(choice.finish_reason.as_deref());
+        match choice.message.content {
+            Some(content) => Ok((content, truncated)),
+            None => Err(OpenAiError::NoAnswer(choice.finish_reason)),
+        }
+    }
+
+    // The first choice as an assistant message, with the tools it calls
+    fn message(self) -> Result<ChatMessage, OpenAiError> {
+        let choice = self
+            .choices
+            .into_iter()
+            .next()
+            .ok_or(OpenAiError::NoAnswer(None))?;
+        let message = choice.message;
+        if message.content.is_none() && message.tool_calls.is_empty() {
+            return Err(OpenAiError::NoAnswer(choice.finish_reason));
+        }
+        Ok(ChatMessage {
+            tool_calls: message
+                .tool_calls
+                .into_iter()
+                .map(|call| ToolCall {
+                    id: call.id,
+                    name: call.function.name,
+                    arguments: call.function.arguments,
+                })
+                .collect(),
+            ..ChatMessage::assistant(&message.content.unwrap_or_default())
+        })
+    }
+}
+
+impl CompletionResponse {
+    // Text of the first choice and whether it was cut off at max_tokens
+    fn answer(self) -> Result<(String, bool), OpenAiError> {
+        let choice = self
+            .choices
+            .into_iter()
+            .next()
+            .ok_or(OpenAiError::NoAnswer(None))?;
+        Ok((choice.text, is_truncated(choice.finish_reason.as_deref())))
+    }
+}
+
+// Decode a response body. An `error` object is an error even with a success
+// status, as some OpenAI-compatible servers and streams report errors so.
+fn decode<T: DeserializeOwned>(status: StatusCode, body: String) -> Result<T, OpenAiError> {
+    if let Ok(ErrorResponse { error }) = serde_json::from_str(&body) {
+        return Err(OpenAiError::Api {
+            status,
+            message: error.message,
+            retry_after: None,
         });
-        ("completions", input, false)
-    };
+    }
+    serde_json::from_str(&body).map_err(|source| OpenAiError::Decode { body, source })
+}
 
-    let res = openai_call_api(openai_api_key, endpoint, input).await;
-    let text = if is_chat_completion {
-        // Extract content from "message" field for chat completion
-        res["choices"][0]["message"]["content"].as_str().unwrap()
-    } else {
-        // Extract content from "text" field for completion
-        res["choices"][0]["text"].as_str().unwrap()
-    };
+// Report the usage of a response to the `CostLayer` of the call
+fn report_usage(model: &str, reported: &Usage) {
+    usage::report(model, reported.prompt_tokens, reported.completion_tokens);
+}
+
+// Follow-up requests for the rest of an answer cut off at max_tokens
+const MAX_CONTINUATIONS: usize = 3;
+
+// Whether generation of a choice stopped at max_tokens
+fn is_truncated(finish_reason: Option<&str>) -> bool {
+    finish_reason == Some("length")
+}
+
+// Conversation as a single prompt for the completions endpoint
+fn completion_prompt(messages: &[ChatMessage]) -> String {
+    messages
+        .iter()
+        .map(|message| message.content.as_str())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+// Conversation asking the model to continue `partial`, its answer so far
+fn continued(messages: &[ChatMessage], partial: &str) -> Vec<ChatMessage> {
+    let mut messages = messages.to_vec();
+    if !partial.is_empty() {
+        messages.push(ChatMessage::assistant(partial));
+        messages.push(ChatMessage::user(
+            "Continue exactly where your answer stopped, without repeating anything.",
+        ));
+    }
+    messages
+}
+
+// OpenAI API client. The underlying `reqwest::Client` is shared, so
+// connections are pooled across requests and with other API clients.
+#[derive(Clone)]
+pub struct OpenAiClient {
+    http: Client,
+    api_key: String,
+    api_base: String,
+    model: String,
+    embedding_model: String,
+    // None to choose by the model and the API base
+    api_mode: Option<ApiMode>,
+    params: LlmParams,
+    max_attempts: u32,
+    timeout: Duration,
+    // MAX_REQUESTS_PER_MINUTE, shared with the other requests of the run
+    rate_limit: RateLimitLayer,
+}
+
+impl OpenAiClient {
+    pub fn new(http: Client, api_key: &str) -> Self {
+        OpenAiClient {
+            http,
+            api_key: api_key.to_string(),
+            api_base: DEFAULT_API_BASE.to_string(),
+            model: DEFAULT_MODEL.to_string(),
+            embedding_model: DEFAULT_EMBEDDING_MODEL.to_string(),
+            api_mode: None,
+            params: LlmParams::default(),
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            timeout: DEFAULT_TIMEOUT,
+            rate_limit: RateLimitLayer::new(0),
+        }
+    }
+
+    // How many times each request is attempted (at least once), and the
+    // time limit of each attempt
+    pub fn with_retries(mut self, max_attempts: u32, timeout: Duration) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self.timeout = timeout.max(Duration::from_secs(1));
+        self
+    }
+
+    // Wait for `rate_limit` before each request, retries and the requests
+    // for the rest of a cut off answer included
+    pub fn with_rate_limit(mut self, rate_limit: RateLimitLayer) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
 
-    text.to_string()
-}
-
-// Call OpenAI API
-pub async fn openai_call_api(
-    openai_api_key: &str,
-    endpoint: &str,
-    input: serde_json::Value,
-) -> serde_json::Value {
-    let openai_url = format!("https://api.openai.com/v1/{}", endpoint);
-
-    loop {
-        println!("Calling OpenAI API...");
-        let client = reqwest::Client::new();
-        let res = client
-            .post(&openai_url)
-            .header("Content-Type", "application/json")
-            .header("Authorization", format!("Bearer {}", openai_api_key))
-            .body(input.to_string())
-            .send()
+    // Any server implementing the OpenAI API, e.g. `http://localhost:8000/v1` for vLLM
+    pub fn with_api_base(mut self, api_base: &str) -> Self {
+        self.api_base = api_base.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = model.to_string();
+        self
+    }
+
+    pub fn with_embedding_model(mut self, model: &str) -> Self {
+        self.embedding_model = model.to_string();
+        self
+    }
+
+    // Send prompts to the endpoint of `api_mode` whatever the model, instead
+    // of choosing by the model and the API base
+    pub fn with_api_mode(mut self, api_mode: ApiMode) -> Self {
+        self.api_mode = Some(api_mode);
+        self
+    }
+
+    // Whether prompts go to the chat completion API. Without a mode set,
+    // OpenAI's own models are told apart by name, while other servers are
+    // assumed to serve chat models, whatever they are called.
+    fn uses_chat(&self) -> bool {
+        match self.api_mode {
+            Some(api_mode) => api_mode == ApiMode::Chat,
+            None => self.api_base != DEFAULT_API_BASE || self.model.starts_with("gpt-"),
+        }
+    }
+
+    pub fn with_params(mut self, params: LlmParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    // Get embedding using OpenAI API
+    pub async fn get_embedding(&self, text: &str) -> Result<EmbeddingData, CrustagiError> {
+        self.get_embeddings(&[text.to_string()])
+            .await?
+            .pop()
+            .ok_or(OpenAiError::NoAnswer(None).into())
+    }
+
+    // Get the embeddings of several texts, up to `EMBEDDING_BATCH` per request
+    pub async fn get_embeddings(
+        &self,
+        texts: &[String],
+    ) -> Result<Vec<EmbeddingData>, CrustagiError> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(EMBEDDING_BATCH) {
+            let request = EmbeddingRequest {
+                model: self.embedding_model.clone(),
+                input: batch.iter().map(|text| text.replace('\n', " ")).collect(),
+            };
+            let mut res: EmbeddingResponse = self
+                .openai_call_api("embeddings", &request.model, &request)
+                .await?;
+            if res.data.len() != batch.len() {
+                return Err(OpenAiError::NoAnswer(None).into());
+            }
+            res.data.sort_by_key(|data| data.index);
+            embeddings.extend(res.data);
+        }
+        Ok(embeddings)
+    }
+
+    // Call OpenAI API with completion or chat completion
+    pub async fn openai_call(&self, prompt: &str) -> Result<String, CrustagiError> {
+        let openai_api_model = self.model.as_str();
+        if self.uses_chat() {
+            // Use chat completion API
+            return self.openai_chat(&[ChatMessage::user(prompt)]).await;
+        }
+
+        // Use completion API, appending the answer so far to the prompt to
+        // continue an answer that was cut off
+        let mut text = String::new();
+        for _ in 0..=MAX_CONTINUATIONS {
+            let request = self.completion_request(&format!("{}{}", prompt, text));
+            let res: CompletionResponse = self
+                .openai_call_api("completions", openai_api_model, &request)
+                .instrument(tracing::info_span!("openai_call", model = %openai_api_model))
+                .await?;
+            let (answer, truncated) = res.answer()?;
+            text.push_str(&answer);
+            if !truncated {
+                break;
+            }
+            tracing::info!("The answer was cut off at max_tokens, requesting the rest...");
+        }
+        Ok(text)
+    }
+
+    // Call OpenAI chat completion API with a conversation, offering `tools`
+    // for the model to call instead of answering. An answer cut off at
+    // max_tokens isn't continued, as tool calls can't be.
+    pub async fn openai_chat_tools(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+    ) -> Result<ChatMessage, CrustagiError> {
+        let mut request = self.chat_request(messages.to_vec());
+        request.tools = tools
+            .iter()
+            .map(|tool| RequestTool {
+                kind: "function",
+                function: tool,
+            })
+            .collect();
+        Ok(self.chat_completion(&request).await?.message()?)
+    }
+
+    // Call OpenAI chat completion API with a whole conversation
+    pub async fn openai_chat(&self, messages: &[ChatMessage]) -> Result<String, CrustagiError> {
+        let mut text = String::new();
+        for _ in 0..=MAX_CONTINUATIONS {
+            let request = self.chat_request(continued(messages, &text));
+            let (answer, truncated) = self.chat_completion(&request).await?.answer()?;
+            text.push_str(&answer);
+            if !truncated {
+                break;
+            }
+            tracing::info!("The answer was cut off at max_tokens, requesting the rest...");
+        }
+        Ok(text)
+    }
+
+    // Call OpenAI API like `openai_chat`, but with a streamed response whose
+    // text deltas are passed to `on_chunk` as they arrive. Completion models
+    // get the messages as one prompt.
+    pub async fn openai_chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut (dyn for<'c> FnMut(&'c str) + Send),
+    ) -> Result<String, CrustagiError> {
+        let chat = self.uses_chat();
+        let mut text = String::new();
+        for _ in 0..=MAX_CONTINUATIONS {
+            let stream_options = Some(StreamOptions {
+                include_usage: true,
+            });
+            let (endpoint, request) = if chat {
+                let mut request = self.chat_request(continued(messages, &text));
+                request.stream = Some(true);
+                request.stream_options = stream_options;
+                ("chat/completions", StreamRequest::Chat(request))
+            } else {
+                let prompt = completion_prompt(messages);
+                let mut request = self.completion_request(&format!("{}{}", prompt, text));
+                request.stream = Some(true);
+                request.stream_options = stream_options;
+                ("completions", StreamRequest::Completion(request))
+            };
+            let truncated = self
+                .openai_stream_api(endpoint, &request, &mut |delta| {
+                    on_chunk(delta);
+                    text.push_str(delta);
+                })
+                .await?;
+            if !truncated {
+                break;
+            }
+        }
+        Ok(text)
+    }
+
+    // Send a streaming request and pass the text deltas to `on_delta`.
+    // Returns whether the answer was cut off at max_tokens.
+    async fn openai_stream_api(
+        &self,
+        endpoint: &str,
+        request: &StreamRequest<'_>,
+        on_delta: &mut (dyn for<'c> FnMut(&'c str) + Send),
+    ) -> Result<bool, CrustagiError> {
+        let span = tracing::info_span!(
+            "openai_api",
+            endpoint,
+            model = %self.model,
+            latency_ms = Empty,
+        );
+        let started = Instant::now();
+
+        async {
+            let mut res = self.send(endpoint, request, true).await?;
+            let status = res.status();
+
+            // Server-sent events, one `data:` line per chunk. Lines are split
+            // on bytes, so multi-byte characters can span network chunks.
+            let mut truncated = false;
+            let mut pending: Vec<u8> = Vec::new();
+            // The `data:` lines, logged together once the stream ends
+            let mut raw: Vec<String> = Vec::new();
+            while let Some(bytes) =
+                time::timeout(self.timeout, res.chunk())
+                    .await
+                    .map_err(|_| {
+                        format!(
+                            "OpenAI stream stalled, no data for {}s",
+                            self.timeout.as_secs()
+                        )
+                    })??
+            {
+                pending.extend_from_slice(&bytes);
+                while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+                    let line: Vec<u8> = pending.drain(..=end).collect();
+                    let line = String::from_utf8_lossy(&line);
+                    let Some(data) = line.trim().strip_prefix("data:") else {
+                        continue;
+                    };
+                    let data = data.trim();
+                    raw.push(data.to_string());
+                    if data == "[DONE]" {
+                        break;
+                    }
+                    let chunk: StreamChunk = decode(status, data.to_string())?;
+                    if let Some(usage) = &chunk.usage {
+                        report_usage(&self.model, usage);
+                    }
+                    let Some(choice) = chunk.choices.into_iter().next() else {
+                        continue;
+                    };
+                    if let Some(delta) = choice.delta.content.or(choice.text) {
+                        on_delta(&delta);
+                    }
+                    truncated |= is_truncated(choice.finish_reason.as_deref());
+                }
+            }
+            logging::response("OpenAI", endpoint, raw.join("\n"));
+            if truncated {
+                tracing::info!("\nThe answer was cut off at max_tokens, requesting the rest...");
+            }
+            span.record("latency_ms", started.elapsed().as_millis() as u64);
+            Ok(truncated)
+        }
+        .instrument(span.clone())
+        .await
+    }
+
+    // Completion models have no system role, so the system prompt goes first
+    fn completion_request(&self, prompt: &str) -> CompletionRequest {
+        let prompt = match &self.params.system_prompt {
+            Some(system_prompt) => format!("{}\n\n{}", system_prompt, prompt),
+            None => prompt.to_string(),
+        };
+        CompletionRequest {
+            model: self.model.clone(),
+            prompt,
+            sampling: self.sampling(0.7, 2000),
+            stream: None,
+            stream_options: None,
+        }
+    }
+
+    fn chat_request<'a>(&self, messages: Vec<ChatMessage>) -> ChatRequest<'a> {
+        ChatRequest {
+            model: self.model.clone(),
+            messages: self
+                .params
+                .with_system_prompt(&messages)
+                .into_iter()
+                .map(RequestMessage::from)
+                .collect(),
+            tools: Vec::new(),
+            n: 1,
+            sampling: self.sampling(0.5, 2000),
+            response_format: None,
+            stream: None,
+            stream_options: None,
+        }
+    }
+
+    // The configured sampling parameters, with the endpoint's defaults for
+    // temperature and max_tokens
+    fn sampling(&self, temperature: f64, max_tokens: u32) -> Sampling {
+        let params = &self.params;
+        Sampling {
+            temperature: params.temperature.unwrap_or(temperature),
+            max_tokens: params.max_tokens.unwrap_or(max_tokens),
+            top_p: params.top_p,
+            presence_penalty: params.presence_penalty,
+            frequency_penalty: params.frequency_penalty,
+            stop: params.stop.clone(),
+            seed: params.seed,
+        }
+    }
+
+    // Call OpenAI chat completion API in JSON mode, which guarantees a
+    // syntactically valid JSON object
+    pub async fn openai_chat_json(
+        &self,
+        messages: &[ChatMessage],
+    ) -> Result<String, CrustagiError> {
+        let mut request = self.chat_request(messages.to_vec());
+        request.response_format = Some(ResponseFormat {
+            kind: "json_object",
+        });
+        let (answer, _) = self.chat_completion(&request).await?.answer()?;
+        Ok(answer)
+    }
+
+    async fn chat_completion(
+        &self,
+        request: &ChatRequest<'_>,
+    ) -> Result<ChatResponse, OpenAiError> {
+        self.openai_call_api("chat/completions", &request.model, request)
+            .instrument(tracing::info_span!("openai_call", model = %request.model))
             .await
-            .unwrap();
-        if res.status().is_success() {
-            let res: serde_json::Value = res.json().await.unwrap();
-            return res;
-        } else if res.status().as_u16() == 429 {
-            println!(
-                "The OpenAI API rate limit has been exceeded. Waiting 10 seconds and trying again."
+    }
+
+    // Send a request, retrying rate limits and transient failures up to the
+    // configured number of attempts
+    async fn send(
+        &self,
+        endpoint: &str,
+        request: &(impl Serialize + Sync),
+        stream: bool,
+    ) -> Result<Response, OpenAiError> {
+        let openai_url = format!("{}/{}", self.api_base, endpoint);
+        let max_attempts = self.max_attempts;
+        let timeout = if stream { STREAM_TIMEOUT } else { self.timeout };
+        let mut attempt = 1;
+        logging::request("OpenAI", endpoint, request);
+        loop {
+            self.rate_limit.wait().await;
+            tracing::debug!("Calling OpenAI API...");
+            let result = match self
+                .http
+                .post(&openai_url)
+                .bearer_auth(&self.api_key)
+                .json(request)
+                .timeout(timeout)
+                .send()
+                .await
+            {
+                Ok(res) if res.status().is_success() => return Ok(res),
+                Ok(res) => {
+                    let status = res.status();
+                    let headers = res.headers().clone();
+                    let body = res.text().await?;
+                    OpenAiError::from_response(status, &headers, body)
+                }
+                Err(err) => OpenAiError::Http(err),
+            };
+            if attempt >= max_attempts || !result.is_transient() {
+                return Err(result);
+            }
+            let delay = result
+                .retry_after()
+                .unwrap_or_else(|| http::backoff(attempt, RETRY_BASE_DELAY, RETRY_MAX_DELAY));
+            tracing::warn!(
+                "{} (attempt {}/{}), retrying in {:.1}s...",
+                result,
+                attempt,
+                max_attempts,
+                delay.as_secs_f64()
             );
-            sleep(Duration::from_secs(10)).await;
+            sleep(delay).await;
+            attempt += 1;
         }
     }
+
+    // Call OpenAI API
+    async fn openai_call_api<T: ApiResponse>(
+        &self,
+        endpoint: &str,
+        model: &str,
+        request: &(impl Serialize + Sync),
+    ) -> Result<T, OpenAiError> {
+        let span = tracing::info_span!(
+            "openai_api",
+            endpoint,
+            model,
+            prompt_tokens = Empty,
+            completion_tokens = Empty,
+            total_tokens = Empty,
+            latency_ms = Empty,
+        );
+        let started = Instant::now();
+
+        async {
+            let res = self.send(endpoint, request, false).await?;
+            let status = res.status();
+            let body = res.text().await?;
+            logging::response("OpenAI", endpoint, &body);
+            let res: T = decode(status, body)?;
+            if let Some(usage) = res.usage() {
+                span.record("prompt_tokens", usage.prompt_tokens);
+                span.record("completion_tokens", usage.completion_tokens);
+                span.record("total_tokens", usage.total_tokens);
+                report_usage(model, usage);
+            }
+            span.record("latency_ms", started.elapsed().as_millis() as u64);
+            Ok(res)
+        }
+        .instrument(span.clone())
+        .await
+    }
 }
 
+#[async_trait]
+impl LlmProvider for OpenAiClient {
+    // Non-chat models go through the legacy completions endpoint
+    async fn complete(&self, prompt: &str) -> Result<String, CrustagiError> {
+        Ok(self.openai_call(prompt).await?)
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut (dyn for<'c> FnMut(&'c str) + Send),
+    ) -> Result<String, CrustagiError> {
+        self.openai_chat_stream(messages, on_chunk).await
+    }
+
+    // JSON mode is only available for chat models
+    async fn complete_json(&self, prompt: &str, schema: &Value) -> Result<String, CrustagiError> {
+        let prompt = json_prompt(prompt, schema);
+        if self.uses_chat() {
+            Ok(self.openai_chat_json(&[ChatMessage::user(&prompt)]).await?)
+        } else {
+            Ok(self.openai_call(&prompt).await?)
+        }
+    }
+
+    async fn chat(&self, messages: &[ChatMessage]) -> Result<String, CrustagiError> {
+        Ok(self.openai_chat(messages).await?)
+    }
+
+    // Function calling is only available for chat models
+    async fn chat_with_tools(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+    ) -> Result<ChatMessage, CrustagiError> {
+        if !self.uses_chat() || tools.is_empty() {
+            return Ok(ChatMessage::assistant(&self.chat(messages).await?));
+        }
+        Ok(self.openai_chat_tools(messages, tools).await?)
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f64>, CrustagiError> {
+        Ok(self.get_embedding(text).await?.embedding)
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f64>>, CrustagiError> {
+        let embeddings = self.get_embeddings(texts).await?;
+        Ok(embeddings.into_iter().map(|data| data.embedding).collect())
+    }
+
+    fn model(&self) -> String {
+        self.model.clone()
+    }
+
+    fn prompt_budget(&self) -> usize {
+        self.params.prompt_budget(&self.model)
+    }
+
+    fn embedding_model(&self) -> &str {
+        &self.embedding_model
+    }
 
+    // Unknown models are asked for an embedding and measured
+    async fn embedding_dimension(&self) -> Result<usize, CrustagiError> {
+        match known_embedding_dimension(&self.embedding_model) {
+            Some(dimension) => Ok(dimension),
+            None => Ok(self.embed("dimension probe").await?.len()),
+        }
+    }
+}
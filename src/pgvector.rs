@@ -0,0 +1,165 @@
+use async_trait::async_trait;
+use tokio_postgres::{Client, NoTls};
+
+use crate::error::CrustagiError;
+use crate::memory::{Match, Metadata, StoredVector, VectorMetadata, VectorStore};
+
+// Postgres table with a pgvector column used as a `VectorStore`
+pub struct PgVectorStore {
+    client: Client,
+    table: String,
+    dimension: usize,
+}
+
+impl PgVectorStore {
+    pub async fn connect(
+        database_url: &str,
+        table: &str,
+        dimension: usize,
+    ) -> Result<Self, CrustagiError> {
+        let (client, connection) = tokio_postgres::connect(database_url, NoTls).await?;
+        tokio::spawn(async move {
+            if let Err(err) = connection.await {
+                tracing::warn!("Postgres connection error: {}", err);
+            }
+        });
+        Ok(PgVectorStore {
+            client,
+            // Quote the identifier so the configured name can't inject SQL
+            table: format!("\"{}\"", table.replace('"', "\"\"")),
+            dimension,
+        })
+    }
+}
+
+#[async_trait]
+impl VectorStore for PgVectorStore {
+    async fn create(&self) -> Result<(), CrustagiError> {
+        self.client
+            .batch_execute(&format!(
+                "CREATE EXTENSION IF NOT EXISTS vector;
+                 CREATE TABLE IF NOT EXISTS {} (
+                     id TEXT PRIMARY KEY,
+                     embedding vector({}) NOT NULL,
+                     metadata JSONB,
+                     created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+                 );",
+                self.table, self.dimension
+            ))
+            .await?;
+        Ok(())
+    }
+
+    async fn upsert(
+        &self,
+        id: &str,
+        vector: &[f64],
+        metadata: &VectorMetadata,
+    ) -> Result<usize, CrustagiError> {
+        tracing::debug!("Storing to Postgres...");
+        let metadata = serde_json::to_string(metadata)?;
+        let count = self
+            .client
+            .execute(
+                &format!(
+                    "INSERT INTO {} (id, embedding, metadata) VALUES ($1, $2::text::vector, $3::text::jsonb)
+                     ON CONFLICT (id) DO UPDATE SET embedding = excluded.embedding, metadata = excluded.metadata",
+                    self.table
+                ),
+                &[&id, &vector_literal(vector), &metadata],
+            )
+            .await?;
+        Ok(count as usize)
+    }
+
+    async fn query(
+        &self,
+        vector: &[f64],
+        top_k: usize,
+        filter: Option<&Metadata>,
+    ) -> Result<Vec<Match>, CrustagiError> {
+        tracing::debug!("Querying Postgres...");
+        // JSONB containment with an empty object matches every row
+        let filter = serde_json::to_string(&filter.cloned().unwrap_or_default())?;
+        // `<=>` is pgvector's cosine distance operator
+        let rows = self
+            .client
+            .query(
+                &format!(
+                    "SELECT id, metadata::text, 1 - (embedding <=> $1::text::vector) AS score
+                     FROM {} WHERE metadata @> $3::text::jsonb
+                     ORDER BY embedding <=> $1::text::vector LIMIT $2",
+                    self.table
+                ),
+                &[&vector_literal(vector), &(top_k as i64), &filter],
+            )
+            .await?;
+
+        let mut matches = Vec::with_capacity(rows.len());
+        for row in rows {
+            let metadata: Option<String> = row.get(1);
+            let metadata: Option<VectorMetadata> = match metadata {
+                Some(json) => Some(serde_json::from_str(&json)?),
+                None => None,
+            };
+            matches.push(Match {
+                id: row.get(0),
+                score: row.get(2),
+                metadata,
+            });
+        }
+        Ok(matches)
+    }
+
+    async fn delete(&self, ids: &[String]) -> Result<(), CrustagiError> {
+        self.client
+            .execute(
+                &format!("DELETE FROM {} WHERE id = ANY($1)", self.table),
+                &[&ids],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn dump(&self) -> Result<Vec<StoredVector>, CrustagiError> {
+        let rows = self
+            .client
+            .query(
+                &format!(
+                    "SELECT id, embedding::text, metadata::text FROM {} ORDER BY created_at",
+                    self.table
+                ),
+                &[],
+            )
+            .await?;
+
+        let mut vectors = Vec::with_capacity(rows.len());
+        for row in rows {
+            // pgvector's text format happens to be a JSON array
+            let vector: String = row.get(1);
+            let metadata: Option<String> = row.get(2);
+            vectors.push(StoredVector {
+                id: row.get(0),
+                vector: serde_json::from_str(&vector)?,
+                metadata: match metadata {
+                    Some(json) => Some(serde_json::from_str(&json)?),
+                    None => None,
+                },
+            });
+        }
+        Ok(vectors)
+    }
+
+    async fn reset(&self) -> Result<(), CrustagiError> {
+        self.client
+            .batch_execute(&format!("TRUNCATE {}", self.table))
+            .await?;
+        Ok(())
+    }
+}
+
+// pgvector's text input format, e.g. `[0.1,0.2,0.3]`
+fn vector_literal(vector: &[f64]) -> String {
+    let values: Vec<String> = vector.iter().map(|v| v.to_string()).collect();
+    format!("[{}]", values.join(","))
+}
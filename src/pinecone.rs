@@ -1,20 +1,43 @@
-use reqwest::{Client, Error};
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::time::sleep;
 
-#[derive(Serialize, Deserialize, Debug)]
+use crate::error::CrustagiError;
+use crate::http;
+use crate::memory::{self, hash_id, Metadata, StoredVector, VectorMetadata, VectorStore};
+use crate::middleware::RateLimitLayer;
+use crate::sparse;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SparseValues {
-    pub indices: Vec<usize>,
+    pub indices: Vec<u32>,
     pub values: Vec<f64>,
 }
 
+// A vector as sent to the upsert endpoint
+#[derive(Serialize, Debug)]
+pub struct Vector {
+    pub id: String,
+    pub values: Vec<f64>,
+    #[serde(rename = "sparseValues", skip_serializing_if = "Option::is_none")]
+    pub sparse_values: Option<SparseValues>,
+    pub metadata: VectorMetadata,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Match {
     pub id: String,
     pub score: f64,
     pub values: Vec<f64>,
-    pub sparseValues: Option<SparseValues>,
+    #[serde(rename = "sparseValues")]
+    pub sparse_values: Option<SparseValues>,
     pub metadata: Option<HashMap<String, serde_json::Value>>,
 }
 
@@ -23,119 +46,885 @@ pub struct QueryResponse {
     pub matches: Vec<Match>,
 }
 
+// A vector returned by the fetch endpoint
+#[derive(Deserialize, Debug)]
+pub struct FetchedVector {
+    pub id: String,
+    pub values: Vec<f64>,
+    pub metadata: Option<HashMap<String, serde_json::Value>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct FetchResponse {
+    vectors: HashMap<String, FetchedVector>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ListResponse {
+    #[serde(default)]
+    vectors: Vec<ListedId>,
+    pagination: Option<Pagination>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ListedId {
+    id: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Pagination {
+    next: String,
+}
+
+// Control plane of the current (serverless) Pinecone API
+const SERVERLESS_CONTROLLER_URL: &str = "https://api.pinecone.io";
+const SERVERLESS_API_VERSION: &str = "2024-07";
+// How many times to poll (every 5 seconds) for a new serverless index
+const INDEX_READY_POLLS: usize = 24;
+
+// Pinecone rejects upsert requests with more vectors than this
+const MAX_UPSERT_BATCH: usize = 100;
+// Ids per fetch request, kept small as they are sent in the query string
+const FETCH_BATCH: usize = 100;
+
+// Transient failures are retried with exponential backoff: the delay doubles
+// from `RETRY_BASE_DELAY` up to `RETRY_MAX_DELAY`, with random jitter
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 4;
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(8);
+
+// Time limit of a single Pinecone request by default. A request that times
+// out counts as a transient failure and is retried.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(120);
+
+// Failure of a Pinecone request
+#[derive(Debug)]
+pub enum PineconeError {
+    // The request could not be sent or the response body not read
+    Http(reqwest::Error),
+    // Pinecone answered with a non-success status
+    Api {
+        status: StatusCode,
+        code: Option<String>,
+        message: String,
+    },
+    // The response body was not the JSON we expected
+    Decode {
+        body: String,
+        source: serde_json::Error,
+    },
+}
+
+impl fmt::Display for PineconeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PineconeError::Http(err) => write!(f, "Pinecone request failed: {}", err),
+            PineconeError::Api {
+                status,
+                code: Some(code),
+                message,
+            } => write!(f, "Pinecone returned {} ({}): {}", status, code, message),
+            PineconeError::Api {
+                status, message, ..
+            } => write!(f, "Pinecone returned {}: {}", status, message),
+            PineconeError::Decode { body, source } => {
+                write!(f, "Unexpected Pinecone response ({}): {}", source, body)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PineconeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PineconeError::Http(err) => Some(err),
+            PineconeError::Api { .. } => None,
+            PineconeError::Decode { source, .. } => Some(source),
+        }
+    }
+}
+
+impl From<reqwest::Error> for PineconeError {
+    fn from(err: reqwest::Error) -> Self {
+        PineconeError::Http(err)
+    }
+}
+
+impl PineconeError {
+    // The control plane nests `{code, message}` under `error`, the data plane
+    // returns it at the top level; anything else is kept as the raw body
+    fn from_response(status: StatusCode, body: String) -> Self {
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap_or_default();
+        let error = json.get("error").unwrap_or(&json);
+        let code = match &error["code"] {
+            serde_json::Value::String(code) => Some(code.clone()),
+            serde_json::Value::Number(code) => Some(code.to_string()),
+            _ => None,
+        };
+        let message = error["message"]
+            .as_str()
+            .map(|m| m.to_string())
+            .unwrap_or(body);
+        PineconeError::Api {
+            status,
+            code,
+            message,
+        }
+    }
+
+    // Timeouts, connection failures, rate limiting and server errors may
+    // succeed when the request is repeated
+    fn is_transient(&self) -> bool {
+        match self {
+            PineconeError::Http(err) => err.is_timeout() || err.is_connect(),
+            PineconeError::Api { status, .. } => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            PineconeError::Decode { .. } => false,
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub struct UpsertResponse {
-    pub upsertedCount: usize,
-}
-
-pub async fn create_index(
-    pinecone_api_key: &str,
-    pinecone_region: &str,
-    index_name: &str,
-) -> Result<(), Error> {
-    let url = format!("{}/databases", get_controller_url(pinecone_region));
-    let client = Client::new();
-    let body = json!({
-        "metric": "cosine",
-        "dimension": 1536,
-        "pods": 1,
-        "replicas": 1,
-        "pod_type": "p1.x1",
-        "name": index_name
-    });
-
-    let res = client
-        .post(&url)
-        .header("Content-Type", "application/json")
-        .header("Api-Key", pinecone_api_key)
-        .body(body.to_string())
-        .send()
+    #[serde(rename = "upsertedCount")]
+    pub upserted_count: usize,
+}
+
+// Settings used when creating an index
+#[derive(Debug, Clone)]
+pub struct IndexParams {
+    pub dimension: usize,
+    pub metric: String,
+    // Only used by legacy pod-based indexes
+    pub pod_type: String,
+    pub pods: usize,
+    pub replicas: usize,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct IndexDescription {
+    pub name: String,
+    pub dimension: usize,
+    pub host: String,
+    pub status: IndexStatus,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct IndexStatus {
+    pub ready: bool,
+}
+
+// Legacy controller response for a single pod-based index
+#[derive(Deserialize, Debug)]
+struct PodIndexDescription {
+    database: PodIndexDatabase,
+}
+
+#[derive(Deserialize, Debug)]
+struct PodIndexDatabase {
+    dimension: usize,
+}
+
+#[derive(Deserialize, Debug)]
+struct IndexList {
+    indexes: Vec<IndexDescription>,
+}
+
+// Client for the Pinecone control and data planes, using the connection
+// pool of the `reqwest::Client` it is given
+#[derive(Clone)]
+pub struct PineconeClient {
+    http: Client,
+    api_key: String,
+    max_attempts: u32,
+    timeout: Duration,
+    // MAX_REQUESTS_PER_MINUTE, shared with the LLM calls of the run
+    rate_limit: RateLimitLayer,
+}
+
+impl PineconeClient {
+    pub fn new(http: Client, api_key: &str) -> Self {
+        PineconeClient {
+            http,
+            api_key: api_key.to_string(),
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            timeout: DEFAULT_TIMEOUT,
+            rate_limit: RateLimitLayer::new(0),
+        }
+    }
+
+    // How many times each request is attempted (at least once), and the
+    // time limit of each attempt
+    pub fn with_retries(mut self, max_attempts: u32, timeout: Duration) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self.timeout = timeout.max(Duration::from_secs(1));
+        self
+    }
+
+    // Wait for `rate_limit` before each request, retries included
+    pub fn with_rate_limit(mut self, rate_limit: RateLimitLayer) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+
+    // Send a request, retrying transient failures up to the configured
+    // number of attempts
+    async fn send(&self, request: RequestBuilder) -> Result<String, PineconeError> {
+        let max_attempts = self.max_attempts;
+        let mut attempt = 1;
+        loop {
+            // All bodies here are in memory, so cloning only fails for streams
+            let Some(current) = request.try_clone() else {
+                return self.send_once(request).await;
+            };
+            match self.send_once(current).await {
+                Err(err) if attempt < max_attempts && err.is_transient() => {
+                    let delay = http::backoff(attempt, RETRY_BASE_DELAY, RETRY_MAX_DELAY);
+                    tracing::warn!(
+                        "{} (attempt {}/{}), retrying in {:.1}s...",
+                        err,
+                        attempt,
+                        max_attempts,
+                        delay.as_secs_f64()
+                    );
+                    sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    // Send a request once, turning non-success responses into
+    // `PineconeError::Api`
+    async fn send_once(&self, request: RequestBuilder) -> Result<String, PineconeError> {
+        self.rate_limit.wait().await;
+        let res = request.timeout(self.timeout).send().await?;
+        let status = res.status();
+        let body = res.text().await?;
+        if status.is_success() {
+            Ok(body)
+        } else {
+            Err(PineconeError::from_response(status, body))
+        }
+    }
+
+    // Send a request and decode its JSON response
+    async fn send_json<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<T, PineconeError> {
+        let body = self.send(request).await?;
+        serde_json::from_str(&body).map_err(|source| PineconeError::Decode { body, source })
+    }
+
+    #[tracing::instrument(skip_all, fields(index = index_name))]
+    pub async fn create_index(
+        &self,
+        pinecone_region: &str,
+        index_name: &str,
+        params: &IndexParams,
+    ) -> Result<(), PineconeError> {
+        let url = format!("{}/databases", get_controller_url(pinecone_region));
+        let body = json!({
+            "metric": params.metric,
+            "dimension": params.dimension,
+            "pods": params.pods,
+            "replicas": params.replicas,
+            "pod_type": params.pod_type,
+            "name": index_name
+        });
+
+        self.send(
+            self.http
+                .post(&url)
+                .header("Content-Type", "application/json")
+                .header("Api-Key", &self.api_key)
+                .body(body.to_string()),
+        )
         .await?;
-    Ok(())
-}
-
-pub async fn list_indexes(
-    pinecone_api_key: &str,
-    pinecone_region: &str,
-) -> Result<Vec<String>, Error> {
-    let url = format!("{}/databases", get_controller_url(pinecone_region));
-    let client = Client::new();
-    let res = client
-        .get(&url)
-        .header("Accept", "application/json; charset=utf-8")
-        .header("Api-Key", pinecone_api_key)
-        .send()
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn list_indexes(&self, pinecone_region: &str) -> Result<Vec<String>, PineconeError> {
+        let url = format!("{}/databases", get_controller_url(pinecone_region));
+        self.send_json(
+            self.http
+                .get(&url)
+                .header("Accept", "application/json; charset=utf-8")
+                .header("Api-Key", &self.api_key),
+        )
+        .await
+    }
+
+    // Dimension of a legacy pod-based index
+    #[tracing::instrument(skip_all, fields(index = index_name))]
+    pub async fn pod_index_dimension(
+        &self,
+        pinecone_region: &str,
+        index_name: &str,
+    ) -> Result<usize, PineconeError> {
+        let url = format!(
+            "{}/databases/{}",
+            get_controller_url(pinecone_region),
+            index_name
+        );
+        let res: PodIndexDescription = self
+            .send_json(
+                self.http
+                    .get(&url)
+                    .header("Accept", "application/json; charset=utf-8")
+                    .header("Api-Key", &self.api_key),
+            )
+            .await?;
+        Ok(res.database.dimension)
+    }
+
+    // Create a serverless index through the global control plane
+    #[tracing::instrument(skip_all, fields(index = index_name))]
+    pub async fn create_serverless_index(
+        &self,
+        cloud: &str,
+        region: &str,
+        index_name: &str,
+        params: &IndexParams,
+    ) -> Result<(), PineconeError> {
+        let url = format!("{}/indexes", SERVERLESS_CONTROLLER_URL);
+        let body = json!({
+            "name": index_name,
+            "dimension": params.dimension,
+            "metric": params.metric,
+            "spec": {
+                "serverless": {
+                    "cloud": cloud,
+                    "region": region
+                }
+            }
+        });
+
+        self.send(
+            self.http
+                .post(&url)
+                .header("Content-Type", "application/json")
+                .header("Api-Key", &self.api_key)
+                .header("X-Pinecone-API-Version", SERVERLESS_API_VERSION)
+                .body(body.to_string()),
+        )
         .await?;
-    let res2 = res.json::<Vec<String>>().await?;
-    Ok(res2)
-}
-
-pub async fn query_index(
-    pinecone_api_key: &str,
-    pinecone_region: &str,
-    project_id: &str,
-    index_name: &str,
-    vector: &Vec<f64>,
-    top_k: &i32,
-    include_metadata: &bool,
-) -> Result<QueryResponse, Error> {
-    let url = format!(
-        "{}/query",
-        get_index_url(index_name, project_id, pinecone_region)
-    );
-    let client = Client::new();
-    let body = json!({
-        "vector": vector,
-        "top_k": top_k,
-        "include_metadata": include_metadata,
-    });
-
-    println!("Querying Pinecone...",);
-
-    let res = client
-        .post(&url)
-        .header("Content-Type", "application/json")
-        .header("Api-Key", pinecone_api_key)
-        .body(body.to_string())
-        .send()
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn list_serverless_indexes(&self) -> Result<Vec<String>, PineconeError> {
+        let url = format!("{}/indexes", SERVERLESS_CONTROLLER_URL);
+        let res: IndexList = self
+            .send_json(
+                self.http
+                    .get(&url)
+                    .header("Api-Key", &self.api_key)
+                    .header("X-Pinecone-API-Version", SERVERLESS_API_VERSION),
+            )
+            .await?;
+        Ok(res.indexes.into_iter().map(|index| index.name).collect())
+    }
+
+    // Look up an index, including the host its data plane is served from
+    #[tracing::instrument(skip_all, fields(index = index_name))]
+    pub async fn describe_index(
+        &self,
+        index_name: &str,
+    ) -> Result<IndexDescription, PineconeError> {
+        let url = format!("{}/indexes/{}", SERVERLESS_CONTROLLER_URL, index_name);
+        self.send_json(
+            self.http
+                .get(&url)
+                .header("Api-Key", &self.api_key)
+                .header("X-Pinecone-API-Version", SERVERLESS_API_VERSION),
+        )
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(skip_all, fields(index = index_url, top_k))]
+    pub async fn query_index(
+        &self,
+        index_url: &str,
+        namespace: &str,
+        vector: &[f64],
+        sparse_vector: Option<&SparseValues>,
+        filter: Option<&Metadata>,
+        top_k: &i32,
+        include_metadata: &bool,
+    ) -> Result<QueryResponse, PineconeError> {
+        let url = format!("{}/query", index_url);
+        let mut body = json!({
+            "namespace": namespace,
+            "vector": vector,
+            "top_k": top_k,
+            "include_metadata": include_metadata,
+        });
+        if let Some(sparse_vector) = sparse_vector {
+            body["sparseVector"] = json!(sparse_vector);
+        }
+        // Exact-match metadata filter, e.g. `{"objective": {"$eq": "..."}}`
+        if let Some(filter) = filter {
+            let filter: Metadata = filter
+                .iter()
+                .map(|(key, value)| (key.clone(), json!({ "$eq": value })))
+                .collect();
+            body["filter"] = json!(filter);
+        }
+
+        tracing::debug!("Querying Pinecone...");
+
+        self.send_json(
+            self.http
+                .post(&url)
+                .header("Content-Type", "application/json")
+                .header("Api-Key", &self.api_key)
+                .body(body.to_string()),
+        )
+        .await
+    }
+
+    // Upsert vectors, sent in requests of at most `MAX_UPSERT_BATCH` vectors
+    #[tracing::instrument(skip_all, fields(index = index_url, count = vectors.len()))]
+    pub async fn upsert(
+        &self,
+        index_url: &str,
+        namespace: &str,
+        vectors: &[Vector],
+    ) -> Result<usize, PineconeError> {
+        let url = format!("{}/vectors/upsert", index_url);
+        let mut upserted_count = 0;
+
+        for chunk in vectors.chunks(MAX_UPSERT_BATCH) {
+            let body = json!({
+                "namespace": namespace,
+                "vectors": chunk
+            });
+
+            tracing::debug!("Storing {} vectors to Pinecone...", chunk.len());
+
+            let res: UpsertResponse = self
+                .send_json(
+                    self.http
+                        .post(&url)
+                        .header("Content-Type", "application/json")
+                        .header("Api-Key", &self.api_key)
+                        .body(body.to_string()),
+                )
+                .await?;
+            upserted_count += res.upserted_count;
+        }
+
+        Ok(upserted_count)
+    }
+
+    // Ids of every vector in a namespace. Only serverless indexes support listing.
+    #[tracing::instrument(skip_all, fields(index = index_url, namespace))]
+    pub async fn list_vector_ids(
+        &self,
+        index_url: &str,
+        namespace: &str,
+    ) -> Result<Vec<String>, PineconeError> {
+        let url = format!("{}/vectors/list", index_url);
+        let mut ids = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![("namespace", namespace.to_string())];
+            if let Some(token) = token {
+                query.push(("paginationToken", token));
+            }
+            let res: ListResponse = self
+                .send_json(
+                    self.http
+                        .get(&url)
+                        .header("Api-Key", &self.api_key)
+                        .query(&query),
+                )
+                .await?;
+            ids.extend(res.vectors.into_iter().map(|v| v.id));
+            match res.pagination {
+                Some(pagination) => token = Some(pagination.next),
+                None => return Ok(ids),
+            }
+        }
+    }
+
+    // Fetch vectors with their values and metadata, in requests of at most
+    // `FETCH_BATCH` ids
+    #[tracing::instrument(skip_all, fields(index = index_url, count = ids.len()))]
+    pub async fn fetch_vectors(
+        &self,
+        index_url: &str,
+        namespace: &str,
+        ids: &[String],
+    ) -> Result<Vec<FetchedVector>, PineconeError> {
+        let url = format!("{}/vectors/fetch", index_url);
+        let mut vectors = Vec::with_capacity(ids.len());
+        for chunk in ids.chunks(FETCH_BATCH) {
+            let mut query: Vec<(&str, &str)> = vec![("namespace", namespace)];
+            query.extend(chunk.iter().map(|id| ("ids", id.as_str())));
+            let res: FetchResponse = self
+                .send_json(
+                    self.http
+                        .get(&url)
+                        .header("Api-Key", &self.api_key)
+                        .query(&query),
+                )
+                .await?;
+            vectors.extend(res.vectors.into_values());
+        }
+        Ok(vectors)
+    }
+
+    #[tracing::instrument(skip_all, fields(index = index_url))]
+    pub async fn delete_vectors(
+        &self,
+        index_url: &str,
+        namespace: &str,
+        ids: &[String],
+    ) -> Result<(), PineconeError> {
+        let url = format!("{}/vectors/delete", index_url);
+        let body = json!({ "ids": ids, "namespace": namespace });
+
+        self.send(
+            self.http
+                .post(&url)
+                .header("Content-Type", "application/json")
+                .header("Api-Key", &self.api_key)
+                .body(body.to_string()),
+        )
         .await?;
-    let res2 = res.json::<QueryResponse>().await?;
-    Ok(res2)
-}
-
-pub async fn upsert(
-    pinecone_api_key: &str,
-    pinecone_region: &str,
-    project_id: &str,
-    index_name: &str,
-    id: &str,
-    vector: &Vec<f64>,
-) -> Result<usize, Error> {
-    let url = format!(
-        "{}/vectors/upsert",
-        get_index_url(index_name, project_id, pinecone_region)
-    );
-    let client = Client::new();
-    let body = json!({
-        "vectors": [{
-            "id": id,
-            "values": vector
-        }]
-    });
-
-    println!("Storing to Pinecone...");
-
-    let res = client
-        .post(&url)
-        .header("Content-Type", "application/json")
-        .header("Api-Key", pinecone_api_key)
-        .body(body.to_string())
-        .send()
+        Ok(())
+    }
+
+    // Delete every vector in a namespace, keeping the index itself
+    #[tracing::instrument(skip_all, fields(index = index_url, namespace))]
+    pub async fn delete_namespace(
+        &self,
+        index_url: &str,
+        namespace: &str,
+    ) -> Result<(), PineconeError> {
+        let url = format!("{}/vectors/delete", index_url);
+        let body = json!({ "deleteAll": true, "namespace": namespace });
+
+        tracing::info!("Deleting Pinecone namespace {}...", namespace);
+
+        self.send(
+            self.http
+                .post(&url)
+                .header("Content-Type", "application/json")
+                .header("Api-Key", &self.api_key)
+                .body(body.to_string()),
+        )
         .await?;
+        Ok(())
+    }
+
+    // Delete a whole index. `pinecone_region` is only used for legacy pod-based
+    // projects; pass None for serverless indexes.
+    #[allow(dead_code)]
+    #[tracing::instrument(skip_all, fields(index = index_name))]
+    pub async fn delete_index(
+        &self,
+        pinecone_region: Option<&str>,
+        index_name: &str,
+    ) -> Result<(), PineconeError> {
+        let request = match pinecone_region {
+            Some(region) => self.http.delete(format!(
+                "{}/databases/{}",
+                get_controller_url(region),
+                index_name
+            )),
+            None => self
+                .http
+                .delete(format!(
+                    "{}/indexes/{}",
+                    SERVERLESS_CONTROLLER_URL, index_name
+                ))
+                .header("X-Pinecone-API-Version", SERVERLESS_API_VERSION),
+        };
+        self.send(request.header("Api-Key", &self.api_key)).await?;
+        Ok(())
+    }
+}
+
+// Pinecone index used as a `VectorStore`.
+// Legacy pod-based projects derive the index URL from region and project id;
+// serverless indexes are resolved to their host with `describe_index`.
+pub struct PineconeStore {
+    client: PineconeClient,
+    region: String,
+    project_id: String,
+    index_name: String,
+    namespace: String,
+    serverless: Option<String>,
+    params: IndexParams,
+    hybrid_alpha: Option<f64>,
+    index_url: OnceLock<String>,
+}
+
+impl PineconeStore {
+    // `serverless` is the cloud provider of a serverless index, None for legacy pods
+    pub fn new(
+        client: PineconeClient,
+        region: &str,
+        project_id: &str,
+        index_name: &str,
+        namespace: &str,
+        serverless: Option<&str>,
+        params: IndexParams,
+    ) -> Self {
+        PineconeStore {
+            client,
+            region: region.to_string(),
+            project_id: project_id.to_string(),
+            index_name: index_name.to_string(),
+            namespace: namespace.to_string(),
+            serverless: serverless.map(|cloud| cloud.to_string()),
+            params,
+            hybrid_alpha: None,
+            index_url: OnceLock::new(),
+        }
+    }
+
+    // Store BM25 sparse vectors next to the dense ones and query both.
+    // `alpha` weights dense against sparse scores; the index must use the
+    // dotproduct metric.
+    pub fn with_hybrid(mut self, alpha: f64) -> Self {
+        self.hybrid_alpha = Some(alpha);
+        self
+    }
+
+    fn to_vector(&self, id: &str, values: &[f64], metadata: &VectorMetadata) -> Vector {
+        Vector {
+            id: id.to_string(),
+            values: values.to_vec(),
+            sparse_values: self
+                .hybrid_alpha
+                .map(|_| sparse::document_vector(&metadata.result)),
+            metadata: metadata.clone(),
+        }
+    }
 
-    let res2 = res.json::<UpsertResponse>().await?;
+    async fn query_matches(
+        &self,
+        vector: &[f64],
+        sparse_vector: Option<&SparseValues>,
+        filter: Option<&Metadata>,
+        top_k: usize,
+    ) -> Result<Vec<memory::Match>, CrustagiError> {
+        let response = self
+            .client
+            .query_index(
+                self.index_url()?,
+                &self.namespace,
+                vector,
+                sparse_vector,
+                filter,
+                &(top_k as i32),
+                &true,
+            )
+            .await?;
+        Ok(response
+            .matches
+            .into_iter()
+            .map(|m| memory::Match {
+                id: m.id,
+                score: m.score,
+                metadata: m.metadata.and_then(VectorMetadata::from_map),
+            })
+            .collect())
+    }
+
+    fn index_url(&self) -> Result<&str, CrustagiError> {
+        match self.index_url.get() {
+            Some(url) => Ok(url),
+            None => Err("Pinecone index has not been created yet".into()),
+        }
+    }
+}
+
+#[async_trait]
+impl VectorStore for PineconeStore {
+    async fn create(&self) -> Result<(), CrustagiError> {
+        let url = match &self.serverless {
+            Some(cloud) => {
+                let indexes = self.client.list_serverless_indexes().await?;
+                if !indexes.contains(&self.index_name) {
+                    self.client
+                        .create_serverless_index(
+                            cloud,
+                            &self.region,
+                            &self.index_name,
+                            &self.params,
+                        )
+                        .await?;
+                }
+                // A freshly created index takes a moment before it accepts requests
+                let mut index = self.client.describe_index(&self.index_name).await?;
+                for _ in 0..INDEX_READY_POLLS {
+                    if index.status.ready {
+                        break;
+                    }
+                    tracing::info!(
+                        "Waiting for Pinecone index {} to be ready...",
+                        self.index_name
+                    );
+                    sleep(Duration::from_secs(5)).await;
+                    index = self.client.describe_index(&self.index_name).await?;
+                }
+                format!("https://{}", index.host)
+            }
+            None => {
+                let indexes = self.client.list_indexes(&self.region).await?;
+                if !indexes.contains(&self.index_name) {
+                    self.client
+                        .create_index(&self.region, &self.index_name, &self.params)
+                        .await?;
+                }
+                get_index_url(&self.index_name, &self.project_id, &self.region)
+            }
+        };
+        let _ = self.index_url.set(url);
+        Ok(())
+    }
+
+    async fn dimension(&self) -> Result<Option<usize>, CrustagiError> {
+        let dimension = match self.serverless {
+            Some(_) => {
+                self.client
+                    .describe_index(&self.index_name)
+                    .await?
+                    .dimension
+            }
+            None => {
+                self.client
+                    .pod_index_dimension(&self.region, &self.index_name)
+                    .await?
+            }
+        };
+        Ok(Some(dimension))
+    }
+
+    async fn upsert(
+        &self,
+        id: &str,
+        vector: &[f64],
+        metadata: &VectorMetadata,
+    ) -> Result<usize, CrustagiError> {
+        Ok(self
+            .client
+            .upsert(
+                self.index_url()?,
+                &self.namespace,
+                &[self.to_vector(id, vector, metadata)],
+            )
+            .await?)
+    }
+
+    async fn upsert_batch(
+        &self,
+        vectors: &[(String, Vec<f64>, VectorMetadata)],
+    ) -> Result<usize, CrustagiError> {
+        let vectors: Vec<Vector> = vectors
+            .iter()
+            .map(|(id, values, metadata)| self.to_vector(id, values, metadata))
+            .collect();
+        Ok(self
+            .client
+            .upsert(self.index_url()?, &self.namespace, &vectors)
+            .await?)
+    }
+
+    async fn query(
+        &self,
+        vector: &[f64],
+        top_k: usize,
+        filter: Option<&Metadata>,
+    ) -> Result<Vec<memory::Match>, CrustagiError> {
+        self.query_matches(vector, None, filter, top_k).await
+    }
+
+    async fn hybrid_query(
+        &self,
+        text: &str,
+        vector: &[f64],
+        top_k: usize,
+        filter: Option<&Metadata>,
+    ) -> Result<Vec<memory::Match>, CrustagiError> {
+        match self.hybrid_alpha {
+            Some(alpha) => {
+                let (dense, sparse) = sparse::weight(vector, sparse::query_vector(text), alpha);
+                self.query_matches(&dense, Some(&sparse), filter, top_k)
+                    .await
+            }
+            None => self.query(vector, top_k, filter).await,
+        }
+    }
+
+    async fn delete(&self, ids: &[String]) -> Result<(), CrustagiError> {
+        Ok(self
+            .client
+            .delete_vectors(self.index_url()?, &self.namespace, ids)
+            .await?)
+    }
+
+    // Exports this run's namespace only
+    async fn dump(&self) -> Result<Vec<StoredVector>, CrustagiError> {
+        if self.serverless.is_none() {
+            return Err("Exporting requires a serverless Pinecone index, \
+                 pod-based indexes cannot list their vectors"
+                .into());
+        }
+        let index_url = self.index_url()?;
+        let ids = self
+            .client
+            .list_vector_ids(index_url, &self.namespace)
+            .await?;
+        let vectors = self
+            .client
+            .fetch_vectors(index_url, &self.namespace, &ids)
+            .await?;
+        Ok(vectors
+            .into_iter()
+            .map(|v| StoredVector {
+                id: v.id,
+                vector: v.values,
+                metadata: v.metadata.and_then(VectorMetadata::from_map),
+            })
+            .collect())
+    }
+
+    // Only this run's namespace is wiped, other objectives sharing the index are kept
+    async fn reset(&self) -> Result<(), CrustagiError> {
+        Ok(self
+            .client
+            .delete_namespace(self.index_url()?, &self.namespace)
+            .await?)
+    }
+}
 
-    Ok(res2.upsertedCount)
+// Namespace used when `PINECONE_NAMESPACE` is not set, so that runs with
+// different objectives sharing an index don't see each other's results
+pub fn namespace_for_objective(objective: &str) -> String {
+    let slug: String = objective
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let slug: Vec<&str> = slug.split('-').filter(|s| !s.is_empty()).collect();
+    let slug: String = slug.join("-").chars().take(32).collect();
+    format!("{}-{:08x}", slug, hash_id(objective) as u32)
 }
 
 fn get_index_url(index_name: &str, project_id: &str, pinecone_region: &str) -> String {
@@ -0,0 +1,180 @@
+// Plugins: what third-party crates contribute to a run without changes to
+// this crate, registered by the program before the run starts. Loading them
+// from WASM modules isn't supported, plugins are Rust types compiled into the
+// program.
+
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use crate::config::Config;
+use crate::enrich::Enricher;
+use crate::error::CrustagiError;
+use crate::memory::{VectorStore, SUPPORTED_BACKENDS};
+use crate::tools::{Tool, BUILT_IN_TOOLS};
+
+// What the registered plugins contribute
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry::new());
+
+/// A vector store a plugin provides, selected with `MEMORY_BACKEND` set to
+/// the name it was registered under
+#[async_trait]
+pub trait MemoryBackend: Send + Sync {
+    /// Open the store for a run with `config`, for vectors of `dimension`.
+    /// Settings of its own are read from the environment.
+    async fn open(
+        &self,
+        config: &Config,
+        dimension: usize,
+    ) -> Result<Box<dyn VectorStore>, CrustagiError>;
+}
+
+/// Contributes enrichers, memory backends and tools to the runs of the
+/// program
+pub trait Plugin: Send + Sync {
+    /// Name in the log and in errors
+    fn name(&self) -> &str;
+
+    /// Add the plugin's contributions to `registry`, once when registered
+    fn register(&self, registry: &mut Registry);
+}
+
+/// The contributions of the plugins
+pub struct Registry {
+    plugins: Vec<String>,
+    enrichers: Vec<Arc<dyn Enricher>>,
+    tools: Vec<Arc<dyn Tool>>,
+    // By name, with the plugin that registered them
+    memory_backends: BTreeMap<String, (String, Arc<dyn MemoryBackend>)>,
+    // The plugin registering into this registry, and the first name it used
+    // that was taken already
+    current: String,
+    conflict: Option<String>,
+}
+
+impl Registry {
+    const fn new() -> Self {
+        Registry {
+            plugins: Vec::new(),
+            enrichers: Vec::new(),
+            tools: Vec::new(),
+            memory_backends: BTreeMap::new(),
+            current: String::new(),
+            conflict: None,
+        }
+    }
+
+    /// Run `enricher` on each result before it is stored, after the
+    /// enrichers registered before it and before chunking with
+    /// `CHUNK_TOKENS`
+    pub fn enricher(&mut self, enricher: impl Enricher + 'static) {
+        self.enrichers.push(Arc::new(enricher));
+    }
+
+    /// Offer `backend` as `MEMORY_BACKEND=<name>`. A name that is built in
+    /// or registered already fails the registration of the plugin.
+    pub fn memory_backend(&mut self, name: &str, backend: impl MemoryBackend + 'static) {
+        let taken = match self.memory_backends.get(name) {
+            Some((plugin, _)) => Some(format!("plugin {}", plugin)),
+            None if SUPPORTED_BACKENDS.contains(&name) => Some("CrustAGI".to_string()),
+            None => None,
+        };
+        match taken {
+            Some(owner) => {
+                self.conflict.get_or_insert_with(|| {
+                    format!("the memory backend {} is provided by {}", name, owner)
+                });
+            }
+            None => {
+                self.memory_backends
+                    .insert(name.to_string(), (self.current.clone(), Arc::new(backend)));
+            }
+        }
+    }
+
+    /// Offer `tool` to the execution agent. A tool whose name is built in
+    /// or registered already fails the registration of the plugin.
+    pub fn tool(&mut self, tool: impl Tool + 'static) {
+        let name = tool.name();
+        let taken = if BUILT_IN_TOOLS.contains(&name) {
+            Some("is built into CrustAGI")
+        } else if self.tools.iter().any(|other| other.name() == name) {
+            Some("is registered already")
+        } else {
+            None
+        };
+        match taken {
+            Some(taken) => {
+                self.conflict
+                    .get_or_insert_with(|| format!("the tool {} {}", name, taken));
+            }
+            None => self.tools.push(Arc::new(tool)),
+        }
+    }
+}
+
+/// Add the contributions of `plugin` to all runs of the program that start
+/// afterwards, those of `crustagi::run` as well as of the builder. Fails
+/// without registering anything if the plugin takes a name already used.
+pub fn register(plugin: impl Plugin) -> Result<(), CrustagiError> {
+    // The plugin registers into a copy, which replaces the registry only if
+    // it succeeds
+    let mut registry = {
+        let registry = REGISTRY.lock().unwrap();
+        Registry {
+            plugins: registry.plugins.clone(),
+            enrichers: registry.enrichers.clone(),
+            tools: registry.tools.clone(),
+            memory_backends: registry.memory_backends.clone(),
+            current: plugin.name().to_string(),
+            conflict: None,
+        }
+    };
+    plugin.register(&mut registry);
+    if let Some(conflict) = registry.conflict {
+        return Err(CrustagiError::Config(format!(
+            "Plugin {} can't be registered: {}",
+            plugin.name(),
+            conflict
+        )));
+    }
+    registry.plugins.push(plugin.name().to_string());
+    tracing::debug!("Registered plugin {}", plugin.name());
+    *REGISTRY.lock().unwrap() = registry;
+    Ok(())
+}
+
+// Names of the registered plugins, in the order they were registered
+pub fn names() -> Vec<String> {
+    REGISTRY.lock().unwrap().plugins.clone()
+}
+
+// The enrichers of the plugins, in the order they were registered
+pub fn enrichers() -> Vec<Arc<dyn Enricher>> {
+    REGISTRY.lock().unwrap().enrichers.clone()
+}
+
+// The tools of the plugins, in the order they were registered
+pub fn tools() -> Vec<Arc<dyn Tool>> {
+    REGISTRY.lock().unwrap().tools.clone()
+}
+
+// The memory backend a plugin registered as `name`
+pub fn memory_backend(name: &str) -> Option<Arc<dyn MemoryBackend>> {
+    let registry = REGISTRY.lock().unwrap();
+    registry
+        .memory_backends
+        .get(name)
+        .map(|(_, backend)| backend.clone())
+}
+
+// Names of the memory backends of the plugins
+pub fn memory_backends() -> Vec<String> {
+    REGISTRY
+        .lock()
+        .unwrap()
+        .memory_backends
+        .keys()
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,69 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// Time and cost of the tasks done so far, to estimate those of the tasks left
+pub struct Progress {
+    started: Instant,
+    tasks_done: usize,
+}
+
+// What the rest of the run is likely to take. A lower bound, since tasks done
+// may add new ones.
+pub struct Estimate {
+    pub tasks_left: usize,
+    pub per_task: Duration,
+    pub time_left: Duration,
+    // Cost of the run once the tasks left are done
+    pub projected_cost: f64,
+}
+
+impl Progress {
+    pub fn start() -> Self {
+        Progress {
+            started: Instant::now(),
+            tasks_done: 0,
+        }
+    }
+
+    pub fn record(&mut self, tasks: usize) {
+        self.tasks_done += tasks;
+    }
+
+    // Estimate for the `tasks_left`, from the time since the start and the
+    // `cost` so far, None until a task is done. Tasks run at the same time,
+    // delays and waits for retries count into the time per task.
+    pub fn estimate(&self, tasks_left: usize, cost: f64) -> Option<Estimate> {
+        let tasks_done = u32::try_from(self.tasks_done).ok().filter(|&n| n > 0)?;
+        let per_task = self.started.elapsed() / tasks_done;
+        Some(Estimate {
+            tasks_left,
+            per_task,
+            time_left: per_task * u32::try_from(tasks_left).unwrap_or(u32::MAX),
+            projected_cost: cost + cost / tasks_done as f64 * tasks_left as f64,
+        })
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} task{} left at {} each, about {} and ${:.4} in total",
+            self.tasks_left,
+            if self.tasks_left == 1 { "" } else { "s" },
+            format_duration(self.per_task),
+            format_duration(self.time_left),
+            self.projected_cost
+        )
+    }
+}
+
+// Like `2h 05m`, `3m 20s` or `4.5s`
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, _) => format!("{:.1}s", duration.as_secs_f64()),
+        (0, mins, secs) => format!("{}m {:02}s", mins, secs),
+        (hours, mins, _) => format!("{}h {:02}m", hours, mins),
+    }
+}
@@ -0,0 +1,79 @@
+use handlebars::{no_escape, Handlebars};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::error::CrustagiError;
+
+// Agent prompts, as Handlebars templates. The files in `prompts/` are built
+// in as defaults, so the binary works from any directory.
+const TEMPLATES: &[(&str, &str)] = &[
+    (
+        "task_creation",
+        include_str!("../prompts/task_creation.hbs"),
+    ),
+    (
+        "prioritization",
+        include_str!("../prompts/prioritization.hbs"),
+    ),
+    ("execution", include_str!("../prompts/execution.hbs")),
+    ("critic", include_str!("../prompts/critic.hbs")),
+    ("planner", include_str!("../prompts/planner.hbs")),
+    ("synthesis", include_str!("../prompts/synthesis.hbs")),
+    ("report", include_str!("../prompts/report.hbs")),
+    (
+        "summarization",
+        include_str!("../prompts/summarization.hbs"),
+    ),
+];
+
+pub struct Prompts {
+    registry: Handlebars<'static>,
+    // Text of each template, as loaded
+    sources: BTreeMap<String, String>,
+}
+
+impl Prompts {
+    // Load the templates, preferring `<name>.hbs` files in `dir` over the
+    // built-in ones. Templates are strict, so a variable that doesn't exist
+    // fails the render instead of leaving a gap in the prompt.
+    pub fn load(dir: &str) -> Result<Self, CrustagiError> {
+        let mut registry = Handlebars::new();
+        registry.set_strict_mode(true);
+        // Prompts are plain text, not HTML
+        registry.register_escape_fn(no_escape);
+        let mut sources = BTreeMap::new();
+        for (name, default) in TEMPLATES {
+            let path = Path::new(dir).join(format!("{}.hbs", name));
+            let source = if path.is_file() {
+                fs::read_to_string(&path).map_err(|err| {
+                    CrustagiError::io(format!("Failed to read {}", path.display()), err)
+                })?
+            } else {
+                default.to_string()
+            };
+            registry
+                .register_template_string(name, &source)
+                .map_err(|err| {
+                    CrustagiError::Config(format!("Invalid {} prompt template: {}", name, err))
+                })?;
+            sources.insert(name.to_string(), source);
+        }
+        Ok(Prompts { registry, sources })
+    }
+
+    // Text of every template by name
+    pub fn sources(&self) -> &BTreeMap<String, String> {
+        &self.sources
+    }
+
+    // The prompt of template `name` with `data` filled in. A template of
+    // PROMPTS_DIR may use a variable the agent doesn't provide.
+    pub fn render(&self, name: &str, data: &impl Serialize) -> Result<String, CrustagiError> {
+        let prompt = self.registry.render(name, data).map_err(|err| {
+            CrustagiError::Config(format!("Failed to render the {} prompt: {}", name, err))
+        })?;
+        Ok(prompt.trim_end().to_string())
+    }
+}
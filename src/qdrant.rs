@@ -0,0 +1,243 @@
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+
+use crate::error::CrustagiError;
+use crate::memory::{hash_id, Match, Metadata, StoredVector, VectorMetadata, VectorStore};
+
+// Payload key holding the caller's string id, since Qdrant point ids must be
+// integers or UUIDs
+const ID_KEY: &str = "_id";
+
+#[derive(Deserialize, Debug)]
+struct SearchResponse {
+    result: Vec<ScoredPoint>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ScoredPoint {
+    id: serde_json::Value,
+    score: f64,
+    payload: Option<HashMap<String, serde_json::Value>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ScrollResponse {
+    result: ScrollResult,
+}
+
+#[derive(Deserialize, Debug)]
+struct ScrollResult {
+    points: Vec<Record>,
+    next_page_offset: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Record {
+    id: serde_json::Value,
+    payload: Option<HashMap<String, serde_json::Value>>,
+    vector: Option<Vec<f64>>,
+}
+
+// Points fetched per scroll request when exporting
+const SCROLL_LIMIT: usize = 256;
+
+// Qdrant collection used as a `VectorStore` via the REST API
+pub struct QdrantStore {
+    pub url: String,
+    pub api_key: Option<String>,
+    pub collection: String,
+    pub dimension: usize,
+}
+
+impl QdrantStore {
+    fn request(&self, method: reqwest::Method, path: &str) -> RequestBuilder {
+        let url = format!(
+            "{}/collections/{}{}",
+            self.url.trim_end_matches('/'),
+            self.collection,
+            path
+        );
+        let request = Client::new().request(method, url);
+        match &self.api_key {
+            Some(api_key) => request.header("api-key", api_key),
+            None => request,
+        }
+    }
+}
+
+#[async_trait]
+impl VectorStore for QdrantStore {
+    #[tracing::instrument(skip_all, fields(collection = %self.collection))]
+    async fn create(&self) -> Result<(), CrustagiError> {
+        let res = self.request(reqwest::Method::GET, "").send().await?;
+        if res.status() != StatusCode::NOT_FOUND {
+            res.error_for_status()?;
+            return Ok(());
+        }
+
+        tracing::info!("Creating Qdrant collection {}...", self.collection);
+        let body = json!({
+            "vectors": {
+                "size": self.dimension,
+                "distance": "Cosine"
+            }
+        });
+        self.request(reqwest::Method::PUT, "")
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    // Collections with named vectors report no single size
+    async fn dimension(&self) -> Result<Option<usize>, CrustagiError> {
+        let res: serde_json::Value = self
+            .request(reqwest::Method::GET, "")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(res["result"]["config"]["params"]["vectors"]["size"]
+            .as_u64()
+            .map(|size| size as usize))
+    }
+
+    #[tracing::instrument(skip_all, fields(collection = %self.collection, id))]
+    async fn upsert(
+        &self,
+        id: &str,
+        vector: &[f64],
+        metadata: &VectorMetadata,
+    ) -> Result<usize, CrustagiError> {
+        let mut payload = metadata.to_map();
+        payload.insert(ID_KEY.to_string(), json!(id));
+        let body = json!({
+            "points": [{
+                "id": hash_id(id),
+                "vector": vector,
+                "payload": payload
+            }]
+        });
+
+        tracing::debug!("Storing to Qdrant...");
+
+        self.request(reqwest::Method::PUT, "/points?wait=true")
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(1)
+    }
+
+    #[tracing::instrument(skip_all, fields(collection = %self.collection, top_k))]
+    async fn query(
+        &self,
+        vector: &[f64],
+        top_k: usize,
+        filter: Option<&Metadata>,
+    ) -> Result<Vec<Match>, CrustagiError> {
+        let mut body = json!({
+            "vector": vector,
+            "limit": top_k,
+            "with_payload": true
+        });
+        if let Some(filter) = filter {
+            let must: Vec<_> = filter
+                .iter()
+                .map(|(key, value)| json!({ "key": key, "match": { "value": value } }))
+                .collect();
+            body["filter"] = json!({ "must": must });
+        }
+
+        tracing::debug!("Querying Qdrant...");
+
+        let res = self
+            .request(reqwest::Method::POST, "/points/search")
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<SearchResponse>()
+            .await?;
+        Ok(res
+            .result
+            .into_iter()
+            .map(|point| {
+                let mut payload = point.payload.unwrap_or_default();
+                let id = match payload.remove(ID_KEY) {
+                    Some(serde_json::Value::String(id)) => id,
+                    _ => point.id.to_string(),
+                };
+                Match {
+                    id,
+                    score: point.score,
+                    metadata: VectorMetadata::from_map(payload),
+                }
+            })
+            .collect())
+    }
+
+    #[tracing::instrument(skip_all, fields(collection = %self.collection))]
+    async fn delete(&self, ids: &[String]) -> Result<(), CrustagiError> {
+        let points: Vec<u64> = ids.iter().map(|id| hash_id(id)).collect();
+        self.request(reqwest::Method::POST, "/points/delete?wait=true")
+            .json(&json!({ "points": points }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all, fields(collection = %self.collection))]
+    async fn dump(&self) -> Result<Vec<StoredVector>, CrustagiError> {
+        let mut vectors = Vec::new();
+        let mut offset = serde_json::Value::Null;
+        loop {
+            let body = json!({
+                "limit": SCROLL_LIMIT,
+                "offset": offset,
+                "with_payload": true,
+                "with_vector": true
+            });
+            let res = self
+                .request(reqwest::Method::POST, "/points/scroll")
+                .json(&body)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<ScrollResponse>()
+                .await?;
+            for point in res.result.points {
+                let mut payload = point.payload.unwrap_or_default();
+                let id = match payload.remove(ID_KEY) {
+                    Some(serde_json::Value::String(id)) => id,
+                    _ => point.id.to_string(),
+                };
+                vectors.push(StoredVector {
+                    id,
+                    vector: point.vector.unwrap_or_default(),
+                    metadata: VectorMetadata::from_map(payload),
+                });
+            }
+            match res.result.next_page_offset {
+                Some(next) if !next.is_null() => offset = next,
+                _ => return Ok(vectors),
+            }
+        }
+    }
+
+    #[tracing::instrument(skip_all, fields(collection = %self.collection))]
+    async fn reset(&self) -> Result<(), CrustagiError> {
+        tracing::info!("Deleting Qdrant collection {}...", self.collection);
+        self.request(reqwest::Method::DELETE, "")
+            .send()
+            .await?
+            .error_for_status()?;
+        self.create().await
+    }
+}
@@ -0,0 +1,329 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::error::CrustagiError;
+use crate::llm::{ChatMessage, LlmProvider, ToolSpec};
+
+// One provider call of a run: the agent that made it, the method called with
+// its arguments, and the answer or the error
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Exchange {
+    pub agent: String,
+    // `complete`, `complete_json`, `chat`, `chat_with_tools`, `embed`,
+    // `embed_batch` or `embedding_dimension`. Streamed chats are recorded as `chat`.
+    pub call: String,
+    pub request: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// Run log the calls of all providers are written to, one JSON line each
+pub struct RunLog {
+    path: String,
+    file: Mutex<File>,
+}
+
+impl RunLog {
+    // Start the log at `path`, or continue it with `append`
+    pub fn open(path: &str, append: bool) -> Result<Self, CrustagiError> {
+        let open = || -> io::Result<File> {
+            if let Some(dir) = Path::new(path).parent() {
+                fs::create_dir_all(dir)?;
+            }
+            OpenOptions::new()
+                .create(true)
+                .write(true)
+                .append(append)
+                .truncate(!append)
+                .open(path)
+        };
+        let file = open().map_err(|err| {
+            CrustagiError::io(format!("Failed to open the run log {}", path), err)
+        })?;
+        Ok(RunLog {
+            path: path.to_string(),
+            file: Mutex::new(file),
+        })
+    }
+
+    // A call that can't be logged is only reported, the run goes on
+    fn write(&self, exchange: &Exchange) {
+        let line = serde_json::to_string(exchange).expect("Exchanges serialize to JSON");
+        let mut file = self.file.lock().unwrap();
+        if let Err(err) = writeln!(file, "{}", line) {
+            tracing::warn!("Failed to write to the run log {}: {}", self.path, err);
+        }
+    }
+}
+
+// Provider wrapper writing every call of one agent to the run log
+pub struct Recording {
+    inner: Arc<dyn LlmProvider>,
+    agent: String,
+    log: Arc<RunLog>,
+}
+
+impl Recording {
+    pub fn new(inner: Arc<dyn LlmProvider>, agent: &str, log: Arc<RunLog>) -> Self {
+        Recording {
+            inner,
+            agent: agent.to_string(),
+            log,
+        }
+    }
+
+    fn record<T: Serialize>(
+        &self,
+        call: &str,
+        request: Value,
+        result: Result<T, CrustagiError>,
+    ) -> Result<T, CrustagiError> {
+        let (response, error) = match &result {
+            Ok(response) => (Some(json!(response)), None),
+            Err(err) => (None, Some(err.to_string())),
+        };
+        self.log.write(&Exchange {
+            agent: self.agent.clone(),
+            call: call.to_string(),
+            request,
+            response,
+            error,
+        });
+        result
+    }
+}
+
+#[async_trait]
+impl LlmProvider for Recording {
+    async fn complete(&self, prompt: &str) -> Result<String, CrustagiError> {
+        let result = self.inner.complete(prompt).await;
+        self.record("complete", json!({ "prompt": prompt }), result)
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut (dyn for<'c> FnMut(&'c str) + Send),
+    ) -> Result<String, CrustagiError> {
+        let result = self.inner.chat_stream(messages, on_chunk).await;
+        self.record("chat", json!({ "messages": messages }), result)
+    }
+
+    async fn chat_with_tools(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+    ) -> Result<ChatMessage, CrustagiError> {
+        let result = self.inner.chat_with_tools(messages, tools).await;
+        let request = json!({ "messages": messages, "tools": tools });
+        self.record("chat_with_tools", request, result)
+    }
+
+    async fn complete_json(&self, prompt: &str, schema: &Value) -> Result<String, CrustagiError> {
+        let result = self.inner.complete_json(prompt, schema).await;
+        let request = json!({ "prompt": prompt, "schema": schema });
+        self.record("complete_json", request, result)
+    }
+
+    async fn chat(&self, messages: &[ChatMessage]) -> Result<String, CrustagiError> {
+        let result = self.inner.chat(messages).await;
+        self.record("chat", json!({ "messages": messages }), result)
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f64>, CrustagiError> {
+        let result = self.inner.embed(text).await;
+        self.record("embed", json!({ "text": text }), result)
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f64>>, CrustagiError> {
+        let result = self.inner.embed_batch(texts).await;
+        self.record("embed_batch", json!({ "texts": texts }), result)
+    }
+
+    fn model(&self) -> String {
+        self.inner.model()
+    }
+
+    fn prompt_budget(&self) -> usize {
+        self.inner.prompt_budget()
+    }
+
+    fn embedding_model(&self) -> &str {
+        self.inner.embedding_model()
+    }
+
+    async fn embedding_dimension(&self) -> Result<usize, CrustagiError> {
+        let result = self.inner.embedding_dimension().await;
+        self.record("embedding_dimension", json!({}), result)
+    }
+}
+
+// The calls of a recorded run, handed out once each
+pub struct Replay {
+    exchanges: Mutex<Vec<Option<Exchange>>>,
+    // Set once a call found no recorded answer
+    exhausted: AtomicBool,
+}
+
+impl Replay {
+    pub fn load(path: &str) -> Result<Self, CrustagiError> {
+        let exchanges = fs::read_to_string(path)
+            .map_err(|err| CrustagiError::io(format!("Failed to read the run log {}", path), err))?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map(Some))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| {
+                CrustagiError::Parse(format!("The run log {} is invalid: {}", path, err))
+            })?;
+        Ok(Replay {
+            exchanges: Mutex::new(exchanges),
+            exhausted: AtomicBool::new(false),
+        })
+    }
+
+    // Whether the run asked for more than was recorded
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted.load(Ordering::Relaxed)
+    }
+
+    // The recorded answer to a call. Calls are matched by agent, method and
+    // arguments, so tasks running at the same time may finish in another
+    // order than they did. A call whose arguments changed, e.g. after
+    // editing a prompt, gets the first unused answer of the same agent and
+    // method.
+    fn take(&self, agent: &str, call: &str, request: Value) -> Result<Value, CrustagiError> {
+        let mut exchanges = self.exchanges.lock().unwrap();
+        let same_call = |exchange: &Exchange| exchange.agent == agent && exchange.call == call;
+        let index = exchanges
+            .iter()
+            .position(|exchange| {
+                exchange
+                    .as_ref()
+                    .is_some_and(|exchange| same_call(exchange) && exchange.request == request)
+            })
+            .or_else(|| {
+                let index = exchanges
+                    .iter()
+                    .position(|exchange| exchange.as_ref().is_some_and(same_call))?;
+                tracing::warn!(
+                    "Replay: the {} call of the {} agent differs from the recorded one",
+                    call,
+                    agent
+                );
+                Some(index)
+            });
+        let Some(exchange) = index.and_then(|index| exchanges[index].take()) else {
+            self.exhausted.store(true, Ordering::Relaxed);
+            return Err(format!(
+                "The run log has no more {} calls of the {} agent",
+                call, agent
+            )
+            .into());
+        };
+        match (exchange.response, exchange.error) {
+            (Some(response), _) => Ok(response),
+            (None, error) => Err(error.unwrap_or_default().into()),
+        }
+    }
+
+    fn answer<T: serde::de::DeserializeOwned>(
+        &self,
+        agent: &str,
+        call: &str,
+        request: Value,
+    ) -> Result<T, CrustagiError> {
+        let response = self.take(agent, call, request)?;
+        Ok(serde_json::from_value(response)?)
+    }
+}
+
+// Provider answering one agent's calls from a recorded run instead of the
+// API. The wrapped provider only supplies the model names and token budget.
+pub struct Replayed {
+    inner: Arc<dyn LlmProvider>,
+    agent: String,
+    replay: Arc<Replay>,
+}
+
+impl Replayed {
+    pub fn new(inner: Arc<dyn LlmProvider>, agent: &str, replay: Arc<Replay>) -> Self {
+        Replayed {
+            inner,
+            agent: agent.to_string(),
+            replay,
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for Replayed {
+    async fn complete(&self, prompt: &str) -> Result<String, CrustagiError> {
+        let request = json!({ "prompt": prompt });
+        self.replay.answer(&self.agent, "complete", request)
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut (dyn for<'c> FnMut(&'c str) + Send),
+    ) -> Result<String, CrustagiError> {
+        let text = self.chat(messages).await?;
+        on_chunk(&text);
+        Ok(text)
+    }
+
+    async fn complete_json(&self, prompt: &str, schema: &Value) -> Result<String, CrustagiError> {
+        let request = json!({ "prompt": prompt, "schema": schema });
+        self.replay.answer(&self.agent, "complete_json", request)
+    }
+
+    async fn chat(&self, messages: &[ChatMessage]) -> Result<String, CrustagiError> {
+        let request = json!({ "messages": messages });
+        self.replay.answer(&self.agent, "chat", request)
+    }
+
+    async fn chat_with_tools(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+    ) -> Result<ChatMessage, CrustagiError> {
+        let request = json!({ "messages": messages, "tools": tools });
+        self.replay.answer(&self.agent, "chat_with_tools", request)
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f64>, CrustagiError> {
+        let request = json!({ "text": text });
+        self.replay.answer(&self.agent, "embed", request)
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f64>>, CrustagiError> {
+        let request = json!({ "texts": texts });
+        self.replay.answer(&self.agent, "embed_batch", request)
+    }
+
+    fn model(&self) -> String {
+        self.inner.model()
+    }
+
+    fn prompt_budget(&self) -> usize {
+        self.inner.prompt_budget()
+    }
+
+    fn embedding_model(&self) -> &str {
+        self.inner.embedding_model()
+    }
+
+    async fn embedding_dimension(&self) -> Result<usize, CrustagiError> {
+        self.replay
+            .answer(&self.agent, "embedding_dimension", json!({}))
+    }
+}
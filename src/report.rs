@@ -0,0 +1,92 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::Task;
+
+// One objective or milestone of the run, with its tasks
+pub struct Section<'a> {
+    // e.g. `Milestone 2: ...`, None for a run with a single objective
+    pub title: Option<String>,
+    pub completed_tasks: &'a [Task],
+    pub failed_tasks: &'a [Task],
+}
+
+// What the report written when the run stops covers
+pub struct Report<'a> {
+    pub objective: &'a str,
+    // Why the run stopped
+    pub outcome: &'a str,
+    pub iterations: usize,
+    // Key findings from the model, or why there are none
+    pub findings: &'a str,
+    pub sections: Vec<Section<'a>>,
+    pub usage: Vec<String>,
+}
+
+impl Report<'_> {
+    pub fn to_markdown(&self) -> String {
+        // A brief of several lines is titled by its first line
+        let mut markdown = match self.objective.split_once('\n') {
+            Some((title, rest)) => format!("# {}\n\n{}\n\n", title.trim(), rest.trim()),
+            None => format!("# {}\n\n", self.objective),
+        };
+        markdown.push_str(&format!(
+            "- Outcome: {}\n- Iterations: {}\n\n",
+            self.outcome, self.iterations
+        ));
+        markdown.push_str(&format!("## Key Findings\n\n{}\n\n", self.findings.trim()));
+        for section in &self.sections {
+            // Tasks are one heading level below their section
+            let level = match &section.title {
+                Some(title) => {
+                    markdown.push_str(&format!("## {}\n\n", title));
+                    "###"
+                }
+                None => "##",
+            };
+            markdown.push_str(&format!("{} Tasks\n\n", level));
+            if section.completed_tasks.is_empty() {
+                markdown.push_str("No tasks were completed.\n\n");
+            }
+            for task in section.completed_tasks {
+                markdown.push_str(&format!(
+                    "{}# {}. {}\n\n{}\n\n",
+                    level,
+                    task.task_id,
+                    task.task_name,
+                    task.result
+                        .as_deref()
+                        .unwrap_or("*No result recorded.*")
+                        .trim()
+                ));
+            }
+            if !section.failed_tasks.is_empty() {
+                markdown.push_str(&format!("{} Failed Tasks\n\n", level));
+                for task in section.failed_tasks {
+                    markdown.push_str(&format!(
+                        "- {}. {} ({} attempts): {}\n",
+                        task.task_id,
+                        task.task_name,
+                        task.failures,
+                        task.error.as_deref().unwrap_or("unknown error")
+                    ));
+                }
+                markdown.push('\n');
+            }
+        }
+        markdown.push_str("## Usage\n\n");
+        for line in &self.usage {
+            markdown.push_str(&format!("- {}\n", line));
+        }
+        markdown
+    }
+}
+
+// Write `report` to `path`, creating its directory
+pub fn write(path: &str, report: &Report) -> io::Result<()> {
+    if let Some(dir) = Path::new(path).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, report.to_markdown())
+}
@@ -0,0 +1,136 @@
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::cohere::COHERE_API_BASE;
+use crate::error::CrustagiError;
+use crate::llm::LlmProvider;
+use crate::middleware::RedactionLayer;
+
+#[derive(Deserialize, Debug)]
+struct CohereRerankResponse {
+    results: Vec<CohereRerankResult>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CohereRerankResult {
+    index: usize,
+}
+
+// Second pass over retrieved context, ordering candidates by relevance to
+// the task at hand rather than by vector similarity to the query
+#[derive(Clone)]
+pub enum Reranker {
+    // Ask the completion model to order the candidates
+    Llm(Arc<dyn LlmProvider>),
+    // Cohere's hosted cross-encoder. The query and candidates are sent with
+    // the matches of `redaction` replaced, like the prompts to the LLM.
+    Cohere {
+        http: Client,
+        api_key: String,
+        model: String,
+        redaction: Option<RedactionLayer>,
+    },
+}
+
+impl Reranker {
+    // The `top_n` most relevant `documents` for `query`, most relevant first
+    pub async fn rerank(
+        &self,
+        query: &str,
+        documents: &[String],
+        top_n: usize,
+    ) -> Result<Vec<String>, CrustagiError> {
+        let order = match self {
+            Reranker::Llm(llm) => llm_order(llm.as_ref(), query, documents).await?,
+            Reranker::Cohere {
+                http,
+                api_key,
+                model,
+                redaction,
+            } => {
+                let redact = |text: &str| match redaction {
+                    Some(redaction) => redaction.redact(text),
+                    None => text.to_string(),
+                };
+                let candidates: Vec<String> = documents.iter().map(|d| redact(d)).collect();
+                cohere_order(http, api_key, model, &redact(query), &candidates, top_n).await?
+            }
+        };
+        Ok(order
+            .into_iter()
+            .take(top_n)
+            .map(|index| documents[index].clone())
+            .collect())
+    }
+}
+
+// Indices in the order the model ranked them. Indices the model left out
+// keep their original order after the ranked ones.
+async fn llm_order(
+    llm: &dyn LlmProvider,
+    query: &str,
+    documents: &[String],
+) -> Result<Vec<usize>, CrustagiError> {
+    let candidates: Vec<String> = documents
+        .iter()
+        .enumerate()
+        .map(|(i, document)| format!("{}. {}", i + 1, document))
+        .collect();
+    let prompt = format!(
+        r#"
+        You are a ranking AI. Order the following completed tasks by how useful they are as context for this task: {}.
+        {}
+        Return only the numbers of the tasks, most useful first, separated by commas."#,
+        query,
+        candidates.join("\n        ")
+    );
+
+    let response = llm.complete(&prompt).await?;
+    let mut order = Vec::with_capacity(documents.len());
+    for number in response.split(|c: char| !c.is_ascii_digit()) {
+        if let Ok(number) = number.parse::<usize>() {
+            if (1..=documents.len()).contains(&number) && !order.contains(&(number - 1)) {
+                order.push(number - 1);
+            }
+        }
+    }
+    for index in 0..documents.len() {
+        if !order.contains(&index) {
+            order.push(index);
+        }
+    }
+    Ok(order)
+}
+
+async fn cohere_order(
+    http: &Client,
+    api_key: &str,
+    model: &str,
+    query: &str,
+    documents: &[String],
+    top_n: usize,
+) -> Result<Vec<usize>, CrustagiError> {
+    let body = json!({
+        "model": model,
+        "query": query,
+        "documents": documents,
+        "top_n": top_n
+    });
+    let res = http
+        .post(format!("{}/rerank", COHERE_API_BASE))
+        .bearer_auth(api_key)
+        .json(&body)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<CohereRerankResponse>()
+        .await?;
+    Ok(res
+        .results
+        .into_iter()
+        .map(|result| result.index)
+        .filter(|index| *index < documents.len())
+        .collect())
+}
@@ -0,0 +1,105 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+// A named run, selected with `--run-name`. Its results, state and logs are
+// kept apart from other runs, so several objectives can be worked on in turns.
+pub struct Run {
+    // The name reduced to lowercase letters, digits and underscores, so it
+    // fits into collection, table and class names
+    pub id: String,
+    dir: PathBuf,
+}
+
+impl Run {
+    pub fn new(name: &str, runs_dir: &str) -> Self {
+        let id: String = name
+            .trim()
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_lowercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let dir = PathBuf::from(runs_dir).join(&id);
+        Run { id, dir }
+    }
+
+    pub fn create_dir(&self) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)
+    }
+
+    // `name` made specific to this run, e.g. `crustagi_research`
+    pub fn scoped(&self, name: &str) -> String {
+        format!("{}_{}", name, self.id)
+    }
+
+    // Path of `file` in the run's directory
+    pub fn path(&self, file: &str) -> String {
+        self.dir.join(file).to_string_lossy().into_owned()
+    }
+
+    pub fn log_dir(&self) -> String {
+        self.path("logs")
+    }
+
+    pub fn tasks_dir(&self) -> String {
+        self.path("tasks")
+    }
+}
+
+// Append a completed task and its result to `tasks.log` in `log_dir`
+pub fn log_task(log_dir: &str, task_id: i32, task_name: &str, result: &str) -> io::Result<()> {
+    fs::create_dir_all(log_dir)?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(PathBuf::from(log_dir).join("tasks.log"))?;
+    writeln!(
+        file,
+        "*****TASK {}: {}*****\n{}\n",
+        task_id, task_name, result
+    )
+}
+
+// Write a completed task to `<task_id>.md` in `tasks_dir`, with its result
+// and the context and prompt it was executed with
+pub fn write_task_file(
+    tasks_dir: &str,
+    task_id: i32,
+    task_name: &str,
+    prompt: &str,
+    context: &[String],
+    result: &str,
+) -> io::Result<()> {
+    fs::create_dir_all(tasks_dir)?;
+    let context = match context {
+        [] => "No earlier results.".to_string(),
+        context => context
+            .iter()
+            .map(|result| format!("- {}", result.replace('\n', "\n  ")))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    };
+    // A fence longer than any backtick run in the prompt keeps it intact
+    let mut fence = "```".to_string();
+    while prompt.contains(&fence) {
+        fence.push('`');
+    }
+    fs::write(
+        PathBuf::from(tasks_dir).join(format!("{}.md", task_id)),
+        format!(
+            "# Task {}: {}\n\n## Result\n\n{}\n\n## Context\n\n{}\n\n## Prompt\n\n{}\n{}\n{}\n",
+            task_id,
+            task_name,
+            result.trim(),
+            context,
+            fence,
+            prompt,
+            fence
+        ),
+    )
+}
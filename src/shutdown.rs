@@ -0,0 +1,50 @@
+use std::sync::OnceLock;
+use tokio::signal;
+use tokio::sync::{mpsc, watch};
+
+// Stop requests that don't come as a signal, e.g. Ctrl+C pressed in the TUI,
+// whose raw terminal mode keeps it from raising SIGINT
+static REQUESTS: OnceLock<mpsc::UnboundedSender<()>> = OnceLock::new();
+
+// Count SIGINT (Ctrl+C) and SIGTERM signals. The first one lets the task in
+// progress finish before the run stops, the second one cancels it.
+pub fn listen() -> watch::Receiver<u32> {
+    let (sender, receiver) = watch::channel(0);
+    let (request_sender, mut requests) = mpsc::unbounded_channel();
+    let _ = REQUESTS.set(request_sender);
+    tokio::spawn(async move {
+        #[cfg(unix)]
+        let mut terminate = signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM");
+        loop {
+            #[cfg(unix)]
+            tokio::select! {
+                _ = signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+                _ = requests.recv() => {}
+            }
+            #[cfg(not(unix))]
+            tokio::select! {
+                _ = signal::ctrl_c() => {}
+                _ = requests.recv() => {}
+            }
+
+            sender.send_modify(|signals| *signals += 1);
+            if *sender.borrow() == 1 {
+                tracing::info!(
+                    "\nStopping after the task in progress, press Ctrl+C again to cancel it..."
+                );
+            } else {
+                tracing::info!("\nCancelling the task in progress...");
+            }
+        }
+    });
+    receiver
+}
+
+// Count as a signal, once `listen` was called
+pub fn request() {
+    if let Some(requests) = REQUESTS.get() {
+        let _ = requests.send(());
+    }
+}
@@ -0,0 +1,50 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+use crate::error::CrustagiError;
+use crate::memory::{StoredVector, VectorStore};
+
+// Vectors sent per `upsert_batch` call when importing
+const IMPORT_BATCH: usize = 100;
+
+// Write every vector in the store to `path` as JSON Lines, one `StoredVector`
+// per line. Returns the number of vectors written.
+pub async fn export(store: &dyn VectorStore, path: &str) -> Result<usize, CrustagiError> {
+    let vectors = store.dump().await?;
+    let mut file = BufWriter::new(File::create(path)?);
+    for vector in &vectors {
+        serde_json::to_writer(&mut file, vector)?;
+        file.write_all(b"\n")?;
+    }
+    file.flush()?;
+    Ok(vectors.len())
+}
+
+// Upsert every vector from a file written by `export`, which may come from a
+// different backend. Returns the number of vectors written.
+pub async fn import(store: &dyn VectorStore, path: &str) -> Result<usize, CrustagiError> {
+    let file = BufReader::new(File::open(path)?);
+    let mut batch = Vec::with_capacity(IMPORT_BATCH);
+    let mut count = 0;
+    for (number, line) in file.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let vector: StoredVector = serde_json::from_str(&line)
+            .map_err(|err| format!("{}:{}: {}", path, number + 1, err))?;
+        batch.push((
+            vector.id,
+            vector.vector,
+            vector.metadata.unwrap_or_default(),
+        ));
+        if batch.len() == IMPORT_BATCH {
+            count += store.upsert_batch(&batch).await?;
+            batch.clear();
+        }
+    }
+    if !batch.is_empty() {
+        count += store.upsert_batch(&batch).await?;
+    }
+    Ok(count)
+}
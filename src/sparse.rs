@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+
+use crate::memory::hash_id;
+use crate::pinecone::SparseValues;
+
+// BM25 parameters; AVG_DOC_LEN approximates the length of a task result in terms
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+const AVG_DOC_LEN: f64 = 120.0;
+
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "has", "have", "in", "is",
+    "it", "its", "of", "on", "or", "that", "the", "this", "to", "was", "were", "will", "with",
+];
+
+// Lowercased alphanumeric terms without stopwords
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_lowercase())
+        .filter(|t| !STOPWORDS.contains(&t.as_str()))
+        .collect()
+}
+
+// Terms are hashed into Pinecone's u32 sparse index space, so no vocabulary
+// has to be stored; rare collisions only blur the keyword signal
+fn term_counts(text: &str) -> (HashMap<u32, f64>, usize) {
+    let terms = tokenize(text);
+    let mut counts = HashMap::new();
+    for term in &terms {
+        *counts.entry(hash_id(term) as u32).or_insert(0.0) += 1.0;
+    }
+    (counts, terms.len())
+}
+
+fn into_sparse_values(weights: HashMap<u32, f64>) -> SparseValues {
+    let mut entries: Vec<(u32, f64)> = weights.into_iter().collect();
+    entries.sort_by_key(|(index, _)| *index);
+    SparseValues {
+        indices: entries.iter().map(|(index, _)| *index).collect(),
+        values: entries.iter().map(|(_, value)| *value).collect(),
+    }
+}
+
+// BM25 term-frequency weights for a stored document
+pub fn document_vector(text: &str) -> SparseValues {
+    let (counts, len) = term_counts(text);
+    let norm = K1 * (1.0 - B + B * len as f64 / AVG_DOC_LEN);
+    let weights = counts
+        .into_iter()
+        .map(|(index, tf)| (index, tf * (K1 + 1.0) / (tf + norm)))
+        .collect();
+    into_sparse_values(weights)
+}
+
+// Query terms weighted by occurrence, so the dot product with a document
+// vector gives its BM25 score (without IDF)
+pub fn query_vector(text: &str) -> SparseValues {
+    let (counts, _) = term_counts(text);
+    into_sparse_values(counts)
+}
+
+// Convex combination of dense and sparse vectors, as recommended by Pinecone
+// for dotproduct hybrid indexes: `alpha` = 1.0 is pure dense, 0.0 pure sparse
+pub fn weight(dense: &[f64], sparse: SparseValues, alpha: f64) -> (Vec<f64>, SparseValues) {
+    let dense = dense.iter().map(|v| v * alpha).collect();
+    let sparse = SparseValues {
+        indices: sparse.indices,
+        values: sparse.values.iter().map(|v| v * (1.0 - alpha)).collect(),
+    };
+    (dense, sparse)
+}
@@ -0,0 +1,153 @@
+use async_trait::async_trait;
+use rusqlite::{params, Connection};
+use std::sync::Mutex;
+
+use crate::error::CrustagiError;
+use crate::memory::{
+    cosine_similarity, Match, Metadata, StoredVector, VectorMetadata, VectorStore,
+};
+
+// Persistent local vector store in a single SQLite file.
+// Vectors are stored as little-endian f64 BLOBs and searched by brute force,
+// which is fine for the few thousand results a run produces.
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &str) -> Result<Self, CrustagiError> {
+        let conn = Connection::open(path)?;
+        Ok(SqliteStore {
+            conn: Mutex::new(conn),
+        })
+    }
+}
+
+#[async_trait]
+impl VectorStore for SqliteStore {
+    async fn create(&self) -> Result<(), CrustagiError> {
+        self.conn.lock().unwrap().execute(
+            "CREATE TABLE IF NOT EXISTS memory (
+                id TEXT PRIMARY KEY,
+                vector BLOB NOT NULL,
+                metadata TEXT,
+                created_at INTEGER NOT NULL DEFAULT (unixepoch())
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    async fn upsert(
+        &self,
+        id: &str,
+        vector: &[f64],
+        metadata: &VectorMetadata,
+    ) -> Result<usize, CrustagiError> {
+        tracing::debug!("Storing to SQLite...");
+        let metadata = serde_json::to_string(metadata)?;
+        let count = self.conn.lock().unwrap().execute(
+            "INSERT INTO memory (id, vector, metadata) VALUES (?1, ?2, ?3)
+             ON CONFLICT(id) DO UPDATE SET vector = excluded.vector, metadata = excluded.metadata",
+            params![id, encode_vector(vector), metadata],
+        )?;
+        Ok(count)
+    }
+
+    async fn query(
+        &self,
+        vector: &[f64],
+        top_k: usize,
+        filter: Option<&Metadata>,
+    ) -> Result<Vec<Match>, CrustagiError> {
+        tracing::debug!("Querying SQLite...");
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT id, vector, metadata FROM memory")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Vec<u8>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+            ))
+        })?;
+
+        let mut matches = Vec::new();
+        for row in rows {
+            let (id, blob, metadata) = row?;
+            let metadata: Option<VectorMetadata> = match metadata {
+                Some(json) => Some(serde_json::from_str(&json)?),
+                None => None,
+            };
+            if let Some(filter) = filter {
+                if !metadata.as_ref().is_some_and(|m| m.matches(filter)) {
+                    continue;
+                }
+            }
+            matches.push(Match {
+                id,
+                score: cosine_similarity(vector, &decode_vector(&blob)),
+                metadata,
+            });
+        }
+        matches.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        matches.truncate(top_k);
+        Ok(matches)
+    }
+
+    async fn delete(&self, ids: &[String]) -> Result<(), CrustagiError> {
+        let conn = self.conn.lock().unwrap();
+        for id in ids {
+            conn.execute("DELETE FROM memory WHERE id = ?1", params![id])?;
+        }
+        Ok(())
+    }
+
+    async fn dump(&self) -> Result<Vec<StoredVector>, CrustagiError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT id, vector, metadata FROM memory ORDER BY created_at")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Vec<u8>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+            ))
+        })?;
+
+        let mut vectors = Vec::new();
+        for row in rows {
+            let (id, blob, metadata) = row?;
+            vectors.push(StoredVector {
+                id,
+                vector: decode_vector(&blob),
+                metadata: match metadata {
+                    Some(json) => Some(serde_json::from_str(&json)?),
+                    None => None,
+                },
+            });
+        }
+        Ok(vectors)
+    }
+
+    async fn reset(&self) -> Result<(), CrustagiError> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM memory", [])?;
+        Ok(())
+    }
+}
+
+pub fn encode_vector(vector: &[f64]) -> Vec<u8> {
+    vector.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+pub fn decode_vector(blob: &[u8]) -> Vec<f64> {
+    blob.chunks_exact(8)
+        .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
+        .collect()
+}
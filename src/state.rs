@@ -0,0 +1,132 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+
+use crate::error::CrustagiError;
+use crate::llm::LlmParams;
+use crate::task_graph::TaskResult;
+use crate::Task;
+
+// What is needed to continue a stopped run: the objective it was working on,
+// the pending tasks in order, the last task id handed out, the completed
+// tasks, the tasks given up on and the results pending tasks depend on
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunState {
+    pub objective: String,
+    pub task_id_counter: i32,
+    pub tasks: Vec<Task>,
+    // Missing in state files of older versions
+    #[serde(default)]
+    pub completed_tasks: Vec<Task>,
+    #[serde(default)]
+    pub results: BTreeMap<i32, TaskResult>,
+    #[serde(default)]
+    pub failed_tasks: Vec<Task>,
+}
+
+// Milestones the planner broke the objective into, kept so a resumed run
+// works through the same ones
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Plan {
+    pub objective: String,
+    pub milestones: Vec<String>,
+}
+
+// What a deterministic run was started with, so that two runs can be
+// compared: the objectives, the models with their sampling parameters and
+// the prompt templates
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: String,
+    pub started_at: u64,
+    pub objectives: Vec<String>,
+    pub initial_task: String,
+    pub seed: u64,
+    pub llm_provider: String,
+    pub embedding_model: String,
+    pub memory_backend: String,
+    pub agents: BTreeMap<String, AgentSettings>,
+    pub prompts: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AgentSettings {
+    pub model: String,
+    pub params: LlmParams,
+}
+
+impl Manifest {
+    // What `other` was started with differently, leaving out the version
+    // and start time
+    pub fn differences(&self, other: &Manifest) -> Vec<String> {
+        let mut differences = Vec::new();
+        let mut compare = |what: String, a: serde_json::Value, b: serde_json::Value| {
+            if a != b {
+                differences.push(what);
+            }
+        };
+        compare(
+            "objectives".to_string(),
+            json!(self.objectives),
+            json!(other.objectives),
+        );
+        compare(
+            "initial task".to_string(),
+            json!(self.initial_task),
+            json!(other.initial_task),
+        );
+        compare("seed".to_string(), json!(self.seed), json!(other.seed));
+        compare(
+            "LLM provider".to_string(),
+            json!(self.llm_provider),
+            json!(other.llm_provider),
+        );
+        compare(
+            "embedding model".to_string(),
+            json!(self.embedding_model),
+            json!(other.embedding_model),
+        );
+        compare(
+            "memory backend".to_string(),
+            json!(self.memory_backend),
+            json!(other.memory_backend),
+        );
+        let agents: BTreeSet<&String> = self.agents.keys().chain(other.agents.keys()).collect();
+        for agent in agents {
+            compare(
+                format!("{} agent", agent),
+                json!(self.agents.get(agent)),
+                json!(other.agents.get(agent)),
+            );
+        }
+        let prompts: BTreeSet<&String> = self.prompts.keys().chain(other.prompts.keys()).collect();
+        for prompt in prompts {
+            compare(
+                format!("{} prompt", prompt),
+                json!(self.prompts.get(prompt)),
+                json!(other.prompts.get(prompt)),
+            );
+        }
+        differences
+    }
+}
+
+// Write the state, plan or manifest to `path`. The file is replaced in one rename, so
+// an interrupted save leaves the previous one intact.
+pub fn save(path: &str, state: &impl Serialize) -> Result<(), CrustagiError> {
+    let json =
+        serde_json::to_string_pretty(state).map_err(|err| CrustagiError::Parse(err.to_string()))?;
+    let tmp = format!("{}.tmp", path);
+    fs::write(&tmp, json)
+        .and_then(|_| fs::rename(&tmp, path))
+        .map_err(|err| CrustagiError::io(format!("Failed to write {}", path), err))
+}
+
+pub fn load<T: DeserializeOwned>(path: &str) -> Result<T, CrustagiError> {
+    let json = fs::read_to_string(path)
+        .map_err(|err| CrustagiError::io(format!("Failed to read {}", path), err))?;
+    serde_json::from_str(&json)
+        .map_err(|err| CrustagiError::Parse(format!("{} is invalid: {}", path, err)))
+}
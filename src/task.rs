@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Where a task is in its life cycle
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskStatus {
+    #[default]
+    Pending,
+    Running,
+    Done,
+    Failed,
+}
+
+// Data structure for tasks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Task {
+    // Assigned when the task is added to the list, not by the model
+    #[serde(default)]
+    pub task_id: i32,
+    pub task_name: String,
+    // Ids of the tasks whose results this task needs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<i32>,
+    // Pending tasks with a higher priority are executed first
+    #[serde(default)]
+    pub priority: f64,
+    #[serde(default)]
+    pub status: TaskStatus,
+    // Unix timestamps in seconds
+    #[serde(default)]
+    pub created_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<u64>,
+    // The task whose result this task was created from, None for the
+    // initial task and tasks typed in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<i32>,
+    // Id of the task's result in the vector store, None until it is stored
+    // or if it was a near duplicate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result_id: Option<String>,
+    // Result of a completed task, for the final report
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<String>,
+    // Failed attempts so far, and the error of the last one
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub failures: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    // Unix timestamp before which a failed task isn't retried
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_at: Option<u64>,
+}
+
+pub fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+impl Task {
+    // A pending task, created after `parent` if given
+    pub fn new(task_id: i32, task_name: String, parent: Option<i32>) -> Self {
+        Task {
+            task_id,
+            task_name,
+            depends_on: Vec::new(),
+            priority: 0.0,
+            status: TaskStatus::Pending,
+            created_at: unix_time(),
+            completed_at: None,
+            parent,
+            result_id: None,
+            result: None,
+            failures: 0,
+            error: None,
+            retry_at: None,
+        }
+    }
+}
+
+// Seconds since the Unix epoch
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::error::CrustagiError;
+use crate::llm::LlmProvider;
+use crate::memory::cosine_similarity;
+use crate::Task;
+
+// Drops new tasks that are nearly identical to a pending or completed one,
+// comparing embeddings of the task names. Embeddings are kept per name, so
+// each task name is embedded once per run.
+pub struct TaskDeduplicator {
+    threshold: f64,
+    embeddings: Mutex<HashMap<String, Vec<f64>>>,
+}
+
+impl TaskDeduplicator {
+    pub fn new(threshold: f64) -> Self {
+        TaskDeduplicator {
+            threshold,
+            embeddings: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // The `candidates` whose similarity to every task in `existing`, and to
+    // the candidates kept before them, stays below the threshold
+    pub async fn filter(
+        &self,
+        llm: &dyn LlmProvider,
+        candidates: Vec<Task>,
+        existing: &[&str],
+    ) -> Result<Vec<Task>, CrustagiError> {
+        if candidates.is_empty() {
+            return Ok(candidates);
+        }
+        let names: Vec<&str> = candidates
+            .iter()
+            .map(|task| task.task_name.as_str())
+            .chain(existing.iter().copied())
+            .collect();
+        self.embed_missing(llm, &names)
+            .await
+            .map_err(|err| CrustagiError::llm("Embedding the new tasks failed", err))?;
+
+        let embeddings = self.embeddings.lock().unwrap();
+        let mut kept: Vec<Task> = Vec::new();
+        for task in candidates {
+            let vector = &embeddings[&task.task_name];
+            let closest = existing
+                .iter()
+                .copied()
+                .chain(kept.iter().map(|task| task.task_name.as_str()))
+                .map(|name| (name, cosine_similarity(vector, &embeddings[name])))
+                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+            match closest {
+                Some((name, score)) if score >= self.threshold => tracing::info!(
+                    "Dropping task \"{}\", it is a near duplicate of \"{}\" (score {:.3})",
+                    task.task_name,
+                    name,
+                    score
+                ),
+                _ => kept.push(task),
+            }
+        }
+        Ok(kept)
+    }
+
+    // Embed the names not seen before, in one batch
+    async fn embed_missing(
+        &self,
+        llm: &dyn LlmProvider,
+        names: &[&str],
+    ) -> Result<(), CrustagiError> {
+        let mut missing: Vec<String> = {
+            let embeddings = self.embeddings.lock().unwrap();
+            names
+                .iter()
+                .filter(|name| !embeddings.contains_key(**name))
+                .map(|name| name.to_string())
+                .collect()
+        };
+        missing.sort();
+        missing.dedup();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        let vectors = llm.embed_batch(&missing).await?;
+        self.embeddings
+            .lock()
+            .unwrap()
+            .extend(missing.into_iter().zip(vectors));
+        Ok(())
+    }
+}
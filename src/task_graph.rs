@@ -0,0 +1,293 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+
+use crate::{Task, TaskStatus};
+
+// Result of a completed task, kept for the tasks depending on it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskResult {
+    pub task_name: String,
+    pub result: String,
+}
+
+// Heap entry of a pending task, ordered by priority. Of two tasks with the
+// same priority the one with the lower id, the older one, comes first.
+#[derive(Debug, Clone)]
+struct Queued(Task);
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .priority
+            .total_cmp(&other.0.priority)
+            .then_with(|| other.0.task_id.cmp(&self.0.task_id))
+    }
+}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Queued {}
+
+// Pending tasks in a priority heap. A task may depend on the results of
+// other tasks and is only handed out once none of them is pending anymore.
+// Dependencies always point to tasks with a lower id, so there are no cycles
+// and the pending task with the lowest id is always ready, unless it failed
+// before and waits for its retry.
+#[derive(Debug, Clone, Default)]
+pub struct TaskGraph {
+    tasks: BinaryHeap<Queued>,
+    // Results of completed tasks that pending tasks depend on
+    results: BTreeMap<i32, TaskResult>,
+}
+
+impl TaskGraph {
+    pub fn new(tasks: Vec<Task>, results: BTreeMap<i32, TaskResult>) -> Self {
+        TaskGraph {
+            tasks: tasks.into_iter().map(Queued).collect(),
+            results,
+        }
+    }
+
+    // The pending tasks in priority order, and the kept results
+    pub fn into_parts(self) -> (Vec<Task>, BTreeMap<i32, TaskResult>) {
+        let tasks = self.tasks.into_sorted_vec();
+        (
+            tasks.into_iter().rev().map(|queued| queued.0).collect(),
+            self.results,
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    // The pending tasks in priority order
+    pub fn tasks(&self) -> Vec<&Task> {
+        let mut tasks: Vec<&Queued> = self.tasks.iter().collect();
+        tasks.sort_by(|a, b| b.cmp(a));
+        tasks.into_iter().map(|queued| &queued.0).collect()
+    }
+
+    fn is_pending(&self, task_id: i32) -> bool {
+        self.tasks.iter().any(|queued| queued.0.task_id == task_id)
+    }
+
+    // Add a new task. Dependencies on tasks that are neither pending nor
+    // have a kept result are dropped, as are those that could form a cycle.
+    pub fn add(&mut self, mut task: Task) {
+        task.depends_on.retain(|&id| {
+            id < task.task_id && (self.is_pending(id) || self.results.contains_key(&id))
+        });
+        task.depends_on.sort();
+        task.depends_on.dedup();
+        self.tasks.push(Queued(task));
+    }
+
+    // Put back a task that was handed out but not completed, keeping its
+    // priority
+    pub fn push_front(&mut self, mut task: Task) {
+        task.status = TaskStatus::Pending;
+        self.tasks.push(Queued(task));
+    }
+
+    // Put back a task below all pending tasks
+    pub fn push_back(&mut self, mut task: Task) {
+        task.status = TaskStatus::Pending;
+        if let Some(lowest) = self
+            .tasks
+            .iter()
+            .map(|queued| queued.0.priority)
+            .min_by(f64::total_cmp)
+        {
+            task.priority = task.priority.min(lowest - 1.0);
+        }
+        self.tasks.push(Queued(task));
+    }
+
+    // Remove and return the first `limit` tasks in priority order whose
+    // dependencies are complete. A task depending on one taken before it
+    // waits, so the taken tasks can run at the same time, as do failed tasks
+    // until their retry is due.
+    pub fn take_ready(&mut self, limit: usize) -> Vec<Task> {
+        let now = crate::task::unix_time();
+        let mut taken: Vec<Task> = Vec::new();
+        let mut waiting: Vec<Queued> = Vec::new();
+        while taken.len() < limit {
+            let Some(Queued(mut task)) = self.tasks.pop() else {
+                break;
+            };
+            let due = task.retry_at.is_none_or(|retry_at| retry_at <= now);
+            let ready = due
+                && task.depends_on.iter().all(|&dependency| {
+                    !self.is_pending(dependency)
+                        && !waiting.iter().any(|queued| queued.0.task_id == dependency)
+                        && !taken.iter().any(|t| t.task_id == dependency)
+                });
+            if ready {
+                task.status = TaskStatus::Running;
+                taken.push(task);
+            } else {
+                waiting.push(Queued(task));
+            }
+        }
+        self.tasks.extend(waiting);
+        taken
+    }
+
+    // Whether a task could be taken now
+    pub fn has_ready(&self) -> bool {
+        let now = crate::task::unix_time();
+        self.tasks.iter().any(|queued| {
+            queued.0.retry_at.is_none_or(|retry_at| retry_at <= now)
+                && queued.0.depends_on.iter().all(|&id| !self.is_pending(id))
+        })
+    }
+
+    // Priority of the first pending task
+    pub fn top_priority(&self) -> Option<f64> {
+        self.tasks.peek().map(|queued| queued.0.priority)
+    }
+
+    // When the first failed task is due for its retry
+    pub fn next_retry(&self) -> Option<u64> {
+        self.tasks
+            .iter()
+            .filter_map(|queued| queued.0.retry_at)
+            .min()
+    }
+
+    // Results of the completed tasks `task` depends on
+    pub fn dependency_results(&self, task: &Task) -> Vec<TaskResult> {
+        task.depends_on
+            .iter()
+            .filter_map(|id| self.results.get(id).cloned())
+            .collect()
+    }
+
+    // Record the result of a completed task, for the tasks created from it.
+    // Results of earlier tasks are dropped once no pending task depends on
+    // them anymore.
+    pub fn complete(&mut self, task: &Task, result: &str) {
+        let needed: HashSet<i32> = self
+            .tasks
+            .iter()
+            .flat_map(|queued| queued.0.depends_on.iter().copied())
+            .collect();
+        self.results.retain(|id, _| needed.contains(id));
+        self.results.insert(
+            task.task_id,
+            TaskResult {
+                task_name: task.task_name.clone(),
+                result: result.to_string(),
+            },
+        );
+    }
+
+    // Set the priorities of pending tasks by id. Tasks without a new
+    // priority keep theirs.
+    pub fn set_priorities(&mut self, priorities: &HashMap<i32, f64>) {
+        let mut tasks = std::mem::take(&mut self.tasks).into_vec();
+        for Queued(task) in &mut tasks {
+            if let Some(&priority) = priorities.get(&task.task_id) {
+                task.priority = priority;
+            }
+        }
+        self.tasks = tasks.into();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(task_id: i32, priority: f64, depends_on: &[i32]) -> Task {
+        Task {
+            priority,
+            depends_on: depends_on.to_vec(),
+            ..Task::new(task_id, format!("Task {}", task_id), None)
+        }
+    }
+
+    fn ids(tasks: &[Task]) -> Vec<i32> {
+        tasks.iter().map(|task| task.task_id).collect()
+    }
+
+    #[test]
+    fn hands_out_tasks_by_priority_then_age() {
+        let mut graph = TaskGraph::default();
+        graph.add(task(1, 1.0, &[]));
+        graph.add(task(2, 3.0, &[]));
+        graph.add(task(3, 1.0, &[]));
+        assert_eq!(ids(&graph.take_ready(3)), [2, 1, 3]);
+        assert!(graph.is_empty());
+    }
+
+    #[test]
+    fn waits_for_dependencies() {
+        let mut graph = TaskGraph::default();
+        graph.add(task(1, 0.0, &[]));
+        graph.add(task(2, 5.0, &[1]));
+        graph.add(task(3, 0.0, &[]));
+        // Task 2 comes first by priority, but needs task 1, which is taken
+        // in the same batch
+        let batch = graph.take_ready(3);
+        assert_eq!(ids(&batch), [1, 3]);
+        graph.complete(&batch[0], "one");
+        let batch = graph.take_ready(3);
+        assert_eq!(ids(&batch), [2]);
+        let results = graph.dependency_results(&batch[0]);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].result, "one");
+    }
+
+    #[test]
+    fn drops_dependencies_that_could_never_complete() {
+        let mut graph = TaskGraph::default();
+        graph.add(task(2, 0.0, &[]));
+        // 1 is neither pending nor completed, 5 comes later and 2 is repeated
+        graph.add(task(3, 0.0, &[5, 2, 1, 2]));
+        assert_eq!(graph.tasks()[1].depends_on, [2]);
+    }
+
+    #[test]
+    fn keeps_results_only_while_needed() {
+        let mut graph = TaskGraph::default();
+        graph.add(task(1, 0.0, &[]));
+        let first = graph.take_ready(1).remove(0);
+        graph.complete(&first, "one");
+        graph.add(task(2, 0.0, &[1]));
+        let second = graph.take_ready(1).remove(0);
+        assert_eq!(graph.dependency_results(&second).len(), 1);
+        graph.complete(&second, "two");
+        let (_, results) = graph.into_parts();
+        assert_eq!(results.keys().copied().collect::<Vec<_>>(), [2]);
+    }
+
+    #[test]
+    fn reorders_by_new_priorities() {
+        let mut graph = TaskGraph::default();
+        graph.add(task(1, 2.0, &[]));
+        graph.add(task(2, 1.0, &[]));
+        graph.add(task(3, 0.0, &[]));
+        graph.set_priorities(&HashMap::from([(3, 5.0), (1, 0.0)]));
+        let order: Vec<i32> = graph.tasks().iter().map(|task| task.task_id).collect();
+        assert_eq!(order, [3, 2, 1]);
+        graph.push_back(task(4, 9.0, &[]));
+        assert_eq!(graph.tasks().last().unwrap().task_id, 4);
+    }
+}
@@ -0,0 +1,32 @@
+// Hands out task ids. Ids only grow and none is handed out twice in a run,
+// also across `resume`, so that dependencies, stored results and logs
+// always refer to one task.
+#[derive(Debug, Clone, Default)]
+pub struct TaskIdAllocator {
+    last: i32,
+}
+
+impl TaskIdAllocator {
+    // Continue after `last`, or after the highest id `in_use` if a state
+    // file has tasks beyond its counter
+    pub fn new(last: i32, in_use: impl IntoIterator<Item = i32>) -> Self {
+        TaskIdAllocator {
+            last: in_use.into_iter().fold(last, i32::max),
+        }
+    }
+
+    pub fn next(&mut self) -> i32 {
+        self.last += 1;
+        self.last
+    }
+
+    // The id the next call to `next` returns
+    pub fn peek(&self) -> i32 {
+        self.last + 1
+    }
+
+    // The last id handed out, 0 before the first
+    pub fn last(&self) -> i32 {
+        self.last
+    }
+}
@@ -0,0 +1,74 @@
+// OpenTelemetry export of the tracing spans emitted by the agent loop.
+//
+// Only compiled in with `--features otel`. Spans are sent via OTLP/HTTP to the
+// endpoint given by the standard `OTEL_EXPORTER_OTLP_ENDPOINT` variable
+// (defaults to `http://localhost:4318`). A run is one trace: its iterations
+// contain the tasks, whose spans contain the agents, their memory queries and
+// API calls.
+
+#[cfg(feature = "otel")]
+mod otlp {
+    use opentelemetry::trace::TracerProvider;
+    use opentelemetry_otlp::SpanExporter;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use opentelemetry_sdk::Resource;
+    use std::env;
+    use tracing_subscriber::{Layer, Registry};
+
+    use super::OtelLayer;
+
+    // Flushes pending spans when dropped
+    pub struct TelemetryGuard {
+        provider: SdkTracerProvider,
+    }
+
+    impl Drop for TelemetryGuard {
+        fn drop(&mut self) {
+            if let Err(err) = self.provider.shutdown() {
+                eprintln!("Failed to flush OpenTelemetry spans: {}", err);
+            }
+        }
+    }
+
+    pub fn layer() -> (Option<OtelLayer>, Option<TelemetryGuard>) {
+        let exporter = match SpanExporter::builder().with_http().build() {
+            Ok(exporter) => exporter,
+            Err(err) => {
+                eprintln!("Failed to create OTLP exporter, tracing disabled: {}", err);
+                return (None, None);
+            }
+        };
+        // OTEL_SERVICE_NAME and OTEL_RESOURCE_ATTRIBUTES are read by the
+        // builder, the service is called crustagi unless OTEL_SERVICE_NAME
+        // names it
+        let resource = match env::var_os("OTEL_SERVICE_NAME") {
+            Some(_) => Resource::builder().build(),
+            None => Resource::builder().with_service_name("crustagi").build(),
+        };
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(resource)
+            .build();
+        let tracer = provider.tracer("crustagi");
+
+        let layer = tracing_opentelemetry::layer::<Registry>()
+            .with_tracer(tracer)
+            .boxed();
+        (Some(layer), Some(TelemetryGuard { provider }))
+    }
+}
+
+// Layer exporting the spans, added to the subscriber by `logging::init`
+pub type OtelLayer = Box<dyn tracing_subscriber::Layer<tracing_subscriber::Registry> + Send + Sync>;
+
+#[cfg(feature = "otel")]
+pub use otlp::{layer, TelemetryGuard};
+
+#[cfg(not(feature = "otel"))]
+pub struct TelemetryGuard;
+
+// No-op when the `otel` feature is disabled
+#[cfg(not(feature = "otel"))]
+pub fn layer() -> (Option<OtelLayer>, Option<TelemetryGuard>) {
+    (None, None)
+}
@@ -0,0 +1,62 @@
+use tiktoken_rs::{bpe_for_model, cl100k_base_singleton, model::get_context_size, CoreBPE};
+
+// Context window assumed for models tiktoken doesn't know, e.g. Gemini,
+// Cohere or local models
+const DEFAULT_CONTEXT_WINDOW: usize = 8192;
+
+// Tokens kept free for the answer when the provider has no max_tokens set
+pub const DEFAULT_ANSWER_TOKENS: usize = 2000;
+
+// Tokenizer of an OpenAI model. Other models are counted with cl100k_base,
+// which is close enough for budgeting.
+fn bpe(model: &str) -> &'static CoreBPE {
+    bpe_for_model(model).unwrap_or_else(|_| cl100k_base_singleton())
+}
+
+// Number of tokens `text` takes up for `model`
+pub fn count_tokens(model: &str, text: &str) -> usize {
+    bpe(model).encode_with_special_tokens(text).len()
+}
+
+// The first `max_tokens` tokens of `text`
+pub fn truncate(model: &str, text: &str, max_tokens: usize) -> String {
+    let bpe = bpe(model);
+    let tokens = bpe.encode_with_special_tokens(text);
+    if tokens.len() <= max_tokens {
+        return text.to_string();
+    }
+    bpe.decode(&tokens[..max_tokens]).unwrap_or_default()
+}
+
+// `text` split into pieces of at most `size` tokens, each starting with the
+// last `overlap` tokens of the one before. A character cut at a piece's
+// edge is replaced with U+FFFD.
+pub fn split(model: &str, text: &str, size: usize, overlap: usize) -> Vec<String> {
+    let bpe = bpe(model);
+    let tokens = bpe.encode_with_special_tokens(text);
+    let size = size.max(1);
+    let step = size.saturating_sub(overlap).max(1);
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    loop {
+        let end = (start + size).min(tokens.len());
+        let bytes = bpe.decode_bytes(&tokens[start..end]).unwrap_or_default();
+        pieces.push(String::from_utf8_lossy(&bytes).into_owned());
+        if end == tokens.len() {
+            return pieces;
+        }
+        start += step;
+    }
+}
+
+// Maximum number of tokens in a prompt and its answer for `model`, unless
+// `context_window` overrides it
+pub fn context_window(model: &str, context_window: Option<usize>) -> usize {
+    context_window.unwrap_or_else(|| get_context_size(model).unwrap_or(DEFAULT_CONTEXT_WINDOW))
+}
+
+// Tokens available for a prompt, leaving room for an answer of `max_tokens`
+pub fn prompt_budget(model: &str, context_window: Option<usize>, max_tokens: Option<u32>) -> usize {
+    let answer = max_tokens.map_or(DEFAULT_ANSWER_TOKENS, |tokens| tokens as usize);
+    self::context_window(model, context_window).saturating_sub(answer)
+}
@@ -0,0 +1,77 @@
+// Tools the execution agent may call while working on a task, in the manner
+// of ReAct: the model calls tools, reads their outputs and calls more until
+// it has its answer
+
+use async_trait::async_trait;
+use serde_json::Value;
+use std::sync::Arc;
+
+use crate::error::CrustagiError;
+use crate::llm::{ToolCall, ToolSpec};
+
+// Names of the tools built into CrustAGI, which plugins can't take
+pub const BUILT_IN_TOOLS: &[&str] = &[
+    "web_search",
+    "web_fetch",
+    "read_file",
+    "write_file",
+    "list_files",
+];
+
+/// A function the execution agent offers the model. Only providers with
+/// function calling use tools, currently the OpenAI chat models.
+#[async_trait]
+pub trait Tool: Send + Sync {
+    /// Name the model calls the tool by, unique among the tools of a run
+    fn name(&self) -> &str;
+
+    /// What the tool does and when to use it, for the model
+    fn description(&self) -> &str;
+
+    /// JSON schema of the arguments, an object
+    fn parameters(&self) -> Value;
+
+    /// Run the tool with the arguments the model passed, which may not match
+    /// the schema. The output is shown to the model, and so is an error, for
+    /// the model to try again or do without.
+    async fn execute(&self, arguments: Value) -> Result<String, CrustagiError>;
+
+    /// The tool as offered to the model
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: self.name().to_string(),
+            description: self.description().to_string(),
+            parameters: self.parameters(),
+        }
+    }
+}
+
+// Run the tool `call` asks for and return what the model is shown of it.
+// Unknown tools, arguments that aren't JSON and failures are described to
+// the model rather than failing the task.
+pub async fn call(tools: &[Arc<dyn Tool>], call: &ToolCall) -> String {
+    let Some(tool) = tools.iter().find(|tool| tool.name() == call.name) else {
+        tracing::warn!("The model called the unknown tool {}", call.name);
+        return format!("Error: there is no tool named {}", call.name);
+    };
+    // Some models send no arguments at all for tools without parameters
+    let arguments = match call.arguments.trim() {
+        "" => Ok(Value::Object(Default::default())),
+        arguments => serde_json::from_str(arguments),
+    };
+    let arguments = match arguments {
+        Ok(arguments) => arguments,
+        Err(err) => {
+            tracing::warn!("Invalid arguments for the tool {}: {}", call.name, err);
+            return format!("Error: the arguments are not valid JSON: {}", err);
+        }
+    };
+    tracing::info!("Calling tool {} with {}", call.name, arguments);
+    match tool.execute(arguments).await {
+        Ok(output) => output,
+        Err(err) => {
+            tracing::warn!("The tool {} failed: {}", call.name, err);
+            format!("Error: {}", err)
+        }
+    }
+}
@@ -0,0 +1,398 @@
+// Live dashboard of a run, shown instead of the scrolling output with
+// `TUI=true` or `--tui`. It lists the task list, streams the output of the
+// tasks being executed and shows the recent results, the token and cost
+// counters, the memory hit rate and the log. Tasks typed into it join the
+// task list as they do on the console.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Position, Rect};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use tokio::sync::mpsc;
+
+use crate::logging;
+use crate::shutdown;
+use crate::usage::UsageTracker;
+
+// Results and log lines kept for the dashboard
+const RECENT_RESULTS: usize = 50;
+const LOG_LINES: usize = 500;
+// How often the dashboard is redrawn and checked for key presses
+const REFRESH: Duration = Duration::from_millis(100);
+
+// The dashboard on screen. The log goes to it, since the tracing subscriber
+// and the terminal belong to the process rather than to a run.
+static SHOWN: Mutex<Option<Dashboard>> = Mutex::new(None);
+
+/// The dashboard of a run, shown with `TUI=true` while the run loop runs.
+/// The engine and the agents of the run update it through their clones.
+#[derive(Clone, Default)]
+pub struct Dashboard(Arc<Mutex<State>>);
+
+#[derive(Default)]
+struct State {
+    objective: String,
+    tasks: Vec<String>,
+    // Time and cost the tasks left will take
+    estimate: String,
+    running: BTreeMap<i32, Running>,
+    results: VecDeque<String>,
+    log: VecDeque<String>,
+    // Context lookups, and those that found stored results
+    lookups: usize,
+    hits: usize,
+    input: String,
+    inbox: Option<mpsc::UnboundedSender<String>>,
+}
+
+struct Running {
+    task_name: String,
+    output: String,
+}
+
+// Shown while alive, the terminal is restored when dropped
+pub struct Tui {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        ratatui::restore();
+        *SHOWN.lock().unwrap() = None;
+        logging::pause_colors(false);
+    }
+}
+
+// Show `dashboard`, unless the output isn't a terminal
+pub fn start(dashboard: &Dashboard, usage: Arc<UsageTracker>) -> Option<Tui> {
+    if logging::is_json() || !io::stdout().is_terminal() {
+        tracing::warn!(
+            "The TUI needs a terminal and LOG_FORMAT other than json, printing the output instead"
+        );
+        return None;
+    }
+    let terminal = match ratatui::try_init() {
+        Ok(terminal) => terminal,
+        Err(err) => {
+            tracing::warn!(
+                "Failed to start the TUI, printing the output instead: {}",
+                err
+            );
+            return None;
+        }
+    };
+    logging::pause_colors(true);
+    *SHOWN.lock().unwrap() = Some(dashboard.clone());
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread = thread::spawn({
+        let dashboard = dashboard.clone();
+        let stop = stop.clone();
+        move || run(terminal, &dashboard, &usage, &stop)
+    });
+    Some(Tui {
+        stop,
+        thread: Some(thread),
+    })
+}
+
+// Whether a dashboard is on screen, which nothing else may print to
+pub fn is_active() -> bool {
+    SHOWN.lock().unwrap().is_some()
+}
+
+impl Dashboard {
+    // Tasks typed into the dashboard, replacing `inbox::listen` while it is
+    // shown
+    pub fn inbox(&self) -> mpsc::UnboundedReceiver<String> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.0.lock().unwrap().inbox = Some(sender);
+        receiver
+    }
+
+    fn update(&self, change: impl FnOnce(&mut State)) {
+        change(&mut self.0.lock().unwrap());
+    }
+
+    // The task list of the objective worked on, one line per task
+    pub fn set_tasks(&self, objective: &str, tasks: Vec<String>) {
+        self.update(|state| {
+            state.objective = objective.to_string();
+            state.tasks = tasks;
+        });
+    }
+
+    pub fn set_estimate(&self, estimate: String) {
+        self.update(|state| state.estimate = estimate);
+    }
+
+    pub fn start_task(&self, task_id: i32, task_name: &str) {
+        self.update(|state| {
+            let running = Running {
+                task_name: task_name.to_string(),
+                output: String::new(),
+            };
+            state.running.insert(task_id, running);
+        });
+    }
+
+    // Part of a task's result, as it is streamed
+    pub fn output(&self, task_id: i32, chunk: &str) {
+        self.update(|state| {
+            if let Some(running) = state.running.get_mut(&task_id) {
+                running.output.push_str(chunk);
+            }
+        });
+    }
+
+    // A task that is done, with its result, or None if it failed
+    pub fn finish_task(&self, task_id: i32, result: Option<&str>) {
+        self.update(|state| {
+            let Some(running) = state.running.remove(&task_id) else {
+                return;
+            };
+            let outcome = match result {
+                Some(result) => result.lines().find(|line| !line.trim().is_empty()),
+                None => Some("(failed, see the log)"),
+            };
+            let line = format!(
+                "{}: {} - {}",
+                task_id,
+                running.task_name,
+                outcome.unwrap_or_default().trim()
+            );
+            push_limited(&mut state.results, line, RECENT_RESULTS);
+        });
+    }
+
+    // A lookup of context in memory, and whether it found stored results
+    pub fn memory_lookup(&self, found: bool) {
+        self.update(|state| {
+            state.lookups += 1;
+            state.hits += usize::from(found);
+        });
+    }
+}
+
+fn push_limited(lines: &mut VecDeque<String>, line: String, limit: usize) {
+    if lines.len() == limit {
+        lines.pop_front();
+    }
+    lines.push_back(line);
+}
+
+// Writer of the log events shown in the dashboard. The formatted event is
+// added once it is complete, when the writer is dropped.
+#[derive(Default)]
+pub struct LogWriter(Vec<u8>);
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for LogWriter {
+    fn drop(&mut self) {
+        let Some(dashboard) = SHOWN.lock().unwrap().clone() else {
+            return;
+        };
+        let text = String::from_utf8_lossy(&self.0);
+        let mut state = dashboard.0.lock().unwrap();
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            push_limited(&mut state.log, line.to_string(), LOG_LINES);
+        }
+    }
+}
+
+fn run(
+    mut terminal: DefaultTerminal,
+    dashboard: &Dashboard,
+    usage: &UsageTracker,
+    stop: &AtomicBool,
+) {
+    while !stop.load(Ordering::Relaxed) {
+        if terminal
+            .draw(|frame| draw(frame, dashboard, usage))
+            .is_err()
+        {
+            break;
+        }
+        match event::poll(REFRESH) {
+            Ok(true) => match event::read() {
+                Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                    handle_key(dashboard, key)
+                }
+                Ok(_) => {}
+                Err(_) => break,
+            },
+            Ok(false) => {}
+            Err(_) => break,
+        }
+    }
+}
+
+fn handle_key(dashboard: &Dashboard, key: KeyEvent) {
+    let mut dashboard = dashboard.0.lock().unwrap();
+    match key.code {
+        // Raw mode turns Ctrl+C into a key press instead of SIGINT
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => shutdown::request(),
+        KeyCode::Char(c) => dashboard.input.push(c),
+        KeyCode::Backspace => {
+            dashboard.input.pop();
+        }
+        KeyCode::Esc => dashboard.input.clear(),
+        KeyCode::Enter => {
+            let task_name = dashboard.input.trim().to_string();
+            dashboard.input.clear();
+            if let (false, Some(inbox)) = (task_name.is_empty(), &dashboard.inbox) {
+                let _ = inbox.send(task_name);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn draw(frame: &mut Frame, dashboard: &Dashboard, usage: &UsageTracker) {
+    let dashboard = dashboard.0.lock().unwrap();
+    let [header, middle, results, log, input] = Layout::vertical([
+        Constraint::Length(5),
+        Constraint::Min(8),
+        Constraint::Length(8),
+        Constraint::Length(8),
+        Constraint::Length(3),
+    ])
+    .areas(frame.area());
+    let [tasks, running] =
+        Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)]).areas(middle);
+
+    let hit_rate = match dashboard.lookups {
+        0 => "-".to_string(),
+        lookups => format!(
+            "{:.0}% of {} lookups",
+            dashboard.hits as f64 * 100.0 / lookups as f64,
+            lookups
+        ),
+    };
+    let counters = format!(
+        "Tokens: {}   Cost: ${:.4}   Memory hit rate: {}",
+        usage.total_tokens(),
+        usage.total_cost(),
+        hit_rate
+    );
+    frame.render_widget(
+        Paragraph::new(vec![
+            Line::from(format!("Objective: {}", dashboard.objective)),
+            Line::from(counters),
+            Line::from(format!("Estimate: {}", dashboard.estimate)),
+        ])
+        .block(Block::bordered().title(" CrustAGI ".bold())),
+        header,
+    );
+
+    let title = format!(" Task list ({}) ", dashboard.tasks.len());
+    let task_lines = dashboard.tasks.iter().map(|task| Line::from(task.as_str()));
+    frame.render_widget(
+        Paragraph::new(task_lines.collect::<Vec<_>>()).block(titled(title, Color::Cyan)),
+        tasks,
+    );
+
+    if dashboard.running.is_empty() {
+        frame.render_widget(
+            Paragraph::new("Waiting for the next task...")
+                .block(titled(" Executing ", Color::Green)),
+            running,
+        );
+    } else {
+        let areas = Layout::vertical(
+            dashboard
+                .running
+                .keys()
+                .map(|_| Constraint::Ratio(1, dashboard.running.len() as u32)),
+        )
+        .split(running);
+        for ((task_id, task), &area) in dashboard.running.iter().zip(areas.iter()) {
+            let title = format!(" Executing task {}: {} ", task_id, task.task_name);
+            frame.render_widget(
+                Paragraph::new(last_lines(task.output.lines(), area))
+                    .block(titled(title, Color::Green)),
+                area,
+            );
+        }
+    }
+
+    let result_lines = dashboard
+        .results
+        .iter()
+        .rev()
+        .map(|result| Line::from(result.as_str()));
+    frame.render_widget(
+        Paragraph::new(result_lines.collect::<Vec<_>>())
+            .block(titled(" Recent results ", Color::Yellow)),
+        results,
+    );
+
+    let log_lines = dashboard.log.iter().map(String::as_str);
+    frame.render_widget(
+        Paragraph::new(last_lines(log_lines, log)).block(Block::bordered().title(" Log ")),
+        log,
+    );
+
+    frame.render_widget(
+        Paragraph::new(format!("> {}", dashboard.input)).block(
+            Block::bordered()
+                .title(" Add a task with Enter, stop after the task in progress with Ctrl+C "),
+        ),
+        input,
+    );
+    let cursor = input.x + 3 + dashboard.input.chars().count() as u16;
+    frame.set_cursor_position(Position::new(
+        cursor.min(input.right().saturating_sub(2)),
+        input.y + 1,
+    ));
+}
+
+fn titled(title: impl Into<String>, color: Color) -> Block<'static> {
+    Block::bordered()
+        .title(title.into())
+        .border_style(Style::default().fg(color))
+}
+
+// The last lines of `lines` that fit into the bordered `area`, wrapped at
+// its width, so that streamed output and the log follow what was added last
+fn last_lines<'a>(lines: impl Iterator<Item = &'a str>, area: Rect) -> Vec<Line<'a>> {
+    let width = area.width.saturating_sub(2).max(1) as usize;
+    let height = area.height.saturating_sub(2) as usize;
+    let mut wrapped: Vec<Line> = Vec::new();
+    for line in lines {
+        let chars: Vec<(usize, char)> = line.char_indices().collect();
+        if chars.is_empty() {
+            wrapped.push(Line::from(""));
+        }
+        for chunk in chars.chunks(width) {
+            let start = chunk[0].0;
+            let end = chunk.last().map_or(start, |(i, c)| i + c.len_utf8());
+            wrapped.push(Line::from(&line[start..end]));
+        }
+    }
+    let skip = wrapped.len().saturating_sub(height);
+    wrapped.split_off(skip)
+}
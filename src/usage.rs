@@ -0,0 +1,279 @@
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use colored::Color;
+
+use crate::error::CrustagiError;
+use crate::logging;
+
+// USD per million prompt and completion tokens, matched by the longest model
+// name prefix so that dated snapshots like `gpt-4o-2024-08-06` are covered
+const PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-4.1", 2.00, 8.00),
+    ("gpt-4.1-mini", 0.40, 1.60),
+    ("gpt-4.1-nano", 0.10, 0.40),
+    ("gpt-4o", 2.50, 10.00),
+    ("gpt-4o-mini", 0.15, 0.60),
+    ("gpt-4-turbo", 10.00, 30.00),
+    ("gpt-4", 30.00, 60.00),
+    ("gpt-3.5-turbo", 0.50, 1.50),
+    ("gpt-3.5-turbo-instruct", 1.50, 2.00),
+    ("text-embedding-ada-002", 0.10, 0.0),
+    ("text-embedding-3-small", 0.02, 0.0),
+    ("text-embedding-3-large", 0.13, 0.0),
+    ("gemini-2.0-flash", 0.10, 0.40),
+    ("gemini-1.5-flash", 0.075, 0.30),
+    ("gemini-1.5-pro", 1.25, 5.00),
+    ("command-r-plus", 2.50, 10.00),
+    ("command-r", 0.15, 0.60),
+    ("embed-english-v3.0", 0.10, 0.0),
+    ("embed-multilingual-v3.0", 0.10, 0.0),
+];
+
+/// Entry of `MODEL_PRICES`: `model=prompt/completion`, in USD per million
+/// tokens, matched by model name prefix like the built-in prices
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelPrice {
+    pub model: String,
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+/// Parse `MODEL_PRICES`, a comma-separated list of `model=prompt/completion`
+pub fn parse_prices(value: &str) -> Result<Vec<ModelPrice>, CrustagiError> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let invalid = || {
+                CrustagiError::Config(format!(
+                    "MODEL_PRICES entry \"{}\" is invalid, expected model=prompt/completion \
+                     in USD per million tokens, e.g. llama3=0.2/0.2",
+                    entry
+                ))
+            };
+            let (model, prices) = entry.split_once('=').ok_or_else(invalid)?;
+            let (prompt, completion) = prices.split_once('/').ok_or_else(invalid)?;
+            let price = |price: &str| {
+                price
+                    .trim()
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|price| *price >= 0.0)
+                    .ok_or_else(invalid)
+            };
+            let model = model.trim();
+            if model.is_empty() {
+                return Err(invalid());
+            }
+            Ok(ModelPrice {
+                model: model.to_string(),
+                prompt: price(prompt)?,
+                completion: price(completion)?,
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokenUsage {
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+}
+
+impl TokenUsage {
+    pub fn total_tokens(&self) -> usize {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    // Cost in USD at the prompt and completion `price`
+    fn cost(&self, (prompt, completion): (f64, f64)) -> f64 {
+        (self.prompt_tokens as f64 * prompt + self.completion_tokens as f64 * completion)
+            / 1_000_000.0
+    }
+}
+
+// Token usage and cost of one agent with one model
+#[derive(Debug, Serialize)]
+pub struct AgentUsage {
+    pub agent: String,
+    pub model: String,
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    // None for models without a known price
+    pub cost_usd: Option<f64>,
+}
+
+// Token usage of a run, per agent and model
+#[derive(Default)]
+pub struct UsageTracker {
+    totals: Mutex<BTreeMap<(String, String), TokenUsage>>,
+    // Prices set with MODEL_PRICES, which take precedence over the table
+    prices: Vec<ModelPrice>,
+}
+
+impl UsageTracker {
+    // A tracker costing the models `prices` match at those prices instead of
+    // the built-in table
+    pub fn with_prices(prices: Vec<ModelPrice>) -> Self {
+        UsageTracker {
+            prices,
+            ..UsageTracker::default()
+        }
+    }
+
+    // Prompt and completion price of a model, None for models neither in
+    // MODEL_PRICES nor in the table
+    fn price(&self, model: &str) -> Option<(f64, f64)> {
+        let overridden = self
+            .prices
+            .iter()
+            .filter(|price| model.starts_with(&price.model))
+            .max_by_key(|price| price.model.len())
+            .map(|price| (price.prompt, price.completion));
+        overridden.or_else(|| {
+            PRICES
+                .iter()
+                .filter(|(prefix, _, _)| model.starts_with(prefix))
+                .max_by_key(|(prefix, _, _)| prefix.len())
+                .map(|(_, prompt, completion)| (*prompt, *completion))
+        })
+    }
+
+    // Whether the cost of `model` is known, as MAX_COST_USD counts models
+    // without a price as free
+    pub fn has_price(&self, model: &str) -> bool {
+        self.price(model).is_some()
+    }
+
+    // Cost in USD, 0 for models without a known price
+    fn cost(&self, model: &str, usage: &TokenUsage) -> f64 {
+        self.price(model).map_or(0.0, |price| usage.cost(price))
+    }
+
+    pub fn record(&self, agent: &str, model: &str, usage: TokenUsage) {
+        let mut totals = self.totals.lock().unwrap();
+        let total = totals
+            .entry((agent.to_string(), model.to_string()))
+            .or_default();
+        total.prompt_tokens += usage.prompt_tokens;
+        total.completion_tokens += usage.completion_tokens;
+    }
+
+    pub fn total_tokens(&self) -> usize {
+        let totals = self.totals.lock().unwrap();
+        totals.values().map(TokenUsage::total_tokens).sum()
+    }
+
+    pub fn total_cost(&self) -> f64 {
+        let totals = self.totals.lock().unwrap();
+        totals
+            .iter()
+            .map(|((_, model), usage)| self.cost(model, usage))
+            .sum()
+    }
+
+    pub fn print_summary(&self) {
+        tracing::info!(
+            "\n{}\n{}",
+            logging::banner("COST SUMMARY", Color::Magenta),
+            self.summary().join("\n")
+        );
+    }
+
+    pub fn agents(&self) -> Vec<AgentUsage> {
+        let totals = self.totals.lock().unwrap();
+        totals
+            .iter()
+            .map(|((agent, model), usage)| AgentUsage {
+                agent: agent.clone(),
+                model: model.clone(),
+                prompt_tokens: usage.prompt_tokens,
+                completion_tokens: usage.completion_tokens,
+                cost_usd: self.price(model).map(|price| usage.cost(price)),
+            })
+            .collect()
+    }
+
+    // Usage and cost per agent and model, followed by the total
+    pub fn summary(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .agents()
+            .into_iter()
+            .map(|usage| {
+                let cost = match usage.cost_usd {
+                    Some(cost) => format!("${:.4}", cost),
+                    None => "unknown price".to_string(),
+                };
+                format!(
+                    "{} ({}): {} prompt + {} completion tokens, {}",
+                    usage.agent, usage.model, usage.prompt_tokens, usage.completion_tokens, cost
+                )
+            })
+            .collect();
+        lines.push(format!(
+            "Total: {} tokens, ${:.4}",
+            self.total_tokens(),
+            self.total_cost()
+        ));
+        lines
+    }
+}
+
+tokio::task_local! {
+    // Usage the providers reported during the current call through a
+    // CostLayer, by model
+    static REPORTED: RefCell<Vec<(String, TokenUsage)>>;
+}
+
+/// Report the tokens an API request used, as the API counted them. The
+/// `CostLayer` the call goes through records them, a call without one drops
+/// them.
+pub fn report(model: &str, prompt_tokens: usize, completion_tokens: usize) {
+    let usage = TokenUsage {
+        prompt_tokens,
+        completion_tokens,
+    };
+    let _ = REPORTED.try_with(|reported| reported.borrow_mut().push((model.to_string(), usage)));
+}
+
+// Run `call`, returning its output with the usage reported while it ran
+pub async fn collect<F: Future>(call: F) -> (F::Output, Vec<(String, TokenUsage)>) {
+    REPORTED
+        .scope(RefCell::new(Vec::new()), async move {
+            let output = call.await;
+            (output, REPORTED.with(RefCell::take))
+        })
+        .await
+}
+
+// Records the token usage of one agent's API calls
+#[derive(Clone)]
+pub struct UsageMeter {
+    tracker: Arc<UsageTracker>,
+    agent: String,
+}
+
+impl UsageMeter {
+    pub fn new(tracker: Arc<UsageTracker>, agent: &str) -> Self {
+        UsageMeter {
+            tracker,
+            agent: agent.to_string(),
+        }
+    }
+
+    pub fn record(&self, model: &str, prompt_tokens: usize, completion_tokens: usize) {
+        self.tracker.record(
+            &self.agent,
+            model,
+            TokenUsage {
+                prompt_tokens,
+                completion_tokens,
+            },
+        );
+    }
+}
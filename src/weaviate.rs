@@ -0,0 +1,257 @@
+use async_trait::async_trait;
+use reqwest::{Client, Method, RequestBuilder, StatusCode};
+use serde_json::{json, Value};
+
+use crate::error::CrustagiError;
+use crate::memory::{hash_id, Match, Metadata, StoredVector, VectorMetadata, VectorStore};
+
+// Property holding the caller's string id, since Weaviate object ids are UUIDs
+const ID_PROPERTY: &str = "resultId";
+
+// Objects fetched per listing request when exporting
+const LIST_PAGE_SIZE: usize = 100;
+
+// Weaviate class used as a `VectorStore` via the REST and GraphQL APIs
+pub struct WeaviateStore {
+    pub url: String,
+    pub api_key: Option<String>,
+    pub class_name: String,
+}
+
+impl WeaviateStore {
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let url = format!("{}/v1{}", self.url.trim_end_matches('/'), path);
+        let request = Client::new().request(method, url);
+        match &self.api_key {
+            Some(api_key) => request.bearer_auth(api_key),
+            None => request,
+        }
+    }
+}
+
+#[async_trait]
+impl VectorStore for WeaviateStore {
+    #[tracing::instrument(skip_all, fields(class = %self.class_name))]
+    async fn create(&self) -> Result<(), CrustagiError> {
+        let res = self
+            .request(Method::GET, &format!("/schema/{}", self.class_name))
+            .send()
+            .await?;
+        if res.status() != StatusCode::NOT_FOUND {
+            res.error_for_status()?;
+            return Ok(());
+        }
+
+        tracing::info!("Creating Weaviate class {}...", self.class_name);
+        // Vectors are supplied by us, so disable Weaviate's own vectorizer
+        let body = json!({
+            "class": self.class_name,
+            "vectorizer": "none",
+            "vectorIndexConfig": { "distance": "cosine" },
+            "properties": [
+                { "name": ID_PROPERTY, "dataType": ["text"] },
+                { "name": "task", "dataType": ["text"] },
+                { "name": "result", "dataType": ["text"] },
+                { "name": "task_id", "dataType": ["int"] },
+                { "name": "objective", "dataType": ["text"] },
+                { "name": "chunk", "dataType": ["int"] }
+            ]
+        });
+        self.request(Method::POST, "/schema")
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all, fields(class = %self.class_name, id))]
+    async fn upsert(
+        &self,
+        id: &str,
+        vector: &[f64],
+        metadata: &VectorMetadata,
+    ) -> Result<usize, CrustagiError> {
+        let mut properties = metadata.to_map();
+        properties.insert(ID_PROPERTY.to_string(), json!(id));
+        // The batch endpoint replaces objects with an existing id
+        let body = json!({
+            "objects": [{
+                "class": self.class_name,
+                "id": object_uuid(id),
+                "vector": vector,
+                "properties": properties
+            }]
+        });
+
+        tracing::debug!("Storing to Weaviate...");
+
+        self.request(Method::POST, "/batch/objects")
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(1)
+    }
+
+    #[tracing::instrument(skip_all, fields(class = %self.class_name, top_k))]
+    async fn query(
+        &self,
+        vector: &[f64],
+        top_k: usize,
+        filter: Option<&Metadata>,
+    ) -> Result<Vec<Match>, CrustagiError> {
+        let vector = serde_json::to_string(vector)?;
+        let filter = match filter {
+            Some(filter) => format!(", where: {}", where_filter(filter)),
+            None => String::new(),
+        };
+        let query = format!(
+            "{{ Get {{ {}(nearVector: {{vector: {}}}, limit: {}{}) {{ {} task result task_id objective _additional {{ distance }} }} }} }}",
+            self.class_name, vector, top_k, filter, ID_PROPERTY
+        );
+
+        tracing::debug!("Querying Weaviate...");
+
+        let res: Value = self
+            .request(Method::POST, "/graphql")
+            .json(&json!({ "query": query }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if let Some(errors) = res.get("errors") {
+            return Err(format!("Weaviate query failed: {}", errors).into());
+        }
+
+        let objects = res["data"]["Get"][&self.class_name]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        Ok(objects
+            .into_iter()
+            .filter_map(|object| {
+                let Value::Object(mut properties) = object else {
+                    return None;
+                };
+                let additional = properties.remove("_additional")?;
+                let id = properties.remove(ID_PROPERTY)?.as_str()?.to_string();
+                // Cosine distance is 1 - similarity
+                let score = 1.0 - additional["distance"].as_f64()?;
+                Some(Match {
+                    id,
+                    score,
+                    metadata: VectorMetadata::from_map(properties.into_iter().collect()),
+                })
+            })
+            .collect())
+    }
+
+    #[tracing::instrument(skip_all, fields(class = %self.class_name))]
+    async fn delete(&self, ids: &[String]) -> Result<(), CrustagiError> {
+        for id in ids {
+            let path = format!("/objects/{}/{}", self.class_name, object_uuid(id));
+            let res = self.request(Method::DELETE, &path).send().await?;
+            if res.status() != StatusCode::NOT_FOUND {
+                res.error_for_status()?;
+            }
+        }
+        Ok(())
+    }
+
+    // Pages through the class with a cursor on the object UUID
+    #[tracing::instrument(skip_all, fields(class = %self.class_name))]
+    async fn dump(&self) -> Result<Vec<StoredVector>, CrustagiError> {
+        let mut vectors = Vec::new();
+        let mut after: Option<String> = None;
+        loop {
+            let mut path = format!(
+                "/objects?class={}&include=vector&limit={}",
+                self.class_name, LIST_PAGE_SIZE
+            );
+            if let Some(after) = &after {
+                path.push_str(&format!("&after={}", after));
+            }
+            let res: Value = self
+                .request(Method::GET, &path)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            let objects = res["objects"].as_array().cloned().unwrap_or_default();
+            let count = objects.len();
+            for mut object in objects {
+                after = object["id"].as_str().map(|id| id.to_string());
+                let vector: Vec<f64> = serde_json::from_value(object["vector"].take())?;
+                let Value::Object(mut properties) = object["properties"].take() else {
+                    continue;
+                };
+                let Some(id) = properties
+                    .remove(ID_PROPERTY)
+                    .and_then(|id| id.as_str().map(|id| id.to_string()))
+                else {
+                    continue;
+                };
+                vectors.push(StoredVector {
+                    id,
+                    vector,
+                    metadata: VectorMetadata::from_map(properties.into_iter().collect()),
+                });
+            }
+            if count < LIST_PAGE_SIZE {
+                return Ok(vectors);
+            }
+        }
+    }
+
+    #[tracing::instrument(skip_all, fields(class = %self.class_name))]
+    async fn reset(&self) -> Result<(), CrustagiError> {
+        tracing::info!("Deleting Weaviate class {}...", self.class_name);
+        let res = self
+            .request(Method::DELETE, &format!("/schema/{}", self.class_name))
+            .send()
+            .await?;
+        if res.status() != StatusCode::NOT_FOUND {
+            res.error_for_status()?;
+        }
+        self.create().await
+    }
+}
+
+// GraphQL `where` argument requiring every field to equal its value
+fn where_filter(filter: &Metadata) -> String {
+    let operands: Vec<String> = filter
+        .iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::Number(n) if n.is_i64() => format!("valueInt: {}", n),
+                Value::Number(n) => format!("valueNumber: {}", n),
+                Value::Bool(b) => format!("valueBoolean: {}", b),
+                Value::String(s) => format!("valueText: {}", Value::from(s.as_str())),
+                other => format!("valueText: {}", Value::from(other.to_string())),
+            };
+            format!(
+                "{{path: [{}], operator: Equal, {}}}",
+                Value::from(key.as_str()),
+                value
+            )
+        })
+        .collect();
+    format!("{{operator: And, operands: [{}]}}", operands.join(", "))
+}
+
+// Deterministic UUID-formatted id derived from the string id
+fn object_uuid(id: &str) -> String {
+    let high = hash_id(id);
+    let low = hash_id(&format!("{}#", id));
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        high >> 32,
+        (high >> 16) & 0xffff,
+        high & 0xffff,
+        low >> 48,
+        low & 0xffff_ffff_ffff
+    )
+}
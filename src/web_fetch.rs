@@ -0,0 +1,408 @@
+// Reading web pages for the execution agent: the readable text of a page,
+// cut into parts of which the model is shown those relevant to what it
+// looks for
+
+use async_trait::async_trait;
+use regex::{Captures, Regex};
+use reqwest::{Client, Url};
+use serde_json::{json, Value};
+use std::net::IpAddr;
+use std::sync::{Arc, LazyLock};
+
+use crate::error::CrustagiError;
+use crate::llm::LlmProvider;
+use crate::memory::cosine_similarity;
+use crate::tokens;
+use crate::tools::Tool;
+
+// Pages larger than this aren't read
+const MAX_PAGE_BYTES: usize = 5_000_000;
+// Redirects followed before giving up, as many as reqwest follows
+const MAX_REDIRECTS: usize = 10;
+
+// Elements that aren't the content of a page, removed with what they hold
+const BOILERPLATE: &[&str] = &[
+    "head", "script", "style", "noscript", "template", "svg", "iframe", "nav", "header", "footer",
+    "aside", "form", "button", "select",
+];
+
+static TITLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<title\b[^>]*>(.*?)</title\s*>").unwrap());
+static COMMENTS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)<!--.*?-->").unwrap());
+static BOILERPLATE_ELEMENTS: LazyLock<Vec<Regex>> = LazyLock::new(|| {
+    BOILERPLATE
+        .iter()
+        .map(|tag| Regex::new(&format!(r"(?is)<{0}\b[^>]*>.*?</{0}\s*>", tag)).unwrap())
+        .collect()
+});
+// The main content, if the page marks it
+static MAIN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<(?:article|main)\b[^>]*>(.*)</(?:article|main)\s*>").unwrap()
+});
+static LIST_ITEMS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)<li\b[^>]*>").unwrap());
+// Tags starting or ending a block of text
+static BLOCKS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)</?(?:p|div|section|article|main|br|hr|ul|ol|dl|dt|dd|h[1-6]|table|tr|td|th|blockquote|pre|figure|figcaption)\b[^>]*>",
+    )
+    .unwrap()
+});
+static LINKS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<a\b[^>]*>(.*?)</a\s*>").unwrap());
+static TAGS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]*>").unwrap());
+static ENTITIES: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"&(#[xX][0-9a-fA-F]+|#[0-9]+|[a-zA-Z]+);").unwrap());
+static WHITESPACE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s+").unwrap());
+
+/// The `web_fetch` tool: reads a web page and shows the model its text. A
+/// page longer than `chunk_tokens` is cut into parts, and the model is shown
+/// the `chunks` parts closest to its query as embedded by `llm`.
+pub struct WebFetch {
+    /// Not following redirects, which `fetch` follows itself to check
+    /// where each one leads
+    pub http: Client,
+    pub llm: Arc<dyn LlmProvider>,
+    pub chunk_tokens: usize,
+    pub chunks: usize,
+    /// Whether pages on loopback, private, link-local and unspecified
+    /// addresses may be read, which would let a page or the model reach
+    /// the services of the local network
+    pub allow_private: bool,
+}
+
+impl WebFetch {
+    /// The title and readable text of the page at `url`. Pages other than
+    /// HTML are read as they are if they are text.
+    pub async fn fetch(&self, url: &str) -> Result<(Option<String>, String), CrustagiError> {
+        let mut url = Url::parse(url).map_err(|err| format!("{} is no URL: {}", url, err))?;
+        let mut redirects = 0;
+        let mut response = loop {
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err(format!("only http and https URLs can be read, not {}", url).into());
+            }
+            if !self.allow_private {
+                check_address(&url).await?;
+            }
+            let response = self.http.get(url.clone()).send().await?;
+            let location = response
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|value| value.to_str().ok());
+            let Some(location) = location.filter(|_| response.status().is_redirection()) else {
+                break response;
+            };
+            if redirects == MAX_REDIRECTS {
+                return Err(format!("{} redirected more than {} times", url, MAX_REDIRECTS).into());
+            }
+            redirects += 1;
+            url = url
+                .join(location)
+                .map_err(|err| format!("{} redirected to {}: {}", url, location, err))?;
+        };
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("{} answered {}", url, status).into());
+        }
+        let too_large = || format!("{} is larger than {} bytes", url, MAX_PAGE_BYTES);
+        if response.content_length().unwrap_or_default() > MAX_PAGE_BYTES as u64 {
+            return Err(too_large().into());
+        }
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("text/html")
+            .to_lowercase();
+        // Read in chunks, as the length the server announced may be missing
+        // or wrong
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if bytes.len() + chunk.len() > MAX_PAGE_BYTES {
+                return Err(too_large().into());
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        let body = String::from_utf8_lossy(&bytes).into_owned();
+        if content_type.contains("html") {
+            Ok(readable(&body))
+        } else if content_type.starts_with("text/")
+            || content_type.contains("json")
+            || content_type.contains("xml")
+        {
+            Ok((None, body))
+        } else {
+            Err(format!("{} is {}, which can't be read as text", url, content_type).into())
+        }
+    }
+
+    // Indices of the parts to show, in the order of the page: the parts
+    // closest to `query`, or the first ones without a query or if the parts
+    // can't be embedded
+    async fn relevant(&self, parts: &[String], query: Option<&str>) -> Vec<usize> {
+        if parts.len() <= self.chunks {
+            return (0..parts.len()).collect();
+        }
+        let Some(query) = query else {
+            return (0..self.chunks).collect();
+        };
+        let texts: Vec<String> = std::iter::once(query.to_string())
+            .chain(parts.iter().cloned())
+            .collect();
+        let embeddings = match self.llm.embed_batch(&texts).await {
+            Ok(embeddings) => embeddings,
+            Err(err) => {
+                tracing::warn!("Failed to embed the page, showing its first parts: {}", err);
+                return (0..self.chunks).collect();
+            }
+        };
+        let (query, parts) = match embeddings.split_first() {
+            Some((query, parts)) if parts.len() == texts.len() - 1 => (query, parts),
+            _ => {
+                tracing::warn!(
+                    "Got {} embeddings for {} texts of the page, showing its first parts",
+                    embeddings.len(),
+                    texts.len()
+                );
+                return (0..self.chunks).collect();
+            }
+        };
+        let mut ranked: Vec<(usize, f64)> = parts
+            .iter()
+            .map(|part| cosine_similarity(query, part))
+            .enumerate()
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let mut indices: Vec<usize> = ranked
+            .into_iter()
+            .take(self.chunks)
+            .map(|(index, _)| index)
+            .collect();
+        indices.sort_unstable();
+        indices
+    }
+}
+
+#[async_trait]
+impl Tool for WebFetch {
+    fn name(&self) -> &str {
+        "web_fetch"
+    }
+
+    fn description(&self) -> &str {
+        "Read a web page. Returns its text without navigation and other boilerplate. Of a \
+         long page only the parts most relevant to the query are returned."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "url": { "type": "string", "description": "URL of the page" },
+                "query": {
+                    "type": "string",
+                    "description": "What to look for on the page",
+                },
+            },
+            "required": ["url"],
+        })
+    }
+
+    async fn execute(&self, arguments: Value) -> Result<String, CrustagiError> {
+        let url = arguments["url"].as_str().ok_or("the url is missing")?;
+        let query = arguments["query"]
+            .as_str()
+            .filter(|query| !query.trim().is_empty());
+        let (title, text) = self.fetch(url).await?;
+        if text.trim().is_empty() {
+            return Ok(format!("{} has no readable text", url));
+        }
+        let mut output = match title {
+            Some(title) => format!("Title: {}\nURL: {}\n", title, url),
+            None => format!("URL: {}\n", url),
+        };
+        let model = self.llm.model();
+        let parts = tokens::split(&model, &text, self.chunk_tokens, self.chunk_tokens / 10);
+        let shown = self.relevant(&parts, query).await;
+        if shown.len() == parts.len() {
+            output.push('\n');
+            output.push_str(&text);
+            return Ok(output);
+        }
+        let which = match query {
+            Some(query) => format!("the {} most relevant to \"{}\"", shown.len(), query),
+            None => format!("the first {}", shown.len()),
+        };
+        output.push_str(&format!(
+            "The page has {} parts, these are {}.\n",
+            parts.len(),
+            which
+        ));
+        for index in shown {
+            output.push_str(&format!(
+                "\n[Part {} of {}]\n{}\n",
+                index + 1,
+                parts.len(),
+                parts[index]
+            ));
+        }
+        Ok(output)
+    }
+}
+
+// Refuse `url` if its host is or resolves to an address that isn't public,
+// e.g. localhost, the local network or the cloud metadata service at
+// 169.254.169.254
+async fn check_address(url: &Url) -> Result<(), CrustagiError> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| format!("{} has no host", url))?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addresses = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|err| format!("failed to resolve {}: {}", host, err))?;
+    for address in addresses {
+        if !is_public(address.ip()) {
+            return Err(format!(
+                "{} leads to {}, which isn't a public address",
+                url,
+                address.ip()
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            // 100.64.0.0/10 is shared by carrier-grade NAT
+            let shared = first == 100 && (64..128).contains(&second);
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || shared)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                // Unique local (fc00::/7) and link-local (fe80::/10) addresses
+                let unique_local = first & 0xfe00 == 0xfc00;
+                let link_local = first & 0xffc0 == 0xfe80;
+                !(ip.is_loopback() || ip.is_unspecified() || unique_local || link_local)
+            }
+        },
+    }
+}
+
+// The title and the readable text of an HTML page, in the manner of
+// readability: the main content if the page marks it, without boilerplate
+// elements and without blocks that are mostly links, e.g. menus
+fn readable(html: &str) -> (Option<String>, String) {
+    let title = TITLE
+        .captures(html)
+        .map(|title| text(&title[1]))
+        .filter(|title| !title.is_empty());
+    let mut html = COMMENTS.replace_all(html, "").into_owned();
+    for element in BOILERPLATE_ELEMENTS.iter() {
+        html = element.replace_all(&html, "").into_owned();
+    }
+    if let Some(main) = MAIN.captures(&html) {
+        html = main[1].to_string();
+    }
+    let html = LIST_ITEMS.replace_all(&html, "<br>- ");
+    let blocks: Vec<String> = BLOCKS
+        .split(&html)
+        .filter_map(|block| {
+            let block_text = text(block);
+            let link_chars: usize = LINKS
+                .captures_iter(block)
+                .map(|link| text(&link[1]).chars().count())
+                .sum();
+            let chars = block_text.chars().count();
+            let content = !block_text.is_empty() && block_text != "-" && link_chars * 2 <= chars;
+            content.then_some(block_text)
+        })
+        .collect();
+    (title, blocks.join("\n"))
+}
+
+// The text of an HTML fragment on one line
+fn text(html: &str) -> String {
+    let text = TAGS.replace_all(html, " ");
+    let text = ENTITIES.replace_all(&text, |entity: &Captures| decode(entity));
+    WHITESPACE.replace_all(&text, " ").trim().to_string()
+}
+
+// The character of an HTML entity, or the entity itself if it is unknown
+fn decode(entity: &Captures) -> String {
+    let name = &entity[1];
+    let character = match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        "ndash" => Some('–'),
+        "mdash" => Some('—'),
+        "hellip" => Some('…'),
+        "lsquo" => Some('‘'),
+        "rsquo" => Some('’'),
+        "ldquo" => Some('“'),
+        "rdquo" => Some('”'),
+        "copy" => Some('©'),
+        _ => {
+            let code = match name.strip_prefix("#x").or(name.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => name
+                    .strip_prefix('#')
+                    .and_then(|decimal| decimal.parse().ok()),
+            };
+            code.and_then(char::from_u32)
+        }
+    };
+    match character {
+        Some(character) => character.to_string(),
+        None => entity[0].to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn check(url: &str) -> Result<(), CrustagiError> {
+        check_address(&Url::parse(url).unwrap()).await
+    }
+
+    #[tokio::test]
+    async fn refuses_addresses_that_arent_public() {
+        for url in [
+            "http://localhost:8080/",
+            "http://127.0.0.1/",
+            "http://10.0.0.1/",
+            "http://192.168.1.1/",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://0.0.0.0/",
+            "http://[::1]/",
+            "http://[fd00::1]/",
+            "http://[::ffff:127.0.0.1]/",
+        ] {
+            assert!(check(url).await.is_err(), "{} was allowed", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn allows_public_addresses() {
+        assert!(check("https://93.184.216.34/").await.is_ok());
+        assert!(check("http://[2606:2800:220:1::]/").await.is_ok());
+    }
+}
@@ -0,0 +1,244 @@
+// Web search for the execution agent, so that research tasks are answered
+// from current sources with their URLs rather than from the model's memory
+
+use async_trait::async_trait;
+use regex::Regex;
+use reqwest::{Client, RequestBuilder};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::LazyLock;
+
+use crate::error::CrustagiError;
+use crate::tools::Tool;
+
+pub const SEARCH_BACKENDS: &[&str] = &["serpapi", "brave", "tavily"];
+
+// Brave marks the query terms in its snippets with HTML
+static TAGS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]*>").unwrap());
+
+/// The search API a [`WebSearch`] asks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchBackend {
+    /// Google results through SerpAPI
+    SerpApi,
+    /// The Brave Search API
+    Brave,
+    /// Tavily, a search API made for agents
+    Tavily,
+}
+
+impl SearchBackend {
+    /// The backend called `name` in `WEB_SEARCH`, one of `SEARCH_BACKENDS`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "serpapi" => Some(SearchBackend::SerpApi),
+            "brave" => Some(SearchBackend::Brave),
+            "tavily" => Some(SearchBackend::Tavily),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SearchBackend::SerpApi => "SerpAPI",
+            SearchBackend::Brave => "Brave Search",
+            SearchBackend::Tavily => "Tavily",
+        }
+    }
+
+    fn default_api_base(self) -> &'static str {
+        match self {
+            SearchBackend::SerpApi => "https://serpapi.com",
+            SearchBackend::Brave => "https://api.search.brave.com/res/v1",
+            SearchBackend::Tavily => "https://api.tavily.com",
+        }
+    }
+}
+
+/// A page found by a search
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub title: String,
+    pub url: String,
+    pub snippet: String,
+}
+
+/// The `web_search` tool: searches the web and shows the model the top
+/// results with their URLs, for it to cite
+pub struct WebSearch {
+    pub http: Client,
+    pub backend: SearchBackend,
+    pub api_key: String,
+    /// URL of the API, the backend's public one if not set
+    pub api_base: Option<String>,
+    /// Results per search
+    pub results: usize,
+}
+
+#[derive(Deserialize)]
+struct SerpApiResponse {
+    #[serde(default)]
+    organic_results: Vec<SerpApiResult>,
+}
+
+#[derive(Deserialize)]
+struct SerpApiResult {
+    title: String,
+    link: String,
+    #[serde(default)]
+    snippet: String,
+}
+
+#[derive(Deserialize)]
+struct BraveResponse {
+    web: Option<BraveResults>,
+}
+
+#[derive(Deserialize)]
+struct BraveResults {
+    results: Vec<BraveResult>,
+}
+
+#[derive(Deserialize)]
+struct BraveResult {
+    title: String,
+    url: String,
+    #[serde(default)]
+    description: String,
+}
+
+#[derive(Deserialize)]
+struct TavilyResponse {
+    results: Vec<TavilyResult>,
+}
+
+#[derive(Deserialize)]
+struct TavilyResult {
+    title: String,
+    url: String,
+    #[serde(default)]
+    content: String,
+}
+
+impl WebSearch {
+    /// The top results for `query`, at most `results` of them
+    pub async fn search(&self, query: &str) -> Result<Vec<SearchResult>, CrustagiError> {
+        let api_base = self
+            .api_base
+            .as_deref()
+            .unwrap_or(self.backend.default_api_base());
+        let count = self.results.to_string();
+        let results: Vec<SearchResult> = match self.backend {
+            SearchBackend::SerpApi => {
+                let request = self.http.get(format!("{}/search.json", api_base)).query(&[
+                    ("engine", "google"),
+                    ("q", query),
+                    ("num", &count),
+                    ("api_key", &self.api_key),
+                ]);
+                let response: SerpApiResponse = self.send(request).await?;
+                response
+                    .organic_results
+                    .into_iter()
+                    .map(|result| SearchResult {
+                        title: result.title,
+                        url: result.link,
+                        snippet: result.snippet,
+                    })
+                    .collect()
+            }
+            SearchBackend::Brave => {
+                let request = self
+                    .http
+                    .get(format!("{}/web/search", api_base))
+                    .header("X-Subscription-Token", &self.api_key)
+                    .query(&[("q", query), ("count", &count)]);
+                let response: BraveResponse = self.send(request).await?;
+                response
+                    .web
+                    .map_or_else(Vec::new, |web| web.results)
+                    .into_iter()
+                    .map(|result| SearchResult {
+                        title: TAGS.replace_all(&result.title, "").into_owned(),
+                        url: result.url,
+                        snippet: TAGS.replace_all(&result.description, "").into_owned(),
+                    })
+                    .collect()
+            }
+            SearchBackend::Tavily => {
+                let request = self
+                    .http
+                    .post(format!("{}/search", api_base))
+                    .bearer_auth(&self.api_key)
+                    .json(&json!({ "query": query, "max_results": self.results }));
+                let response: TavilyResponse = self.send(request).await?;
+                response
+                    .results
+                    .into_iter()
+                    .map(|result| SearchResult {
+                        title: result.title,
+                        url: result.url,
+                        snippet: result.content,
+                    })
+                    .collect()
+            }
+        };
+        Ok(results.into_iter().take(self.results).collect())
+    }
+
+    // Send a search request. A failed one fails with what the API said.
+    // Errors leave out the URL, which holds the API key for SerpAPI, as the
+    // model and the logs see them.
+    async fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, CrustagiError> {
+        let response = request.send().await.map_err(reqwest::Error::without_url)?;
+        let status = response.status();
+        let body = response.text().await.map_err(reqwest::Error::without_url)?;
+        if !status.is_success() {
+            return Err(format!("{} answered {}: {}", self.backend.name(), status, body).into());
+        }
+        Ok(serde_json::from_str(&body)?)
+    }
+}
+
+#[async_trait]
+impl Tool for WebSearch {
+    fn name(&self) -> &str {
+        "web_search"
+    }
+
+    fn description(&self) -> &str {
+        "Search the web for current information. Returns the top results with their title, \
+         URL and a snippet. Base your answer on the results and cite the URLs you use."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": "string", "description": "What to search for" },
+            },
+            "required": ["query"],
+        })
+    }
+
+    async fn execute(&self, arguments: Value) -> Result<String, CrustagiError> {
+        let query = arguments["query"].as_str().ok_or("the query is missing")?;
+        let results = self.search(query).await?;
+        if results.is_empty() {
+            return Ok(format!("No results for \"{}\"", query));
+        }
+        Ok(results
+            .iter()
+            .enumerate()
+            .map(|(i, result)| {
+                let mut entry = format!("{}. {}\n   {}", i + 1, result.title, result.url);
+                if !result.snippet.is_empty() {
+                    entry.push_str(&format!("\n   {}", result.snippet));
+                }
+                entry
+            })
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+}
@@ -0,0 +1,151 @@
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use crate::events::Event;
+use crate::observer::Observer;
+
+// Events webhooks can be sent for, the default being all of them
+pub const WEBHOOK_EVENTS: &[&str] = &["task_completed", "run_finished", "budget_exceeded", "error"];
+
+// How long the end of a run waits for webhooks still being sent
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+// Longer texts are cut, Discord refuses messages over 2000 characters
+const MAX_TEXT_CHARS: usize = 1500;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Format {
+    // The event as JSON, with a `text` summary, for any endpoint
+    Generic,
+    // Slack and Discord incoming webhooks
+    Slack,
+    Discord,
+}
+
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    pub format: Format,
+    pub url: String,
+}
+
+// Sends the webhooks for the events of the run it observes
+pub struct Webhooks {
+    http: Client,
+    webhooks: Vec<Webhook>,
+    events: Vec<String>,
+    // Told apart from other runs posting to the same channel
+    label: String,
+    // The webhooks still being sent
+    pending: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Webhooks {
+    // Send the `events` of the run, out of `WEBHOOK_EVENTS`, to `webhooks`.
+    // The messages start with `label`, e.g. the objective.
+    pub fn new(http: Client, webhooks: Vec<Webhook>, events: Vec<String>, label: &str) -> Self {
+        Webhooks {
+            http,
+            webhooks,
+            events,
+            label: label.to_string(),
+            pending: Mutex::new(Vec::new()),
+        }
+    }
+
+    // Wait for the webhooks still being sent, e.g. before the process exits
+    pub async fn flush(&self) {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        let all = async {
+            for handle in pending {
+                let _ = handle.await;
+            }
+        };
+        if time::timeout(FLUSH_TIMEOUT, all).await.is_err() {
+            tracing::warn!("Gave up waiting for the webhooks still being sent");
+        }
+    }
+}
+
+// The webhooks for an event are only sent if it is one of the configured
+// events. They are sent in the background, so that a slow endpoint doesn't
+// hold up the run.
+impl Observer for Webhooks {
+    fn on_event(&self, event: &Event) {
+        let Some((name, text)) = describe(event) else {
+            return;
+        };
+        if !self.events.iter().any(|enabled| enabled == name) {
+            return;
+        }
+        let text = format!("[{}] {}", self.label, text);
+        let text = match text.char_indices().nth(MAX_TEXT_CHARS) {
+            Some((end, _)) => format!("{}...", &text[..end]),
+            None => text,
+        };
+        let details = serde_json::to_value(event).unwrap_or_default();
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|handle| !handle.is_finished());
+        for webhook in &self.webhooks {
+            let request =
+                self.http
+                    .post(&webhook.url)
+                    .json(&payload(webhook.format, name, &text, &details));
+            let url = webhook.url.clone();
+            pending.push(tokio::spawn(async move {
+                let result = request.send().await.and_then(|res| res.error_for_status());
+                if let Err(err) = result {
+                    // The URL may hold a token, only the host is shown
+                    let host = reqwest::Url::parse(&url)
+                        .ok()
+                        .and_then(|url| url.host_str().map(str::to_string))
+                        .unwrap_or_default();
+                    tracing::warn!(
+                        "Failed to send the {} webhook to {}: {}",
+                        name,
+                        host,
+                        err.without_url()
+                    );
+                }
+            }));
+        }
+    }
+}
+
+// Webhook event and message of `event`, None if it doesn't send one
+fn describe(event: &Event) -> Option<(&'static str, String)> {
+    match event {
+        Event::TaskResult {
+            task_id,
+            task_name,
+            result,
+        } => Some((
+            "task_completed",
+            format!("Completed task {}: {}\n{}", task_id, task_name, result),
+        )),
+        Event::RunFinished { reason, iterations } => Some((
+            "run_finished",
+            format!("Run finished after {} iterations: {}", iterations, reason),
+        )),
+        Event::BudgetExceeded { reason } => {
+            Some(("budget_exceeded", format!("{}, stopping the run", reason)))
+        }
+        // Failures that are retried may still work out
+        Event::TaskFailed {
+            task_id,
+            error,
+            retried: false,
+        } => Some(("error", format!("Gave up on task {}: {}", task_id, error))),
+        _ => None,
+    }
+}
+
+fn payload(format: Format, name: &str, text: &str, details: &Value) -> Value {
+    match format {
+        Format::Generic => json!({ "event": name, "text": text, "details": details }),
+        Format::Slack => json!({ "text": text }),
+        Format::Discord => json!({ "content": text }),
+    }
+}